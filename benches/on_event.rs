@@ -0,0 +1,100 @@
+//! `BroadcastLogLayer::on_event` 端到端基准：关闭持久化，只测 Layer 本身的开销
+//!
+//! 运行：`cargo bench --bench on_event`（`harness = false` 的简单计时，不依赖 criterion）。
+//! 每个场景分别在没有广播订阅者、有一个不消费的订阅者（每条日志需拷贝一份进广播通道）
+//! 两种情况下测量，后台写入任务在批次之间运行，其耗时不计入。
+//!
+//! 只输出本次运行的绝对耗时，数字随机器变化；比较两个版本时在同一台机器上先后运行本基准。
+//! 剩余开销分散在构造记录的各个步骤（读时钟、格式化时间戳、关联 ID、字段捕获、
+//! `LogEntry` 各个 `String` 字段与字段表的分配），没有单独的热点。
+//!
+//! 最后一组场景没有任何去向（无订阅者、缓存容量为 0、关闭持久化），
+//! `on_event` 直接返回，只剩 tracing 本身分发事件的开销。
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use listen_tracing::{BroadcastLogLayer, LogBuffer, LogCache, LogEntry};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+const BATCH: usize = 1024;
+const BATCHES: usize = 500;
+
+fn run(name: &str, subscribed: bool, emit: impl Fn(usize)) {
+    let per_event = measure(LogCache::default(), subscribed, &emit);
+    let label = if subscribed {
+        "1 subscriber"
+    } else {
+        "no subscriber"
+    };
+    println!("{:<20} {:<14} {:>6.0} ns", name, label, per_event);
+}
+
+/// 没有任何去向时的开销，与有缓存、无订阅者的情况对比
+fn run_without_consumers(name: &str, emit: impl Fn(usize)) {
+    let cached = measure(LogCache::default(), false, &emit);
+    let skipped = measure(LogBuffer::new(0).shared(), false, &emit);
+    println!(
+        "{:<20} {:<14} cached {:>6.0} ns  skipped {:>4.0} ns  {:>4.1}x",
+        name,
+        "no consumers",
+        cached,
+        skipped,
+        cached / skipped
+    );
+}
+
+/// 每条日志的耗时中位数（ns）
+fn measure(cache: LogCache, subscribed: bool, emit: &impl Fn(usize)) -> f64 {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let _enter = runtime.enter();
+    let (tx, rx) = tokio::sync::broadcast::channel::<LogEntry>(BATCH);
+    let _rx = subscribed.then_some(rx);
+    let layer = BroadcastLogLayer::new(tx, cache).without_persistence();
+    let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+    // 预热：启动写入任务、填满缓存
+    for i in 0..BATCH * 2 {
+        emit(i);
+    }
+    runtime.block_on(tokio::task::yield_now());
+
+    let mut samples: Vec<Duration> = (0..BATCHES)
+        .map(|batch| {
+            let start = Instant::now();
+            for i in 0..BATCH {
+                emit(black_box(batch * BATCH + i));
+            }
+            let spent = start.elapsed();
+            runtime.block_on(tokio::task::yield_now());
+            spent
+        })
+        .collect();
+    // 取中位数，降低调度抖动的影响
+    samples.sort();
+    samples[BATCHES / 2].as_nanos() as f64 / BATCH as f64
+}
+
+fn main() {
+    for subscribed in [false, true] {
+        run("message only", subscribed, |_| {
+            tracing::info!("order filled")
+        });
+        run(
+            "message + 3 fields",
+            subscribed,
+            |i| tracing::info!(symbol = "BTC", qty = i, side = ?"buy", "order filled"),
+        );
+        run("fields only", subscribed, |i| {
+            tracing::warn!(symbol = "ETH", qty = i)
+        });
+    }
+    run_without_consumers(
+        "message + 3 fields",
+        |i| tracing::info!(symbol = "BTC", qty = i, side = ?"buy", "order filled"),
+    );
+}
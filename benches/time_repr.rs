@@ -0,0 +1,97 @@
+//! `TimeRepr` 两种时间戳存储形式的对比：每条日志在 `on_event` 内的堆分配次数与耗时
+//!
+//! 运行：`cargo bench --bench time_repr`。计数分配器统计发出日志期间的全部分配，
+//! 单线程运行时下后台写入任务只在批次之间运行，其分配不计入。
+//! `EpochNanos` 省去时间戳字符串在条目及其副本上的分配，格式化推迟到序列化时。
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use listen_tracing::{BroadcastLogLayer, LogCache, LogEntry, TimeRepr};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const BATCH: usize = 1024;
+const BATCHES: usize = 200;
+
+/// (每条日志的分配次数, 每条日志耗时中位数 ns)
+fn measure(repr: TimeRepr, emit: &impl Fn(usize)) -> (f64, f64) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let _enter = runtime.enter();
+    let (tx, _rx) = tokio::sync::broadcast::channel::<LogEntry>(BATCH);
+    let layer = BroadcastLogLayer::new(tx, LogCache::default())
+        .without_persistence()
+        .with_time_repr(repr);
+    let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+    // 预热：启动写入任务、填满缓存
+    for i in 0..BATCH * 2 {
+        emit(i);
+    }
+    runtime.block_on(tokio::task::yield_now());
+
+    let mut allocations = 0;
+    let mut samples: Vec<Duration> = (0..BATCHES)
+        .map(|batch| {
+            let before = ALLOCATIONS.load(Ordering::Relaxed);
+            let start = Instant::now();
+            for i in 0..BATCH {
+                emit(black_box(batch * BATCH + i));
+            }
+            let spent = start.elapsed();
+            allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+            runtime.block_on(tokio::task::yield_now());
+            spent
+        })
+        .collect();
+    samples.sort();
+    (
+        allocations as f64 / (BATCH * BATCHES) as f64,
+        samples[BATCHES / 2].as_nanos() as f64 / BATCH as f64,
+    )
+}
+
+fn run(name: &str, emit: impl Fn(usize)) {
+    let (string_allocs, string_ns) = measure(TimeRepr::Rfc3339String, &emit);
+    let (nanos_allocs, nanos_ns) = measure(TimeRepr::EpochNanos, &emit);
+    println!(
+        "{:<20} rfc3339 {:>5.2} allocs {:>6.0} ns  epoch_nanos {:>5.2} allocs {:>6.0} ns",
+        name, string_allocs, string_ns, nanos_allocs, nanos_ns
+    );
+}
+
+fn main() {
+    run("message only", |_| tracing::info!("order filled"));
+    run(
+        "message + 3 fields",
+        |i| tracing::info!(symbol = "BTC", qty = i, side = ?"buy", "order filled"),
+    );
+}
@@ -0,0 +1,199 @@
+//! 实时查看日志：跟随另一个进程写出的 JSONL 文件，或订阅内置演示程序的广播
+//!
+//! ```text
+//! cargo run --example logtail -- --follow logs.jsonl --level WARN --target order
+//! cargo run --example logtail -- --keyword fill --json
+//! ```
+//!
+//! 过滤直接复用库里的 `LogQuery` / `matches_query` 与 `QueryExpr`，渲染使用 `LogStyle::render_entry`。
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use listen_tracing::follow::{FollowError, LogFollower};
+use listen_tracing::query::{QueryExpr, TextField, TextOp};
+use listen_tracing::store::matches_query;
+use listen_tracing::{BroadcastLogLayer, LogBroadcaster, LogCache, LogEntry, LogQuery, LogStyle};
+use tokio::sync::broadcast::error::RecvError;
+use tracing_subscriber::layer::SubscriberExt;
+
+const USAGE: &str = "usage: logtail [--follow <path> [--from-start]] [--level <LEVEL>] \
+                     [--target <text>] [--keyword <text>] [--field <name>=<value>] [--q <expr>] [--json] \
+                     [--no-color]";
+
+#[derive(Default)]
+struct Args {
+    follow: Option<PathBuf>,
+    from_start: bool,
+    query: LogQuery,
+    target: Option<String>,
+    q: Option<String>,
+    json: bool,
+    no_color: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--follow" => args.follow = Some(value()?.into()),
+            "--from-start" => args.from_start = true,
+            "--level" => args.query.level = Some(value()?),
+            "--target" => args.target = Some(value()?),
+            "--keyword" => args.query.keyword = Some(value()?),
+            "--field" => {
+                let pair = value()?;
+                let (name, value) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("--field expects <name>=<value>, got {}", pair))?;
+                args.query.field = Some((name.to_string(), value.to_string()));
+            }
+            "--q" => args.q = Some(value()?),
+            "--json" => args.json = true,
+            "--no-color" => args.no_color = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown argument {}\n{}", other, USAGE)),
+        }
+    }
+    Ok(args)
+}
+
+/// `--level` / `--keyword` / `--field` 按 `LogQuery` 的语义匹配，`--target` 与 `--q` 组合为查询表达式
+struct Filter {
+    query: LogQuery,
+    expr: Option<QueryExpr>,
+}
+
+impl Filter {
+    fn new(args: &Args) -> Result<Self, String> {
+        let mut exprs = Vec::new();
+        if let Some(target) = &args.target {
+            exprs.push(QueryExpr::Text {
+                field: TextField::Target,
+                op: TextOp::Contains,
+                value: target.clone(),
+            });
+        }
+        if let Some(q) = &args.q {
+            exprs.push(QueryExpr::parse(q).map_err(|e| format!("[{}] {}", e.code(), e))?);
+        }
+        Ok(Self {
+            query: args.query.clone(),
+            expr: (!exprs.is_empty()).then_some(QueryExpr::And(exprs)),
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        matches_query(entry, &self.query) && self.expr.as_ref().is_none_or(|e| e.matches(entry))
+    }
+}
+
+struct Printer {
+    filter: Filter,
+    style: LogStyle,
+    json: bool,
+}
+
+impl Printer {
+    fn print(&self, entry: &LogEntry) {
+        if !self.filter.matches(entry) {
+            return;
+        }
+        if self.json {
+            println!("{}", serde_json::to_string(entry).unwrap_or_default());
+        } else {
+            println!("{}", self.style.render_entry(entry));
+        }
+    }
+}
+
+/// 跟随文件，轮转与半行由 `LogFollower` 处理；坏行跳过，读取失败时退出
+async fn follow(path: PathBuf, from_start: bool, printer: &Printer) -> ExitCode {
+    let mut follower = LogFollower::new(&path, from_start.then_some(0));
+    loop {
+        match follower.next().await {
+            Ok(entry) => printer.print(&entry),
+            Err(e @ FollowError::Parse { .. }) => eprintln!("logtail: [{}] {}", e.code(), e),
+            Err(e) => {
+                eprintln!("logtail: [{}] {}", e.code(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+}
+
+/// 在本进程内安装广播层并运行一个演示负载，订阅广播实时输出，Ctrl-C 退出
+async fn tail_demo(printer: &Printer) -> ExitCode {
+    let broadcaster = LogBroadcaster::new(1024);
+    let layer = BroadcastLogLayer::new(broadcaster.sender().clone(), LogCache::default())
+        .without_persistence();
+    if let Err(e) =
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+    {
+        eprintln!("logtail: {}", e);
+        return ExitCode::FAILURE;
+    }
+    let mut rx = broadcaster.subscribe("logtail");
+    tokio::spawn(demo_workload());
+
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Ok(entry) => printer.print(&entry),
+                Err(RecvError::Lagged(n)) => eprintln!("logtail: lagged, {} entries skipped", n),
+                Err(RecvError::Closed) => return ExitCode::SUCCESS,
+            },
+            _ = tokio::signal::ctrl_c() => return ExitCode::SUCCESS,
+        }
+    }
+}
+
+async fn demo_workload() {
+    let mut ticker = tokio::time::interval(Duration::from_millis(300));
+    for order in 1u64.. {
+        ticker.tick().await;
+        let span = tracing::info_span!("order", id = order);
+        let _entered = span.enter();
+        match order % 7 {
+            0 => tracing::error!(target: "demo::order", venue = "binance", "order rejected"),
+            3 => tracing::warn!(target: "demo::order", latency_ms = 850, "slow fill"),
+            _ => tracing::info!(target: "demo::order", qty = order * 10, "order filled"),
+        }
+        if order % 5 == 0 {
+            tracing::debug!(target: "demo::book", depth = 20, "book snapshot");
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    let filter = match Filter::new(&args) {
+        Ok(filter) => filter,
+        Err(message) => {
+            eprintln!("logtail: {}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    let printer = Printer {
+        filter,
+        style: LogStyle {
+            ansi: !args.no_color,
+            ..Default::default()
+        },
+        json: args.json,
+    };
+    match args.follow {
+        Some(path) => follow(path, args.from_start, &printer).await,
+        None => tail_demo(&printer).await,
+    }
+}
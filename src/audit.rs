@@ -0,0 +1,173 @@
+//! 合规审计日志：同步、逐条 fsync 写入独立的只追加文件
+//!
+//! target 为 `audit`（或以 `audit::` 开头）的事件视为审计事件，通常由 `audit!` 产生。
+//! 设置了 `BroadcastLogLayer::with_audit_path` 后，审计事件在 `on_event` 内同步写入审计文件：
+//!
+//! - `audit!` 返回时该条目已写入并 `fsync`（`File::sync_data`），进程随后崩溃或断电也不会丢失；
+//!   文件首次创建时同时 fsync 所在目录，保证目录项本身落盘
+//! - 审计文件以追加方式打开，unix 上权限为 0600（已存在且更宽松的文件会被收紧）
+//! - 审计事件不受 `with_min_level`、采样、持久化暂停与路由规则影响；
+//!   但仍须通过 subscriber 的过滤器，`RUST_LOG` 等指令不能把 `audit` target 压到 INFO 以下
+//! - 写入失败时计入 `LogStats::write_errors` 并输出到 stderr，下次写入重新打开文件；
+//!   事件本身照常进入广播、缓存和普通持久化文件
+//!
+//! 每条审计日志都要等待磁盘确认，吞吐远低于普通日志，只应用于登录、权限变更等少量事件。
+//! 普通日志仍走异步写入任务，不受影响。
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// 审计事件的 target
+pub const AUDIT_TARGET: &str = "audit";
+
+/// 是否为审计事件的 target：`audit` 或 `audit::*`
+pub fn is_audit_target(target: &str) -> bool {
+    target
+        .strip_prefix(AUDIT_TARGET)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// 记录一条审计事件（INFO 级别，target 为 `AUDIT_TARGET`），参数同 `tracing::info!`：
+/// `audit!(user = %user_id, action = "grant", role = "admin", "permission changed")`
+#[macro_export]
+macro_rules! audit {
+    ($($arg:tt)+) => {
+        tracing::info!(target: $crate::audit::AUDIT_TARGET, $($arg)+)
+    };
+}
+
+/// 同步写入的审计文件，见模块文档
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl AuditLog {
+    /// 文件在第一次写入时打开（不存在则创建）
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 追加一行并 fsync，返回 Ok 时数据已落盘
+    pub fn append(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if file.is_none() {
+            *file = Some(open_audit_file(&self.path)?);
+        }
+        let mut buf = Vec::with_capacity(line.len() + 1);
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+        let result = file.as_mut().map_or(Ok(()), |f| {
+            f.write_all(&buf)?;
+            f.sync_data()
+        });
+        if result.is_err() {
+            *file = None;
+        }
+        result
+    }
+}
+
+fn open_audit_file(path: &Path) -> io::Result<File> {
+    let created = !path.exists();
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(0o600);
+        let file = options.open(path)?;
+        let mut permissions = file.metadata()?.permissions();
+        if permissions.mode() & 0o077 != 0 {
+            permissions.set_mode(0o600);
+            file.set_permissions(permissions)?;
+        }
+        if created {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = created;
+        options.open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampling::SamplingConfig;
+    use crate::{BroadcastLogLayer, LogCache, LogEntry};
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_audit_target() {
+        assert!(is_audit_target("audit"));
+        assert!(is_audit_target("audit::auth"));
+        assert!(!is_audit_target("auditor"));
+        assert!(!is_audit_target("app::audit"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_written_before_return() {
+        skip_if_events_compiled_out!();
+        let path =
+            std::env::temp_dir().join(format!("listen-tracing-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        // 审计事件不受最低级别和采样影响
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_min_level(Some(Level::ERROR))
+            .with_sampling(SamplingConfig::new(0.0))
+            .with_audit_path(&path);
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::warn!("not audited");
+        crate::audit!(user = "alice", action = "login", "user logged in");
+        // 不等待写入任务：audit! 返回时已在磁盘上
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let entries: Vec<LogEntry> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].target, AUDIT_TARGET);
+        assert_eq!(entries[0].message, "user logged in");
+        assert_eq!(entries[0].fields["user"], "alice");
+
+        tracing::info!(target: "audit::perm", role = "admin", "permission changed");
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+        assert!(lines.ends_with('\n'));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // 审计事件照常进入缓存
+        let cached = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        assert_eq!(cached.len(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,1633 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
+
+use crate::audit::{is_audit_target, AuditLog};
+use crate::clock::{Clock, ClockWatch, SharedClock};
+use crate::collapse::{Collapser, DEFAULT_COLLAPSE_TIMEOUT};
+use crate::config::{mask_secrets, EffectiveConfig, CONFIG_TARGET};
+use crate::digest::{spawn_digest, DigestConfig};
+use crate::escalation::EscalationRules;
+use crate::file_lock::{LockConflict, LockError, LockFallback};
+use crate::json_schema::{
+    ecs_to_native, native_timestamp, JsonSchema, TimestampFormat, Timestamps,
+};
+use crate::limits::FieldLimits;
+use crate::pipeline::{Pipeline, DEFAULT_CHANNEL_CAPACITY};
+use crate::query::QueryExpr;
+use crate::routing::RouteRules;
+use crate::sampling::{Sampler, SamplingConfig};
+use crate::sanitize::{sanitize, sanitize_owned, truncate_bytes, Sanitize};
+use crate::serializer::LogSerializer;
+use crate::setup::{self, attach_layer, SetupError};
+use crate::span_cache;
+use crate::span_events::{self, SpanEvents};
+use crate::target_levels::TargetLevelOverrides;
+use crate::timestamp::{LogTimestamp, TimeRepr};
+use crate::verbosity::{AdaptiveVerbosity, VerbosityGate};
+use crate::{LogCache, LogRecord, LogStats, RecordContext};
+
+/// 当前 `LogEntry` 的 schema 版本
+///
+/// - 1：最初的 timestamp / level / target / message 四个字段（无 `schema` 字段）
+/// - 2：新增可缺省的 `correlation_id`、`fields`，并开始写出 `schema`
+/// - 3：新增可缺省的 `seq`
+/// - 4：新增可缺省的 `fingerprint`
+/// - 5：新增可缺省的 `mono_ns`
+/// - 6：新增可缺省的 `date_bucket`
+/// - 7：新增可缺省的 `name`
+pub const LOG_ENTRY_SCHEMA: u8 = 7;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    /// 写出时为 `LOG_ENTRY_SCHEMA`，反序列化缺省时视为 1
+    #[serde(default = "legacy_schema")]
+    pub schema: u8,
+    /// 序列化为 RFC 3339 字符串，存储形式见 `BroadcastLogLayer::with_time_repr`
+    pub timestamp: LogTimestamp,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// 事件携带的结构化字段（不含 message）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, serde_json::Value>,
+    /// 写入内存缓存时分配的递增序号（从 1 开始），广播和持久化的条目没有该字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// 调用点指纹（见 `BroadcastLogLayer::with_fingerprint`），同一处日志语句的条目相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// 产生时的进程内单调时间（纳秒，见 `clock::monotonic_ns`），不受墙上时钟回拨影响
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mono_ns: Option<u64>,
+    /// 由时间戳得出的分桶键（见 `BroadcastLogLayer::with_bucket`），如 `2024-06-01` 或 `2024-06-01T14`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_bucket: Option<String>,
+    /// 显式指定的事件名（`event!(name: "order_filled", ..)`），区分事件种类；
+    /// 宏自动生成的 `event <文件>:<行号>` 形式不记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+fn legacy_schema() -> u8 {
+    1
+}
+
+impl Default for LogEntry {
+    fn default() -> Self {
+        Self {
+            schema: LOG_ENTRY_SCHEMA,
+            timestamp: LogTimestamp::default(),
+            level: String::new(),
+            target: String::new(),
+            message: String::new(),
+            correlation_id: None,
+            fields: BTreeMap::new(),
+            seq: None,
+            fingerprint: None,
+            mono_ns: None,
+            date_bucket: None,
+            name: None,
+        }
+    }
+}
+
+/// `LogEntry::migrate` 的失败原因
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MigrateError {
+    /// 不是 JSON 对象
+    NotAnObject,
+    /// `schema` 不是非负整数
+    InvalidSchema(serde_json::Value),
+    /// 比当前版本更新（或为 0）的 schema，无法安全降级
+    UnsupportedSchema(u64),
+    /// 升级后仍不符合当前结构（例如缺少必需字段）
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrateError::NotAnObject => write!(f, "log entry is not a JSON object"),
+            MigrateError::InvalidSchema(value) => write!(f, "invalid log entry schema: {}", value),
+            MigrateError::UnsupportedSchema(schema) => write!(
+                f,
+                "unsupported log entry schema {} (current is {})",
+                schema, LOG_ENTRY_SCHEMA
+            ),
+            MigrateError::Json(e) => write!(f, "invalid log entry: {}", e),
+        }
+    }
+}
+
+impl MigrateError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        match self {
+            MigrateError::NotAnObject => "LT-PRS-001",
+            MigrateError::InvalidSchema(_) => "LT-PRS-002",
+            MigrateError::UnsupportedSchema(_) => "LT-PRS-003",
+            MigrateError::Json(_) => "LT-PRS-004",
+        }
+    }
+}
+
+impl std::error::Error for MigrateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrateError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for MigrateError {
+    fn from(e: serde_json::Error) -> Self {
+        MigrateError::Json(e)
+    }
+}
+
+impl LogEntry {
+    /// 把任意历史版本的 JSON 升级为当前结构，`schema` 缺省时按版本 1 处理；
+    /// 以 `JsonSchema::Ecs` 命名写出的行先改回原生键名，以 `TimestampFormat` 中
+    /// 非 `Custom` 格式写出的时间改回原生的 RFC 3339 文本
+    ///
+    /// 读取旧的 `logs.jsonl` 或旧版本生产者发来的数据时应走这里，而不是直接反序列化。
+    pub fn migrate(value: serde_json::Value) -> Result<LogEntry, MigrateError> {
+        let serde_json::Value::Object(mut map) = value else {
+            return Err(MigrateError::NotAnObject);
+        };
+        ecs_to_native(&mut map);
+        native_timestamp(&mut map);
+        let schema = match map.get("schema") {
+            None => 1,
+            Some(value) => value
+                .as_u64()
+                .ok_or_else(|| MigrateError::InvalidSchema(value.clone()))?,
+        };
+        if schema == 0 || schema > LOG_ENTRY_SCHEMA as u64 {
+            return Err(MigrateError::UnsupportedSchema(schema));
+        }
+        // 逐级升级；新增字段均可缺省，目前的各步无需改写已有键：
+        // 1 -> 2：correlation_id、fields
+        // 2 -> 3：seq
+        // 3 -> 4：fingerprint
+        // 4 -> 5：mono_ns
+        // 5 -> 6：date_bucket
+        // 6 -> 7：name
+        map.insert("schema".to_string(), LOG_ENTRY_SCHEMA.into());
+        Ok(serde_json::from_value(serde_json::Value::Object(map))?)
+    }
+
+    /// 解析 level 字符串，无法识别时返回 None
+    pub fn level_value(&self) -> Option<tracing::Level> {
+        self.level.parse().ok()
+    }
+
+    /// 级别是否不低于 `min`（例如 min 为 WARN 时 WARN/ERROR 返回 true）
+    pub fn is_at_least(&self, min: tracing::Level) -> bool {
+        self.level_value().is_some_and(|level| level <= min)
+    }
+}
+
+/// 默认的 JSONL 持久化文件
+pub const DEFAULT_LOG_FILE: &str = "logs.jsonl";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LogQuery {
+    pub level: Option<String>,
+    pub keyword: Option<String>,
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+    /// 为每条结果计算 keyword 的命中位置（见 `store::Highlights`）
+    #[serde(default)]
+    pub include_highlights: bool,
+    /// 组件名，仅 `RoutedLogCache::query` 使用，用于选择分桶
+    pub component: Option<String>,
+    /// 查询表达式（语法见 `query` 模块），非空时取代 level / keyword 过滤，且不计算 highlights
+    pub q: Option<String>,
+    /// 结构化字段精确匹配：(字段名, 值)，与其他条件（包括 `q`）同时生效；
+    /// 数字与布尔字段按其 JSON 文本比较，`42` 匹配 `user_id = 42`
+    pub field: Option<(String, String)>,
+    /// 展示时区（IANA 名称，如 `Asia/Shanghai`，不区分大小写），只影响返回的时间戳写法，
+    /// 需要 `tz` feature，见 `timezone::localize_page`
+    pub tz: Option<String>,
+}
+
+/// 安装带广播层的全局 subscriber；在 tokio 运行时内调用时同时按 `DigestConfig::from_env` 启动周期摘要
+///
+/// 若全局 subscriber 已由本 crate 安装（例如先调用了 `setup_tracing`），广播层会通过 reload
+/// 槽位挂载上去而不重新初始化；由其他代码安装时返回 `SetupError::ForeignSubscriber`。
+/// 成功后返回生效的配置，并以一条 `CONFIG_TARGET` 日志记录下来。
+pub fn setup_tracing_with_broadcast(
+    tx: broadcast::Sender<LogEntry>,
+    cache: LogCache,
+) -> Result<EffectiveConfig, SetupError> {
+    setup_tracing_with_layer(BroadcastLogLayer::new(tx, cache))
+}
+
+/// 同 `setup_tracing_with_broadcast`，使用调用方配置好的 Layer（记录类型可为自定义的 `LogRecord`）
+pub fn setup_tracing_with_layer<R: LogRecord>(
+    layer: BroadcastLogLayer<R>,
+) -> Result<EffectiveConfig, SetupError> {
+    install_layer(
+        layer,
+        EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()),
+        Vec::new(),
+    )
+}
+
+/// 全局安装的 Layer 正在写入的文件，见 `active_log_paths`
+static ACTIVE_LOG_PATHS: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+
+/// 最近一次全局安装或挂载的 Layer 当前写入的文件（持久化文件、审计文件），尚未安装时为空
+///
+/// 持久化文件被他人锁定而改用带后缀的路径时返回实际写入的路径。
+/// `index::rotate_log_file` 把旧文件改名移走，之后的日志写入原路径的新文件，因此轮转前后返回值不变。
+pub fn active_log_paths() -> Vec<PathBuf> {
+    ACTIVE_LOG_PATHS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// 安装或挂载 Layer；挂载到已有 subscriber 时沿用其过滤器，`filter` 不生效
+///
+/// `remotes` 为 (名称, 地址)，打码后记入返回的配置。
+pub(crate) fn install_layer<R: LogRecord>(
+    mut layer: BroadcastLogLayer<R>,
+    filter: EnvFilter,
+    remotes: Vec<(String, String)>,
+) -> Result<EffectiveConfig, SetupError> {
+    let fallback = layer.lock_persist_path().map_err(SetupError::Lock)?;
+    let pipeline = layer.pipeline().clone();
+    let active_paths = layer.active_log_paths();
+    let attached = tracing::dispatcher::has_been_set();
+    let mut config = layer.effective_config();
+    config.attached = attached;
+    config.directives = (!attached).then(|| filter.to_string());
+    config.remotes = remotes
+        .into_iter()
+        .map(|(name, addr)| (name, mask_secrets(&addr)))
+        .collect();
+    if attached {
+        attach_layer(Box::new(layer))?;
+    } else {
+        let (slot, handle) = setup::extra_layers_slot();
+        Registry::default()
+            .with(slot)
+            .with(filter)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_filter(pipeline.routes().clone().console_filter()),
+            )
+            .with(layer)
+            .try_init()
+            .map_err(|e| SetupError::Init(e.to_string()))?;
+        setup::register_extra_layers(handle);
+    }
+    pipeline.install_global();
+    *ACTIVE_LOG_PATHS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = active_paths;
+
+    let digest = tokio::runtime::Handle::try_current()
+        .is_ok()
+        .then(DigestConfig::from_env);
+    config.set_digest(digest.as_ref());
+    pipeline.ingest(R::from(config.banner()));
+    if let Some(fallback) = fallback {
+        pipeline.ingest(R::from(fallback_entry(&fallback)));
+    }
+    if let Some(digest) = digest {
+        spawn_digest(pipeline, digest);
+    }
+    Ok(config)
+}
+
+/// 持久化文件被锁定、改用带后缀路径时的诊断
+fn fallback_entry(fallback: &LockFallback) -> LogEntry {
+    LogEntry {
+        timestamp: Utc::now().to_rfc3339().into(),
+        level: "WARN".to_string(),
+        target: CONFIG_TARGET.to_string(),
+        message: format!(
+            "log file {} is locked by another process, writing to {}",
+            fallback.original.display(),
+            fallback.fallback.display()
+        ),
+        fields: [
+            ("original", fallback.original.display().to_string()),
+            ("fallback", fallback.fallback.display().to_string()),
+            ("holder", fallback.holder.clone().unwrap_or_default()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), serde_json::json!(v)))
+        .collect(),
+        ..Default::default()
+    }
+}
+
+/// 记录构造完成后、进入广播 / 缓存 / 持久化之前对其做的改写，见 `BroadcastLogLayer::with_transform`
+pub type Transform<R = LogEntry> = dyn Fn(R) -> R + Send + Sync;
+
+/// 把每个事件转换为日志记录并广播、缓存、持久化的 Layer
+///
+/// 记录类型默认为 `LogEntry`，也可以是实现了 `LogRecord` 的自定义类型。
+pub struct BroadcastLogLayer<R = LogEntry> {
+    pipeline: Pipeline<R>,
+    visitor_config: Arc<VisitorConfig>,
+    span_events: SpanEvents,
+    min_level: Option<Level>,
+    target_level_overrides: TargetLevelOverrides,
+    sampler: Option<Arc<Sampler>>,
+    transform: Option<Arc<Transform<R>>>,
+    audit: Option<Arc<AuditLog>>,
+    collapse: Option<Arc<Collapser<R>>>,
+    verbosity: Option<Arc<VerbosityGate>>,
+    clock: ClockWatch,
+    file_lock: Option<LockConflict>,
+}
+
+impl BroadcastLogLayer {
+    pub fn new(tx: broadcast::Sender<LogEntry>, cache: LogCache) -> Self {
+        Self::for_record(tx, cache)
+    }
+
+    /// 在主通道之外登记一个具名广播通道，只转发满足 `filter` 的日志，
+    /// 例如管理界面与审计监控各订阅一路；容量为 `DEFAULT_CHANNEL_CAPACITY`
+    pub fn add_broadcast_channel(
+        &self,
+        name: &str,
+        filter: QueryExpr,
+    ) -> broadcast::Receiver<LogEntry> {
+        self.add_broadcast_channel_with_capacity(name, DEFAULT_CHANNEL_CAPACITY, filter)
+    }
+
+    /// 同 `add_broadcast_channel`，指定通道容量
+    pub fn add_broadcast_channel_with_capacity(
+        &self,
+        name: &str,
+        capacity: usize,
+        filter: QueryExpr,
+    ) -> broadcast::Receiver<LogEntry> {
+        self.pipeline
+            .add_channel(name, capacity, move |entry| filter.matches(entry))
+    }
+}
+
+impl<R: LogRecord> BroadcastLogLayer<R> {
+    /// 使用自定义记录类型构造，见 `LogRecord`
+    pub fn for_record(tx: broadcast::Sender<R>, cache: LogCache<R>) -> Self {
+        Self {
+            pipeline: Pipeline::for_record(tx, cache),
+            visitor_config: Arc::default(),
+            span_events: SpanEvents::None,
+            min_level: None,
+            target_level_overrides: TargetLevelOverrides::default(),
+            sampler: None,
+            transform: None,
+            audit: None,
+            collapse: None,
+            verbosity: None,
+            clock: ClockWatch::default(),
+            file_lock: None,
+        }
+    }
+
+    /// 修改 JSONL 持久化文件路径
+    pub fn with_persist_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.pipeline.set_persist_path(Some(path.into()));
+        self
+    }
+
+    /// 关闭文件持久化，仅保留广播和内存缓存
+    pub fn without_persistence(mut self) -> Self {
+        self.pipeline.set_persist_path(None);
+        self
+    }
+
+    /// 对持久化文件取锁，防止多个进程交错写入，`conflict` 为被锁定时的处理方式，见 `file_lock`；
+    /// 默认（None）不取锁，多个进程或 Layer 指向同一文件时各自追加
+    pub fn with_file_lock(mut self, conflict: Option<LockConflict>) -> Self {
+        self.file_lock = conflict;
+        self
+    }
+
+    /// 按 `with_file_lock` 对持久化文件取锁，安装时自动调用；不经安装、直接挂到 subscriber 上时
+    /// 应在第一条日志之前手动调用。冲突后改用了带后缀的路径时返回该路径
+    pub fn lock_persist_path(&mut self) -> Result<Option<LockFallback>, LockError> {
+        match self.file_lock {
+            Some(conflict) => self.pipeline.lock_persist_path(conflict),
+            None => Ok(None),
+        }
+    }
+
+    /// 调整传入缓存的条目数与估算字节上限，应在安装前调用
+    pub fn with_cache_limits(mut self, max_entries: usize, max_bytes: Option<usize>) -> Self {
+        self.pipeline.set_cache_limits(max_entries, max_bytes);
+        self
+    }
+
+    /// 只处理不低于 `level` 的事件，更低级别的事件不广播、不缓存、不持久化；
+    /// 与全局过滤指令无关，控制台输出不受影响
+    pub fn with_min_level(mut self, level: Option<Level>) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    /// 来自 `target`（及其子模块）且低于 `level` 的事件不广播、不缓存、不持久化，
+    /// 在全局过滤指令之后生效，见 `target_levels` 模块
+    pub fn with_target_level_override(self, target: impl Into<String>, level: Level) -> Self {
+        self.target_level_overrides.set(target, level);
+        self
+    }
+
+    /// 按 target 覆盖最低级别的表，安装后仍可经此调整
+    pub fn target_level_overrides(&self) -> TargetLevelOverrides {
+        self.target_level_overrides.clone()
+    }
+
+    /// 持久化文件的最低级别，运行时可经 `Pipeline::set_persist_level` 调整
+    pub fn with_persist_level(self, level: Option<Level>) -> Self {
+        self.pipeline.set_persist_level(level);
+        self
+    }
+
+    /// 广播的最低级别，运行时可经 `Pipeline::set_broadcast_level` 调整
+    pub fn with_broadcast_level(self, level: Option<Level>) -> Self {
+        self.pipeline.set_broadcast_level(level);
+        self
+    }
+
+    /// 缓存的最低级别，运行时可经 `Pipeline::set_cache_level` 调整
+    pub fn with_cache_level(self, level: Option<Level>) -> Self {
+        self.pipeline.set_cache_level(level);
+        self
+    }
+
+    /// 为这些数值字段维护近似分位数，按 target 前缀另外分组（`""` 汇总全部，总是包含），
+    /// 经 `LogStats::field_percentiles` 读取并写入周期摘要，见 `quantile` 模块
+    pub fn with_field_percentiles<F, P>(self, fields: F, target_prefixes: P) -> Self
+    where
+        F: IntoIterator,
+        F::Item: Into<String>,
+        P: IntoIterator,
+        P::Item: Into<String>,
+    {
+        self.pipeline
+            .stats()
+            .track_field_percentiles(fields, target_prefixes);
+        self
+    }
+
+    /// 连续内容相同的记录折叠为一条并带上 `repeated: N`，超时为 `DEFAULT_COLLAPSE_TIMEOUT`，
+    /// 见 `collapse` 模块
+    pub fn with_collapse_consecutive(mut self, enabled: bool) -> Self {
+        self.collapse = enabled.then(|| Arc::new(Collapser::new(DEFAULT_COLLAPSE_TIMEOUT)));
+        self
+    }
+
+    /// 同 `with_collapse_consecutive(true)`，暂存的记录自第一次出现起最多保留 `timeout`
+    pub fn with_collapse_timeout(mut self, timeout: Duration) -> Self {
+        self.collapse = Some(Arc::new(Collapser::new(timeout)));
+        self
+    }
+
+    /// 平时只处理不低于 `config.base` 的事件，ERROR 之后的一段时间内临时放宽到 `config.verbose`，
+    /// 代替 `with_min_level`，见 `verbosity` 模块
+    pub fn with_adaptive_verbosity(mut self, config: AdaptiveVerbosity) -> Self {
+        self.verbosity = Some(Arc::new(VerbosityGate::new(config)));
+        self
+    }
+
+    /// 按 trace 一致地采样事件，见 `sampling` 模块；被丢弃的计入 `LogStats::sampled_out`
+    pub fn with_sampling(mut self, config: SamplingConfig) -> Self {
+        self.sampler = Some(Arc::new(Sampler::new(config)));
+        self
+    }
+
+    /// 把审计事件（target 为 `audit` / `audit::*`）同步、逐条 fsync 写入独立的 `path`，见 `audit` 模块
+    pub fn with_audit_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit = Some(Arc::new(AuditLog::new(path)));
+        self
+    }
+
+    /// 审计文件路径，未设置时为 None
+    pub fn audit_path(&self) -> Option<&Path> {
+        self.audit.as_deref().map(AuditLog::path)
+    }
+
+    /// 当前写入的文件：持久化文件（取锁冲突后为改用的路径）与审计文件，见 `active_log_paths`
+    pub fn active_log_paths(&self) -> Vec<PathBuf> {
+        self.pipeline
+            .persist_path()
+            .into_iter()
+            .chain(self.audit_path())
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    /// 在事件（以及 span 事件）构造成记录之后、广播 / 缓存 / 持久化之前调用 `transform` 改写记录，
+    /// 用于补充字段、改写 target、规范化 message 等一次性需求；要丢弃日志请用过滤器
+    ///
+    /// 改写后的记录原样进入管线，sanitize 与长度上限不会再次应用，内容是否合理由调用方负责。
+    /// `transform` 发生 panic 时使用改写前的记录，不影响日志管线；每条记录因此会多一次克隆。
+    pub fn with_transform(mut self, transform: impl Fn(R) -> R + Send + Sync + 'static) -> Self {
+        self.transform = Some(Arc::new(transform));
+        self
+    }
+
+    /// 设置缓存写入通道容量（默认 `DEFAULT_WRITER_CAPACITY`），写满后新日志只广播不入缓存
+    pub fn with_writer_capacity(mut self, capacity: usize) -> Self {
+        self.pipeline.set_writer_capacity(capacity);
+        self
+    }
+
+    /// 每隔多少条日志在 `<persist_path>.idx` 中记录一个时间索引点（默认 `DEFAULT_INDEX_INTERVAL`），0 表示不维护索引
+    pub fn with_index_interval(mut self, interval: u64) -> Self {
+        self.pipeline.set_index_interval(interval);
+        self
+    }
+
+    /// 持久化文件的字段命名（默认 `JsonSchema::Native`），例如按 ECS 命名供外部采集
+    pub fn with_json_schema(mut self, schema: JsonSchema) -> Self {
+        self.pipeline.set_json_schema(schema);
+        self
+    }
+
+    /// 持久化文件的编码，默认 JSONL，见 `serializer` 模块；应在安装前调用
+    pub fn with_serializer(mut self, serializer: impl LogSerializer + 'static) -> Self {
+        self.pipeline.set_serializer(Arc::new(serializer));
+        self
+    }
+
+    /// 持久化文件中 `timestamp` 的格式（默认 `TimestampFormat::Native`），例如 `Z` 结尾的毫秒精度或 epoch 毫秒
+    pub fn with_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.pipeline.set_timestamp_format(format);
+        self
+    }
+
+    /// 指定按 Display 风格记录的字段：通过 `?` 以 Debug 捕获时去掉 tracing 加上的外层引号
+    pub fn with_display_fields<I, F>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        Arc::make_mut(&mut self.visitor_config).display_fields =
+            fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// 设置 message 和字符串字段中控制字符的处理方式
+    pub fn with_sanitize(mut self, mode: Sanitize) -> Self {
+        Arc::make_mut(&mut self.visitor_config).sanitize = mode;
+        self
+    }
+
+    /// 限制 message 的字节数，超出时在 UTF-8 边界截断并追加 `…(truncated)`，
+    /// 在广播、缓存和持久化之前生效，防止异常的超长日志拖垮管线
+    pub fn with_max_message_bytes(mut self, max: usize) -> Self {
+        Arc::make_mut(&mut self.visitor_config).max_message_bytes = Some(max);
+        self
+    }
+
+    /// 同 `with_max_message_bytes`，作用于字段值
+    pub fn with_max_field_bytes(mut self, max: usize) -> Self {
+        Arc::make_mut(&mut self.visitor_config).max_field_bytes = Some(max);
+        self
+    }
+
+    /// 条目 `timestamp` 的存储形式（默认 `TimeRepr::Rfc3339String`）；`EpochNanos` 省去每条日志格式化时间戳的分配，
+    /// 序列化时再格式化，写出的 JSON 不变
+    pub fn with_time_repr(mut self, repr: TimeRepr) -> Self {
+        Arc::make_mut(&mut self.visitor_config).time_repr = repr;
+        self
+    }
+
+    /// 是否捕获事件的结构化字段（默认开启），关闭后只保留 message
+    pub fn with_field_capture(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.visitor_config).skip_fields = !enabled;
+        self
+    }
+
+    /// 为每条日志附带 `fingerprint`：由 target、文件、行号和字段名（即调用点）计算的 SHA-256 前 16 位十六进制，
+    /// 与插值后的 message 无关，用于统计哪些日志语句最频繁
+    pub fn with_fingerprint(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.visitor_config).fingerprint = enabled;
+        self
+    }
+
+    /// 按 target 路由规则决定广播与持久化，取代默认的 `routing::env_routes`；
+    /// `console = false` 作用于 `setup_tracing_with_layer` 安装的控制台输出
+    pub fn with_routes(mut self, routes: RouteRules) -> Self {
+        self.pipeline.set_routes(routes);
+        self
+    }
+
+    /// 为每条日志附带由时间戳得出的 `date_bucket`（按天或按小时，UTC），
+    /// 便于下游分区存储和分组统计时不必解析时间戳；默认不附带
+    pub fn with_bucket(mut self, bucket: Option<BucketGranularity>) -> Self {
+        Arc::make_mut(&mut self.visitor_config).bucket = bucket;
+        self
+    }
+
+    /// 结构化字段的上限（默认每条最多 32 个字段），超出的字段被丢弃并以 `_truncated_fields` 标记个数
+    pub fn with_field_limits(mut self, limits: FieldLimits) -> Self {
+        Arc::make_mut(&mut self.visitor_config).field_limits = limits;
+        self
+    }
+
+    /// 按字段取值提升日志级别，见 `escalation` 模块；多条规则匹配时取最严重的级别
+    pub fn with_escalations(mut self, rules: EscalationRules) -> Self {
+        Arc::make_mut(&mut self.visitor_config).escalations = Arc::new(rules);
+        self
+    }
+
+    /// 当前的提升规则，可在安装后通过 `EscalationRules::stats` 查看命中次数
+    pub fn escalations(&self) -> Arc<EscalationRules> {
+        self.visitor_config.escalations.clone()
+    }
+
+    /// 替换时钟源（默认 `SystemClock`），作用于条目时间戳、span 计时和管线的管理记录，
+    /// 测试中配合 `MockClock` 使用
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        let clock = SharedClock::new(clock);
+        self.pipeline.set_clock(clock.clone());
+        Arc::make_mut(&mut self.visitor_config).clock = clock;
+        self
+    }
+
+    /// 把 span 的生命周期也作为 LogEntry 输出（字段 `kind: "span"`），
+    /// 通过 `Layer::with_filter` 加的过滤同样作用于这些条目
+    pub fn with_span_events(mut self, mode: SpanEvents) -> Self {
+        self.span_events = mode;
+        self
+    }
+
+    /// 轻量的 span 耗时统计：开启时等价于 `with_span_events(SpanEvents::Close)`，
+    /// 每个 span 关闭时输出一条 `"span closed"`，带 `span`、`busy_ms`、`idle_ms`；
+    /// 已设置 `SpanEvents::Full` 时保持不变，关闭时不输出任何 span 条目
+    pub fn with_span_timing(mut self, enabled: bool) -> Self {
+        self.span_events = match (enabled, self.span_events) {
+            (true, SpanEvents::Full) => SpanEvents::Full,
+            (true, _) => SpanEvents::Close,
+            (false, _) => SpanEvents::None,
+        };
+        self
+    }
+
+    /// 该 Layer 使用的管线，可交给后台任务复用
+    pub fn pipeline(&self) -> &Pipeline<R> {
+        &self.pipeline
+    }
+
+    /// 管线计数器
+    pub fn stats(&self) -> Arc<LogStats> {
+        self.pipeline.stats().clone()
+    }
+
+    /// 由 Layer 自身决定的配置项；过滤指令、摘要和远端由安装方补全
+    fn effective_config(&self) -> EffectiveConfig {
+        let config = &self.visitor_config;
+        let mut display_fields: Vec<String> = config.display_fields.iter().cloned().collect();
+        display_fields.sort();
+        // 安装时缓存尚未被写入任务持有，try_read 不会失败
+        let cache = self
+            .pipeline
+            .cache()
+            .try_read()
+            .map(|cache| cache.stats())
+            .unwrap_or_default();
+        EffectiveConfig {
+            persist_path: self.pipeline.persist_path().map(Path::to_path_buf),
+            file_lock: self.file_lock,
+            index_interval: self.pipeline.index_interval(),
+            json_schema: self.pipeline.json_schema(),
+            timestamp_format: self.pipeline.timestamp_format(),
+            serializer: self
+                .pipeline
+                .serializer()
+                .map(|serializer| serializer.name()),
+            writer_capacity: self.pipeline.status().writer_capacity,
+            cache_max_entries: cache.max_entries,
+            cache_max_bytes: cache.max_bytes,
+            sanitize: config.sanitize,
+            max_message_bytes: config.max_message_bytes,
+            max_field_bytes: config.max_field_bytes,
+            display_fields,
+            fingerprint: config.fingerprint,
+            bucket: config.bucket,
+            field_limits: config.field_limits,
+            field_capture: !config.skip_fields,
+            time_repr: config.time_repr,
+            min_level: self.min_level.map(|level| level.to_string()),
+            target_level_overrides: self
+                .target_level_overrides
+                .to_vec()
+                .into_iter()
+                .map(|(target, level)| (target, level.to_string()))
+                .collect(),
+            persist_level: self.pipeline.persist_level().map(|level| level.to_string()),
+            broadcast_level: self
+                .pipeline
+                .broadcast_level()
+                .map(|level| level.to_string()),
+            cache_level: self.pipeline.cache_level().map(|level| level.to_string()),
+            percentile_fields: self.pipeline.stats().percentile_fields(),
+            collapse_timeout_ms: self
+                .collapse
+                .as_ref()
+                .map(|collapse| collapse.timeout().as_millis() as u64),
+            adaptive_verbosity: self
+                .verbosity
+                .as_ref()
+                .map(|verbosity| verbosity.config().clone()),
+            sampling: self
+                .sampler
+                .as_ref()
+                .map(|sampler| sampler.config().clone()),
+            span_events: self.span_events,
+            transform: self.transform.is_some(),
+            audit_path: self.audit_path().map(Path::to_path_buf),
+            routes: self.pipeline.routes().rules().cloned().collect(),
+            escalations: config.escalations.rules().cloned().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// span 事件以 `LogEntry` 合成，再转换为记录类型
+    fn emit(&self, log: LogEntry) {
+        self.ingest(self.transformed(R::from(log)));
+    }
+
+    /// 开启折叠时经 `Collapser` 写入管线
+    fn ingest(&self, log: R) {
+        match &self.collapse {
+            Some(collapse) => collapse.push(log, &self.pipeline),
+            None => self.pipeline.ingest(log),
+        }
+    }
+
+    /// 同步写入审计文件；失败只计数并报告，不影响日志进入管线
+    fn write_audit(&self, audit: &AuditLog, log: &R) {
+        let result = self
+            .pipeline
+            .json_schema()
+            .to_line(&Timestamps(log, self.pipeline.timestamp_format()))
+            .map_err(std::io::Error::from)
+            .and_then(|line: String| audit.append(&line));
+        if let Err(e) = result {
+            self.pipeline.stats().record_write_error();
+            eprintln!(
+                "listen-tracing: audit write to {} failed: {}",
+                audit.path().display(),
+                e
+            );
+        }
+    }
+
+    fn transformed(&self, log: R) -> R {
+        let Some(transform) = &self.transform else {
+            return log;
+        };
+        let original = log.clone();
+        catch_unwind(AssertUnwindSafe(|| transform(log))).unwrap_or(original)
+    }
+}
+
+impl<S, R> Layer<S> for BroadcastLogLayer<R>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    R: LogRecord,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // 审计事件不受最低级别、采样与"无去向"的跳过影响
+        let audit = self
+            .audit
+            .as_deref()
+            .filter(|_| is_audit_target(event.metadata().target()));
+        let mut opened = None;
+        if audit.is_none() {
+            let metadata = event.metadata();
+            if self
+                .target_level_overrides
+                .suppresses(metadata.target(), metadata.level())
+            {
+                return;
+            }
+            match &self.verbosity {
+                Some(verbosity) => {
+                    let admission = verbosity.admit(
+                        metadata.target(),
+                        *metadata.level(),
+                        &self.visitor_config.clock,
+                        self.pipeline.stats(),
+                    );
+                    for marker in admission.closed {
+                        self.emit(marker);
+                    }
+                    if !admission.admitted {
+                        return;
+                    }
+                    opened = admission.opened;
+                }
+                None if self.min_level.is_some_and(|min| *metadata.level() > min) => return,
+                None => {}
+            }
+            // 没有任何去向时跳过字段捕获和记录构造，只保留事件计数
+            if !self.pipeline.has_consumers() && !span_cache::any_attached() {
+                self.pipeline
+                    .stats()
+                    .record_event(event.metadata().level().as_str());
+                return;
+            }
+            if let Some(sampler) = &self.sampler {
+                let key = sampler.event_key(event, &ctx);
+                let now = self.visitor_config.clock.instant();
+                if !sampler.keep(*event.metadata().level(), key.as_deref(), now) {
+                    self.pipeline.stats().record_sampled_out();
+                    return;
+                }
+            }
+        }
+        // 墙上时钟回拨时先记录一条诊断，说明之后的 timestamp 不再单调
+        let now = self.visitor_config.clock.now();
+        if let Some(diagnostic) = self.clock.check(now.timestamp_millis()) {
+            self.emit(diagnostic);
+        }
+        let ctx = RecordContext::new(ctx, &self.visitor_config, self.pipeline.stats(), now);
+        let log = self.transformed(R::from_event(event, &ctx));
+        if let Some(audit) = audit {
+            self.write_audit(audit, &log);
+        }
+        span_cache::write_span_caches(event, ctx.layer_context(), &log);
+        self.ingest(log);
+        // 窗口打开的标记紧跟在触发它的 ERROR 之后
+        if let Some(marker) = opened {
+            self.emit(marker);
+        }
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(sampler) = &self.sampler {
+            sampler.on_new_span(attrs, id, &ctx);
+        }
+        if let Some(log) =
+            span_events::on_new_span(self.span_events, &self.visitor_config, attrs, id, &ctx)
+        {
+            self.emit(log);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(sampler) = &self.sampler {
+            sampler.on_record(values, id, &ctx);
+        }
+        if self.span_events != SpanEvents::None {
+            span_events::on_record(&self.visitor_config, id, values, &ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if self.span_events == SpanEvents::None {
+            return;
+        }
+        if let Some(log) = span_events::on_enter(self.span_events, &self.visitor_config, id, &ctx) {
+            self.emit(log);
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if self.span_events == SpanEvents::None {
+            return;
+        }
+        if let Some(log) = span_events::on_exit(self.span_events, &self.visitor_config, id, &ctx) {
+            self.emit(log);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if self.span_events == SpanEvents::None {
+            return;
+        }
+        if let Some(log) = span_events::on_close(&self.visitor_config, &id, &ctx) {
+            self.emit(log);
+        }
+    }
+}
+
+/// TracingVisitor 的捕获配置，由 Layer 持有并在每个事件间共享
+#[derive(Clone, Debug, Default)]
+pub struct VisitorConfig {
+    /// 以 Debug 捕获时去掉外层引号的字段名
+    pub display_fields: HashSet<String>,
+    /// 控制字符处理方式
+    pub sanitize: Sanitize,
+    /// message 的最大字节数，超出部分在捕获时截断
+    pub max_message_bytes: Option<usize>,
+    /// 字符串字段值的最大字节数
+    pub max_field_bytes: Option<usize>,
+    /// 为每条日志计算调用点指纹
+    pub fingerprint: bool,
+    /// 按字段取值提升级别的规则
+    pub escalations: Arc<EscalationRules>,
+    /// 条目时间戳与 span 计时的时钟
+    pub clock: SharedClock,
+    /// 为每条日志附带 `date_bucket` 的粒度
+    pub bucket: Option<BucketGranularity>,
+    /// 结构化字段的数量、键长、值长度与嵌套深度上限
+    pub field_limits: FieldLimits,
+    /// 不捕获结构化字段，只保留 message
+    pub skip_fields: bool,
+    /// 条目 `timestamp` 的存储形式
+    pub time_repr: TimeRepr,
+}
+
+/// `LogEntry::date_bucket` 的粒度
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BucketGranularity {
+    /// `2024-06-01`
+    Day,
+    /// `2024-06-01T14`
+    Hour,
+}
+
+impl BucketGranularity {
+    /// UTC 时间所在的桶
+    pub fn bucket(self, time: DateTime<Utc>) -> String {
+        match self {
+            BucketGranularity::Day => time.format("%Y-%m-%d").to_string(),
+            BucketGranularity::Hour => time.format("%Y-%m-%dT%H").to_string(),
+        }
+    }
+}
+
+impl VisitorConfig {
+    /// 按字段类型（message 或普通字段）应用长度上限
+    fn limit(&self, field: &str, value: String) -> String {
+        let max = if field == "message" {
+            self.max_message_bytes
+        } else {
+            self.max_field_bytes
+        };
+        match max {
+            Some(max) => truncate_bytes(value, max),
+            None => value,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TracingVisitor {
+    pub(crate) message: Option<String>,
+    pub(crate) fields: BTreeMap<String, serde_json::Value>,
+    /// 因 `FieldLimits` 被丢弃的字段数
+    pub(crate) dropped_fields: u64,
+    config: Arc<VisitorConfig>,
+}
+
+impl TracingVisitor {
+    pub fn new(config: Arc<VisitorConfig>) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn fields(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.fields
+    }
+
+    /// 因超出 `FieldLimits` 被丢弃的字段数
+    pub fn dropped_fields(&self) -> u64 {
+        self.dropped_fields
+    }
+
+    /// 数值与布尔字段按 JSON 类型保存；作为 message 时仍按 Debug 渲染为字符串
+    fn record_typed(
+        &mut self,
+        field: &tracing::field::Field,
+        value: serde_json::Value,
+        debug: &dyn std::fmt::Debug,
+    ) {
+        use tracing::field::Visit;
+
+        if field.name() == "message" {
+            self.record_debug(field, debug);
+        } else if !self.config.skip_fields {
+            self.insert_field(field.name(), value);
+        }
+    }
+
+    fn insert_field(&mut self, name: &str, value: serde_json::Value) {
+        if self.config.field_limits.admits(&self.fields, name, &value) {
+            self.fields.insert(name.to_string(), value);
+        } else {
+            self.dropped_fields += 1;
+        }
+    }
+}
+
+thread_local! {
+    /// record_debug 的格式化缓冲区，避免每个字段从零开始扩容
+    static RENDER_BUF: RefCell<String> = const { RefCell::new(String::new()) };
+
+    /// 当前秒的 `YYYY-MM-DDTHH:MM:SS` 前缀缓存
+    static SECOND_PREFIX: RefCell<(i64, String)> = const { RefCell::new((i64::MIN, String::new())) };
+}
+
+/// 以 Debug 格式渲染，先写入线程本地缓冲区再按实际长度拷贝一次
+fn render_debug(value: &dyn std::fmt::Debug) -> String {
+    RENDER_BUF.with(|buf| match buf.try_borrow_mut() {
+        Ok(mut buf) => {
+            buf.clear();
+            let _ = write!(buf, "{:?}", value);
+            buf.as_str().to_owned()
+        }
+        // Debug 实现内部又打日志时的重入
+        Err(_) => format!("{:?}", value),
+    })
+}
+
+/// 与 `Utc::now().to_rfc3339()` 输出一致，同一秒内复用已格式化的日期时间部分
+pub(crate) fn rfc3339_now() -> String {
+    format_rfc3339(Utc::now())
+}
+
+pub(crate) fn format_rfc3339(now: DateTime<Utc>) -> String {
+    let secs = now.timestamp();
+    let nanos = now.timestamp_subsec_nanos();
+    SECOND_PREFIX.with(|cached| {
+        let Ok(mut cached) = cached.try_borrow_mut() else {
+            return now.to_rfc3339();
+        };
+        if cached.0 != secs || nanos >= 1_000_000_000 {
+            if nanos >= 1_000_000_000 {
+                return now.to_rfc3339();
+            }
+            cached.1.clear();
+            let _ = write!(cached.1, "{}", now.format("%Y-%m-%dT%H:%M:%S"));
+            cached.0 = secs;
+        }
+        let mut out = String::with_capacity(cached.1.len() + 16);
+        out.push_str(&cached.1);
+        // 与 chrono 的 SecondsFormat::AutoSi 一致：按需输出 0/3/6/9 位小数
+        let (value, digits) = if nanos == 0 {
+            (0, 0)
+        } else if nanos.is_multiple_of(1_000_000) {
+            (nanos / 1_000_000, 3)
+        } else if nanos.is_multiple_of(1_000) {
+            (nanos / 1_000, 6)
+        } else {
+            (nanos, 9)
+        };
+        if digits > 0 {
+            let mut buf = [b'.'; 10];
+            let mut rest = value;
+            for slot in buf[1..=digits].iter_mut().rev() {
+                *slot = b'0' + (rest % 10) as u8;
+                rest /= 10;
+            }
+            out.push_str(std::str::from_utf8(&buf[..=digits]).unwrap());
+        }
+        out.push_str("+00:00");
+        out
+    })
+}
+
+/// 去掉 Debug 输出的字符串外层引号并还原转义
+pub(crate) fn unquote_debug(s: String) -> String {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        serde_json::from_str::<String>(&s).unwrap_or_else(|_| s[1..s.len() - 1].to_string())
+    } else {
+        s
+    }
+}
+
+impl tracing::field::Visit for TracingVisitor {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if self.config.skip_fields && field.name() != "message" {
+            return;
+        }
+        let value = sanitize(value, self.config.sanitize).into_owned();
+        let value = self.config.limit(field.name(), value);
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.insert_field(field.name(), serde_json::Value::String(value));
+        }
+    }
+
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.record_typed(field, value.into(), &value);
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.record_typed(field, value.into(), &value);
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        // JSON 没有 NaN / 无穷大，按 Debug 文本保存
+        let json = serde_json::Number::from_f64(value)
+            .map_or_else(|| format!("{:?}", value).into(), serde_json::Value::Number);
+        self.record_typed(field, json, &value);
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.record_typed(field, value.into(), &value);
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        // 关闭字段捕获时连格式化也省掉
+        if self.config.skip_fields && field.name() != "message" {
+            return;
+        }
+        let mut rendered = render_debug(value);
+        if field.name() != "message" && self.config.display_fields.contains(field.name()) {
+            rendered = unquote_debug(rendered);
+        }
+        let rendered = sanitize_owned(rendered, self.config.sanitize);
+        let rendered = self.config.limit(field.name(), rendered);
+        if field.name() == "message" {
+            self.message = Some(rendered);
+        } else {
+            self.insert_field(field.name(), serde_json::Value::String(rendered));
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 等待异步缓存写入至少 `len` 条，5 秒内未到齐时测试失败
+    pub(crate) async fn wait_for_cache(cache: &LogCache, len: usize) -> Vec<LogEntry> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let logs = cache.read().await.to_vec();
+            if logs.len() >= len {
+                return logs;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "expected {} cached entries, got {}: {:?}",
+                len,
+                logs.len(),
+                logs.iter().map(|log| &log.message).collect::<Vec<_>>()
+            );
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    /// 固定种子的 xorshift64 伪随机数，返回的闭包每次给出 `[0, n)` 内的下一个数，
+    /// 供随机化测试复现同一序列
+    pub(crate) fn xorshift(seed: u64) -> impl FnMut(u64) -> u64 {
+        let mut state = seed;
+        move |n| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        }
+    }
+
+    #[test]
+    fn test_migrate_historical_fixtures() {
+        let fixtures = [
+            // schema 1：最初的四字段形式
+            r#"{"timestamp":"2024-06-01T00:00:00+00:00","level":"INFO","target":"app","message":"started"}"#,
+            // 写出 schema 之前已带 correlation_id / fields 的行
+            r#"{"timestamp":"2024-06-01T00:00:00+00:00","level":"WARN","target":"app","message":"slow","correlation_id":"req-1","fields":{"ms":120}}"#,
+            // schema 2
+            r#"{"schema":2,"timestamp":"2024-06-01T00:00:00+00:00","level":"ERROR","target":"app","message":"failed","fields":{"code":"E1"}}"#,
+            // schema 3
+            r#"{"schema":3,"timestamp":"2024-06-01T00:00:00+00:00","level":"INFO","target":"app","message":"cached","seq":42}"#,
+            // schema 4
+            r#"{"schema":4,"timestamp":"2024-06-01T00:00:00+00:00","level":"INFO","target":"app","message":"user 5 failed","fingerprint":"9f86d081884c7d65"}"#,
+            // schema 5
+            r#"{"schema":5,"timestamp":"2024-06-01T00:00:00+00:00","level":"INFO","target":"app","message":"tick","mono_ns":1500000}"#,
+            // schema 6
+            r#"{"schema":6,"timestamp":"2024-06-01T14:00:00+00:00","level":"INFO","target":"app","message":"tick","date_bucket":"2024-06-01T14"}"#,
+            // schema 7
+            r#"{"schema":7,"timestamp":"2024-06-01T14:00:00+00:00","level":"INFO","target":"app","message":"filled","name":"order_filled"}"#,
+        ];
+        for line in fixtures {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            let entry = LogEntry::migrate(value.clone()).unwrap();
+            assert_eq!(entry.schema, LOG_ENTRY_SCHEMA);
+            assert_eq!(value["timestamp"], entry.timestamp.to_string());
+            assert_eq!(entry.message, value["message"]);
+
+            // 升级后的结果可以原样往返
+            let written = serde_json::to_value(&entry).unwrap();
+            assert_eq!(written["schema"], LOG_ENTRY_SCHEMA);
+            assert_eq!(LogEntry::migrate(written).unwrap(), entry);
+        }
+
+        let legacy: LogEntry = serde_json::from_str(fixtures[0]).unwrap();
+        assert_eq!(legacy.schema, 1);
+        assert!(legacy.fields.is_empty());
+        let current: LogEntry = serde_json::from_str(fixtures[2]).unwrap();
+        assert_eq!(current.fields["code"], "E1");
+
+        assert!(matches!(
+            LogEntry::migrate(serde_json::json!({"schema": 9, "message": "x"})),
+            Err(MigrateError::UnsupportedSchema(9))
+        ));
+        assert!(matches!(
+            LogEntry::migrate(serde_json::json!({"level": "INFO"})),
+            Err(MigrateError::Json(_))
+        ));
+        assert!(matches!(
+            LogEntry::migrate(serde_json::json!("INFO")),
+            Err(MigrateError::NotAnObject)
+        ));
+    }
+
+    #[test]
+    fn test_rfc3339_matches_chrono() {
+        for nanos in [0, 120_000_000, 123_456_000, 123_456_789] {
+            let t = DateTime::from_timestamp(1_717_200_000, nanos).unwrap();
+            assert_eq!(format_rfc3339(t), t.to_rfc3339());
+            let next = DateTime::from_timestamp(1_717_200_001, nanos).unwrap();
+            assert_eq!(format_rfc3339(next), next.to_rfc3339());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timestamps_roll_over_midnight() {
+        skip_if_events_compiled_out!();
+        let clock = crate::clock::MockClock::new(
+            DateTime::parse_from_rfc3339("2024-06-01T23:59:59.999+00:00")
+                .unwrap()
+                .into(),
+        );
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_clock(clock.clone());
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!("before midnight");
+        clock.advance(Duration::from_millis(1));
+        tracing::info!("after midnight");
+
+        let logs = wait_for_cache(&cache, 2).await;
+        assert_eq!(logs[0].timestamp, "2024-06-01T23:59:59.999+00:00");
+        assert_eq!(logs[1].timestamp, "2024-06-02T00:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_date_bucket() {
+        skip_if_events_compiled_out!();
+        let at = DateTime::parse_from_rfc3339("2024-06-01T14:30:05.250+00:00").unwrap();
+        for (bucket, expected) in [
+            (None, None),
+            (Some(BucketGranularity::Day), Some("2024-06-01")),
+            (Some(BucketGranularity::Hour), Some("2024-06-01T14")),
+        ] {
+            let (tx, _rx) = broadcast::channel(16);
+            let cache = LogCache::default();
+            let layer = BroadcastLogLayer::new(tx, cache.clone())
+                .without_persistence()
+                .with_clock(crate::clock::MockClock::new(at.into()))
+                .with_bucket(bucket);
+            let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+            tracing::info!("bucketed");
+
+            let logs = wait_for_cache(&cache, 1).await;
+            assert_eq!(logs[0].date_bucket.as_deref(), expected);
+            let json = serde_json::to_value(&logs[0]).unwrap();
+            assert_eq!(json.get("date_bucket").is_some(), bucket.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_truncated() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_max_message_bytes(1000)
+            .with_max_field_bytes(16);
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        // 多字节字符使 1000 字节处落在字符中间
+        let huge = "日志".repeat(200_000);
+        tracing::info!(payload = huge.as_str(), "{}", huge);
+
+        let logs = wait_for_cache(&cache, 1).await;
+        let message = &logs[0].message;
+        assert!(message.ends_with(crate::sanitize::TRUNCATED_MARKER));
+        assert!(message.len() <= 1000 + crate::sanitize::TRUNCATED_MARKER.len());
+        assert_eq!(message.len(), 999 + crate::sanitize::TRUNCATED_MARKER.len());
+        assert!(huge.starts_with(message.trim_end_matches(crate::sanitize::TRUNCATED_MARKER)));
+        assert_eq!(logs[0].fields["payload"], "日志日志日…(truncated)");
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_by_callsite() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_fingerprint(true);
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        for user in [5, 9] {
+            tracing::warn!("user {} failed", user);
+        }
+        tracing::warn!("user {} failed", 5);
+
+        let logs = wait_for_cache(&cache, 3).await;
+        assert_eq!(logs[0].message, "user 5 failed");
+        assert_eq!(logs[1].message, "user 9 failed");
+        let fingerprint = logs[0].fingerprint.as_deref().unwrap();
+        assert_eq!(fingerprint.len(), 16);
+        assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(logs[1].fingerprint, logs[0].fingerprint);
+        // 同样的模板和值，但调用点不同
+        assert_eq!(logs[2].message, "user 5 failed");
+        assert_ne!(logs[2].fingerprint, logs[0].fingerprint);
+
+        // 未开启时不附带
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+        tracing::warn!("user {} failed", 5);
+        assert_eq!(wait_for_cache(&cache, 1).await[0].fingerprint, None);
+    }
+
+    #[tokio::test]
+    async fn test_event_name() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::event!(name: "order_filled", Level::INFO, qty = 3, "filled");
+        tracing::info!(name: "order_filled", "filled again");
+        tracing::info!("no name");
+
+        let logs = wait_for_cache(&cache, 3).await;
+        assert_eq!(logs[0].name.as_deref(), Some("order_filled"));
+        assert_eq!(logs[0].message, "filled");
+        assert_eq!(logs[0].fields["qty"], 3);
+        assert!(!logs[0].fields.contains_key("name"));
+        assert_eq!(logs[1].name.as_deref(), Some("order_filled"));
+        // 自动生成的 `event <file>:<line>` 不记录
+        assert_eq!(logs[2].name, None);
+        let line = serde_json::to_string(&logs[2]).unwrap();
+        assert!(!line.contains(r#""name""#));
+    }
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Order {
+        qty: u32,
+    }
+
+    #[tokio::test]
+    async fn test_display_fields_unquoted() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_display_fields(["note"]);
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        let note = format!("filled {} @ {}", 1, "65000.00");
+        tracing::info!(order = ?Order { qty: 1 }, note = ?note, raw = ?note, "order filled");
+
+        let logs = wait_for_cache(&cache, 1).await;
+        let fields = &logs[0].fields;
+        assert_eq!(logs[0].message, "order filled");
+        assert_eq!(fields["order"], "Order { qty: 1 }");
+        assert_eq!(fields["note"], "filled 1 @ 65000.00");
+        assert_eq!(fields["raw"], "\"filled 1 @ 65000.00\"");
+    }
+
+    #[tokio::test]
+    async fn test_field_types_preserved() {
+        skip_if_events_compiled_out!();
+        let path = std::env::temp_dir().join(format!(
+            "listen-tracing-field-types-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let (tx, _rx) = broadcast::channel(16);
+        let layer = BroadcastLogLayer::new(tx, LogCache::default())
+            .with_persist_path(&path)
+            .with_index_interval(0);
+        let pipeline = layer.pipeline().clone();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!(
+            delta = -3i64,
+            qty = 7u64,
+            price = 65000.5,
+            filled = true,
+            venue = "binance",
+            spread = f64::NAN,
+            order = ?Order { qty: 1 },
+            "order filled"
+        );
+        pipeline.writer_guard().flush_and_close().await;
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(
+            value["fields"],
+            serde_json::json!({
+                "delta": -3,
+                "qty": 7,
+                "price": 65000.5,
+                "filled": true,
+                "venue": "binance",
+                "spread": "NaN",
+                "order": "Order { qty: 1 }",
+            })
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_named_channels_filter_independently() {
+        skip_if_events_compiled_out!();
+        let (tx, mut main_rx) = broadcast::channel(16);
+        let layer = BroadcastLogLayer::new(tx, LogCache::default()).without_persistence();
+        let mut admin =
+            layer.add_broadcast_channel("admin", QueryExpr::parse("level>=warn").unwrap());
+        let mut audit = layer.add_broadcast_channel_with_capacity(
+            "audit",
+            2,
+            QueryExpr::parse("level<warn").unwrap(),
+        );
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!("login");
+        tracing::error!("disk full");
+        tracing::debug!("cache hit");
+        tracing::warn!("slow query");
+
+        let drain = |rx: &mut broadcast::Receiver<LogEntry>| {
+            let mut messages = Vec::new();
+            loop {
+                match rx.try_recv() {
+                    Ok(entry) => messages.push(entry.message),
+                    Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                    Err(_) => return messages,
+                }
+            }
+        };
+        assert_eq!(drain(&mut admin), ["disk full", "slow query"]);
+        assert_eq!(drain(&mut audit), ["login", "cache hit"]);
+        assert_eq!(drain(&mut main_rx).len(), 4);
+
+        // audit 容量为 2，落后只影响它自己
+        tracing::info!("a");
+        tracing::info!("b");
+        tracing::info!("c");
+        tracing::error!("still delivered");
+        assert_eq!(drain(&mut audit), ["b", "c"]);
+        assert_eq!(drain(&mut admin), ["still delivered"]);
+    }
+
+    /// 被格式化时计数，用来确认事件字段是否被捕获
+    struct Probe(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl std::fmt::Debug for Probe {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            write!(f, "probe")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_event_without_consumers() {
+        skip_if_events_compiled_out!();
+        let (tx, rx) = broadcast::channel(16);
+        drop(rx);
+        let layer = BroadcastLogLayer::new(tx.clone(), crate::LogBuffer::new(0).shared())
+            .without_persistence();
+        let stats = layer.stats();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+        let formatted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        tracing::warn!(probe = ?Probe(formatted.clone()), "nobody listens");
+        assert_eq!(formatted.load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert_eq!(stats.snapshot().events.warn, 1);
+
+        let mut rx = tx.subscribe();
+        tracing::warn!(probe = ?Probe(formatted.clone()), "now someone does");
+        assert_eq!(formatted.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert_eq!(rx.try_recv().unwrap().fields["probe"], "probe");
+        assert_eq!(stats.snapshot().events.warn, 2);
+    }
+
+    #[tokio::test]
+    async fn test_transform_enriches_entries() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_transform(|mut entry: LogEntry| {
+                if entry.message == "boom" {
+                    panic!("transform bug");
+                }
+                entry
+                    .fields
+                    .insert("region".to_string(), serde_json::json!("eu-1"));
+                entry.target = entry.target.replace("::", ".");
+                entry
+            });
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!("enriched");
+        tracing::info!("boom");
+        let logs = wait_for_cache(&cache, 2).await;
+        assert_eq!(logs[0].fields["region"], "eu-1");
+        assert_eq!(logs[0].target, module_path!().replace("::", "."));
+        // panic 时保留改写前的条目
+        assert_eq!(logs[1].message, "boom");
+        assert!(!logs[1].fields.contains_key("region"));
+        assert_eq!(logs[1].target, module_path!());
+    }
+
+    #[tokio::test]
+    async fn test_active_log_paths_across_rotation() {
+        skip_if_events_compiled_out!();
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!(
+            "listen-tracing-active-{}.jsonl",
+            std::process::id()
+        ));
+        let audit = dir.join(format!(
+            "listen-tracing-active-audit-{}.jsonl",
+            std::process::id()
+        ));
+        let rotated = path.with_extension("jsonl.1");
+        for p in [&path, &rotated] {
+            let _ = std::fs::remove_file(p);
+        }
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .with_persist_path(&path)
+            .with_audit_path(&audit)
+            .with_index_interval(0);
+        assert_eq!(layer.active_log_paths(), [path.clone(), audit.clone()]);
+        let pipeline = layer.pipeline().clone();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!("before rotation");
+        wait_for_cache(&cache, 1).await;
+        crate::index::rotate_log_file(&path, &rotated).unwrap();
+        tracing::info!("after rotation");
+        pipeline.writer_guard().flush_and_close().await;
+
+        // 轮转后仍写入原路径，旧内容在改名后的文件中
+        assert_eq!(pipeline.persist_path(), Some(path.as_path()));
+        let active = std::fs::read_to_string(&path).unwrap();
+        assert!(active.contains("after rotation") && !active.contains("before rotation"));
+        assert!(std::fs::read_to_string(&rotated)
+            .unwrap()
+            .contains("before rotation"));
+        for p in [&path, &rotated] {
+            let _ = std::fs::remove_file(p);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+
+use crate::LogEntry;
+
+/// 对 `broadcast::Sender` 的包装，按名字登记订阅方并统计各自的接收与落后情况
+///
+/// `sender()` 仍可交给 `BroadcastLogLayer` 等使用原始通道的代码；
+/// 需要定位是哪个订阅方在 `Lagged` 丢日志时，改用 `subscribe(name)`。
+#[derive(Clone)]
+pub struct LogBroadcaster<R = LogEntry> {
+    tx: broadcast::Sender<R>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+/// (订阅名, 计数器)
+type Subscriber = (String, Arc<SubscriberCounters>);
+
+#[derive(Debug, Default)]
+struct SubscriberCounters {
+    active: AtomicUsize,
+    received: AtomicU64,
+    lagged: AtomicU64,
+    lag_events: AtomicU64,
+}
+
+/// 单个订阅名的统计，同名的多次订阅（例如重连）累计在一起
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubscriberStats {
+    pub name: String,
+    /// 当前仍存活的接收端数量
+    pub active: usize,
+    pub received: u64,
+    /// 因落后被跳过的条目总数
+    pub lagged: u64,
+    /// 发生落后的次数
+    pub lag_events: u64,
+}
+
+impl LogBroadcaster {
+    pub fn new(capacity: usize) -> Self {
+        Self::from_sender(broadcast::channel(capacity).0)
+    }
+}
+
+impl<R: Clone> LogBroadcaster<R> {
+    /// 包装已有的广播通道
+    pub fn from_sender(tx: broadcast::Sender<R>) -> Self {
+        Self {
+            tx,
+            subscribers: Arc::default(),
+        }
+    }
+
+    /// 原始发送端，兼容直接使用 `broadcast::Sender` 的接口
+    pub fn sender(&self) -> &broadcast::Sender<R> {
+        &self.tx
+    }
+
+    /// 以 `name` 订阅，返回的接收端会记录接收数和落后情况
+    pub fn subscribe(&self, name: &str) -> NamedReceiver<R> {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let counters = match subscribers.iter().find(|(n, _)| n == name) {
+            Some((_, counters)) => counters.clone(),
+            None => {
+                let counters = Arc::new(SubscriberCounters::default());
+                subscribers.push((name.to_string(), counters.clone()));
+                counters
+            }
+        };
+        counters.active.fetch_add(1, Ordering::Relaxed);
+        NamedReceiver {
+            name: name.to_string(),
+            rx: self.tx.subscribe(),
+            counters,
+        }
+    }
+
+    /// 各订阅名的统计，按首次订阅顺序排列
+    ///
+    /// 已没有存活接收端（`active` 为 0）的订阅名在这次返回其最终统计后移除，
+    /// 之后同名重新订阅从零开始计数，订阅名不断变化时登记表不会无限增长。
+    pub fn subscriber_stats(&self) -> Vec<SubscriberStats> {
+        let mut subscribers = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let stats: Vec<SubscriberStats> = subscribers
+            .iter()
+            .map(|(name, counters)| SubscriberStats {
+                name: name.clone(),
+                active: counters.active.load(Ordering::Relaxed),
+                received: counters.received.load(Ordering::Relaxed),
+                lagged: counters.lagged.load(Ordering::Relaxed),
+                lag_events: counters.lag_events.load(Ordering::Relaxed),
+            })
+            .collect();
+        // 接收端只在持锁时增加，读到 0 之后不会再有同一计数器的接收端
+        let mut stats_iter = stats.iter();
+        subscribers.retain(|_| stats_iter.next().is_some_and(|stats| stats.active > 0));
+        stats
+    }
+}
+
+/// `LogBroadcaster::subscribe` 返回的接收端，用法同 `broadcast::Receiver`
+pub struct NamedReceiver<R> {
+    name: String,
+    rx: broadcast::Receiver<R>,
+    counters: Arc<SubscriberCounters>,
+}
+
+impl<R: Clone> NamedReceiver<R> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn recv(&mut self) -> Result<R, RecvError> {
+        let result = self.rx.recv().await;
+        match &result {
+            Ok(_) => self.record_received(),
+            Err(RecvError::Lagged(n)) => self.record_lagged(*n),
+            Err(RecvError::Closed) => {}
+        }
+        result
+    }
+
+    pub fn try_recv(&mut self) -> Result<R, TryRecvError> {
+        let result = self.rx.try_recv();
+        match &result {
+            Ok(_) => self.record_received(),
+            Err(TryRecvError::Lagged(n)) => self.record_lagged(*n),
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn record_received(&self) {
+        self.counters.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_lagged(&self, n: u64) {
+        self.counters.lagged.fetch_add(n, Ordering::Relaxed);
+        self.counters.lag_events.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<R> Drop for NamedReceiver<R> {
+    fn drop(&mut self) {
+        self.counters.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(i: usize) -> LogEntry {
+        LogEntry {
+            message: format!("event {}", i),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_subscriber_stats() {
+        let broadcaster = LogBroadcaster::new(4);
+        let mut fast = broadcaster.subscribe("websocket");
+        let mut slow = broadcaster.subscribe("postgres");
+        let _raw = broadcaster.sender().subscribe();
+
+        for i in 0..10 {
+            broadcaster.sender().send(entry(i)).unwrap();
+            assert_eq!(fast.recv().await.unwrap().message, format!("event {}", i));
+        }
+        assert!(matches!(slow.recv().await, Err(RecvError::Lagged(6))));
+        let mut rest = Vec::new();
+        while let Ok(entry) = slow.try_recv() {
+            rest.push(entry.message);
+        }
+        assert_eq!(rest, ["event 6", "event 7", "event 8", "event 9"]);
+
+        // 同名重新订阅累计到同一条统计
+        drop(fast);
+        let _reconnected = broadcaster.subscribe("websocket");
+
+        let stats = broadcaster.subscriber_stats();
+        assert_eq!(
+            stats,
+            [
+                SubscriberStats {
+                    name: "websocket".to_string(),
+                    active: 1,
+                    received: 10,
+                    lagged: 0,
+                    lag_events: 0,
+                },
+                SubscriberStats {
+                    name: "postgres".to_string(),
+                    active: 1,
+                    received: 4,
+                    lagged: 6,
+                    lag_events: 1,
+                },
+            ]
+        );
+
+        // 全部接收端断开后，最终统计只报告一次，之后移除
+        drop(slow);
+        let stats = broadcaster.subscriber_stats();
+        assert_eq!((stats[1].name.as_str(), stats[1].active), ("postgres", 0));
+        let names: Vec<String> = broadcaster
+            .subscriber_stats()
+            .into_iter()
+            .map(|stats| stats.name)
+            .collect();
+        assert_eq!(names, ["websocket"]);
+    }
+}
@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+use crate::broadcast::install_layer;
+use crate::clock::Clock;
+use crate::escalation::{EscalationRule, EscalationRules};
+use crate::setup::SetupError;
+use crate::{BroadcastLogLayer, EffectiveConfig, LogCache, LogEntry};
+
+/// 默认的过滤指令
+pub const DEFAULT_DIRECTIVES: &str = "info";
+
+/// `Profile::Lean` 的缓存条目数
+pub const LEAN_CACHE_ENTRIES: usize = 200;
+
+/// `Profile::Lean` 的 message 字节上限
+pub const LEAN_MAX_MESSAGE_BYTES: usize = 1024;
+
+/// `Profile::Lean` 缓存的估算字节上限：200 条 × (1 KiB message + `ENTRY_OVERHEAD_BYTES`)
+/// 约 225 KiB，取 256 KiB；字段不捕获，条目不会更大
+pub const LEAN_CACHE_BYTES: usize = 256 * 1024;
+
+/// `Profile::Lean` 的写入通道容量，积压时最多占用约 256 条日志的内存
+pub const LEAN_WRITER_CAPACITY: usize = 256;
+
+/// `Profile::Minimal` 只缓存 ERROR 日志的条目数
+pub const MINIMAL_CACHE_ENTRIES: usize = 16;
+
+/// `Profile::Minimal` 缓存的估算字节上限
+pub const MINIMAL_CACHE_BYTES: usize = 32 * 1024;
+
+/// `Profile::Minimal` 的写入通道容量
+pub const MINIMAL_WRITER_CAPACITY: usize = 16;
+
+/// 按内存预算预设的配置，见 `TracingBuilder::profile`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    /// 默认配置
+    #[default]
+    Full,
+    /// 不捕获结构化字段，message 截断到 1 KiB，缓存 200 条（约 256 KiB 以内），
+    /// 写入通道缩小到 256 条
+    Lean,
+    /// 只保留控制台输出和 16 条 ERROR 日志的小缓存（约 32 KiB 以内），不持久化
+    Minimal,
+}
+
+/// 单个配置项的错误，`option` 与 `value` 指出出错的配置项及其取值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub option: String,
+    pub value: String,
+    pub reason: String,
+}
+
+impl ConfigError {
+    pub(crate) fn new(
+        option: impl Into<String>,
+        value: impl Into<String>,
+        reason: impl fmt::Display,
+    ) -> Self {
+        Self {
+            option: option.into(),
+            value: value.into(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {} = {:?}: {}",
+            self.option, self.value, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        "LT-CFG-001"
+    }
+}
+
+/// `TracingBuilder::init` 的错误
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InitError {
+    Invalid(Vec<ConfigError>),
+    Setup(SetupError),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::Invalid(errors) => {
+                write!(f, "invalid logging config:")?;
+                for e in errors {
+                    write!(f, "\n  {}", e)?;
+                }
+                Ok(())
+            }
+            InitError::Setup(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+impl InitError {
+    /// 稳定的错误码；`Setup` 返回内层 `SetupError` 的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            InitError::Invalid(_) => "LT-CFG-002",
+            InitError::Setup(e) => e.code(),
+        }
+    }
+}
+
+/// 校验通过时的摘要，可直接打印给部署检查脚本
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    pub directives: String,
+    pub persist_path: Option<PathBuf>,
+    /// 远端 sink 名称及解析到的地址
+    pub remotes: Vec<(String, Vec<SocketAddr>)>,
+    /// 不阻止启动但值得注意的情况，例如日志文件尚不存在、将会新建
+    pub warnings: Vec<String>,
+    /// `init` 安装后生效的配置，`validate` 时为 None
+    pub effective: Option<EffectiveConfig>,
+}
+
+/// 组装并安装带广播层的全局 subscriber，安装前可用 `validate` 做部署期检查
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use listen_tracing::{LogCache, TracingBuilder};
+///
+/// let (tx, _rx) = tokio::sync::broadcast::channel(1024);
+/// let builder = TracingBuilder::new(tx, LogCache::default())
+///     .directives("info,hyper=warn")
+///     .persist_path("/var/log/app/logs.jsonl")
+///     .remote("syslog", "tcp://logs.internal:601");
+/// if std::env::args().any(|a| a == "--check-logging-config") {
+///     println!("{:?}", builder.validate().map_err(|e| format!("{:?}", e))?);
+///     return Ok(());
+/// }
+/// builder.init()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TracingBuilder {
+    layer: BroadcastLogLayer,
+    directives: String,
+    remotes: Vec<(String, String)>,
+    /// (target, field, op, value, level)，`validate` 时校验
+    escalations: Vec<[String; 5]>,
+    escalation_file: Option<PathBuf>,
+    /// `shutdown_on` 的触发条件与宽限期
+    shutdown: Option<(ShutdownTrigger, Duration)>,
+}
+
+type ShutdownTrigger = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+impl TracingBuilder {
+    pub fn new(tx: broadcast::Sender<LogEntry>, cache: LogCache) -> Self {
+        Self {
+            layer: BroadcastLogLayer::new(tx, cache),
+            directives: DEFAULT_DIRECTIVES.to_string(),
+            remotes: Vec::new(),
+            escalations: Vec::new(),
+            escalation_file: None,
+            shutdown: None,
+        }
+    }
+
+    /// 应用内存预设；预设只是起点，之后调用的选项会覆盖其中对应的一项，因此应最先调用
+    ///
+    /// 预设会直接调整传入的缓存的容量。`Full` 不做任何修改。
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.layer = match profile {
+            Profile::Full => self.layer,
+            Profile::Lean => self
+                .layer
+                .with_field_capture(false)
+                .with_max_message_bytes(LEAN_MAX_MESSAGE_BYTES)
+                .with_cache_limits(LEAN_CACHE_ENTRIES, Some(LEAN_CACHE_BYTES))
+                .with_writer_capacity(LEAN_WRITER_CAPACITY),
+            Profile::Minimal => self
+                .layer
+                .without_persistence()
+                .with_field_capture(false)
+                .with_min_level(Some(Level::ERROR))
+                .with_max_message_bytes(LEAN_MAX_MESSAGE_BYTES)
+                .with_cache_limits(MINIMAL_CACHE_ENTRIES, Some(MINIMAL_CACHE_BYTES))
+                .with_writer_capacity(MINIMAL_WRITER_CAPACITY),
+        };
+        self
+    }
+
+    /// EnvFilter 指令，例如 `info,hyper=warn`
+    pub fn directives(mut self, directives: impl Into<String>) -> Self {
+        self.directives = directives.into();
+        self
+    }
+
+    pub fn persist_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layer = self.layer.with_persist_path(path);
+        self
+    }
+
+    pub fn without_persistence(mut self) -> Self {
+        self.layer = self.layer.without_persistence();
+        self
+    }
+
+    /// 替换时钟源，见 `BroadcastLogLayer::with_clock`
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.layer = self.layer.with_clock(clock);
+        self
+    }
+
+    /// 调整广播层的其余选项（脱敏、截断、span 事件等）
+    pub fn layer(mut self, configure: impl FnOnce(BroadcastLogLayer) -> BroadcastLogLayer) -> Self {
+        self.layer = configure(self.layer);
+        self
+    }
+
+    /// 登记一个远端 sink 地址（可带 `tcp://` / `udp://` 前缀），`validate` 会做 DNS 解析；
+    /// sink 本身仍需通过 `spawn_sink` 等启动
+    pub fn remote(mut self, name: impl Into<String>, addr: impl Into<String>) -> Self {
+        self.remotes.push((name.into(), addr.into()));
+        self
+    }
+
+    /// 添加一条级别提升规则，例如 `.escalate("*", "latency_ms", "gt", "5000", "WARN")`，见 `escalation` 模块
+    pub fn escalate(
+        mut self,
+        target: impl Into<String>,
+        field: impl Into<String>,
+        op: impl Into<String>,
+        value: impl Into<String>,
+        level: impl Into<String>,
+    ) -> Self {
+        self.escalations.push([
+            target.into(),
+            field.into(),
+            op.into(),
+            value.into(),
+            level.into(),
+        ]);
+        self
+    }
+
+    /// 从文件读取级别提升规则，排在 `escalate` 添加的规则之前
+    pub fn escalation_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.escalation_file = Some(path.into());
+        self
+    }
+
+    /// `trigger` 完成时（例如 `token.cancelled_owned()`）有序关闭管线，见 `LogWriterGuard::shutdown`
+    ///
+    /// 宽限期内仍接收日志，因此可在关闭顺序的最后再触发，其他组件的关闭过程照常落盘。
+    /// 等待任务在 `init` 时启动于当前 tokio 运行时；不在运行时内时忽略，并在报告中给出警告。
+    pub fn shutdown_on(mut self, trigger: impl Future + Send + 'static, grace: Duration) -> Self {
+        self.shutdown = Some((
+            Box::pin(async move {
+                trigger.await;
+            }),
+            grace,
+        ));
+        self
+    }
+
+    /// 文件与 `escalate` 中的全部规则
+    fn escalation_rules(&self) -> Result<EscalationRules, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut rules: Vec<EscalationRule> = match &self.escalation_file {
+            Some(path) => EscalationRules::from_file(path)
+                .map_err(|e| errors.push(e))
+                .map(|rules| rules.rules().cloned().collect())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        for (i, [target, field, op, value, level]) in self.escalations.iter().enumerate() {
+            match EscalationRule::new(target, field, op, value, level) {
+                Ok(rule) => rules.push(rule),
+                Err(reason) => errors.push(ConfigError::new(
+                    format!("escalate[{}]", i),
+                    format!("{} {} {} {} => {}", target, field, op, value, level),
+                    reason,
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(EscalationRules::new(rules))
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// 检查全部配置而不安装任何东西，返回所有发现的问题
+    pub fn validate(&self) -> Result<ValidationReport, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        let mut report = ValidationReport {
+            directives: self.directives.clone(),
+            persist_path: self.layer.pipeline().persist_path().map(Path::to_path_buf),
+            ..Default::default()
+        };
+
+        if let Err(e) = EnvFilter::builder().parse(&self.directives) {
+            errors.push(ConfigError::new("directives", &self.directives, e));
+        }
+
+        if let Some(path) = &report.persist_path {
+            match check_writable(path) {
+                Ok(Some(warning)) => report.warnings.push(warning),
+                Ok(None) => {}
+                Err(reason) => errors.push(ConfigError::new(
+                    "persist_path",
+                    path.display().to_string(),
+                    reason,
+                )),
+            }
+        }
+
+        if let Some(path) = self.layer.audit_path() {
+            match check_writable(path) {
+                Ok(Some(warning)) => report.warnings.push(warning),
+                Ok(None) => {}
+                Err(reason) => errors.push(ConfigError::new(
+                    "audit_path",
+                    path.display().to_string(),
+                    reason,
+                )),
+            }
+        }
+
+        let mut seen: HashMap<&str, &str> = HashMap::new();
+        for (name, addr) in &self.remotes {
+            let option = format!("remote.{}", name);
+            let host = strip_scheme(addr);
+            if let Some(other) = seen.insert(host, name) {
+                errors.push(ConfigError::new(
+                    option,
+                    addr,
+                    format!("same address as remote.{}", other),
+                ));
+                continue;
+            }
+            match host.to_socket_addrs() {
+                Ok(addrs) => report.remotes.push((name.clone(), addrs.collect())),
+                Err(e) => errors.push(ConfigError::new(option, addr, e)),
+            }
+        }
+
+        if let Err(e) = self.escalation_rules() {
+            errors.extend(e);
+        }
+
+        if errors.is_empty() {
+            Ok(report)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// 校验后安装；已有本 crate 安装的 subscriber 时挂载广播层（此时 `directives` 不生效）
+    pub fn init(self) -> Result<ValidationReport, InitError> {
+        let mut report = self.validate().map_err(InitError::Invalid)?;
+        let filter = EnvFilter::builder()
+            .parse(&self.directives)
+            .expect("validated above");
+        let escalations = self.escalation_rules().expect("validated above");
+        let layer = if escalations.is_empty() {
+            self.layer
+        } else {
+            self.layer.with_escalations(escalations)
+        };
+        let guard = layer.pipeline().writer_guard();
+        report.effective =
+            Some(install_layer(layer, filter, self.remotes).map_err(InitError::Setup)?);
+        if let Some((trigger, grace)) = self.shutdown {
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(guard.shutdown_on(trigger, grace));
+                }
+                Err(_) => report.warnings.push(
+                    "shutdown_on ignored: init was called outside a tokio runtime".to_string(),
+                ),
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn strip_scheme(addr: &str) -> &str {
+    addr.split_once("://").map_or(addr, |(_, rest)| rest)
+}
+
+/// 检查日志文件可追加写入；文件不存在时检查目录，返回提示而非错误
+fn check_writable(path: &Path) -> Result<Option<String>, String> {
+    if path.is_dir() {
+        return Err("is a directory".to_string());
+    }
+    if path.exists() {
+        return OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(|_| None)
+            .map_err(|e| e.to_string());
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match dir.metadata() {
+        Ok(meta) if !meta.is_dir() => Err(format!("{} is not a directory", dir.display())),
+        Ok(meta) if meta.permissions().readonly() => {
+            Err(format!("directory {} is read-only", dir.display()))
+        }
+        Ok(_) => Ok(Some(format!("{} will be created", path.display()))),
+        Err(e) => Err(format!("directory {}: {}", dir.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sanitize::TRUNCATED_MARKER;
+    use crate::DEFAULT_CACHE_CAPACITY;
+
+    fn builder() -> TracingBuilder {
+        let (tx, _rx) = broadcast::channel(16);
+        TracingBuilder::new(tx, LogCache::default())
+    }
+
+    #[test]
+    fn test_validate_reports_each_bad_option() {
+        let dir = std::env::temp_dir();
+        let report = builder()
+            .directives("info,hyper=warn")
+            .persist_path(dir.join("listen_tracing_builder_ok.jsonl"))
+            .remote("syslog", "udp://127.0.0.1:514")
+            .validate()
+            .unwrap();
+        assert_eq!(report.remotes[0].1, ["127.0.0.1:514".parse().unwrap()]);
+
+        let errors = builder()
+            .directives("info,[bad")
+            .persist_path(dir.join("listen_tracing_missing_dir/logs.jsonl"))
+            .remote("syslog", "tcp://127.0.0.1:601")
+            .remote("gelf", "udp://127.0.0.1:601")
+            .remote("redis", "no-port-given")
+            .validate()
+            .unwrap_err();
+        let options: Vec<&str> = errors.iter().map(|e| e.option.as_str()).collect();
+        assert_eq!(
+            options,
+            ["directives", "persist_path", "remote.gelf", "remote.redis"]
+        );
+        assert_eq!(errors[0].value, "info,[bad");
+        assert!(errors[1].value.ends_with("logs.jsonl"));
+        assert_eq!(errors[2].reason, "same address as remote.syslog");
+        assert!(errors[3]
+            .to_string()
+            .starts_with("invalid remote.redis = \"no-port-given\""));
+
+        let errors = builder().persist_path(&dir).validate().unwrap_err();
+        assert_eq!(errors[0].reason, "is a directory");
+        let errors = builder()
+            .without_persistence()
+            .layer(|layer| layer.with_audit_path(&dir))
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors[0].option, "audit_path");
+
+        let errors = builder()
+            .escalate("*", "latency_ms", "gt", "5000", "WARN")
+            .escalate("*", "latency_ms", "above", "5000", "WARN")
+            .escalate("*", "latency_ms", "lt", "fast", "WARN")
+            .escalation_file(dir.join("listen_tracing_missing_escalations.txt"))
+            .validate()
+            .unwrap_err();
+        let options: Vec<&str> = errors.iter().map(|e| e.option.as_str()).collect();
+        assert_eq!(options, ["escalation_file", "escalate[1]", "escalate[2]"]);
+        assert_eq!(errors[2].reason, "`lt` needs a numeric value, got \"fast\"");
+    }
+
+    /// 经 builder 的广播层写入一批大日志，返回缓存内容与估算字节数
+    async fn flood(builder: TracingBuilder) -> (Vec<LogEntry>, usize) {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let pipeline = builder.layer.pipeline().clone();
+        let subscriber = tracing_subscriber::registry().with(builder.layer);
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let payload = "x".repeat(4096);
+        for i in 0..1000 {
+            tracing::info!(i, payload = %payload, "{} {}", i, payload);
+        }
+        tracing::error!(code = 7, "failed");
+        pipeline.writer_guard().flush_and_close().await;
+        let cache = pipeline.cache().read().await;
+        (cache.to_vec(), cache.bytes())
+    }
+
+    #[tokio::test]
+    async fn test_profiles_bound_cache_memory() {
+        skip_if_events_compiled_out!();
+        let (logs, bytes) = flood(builder().without_persistence()).await;
+        assert_eq!(logs.len(), DEFAULT_CACHE_CAPACITY);
+        assert!(bytes > 8_000_000);
+
+        let (logs, bytes) = flood(builder().profile(Profile::Lean).without_persistence()).await;
+        assert_eq!(logs.len(), LEAN_CACHE_ENTRIES);
+        assert!(bytes <= LEAN_CACHE_BYTES, "{} bytes", bytes);
+        assert!(logs.iter().all(|e| e.fields.is_empty()));
+        assert!(logs[0].message.len() <= LEAN_MAX_MESSAGE_BYTES + TRUNCATED_MARKER.len());
+
+        let (logs, bytes) = flood(builder().profile(Profile::Minimal)).await;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].message, "failed");
+        assert!(bytes <= MINIMAL_CACHE_BYTES);
+
+        // 之后的显式调用覆盖预设
+        let (logs, _) = flood(
+            builder()
+                .profile(Profile::Lean)
+                .without_persistence()
+                .layer(|layer| layer.with_field_capture(true).with_max_message_bytes(8192)),
+        )
+        .await;
+        assert!(logs[0].message.len() > 4096);
+        assert_eq!(logs[0].fields["payload"].as_str().unwrap().len(), 4096);
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::{LogEntry, LogRecord};
+
+/// 内存缓存默认保留的最大条数
+pub const DEFAULT_CACHE_CAPACITY: usize = 1000;
+
+/// 每条日志在字节估算中计入的固定开销（时间戳、级别、target 及结构体本身）
+pub const ENTRY_OVERHEAD_BYTES: usize = 128;
+
+/// 默认最多钉住的条目数
+pub const DEFAULT_MAX_PINNED: usize = 100;
+
+pub type LogCache<R = LogEntry> = Arc<RwLock<LogBuffer<R>>>;
+
+/// 有界的最近日志缓冲区，可同时按条数和估算字节数限制
+///
+/// 超出任一限制时从最旧的一端淘汰；单条超过字节预算时仍保留最新的这一条。
+/// 另外按 level（忽略大小写）维护条目序号索引，按级别过滤时无需扫描全部条目。
+///
+/// 钉住（`pin`）的条目另存一份副本，不受淘汰和 `clear` 影响，数量受 `max_pinned` 限制；
+/// 只保存在内存中，进程重启后需要时可先用 `pinned` 导出。
+#[derive(Clone, Debug)]
+pub struct LogBuffer<R = LogEntry> {
+    entries: VecDeque<R>,
+    max_entries: usize,
+    max_bytes: Option<usize>,
+    bytes: usize,
+    evicted: u64,
+    /// entries 队首条目的序号，第 i 条的序号为 `first_seq + i`；序号从 1 开始，0 表示"尚无条目"
+    first_seq: u64,
+    /// (level, 该级别条目的序号，旧 -> 新)；级别种类很少，线性查找即可
+    level_index: Vec<(String, VecDeque<u64>)>,
+    /// 序号 -> 钉住的条目副本
+    pinned: BTreeMap<u64, R>,
+    max_pinned: usize,
+}
+
+/// 缓存状态快照
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub evicted: u64,
+    pub max_entries: usize,
+    pub max_bytes: Option<usize>,
+    /// 钉住的条目数
+    pub pinned: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PinError {
+    /// 缓存中没有该序号的条目（已被淘汰或尚未写入）
+    NotFound { seq: u64 },
+    /// 钉住的条目已达上限
+    LimitReached { max: usize },
+}
+
+impl PinError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        match self {
+            PinError::NotFound { .. } => "LT-QRY-002",
+            PinError::LimitReached { .. } => "LT-CFG-005",
+        }
+    }
+}
+
+impl fmt::Display for PinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinError::NotFound { seq } => write!(f, "no cached log entry with seq {}", seq),
+            PinError::LimitReached { max } => {
+                write!(f, "cannot pin more than {} log entries", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PinError {}
+
+/// 只统计写入长度的 Writer，估算字段大小时不必真正分配 JSON 字符串
+pub(crate) struct ByteCounter(pub(crate) usize);
+
+/// 按结构估算 `value` 的紧凑 JSON 长度，不做序列化：字符串不计转义，浮点数按 8 字节计，
+/// 其余与 `serde_json::to_string` 的长度一致
+pub(crate) fn estimate_json_len(value: &Value) -> usize {
+    fn digits(n: u64) -> usize {
+        n.checked_ilog10().map_or(1, |d| d as usize + 1)
+    }
+    match value {
+        Value::Null => 4,
+        Value::Bool(b) => 4 + !b as usize,
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => digits(u),
+            (None, Some(i)) => 1 + digits(i.unsigned_abs()),
+            _ => 8,
+        },
+        Value::String(s) => s.len() + 2,
+        Value::Array(items) => {
+            2 + items.len().saturating_sub(1) + items.iter().map(estimate_json_len).sum::<usize>()
+        }
+        Value::Object(map) => estimate_map_json_len(map.iter()),
+    }
+}
+
+/// `{"k":v,...}` 的估算长度，见 `estimate_json_len`
+pub(crate) fn estimate_map_json_len<'a>(
+    entries: impl ExactSizeIterator<Item = (&'a String, &'a Value)>,
+) -> usize {
+    let commas = entries.len().saturating_sub(1);
+    2 + commas
+        + entries
+            .map(|(key, value)| key.len() + 3 + estimate_json_len(value))
+            .sum::<usize>()
+}
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 仅为默认的 `LogEntry` 实现，保证 `LogCache::default()` 无需标注类型；
+/// 自定义记录使用 `LogBuffer::<R>::new(DEFAULT_CACHE_CAPACITY)`
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl<R: LogRecord> LogBuffer<R> {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries,
+            max_bytes: None,
+            bytes: 0,
+            evicted: 0,
+            first_seq: 1,
+            level_index: Vec::new(),
+            pinned: BTreeMap::new(),
+            max_pinned: DEFAULT_MAX_PINNED,
+        }
+    }
+
+    /// 最多钉住的条目数（默认 `DEFAULT_MAX_PINNED`）
+    pub fn with_max_pinned(mut self, max_pinned: usize) -> Self {
+        self.max_pinned = max_pinned;
+        self
+    }
+
+    /// 额外按估算字节数限制缓存大小
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// 包装成可在 Layer 与查询之间共享的 LogCache
+    pub fn shared(self) -> LogCache<R> {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// 估算单条日志占用，见 `LogRecord::estimate_bytes`；
+    /// `LogEntry` 为 message 长度 + 字段序列化长度 + 固定开销
+    pub fn estimate_entry_bytes(entry: &R) -> usize {
+        entry.estimate_bytes()
+    }
+
+    /// 追加一条日志并通过 `LogRecord::set_seq` 写入其序号
+    pub fn push(&mut self, mut entry: R) {
+        self.bytes += Self::estimate_entry_bytes(&entry);
+        let seq = self.first_seq + self.entries.len() as u64;
+        entry.set_seq(seq);
+        match self.level_seqs_mut(entry.level()) {
+            Some(seqs) => seqs.push_back(seq),
+            None => self
+                .level_index
+                .push((entry.level().to_string(), VecDeque::from([seq]))),
+        }
+        self.entries.push_back(entry);
+        self.evict();
+    }
+
+    /// 清空全部条目与级别索引，钉住的条目保留；序号继续递增，不计入 evicted
+    pub fn clear(&mut self) {
+        self.first_seq += self.entries.len() as u64;
+        self.entries.clear();
+        self.level_index.clear();
+        self.bytes = 0;
+    }
+
+    /// 修改容量上限，超出的部分立即从最旧的条目开始淘汰
+    pub fn set_limits(&mut self, max_entries: usize, max_bytes: Option<usize>) {
+        self.max_entries = max_entries;
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_entries
+            || (self.entries.len() > 1 && self.max_bytes.is_some_and(|max| self.bytes > max))
+        {
+            self.pop_oldest();
+        }
+    }
+
+    fn pop_oldest(&mut self) -> Option<R> {
+        let entry = self.entries.pop_front()?;
+        self.bytes -= Self::estimate_entry_bytes(&entry);
+        self.evicted += 1;
+        // 被淘汰的总是最旧的条目，也必然位于其级别索引的队首
+        if let Some(seqs) = self.level_seqs_mut(entry.level()) {
+            seqs.pop_front();
+        }
+        self.level_index.retain(|(_, seqs)| !seqs.is_empty());
+        self.first_seq += 1;
+        Some(entry)
+    }
+
+    fn level_seqs_mut(&mut self, level: &str) -> Option<&mut VecDeque<u64>> {
+        self.level_index
+            .iter_mut()
+            .find(|(indexed, _)| indexed.eq_ignore_ascii_case(level))
+            .map(|(_, seqs)| seqs)
+    }
+
+    /// 按时间顺序遍历指定级别（忽略大小写）的条目，只访问命中的条目
+    pub fn iter_level<'a>(&'a self, level: &str) -> impl DoubleEndedIterator<Item = &'a R> {
+        self.level_index
+            .iter()
+            .find(|(indexed, _)| indexed.eq_ignore_ascii_case(level))
+            .into_iter()
+            .flat_map(|(_, seqs)| seqs.iter())
+            .map(|seq| &self.entries[(seq - self.first_seq) as usize])
+    }
+
+    /// 按时间顺序遍历序号大于 `after_seq` 的条目，直接从对应位置开始，不扫描更早的条目
+    pub fn iter_since(&self, after_seq: u64) -> impl DoubleEndedIterator<Item = &R> {
+        let start = (after_seq + 1).saturating_sub(self.first_seq) as usize;
+        self.entries.range(start.min(self.entries.len())..)
+    }
+
+    /// 按序号定位条目下标：命中返回 `Ok`，否则返回 `Err(插入位置)`
+    ///
+    /// 缓存中的序号连续递增，直接由 `first_seq` 换算；`Err(0)` 表示已被淘汰，`Err(len)` 表示尚未分配。
+    pub fn position(&self, seq: u64) -> Result<usize, usize> {
+        if seq < self.first_seq {
+            return Err(0);
+        }
+        let index = (seq - self.first_seq) as usize;
+        if index < self.entries.len() {
+            Ok(index)
+        } else {
+            Err(self.entries.len())
+        }
+    }
+
+    /// 钉住序号为 `seq` 的条目；已钉住时返回 `Ok(false)`
+    pub fn pin(&mut self, seq: u64) -> Result<bool, PinError> {
+        if self.pinned.contains_key(&seq) {
+            return Ok(false);
+        }
+        let index = self.position(seq).map_err(|_| PinError::NotFound { seq })?;
+        if self.pinned.len() >= self.max_pinned {
+            return Err(PinError::LimitReached {
+                max: self.max_pinned,
+            });
+        }
+        self.pinned.insert(seq, self.entries[index].clone());
+        Ok(true)
+    }
+
+    /// 取消钉住，返回被取消的条目
+    pub fn unpin(&mut self, seq: u64) -> Option<R> {
+        self.pinned.remove(&seq)
+    }
+
+    pub fn is_pinned(&self, seq: u64) -> bool {
+        self.pinned.contains_key(&seq)
+    }
+
+    /// 钉住的条目，按序号（时间）顺序
+    pub fn pinned(&self) -> impl DoubleEndedIterator<Item = &R> + ExactSizeIterator {
+        self.pinned.values()
+    }
+
+    /// 钉住但已不在主队列中（被淘汰或清空）的条目，都早于主队列中的任何条目
+    pub fn pinned_evicted(&self) -> impl DoubleEndedIterator<Item = &R> {
+        self.pinned.range(..self.first_seq).map(|(_, entry)| entry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 按时间顺序（旧 -> 新）遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &R> + ExactSizeIterator {
+        self.entries.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<R> {
+        self.entries.iter().cloned().collect()
+    }
+
+    /// 当前估算占用的字节数
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            evicted: self.evicted,
+            max_entries: self.max_entries,
+            max_bytes: self.max_bytes,
+            pinned: self.pinned.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message_len: usize) -> LogEntry {
+        LogEntry {
+            message: "x".repeat(message_len),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_byte_budget_eviction() {
+        // 每条 872 + 128 = 1000 字节，预算 3000 字节正好容纳 3 条
+        let mut buffer = LogBuffer::new(100).with_max_bytes(3000);
+        for _ in 0..3 {
+            buffer.push(entry(872));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.bytes(), 3000);
+
+        buffer.push(entry(872));
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.stats().evicted, 1);
+
+        // 2000 字节的大条目挤掉两条旧条目
+        buffer.push(entry(1872));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.bytes(), 3000);
+
+        let mut with_fields = entry(0);
+        with_fields
+            .fields
+            .insert("k".to_string(), serde_json::json!("v"));
+        assert_eq!(
+            LogBuffer::estimate_entry_bytes(&with_fields),
+            r#"{"k":"v"}"#.len() + 128
+        );
+        let nested = serde_json::json!({
+            "id": 0, "n": -1234, "big": u64::MAX, "ok": false, "none": null,
+            "tags": ["a", "bc", []], "inner": {"x": true, "y": {}},
+        });
+        assert_eq!(estimate_json_len(&nested), nested.to_string().len());
+
+        // 单条超出预算时只保留这一条
+        buffer.push(entry(5000));
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.bytes(), 5128);
+    }
+
+    #[test]
+    fn test_level_index_follows_eviction() {
+        let mut buffer = LogBuffer::new(3);
+        for (level, message) in [("INFO", "a"), ("error", "b"), ("INFO", "c"), ("ERROR", "d")] {
+            buffer.push(LogEntry {
+                level: level.to_string(),
+                message: message.to_string(),
+                ..Default::default()
+            });
+        }
+        let errors: Vec<_> = buffer.iter_level("Error").map(|e| &e.message).collect();
+        assert_eq!(errors, ["b", "d"]);
+        let infos: Vec<_> = buffer.iter_level("info").map(|e| &e.message).collect();
+        assert_eq!(infos, ["c"]);
+        assert_eq!(buffer.iter_level("DEBUG").count(), 0);
+    }
+
+    #[test]
+    fn test_count_and_byte_limits_together() {
+        let mut buffer = LogBuffer::new(2).with_max_bytes(10_000);
+        for _ in 0..5 {
+            buffer.push(entry(10));
+        }
+        assert_eq!(buffer.stats().entries, 2);
+        assert_eq!(buffer.stats().bytes, 2 * 138);
+        assert_eq!(buffer.stats().evicted, 3);
+    }
+}
@@ -0,0 +1,182 @@
+//! 极简 CBOR（RFC 8949）编解码，只覆盖 JSON 数据模型
+//!
+//! 编码使用定长的最短头部，浮点数一律写为 float64；解码另外接受 float32，
+//! 拒绝不定长、byte string、tag 等 JSON 中没有对应的结构。
+
+use serde_json::{Map, Number, Value};
+
+use crate::serializer::{DecodeError, Reader, MAX_DEPTH};
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+
+pub(crate) fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                encode_head(UNSIGNED, u, out);
+            } else if let Some(i) = n.as_i64() {
+                // 负数 n 编码为 -1 - n
+                encode_head(NEGATIVE, (-1 - i) as u64, out);
+            } else {
+                out.push(0xfb);
+                out.extend_from_slice(&n.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        }
+        Value::String(s) => encode_str(s, out),
+        Value::Array(items) => {
+            encode_head(ARRAY, items.len() as u64, out);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Object(map) => {
+            encode_head(MAP, map.len() as u64, out);
+            for (key, value) in map {
+                encode_str(key, out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    encode_head(TEXT, s.len() as u64, out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// 主类型与参数：不足 24 时内联，否则跟 1 / 2 / 4 / 8 字节
+fn encode_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if arg <= 0xff {
+        out.extend_from_slice(&[major | 24, arg as u8]);
+    } else if arg <= 0xffff {
+        out.push(major | 25);
+        out.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= 0xffff_ffff {
+        out.push(major | 26);
+        out.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Value, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let value = decode_value(&mut reader, 0)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+fn decode_value(r: &mut Reader<'_>, depth: usize) -> Result<Value, DecodeError> {
+    if depth > MAX_DEPTH {
+        return Err(r.error("nesting too deep"));
+    }
+    let initial = r.byte()?;
+    let major = initial >> 5;
+    let info = initial & 0x1f;
+    if major == 7 {
+        return match info {
+            20 => Ok(Value::Bool(false)),
+            21 => Ok(Value::Bool(true)),
+            22 => Ok(Value::Null),
+            26 => {
+                let f = f32::from_be_bytes(r.array()?) as f64;
+                float(r, f)
+            }
+            27 => {
+                let f = f64::from_be_bytes(r.array()?);
+                float(r, f)
+            }
+            _ => Err(r.error("unsupported CBOR simple value")),
+        };
+    }
+    let arg = match info {
+        0..=23 => info as u64,
+        24 => r.byte()? as u64,
+        25 => u16::from_be_bytes(r.array()?) as u64,
+        26 => u32::from_be_bytes(r.array()?) as u64,
+        27 => u64::from_be_bytes(r.array()?),
+        _ => return Err(r.error("indefinite length is not supported")),
+    };
+    match major {
+        UNSIGNED => Ok(Value::from(arg)),
+        NEGATIVE => i64::try_from(arg)
+            .map(|n| Value::from(-1 - n))
+            .map_err(|_| r.error("negative integer out of range")),
+        TEXT => r.string(arg as usize),
+        ARRAY => {
+            let len = arg as usize;
+            let mut items = Vec::with_capacity(len.min(r.remaining()));
+            for _ in 0..len {
+                items.push(decode_value(r, depth + 1)?);
+            }
+            Ok(Value::Array(items))
+        }
+        MAP => {
+            let mut map = Map::new();
+            for _ in 0..arg {
+                let Value::String(key) = decode_value(r, depth + 1)? else {
+                    return Err(r.error("map key is not a string"));
+                };
+                let value = decode_value(r, depth + 1)?;
+                map.insert(key, value);
+            }
+            Ok(Value::Object(map))
+        }
+        _ => Err(r.error("unsupported CBOR type")),
+    }
+}
+
+fn float(r: &Reader<'_>, f: f64) -> Result<Value, DecodeError> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| r.error("NaN or infinite float"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_value_round_trip_and_rfc_examples() {
+        let value = json!({
+            "small": 5,
+            "wide": 70000,
+            "huge": u64::MAX,
+            "neg": -500,
+            "neg64": i64::MIN,
+            "float": -2.25,
+            "text": "日志".repeat(20),
+            "list": (0..30).collect::<Vec<_>>(),
+            "nested": {"ok": false, "none": null},
+        });
+        let mut out = Vec::new();
+        encode(&value, &mut out);
+        assert_eq!(decode(&out).unwrap(), value);
+
+        // RFC 8949 附录 A 的示例
+        let encoded = |value: Value| {
+            let mut out = Vec::new();
+            encode(&value, &mut out);
+            out
+        };
+        assert_eq!(encoded(json!(1000)), [0x19, 0x03, 0xe8]);
+        assert_eq!(encoded(json!(-100)), [0x38, 0x63]);
+        assert_eq!(encoded(json!("IETF")), [0x64, 0x49, 0x45, 0x54, 0x46]);
+        assert_eq!(encoded(json!([1, [2, 3]])), [0x82, 0x01, 0x82, 0x02, 0x03]);
+
+        // 不定长数组
+        assert!(decode(&[0x9f, 0x01, 0xff]).is_err());
+        assert!(decode(&[0x82, 0x01]).is_err());
+    }
+}
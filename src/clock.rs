@@ -0,0 +1,209 @@
+//! 时钟源与墙上时钟回拨检测
+//!
+//! Layer、管线和限流等处的当前时间都取自 `Clock`，默认为 `SystemClock`；
+//! 测试中换成 `MockClock` 即可精确控制跨零点、窗口到期等时刻。
+//!
+//! NTP 步进等原因会让系统时间倒退，之后日志的 `timestamp` 不再单调。
+//! 每条日志另带进程内单调的 `mono_ns`，缓存中还有 `seq`，需要可靠顺序的地方应以它们为准。
+
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::tracing_utils::epoch_millis_to_rfc3339;
+use crate::{LogEntry, LOG_ENTRY_SCHEMA};
+
+/// 时钟回拨诊断条目使用的 target
+pub const CLOCK_TARGET: &str = "listen_tracing::clock";
+
+/// 小于该幅度的倒退视为多线程间的正常乱序，不报告
+pub const CLOCK_REGRESSION_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// 当前时间的来源
+pub trait Clock: fmt::Debug + Send + Sync + 'static {
+    /// 墙上时间，用于日志的 `timestamp`
+    fn now(&self) -> DateTime<Utc>;
+
+    /// 单调时间，用于计时与时间窗口
+    fn instant(&self) -> Instant;
+}
+
+/// 系统时钟
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// 手动设置 / 推进的时钟，克隆出的实例共享同一时间
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    state: Arc<Mutex<(DateTime<Utc>, Instant)>>,
+}
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new((now, Instant::now()))),
+        }
+    }
+
+    /// 只改墙上时间，可以倒退；单调时间不变
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.state.lock().unwrap().0 = now;
+    }
+
+    /// 墙上时间与单调时间同时前进
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.0 += by;
+        state.1 += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.state.lock().unwrap().0
+    }
+
+    fn instant(&self) -> Instant {
+        self.state.lock().unwrap().1
+    }
+}
+
+/// 在 Layer、管线与 sink 间共享的时钟，默认为 `SystemClock`
+#[derive(Clone, Debug)]
+pub struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new(clock: impl Clock) -> Self {
+        Self(Arc::new(clock))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0.now()
+    }
+
+    fn instant(&self) -> Instant {
+        self.0.instant()
+    }
+}
+
+/// 自进程内首次调用以来的单调纳秒数
+pub fn monotonic_ns() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+/// 记录上一条日志的墙上时间，发现明显倒退时生成一条诊断
+#[derive(Debug)]
+pub(crate) struct ClockWatch {
+    last_wall_ms: AtomicI64,
+}
+
+impl Default for ClockWatch {
+    fn default() -> Self {
+        Self {
+            last_wall_ms: AtomicI64::new(i64::MIN),
+        }
+    }
+}
+
+impl ClockWatch {
+    /// 与上一次相比倒退超过 `CLOCK_REGRESSION_THRESHOLD` 时返回 WARN 诊断；每次跳变只报告一次
+    pub(crate) fn check(&self, wall_ms: i64) -> Option<LogEntry> {
+        let previous = self.last_wall_ms.swap(wall_ms, Ordering::Relaxed);
+        if previous == i64::MIN {
+            return None;
+        }
+        let regression_ms = previous.saturating_sub(wall_ms);
+        if regression_ms <= CLOCK_REGRESSION_THRESHOLD.as_millis() as i64 {
+            return None;
+        }
+        Some(LogEntry {
+            schema: LOG_ENTRY_SCHEMA,
+            timestamp: epoch_millis_to_rfc3339(wall_ms).into(),
+            level: "WARN".to_string(),
+            target: CLOCK_TARGET.to_string(),
+            message: format!(
+                "wall clock went backwards by {} ms; order by seq / mono_ns instead of timestamp",
+                regression_ms
+            ),
+            fields: [
+                ("regression_ms", json!(regression_ms)),
+                ("previous", json!(epoch_millis_to_rfc3339(previous))),
+                ("current", json!(epoch_millis_to_rfc3339(wall_ms))),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+            mono_ns: Some(monotonic_ns()),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_regression_once() {
+        let watch = ClockWatch::default();
+        let base = 1_717_200_000_000;
+        assert!(watch.check(base).is_none());
+        assert!(watch.check(base + 500).is_none());
+        // 线程间的轻微乱序
+        assert!(watch.check(base + 100).is_none());
+
+        let diagnostic = watch.check(base - 39_900).unwrap();
+        assert_eq!(diagnostic.target, CLOCK_TARGET);
+        assert_eq!(diagnostic.level, "WARN");
+        assert_eq!(diagnostic.fields["regression_ms"], 40_000);
+        assert_eq!(
+            diagnostic.fields["current"],
+            epoch_millis_to_rfc3339(base - 39_900)
+        );
+        // 跳变之后继续单调前进，不再重复报告
+        assert!(watch.check(base - 39_000).is_none());
+        assert!(watch.check(base + 1_000).is_none());
+
+        let first = monotonic_ns();
+        assert!(monotonic_ns() >= first);
+    }
+
+    #[test]
+    fn test_mock_clock() {
+        let start = DateTime::from_timestamp(1_717_200_000, 0).unwrap();
+        let clock = MockClock::new(start);
+        let shared = SharedClock::new(clock.clone());
+        let instant = shared.instant();
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(shared.now(), start + Duration::from_millis(1500));
+        assert_eq!(shared.instant() - instant, Duration::from_millis(1500));
+
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+        assert_eq!(shared.instant() - instant, Duration::from_millis(1500));
+    }
+}
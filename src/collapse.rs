@@ -0,0 +1,233 @@
+//! 连续重复日志的折叠
+//!
+//! 类似 syslog 的 "last message repeated N times"：`BroadcastLogLayer::with_collapse_consecutive`
+//! 开启后，与上一条内容相同（见 `LogRecord::same_content`，`LogEntry` 比较级别、target、
+//! message 与字段）的记录不立即进入管线，只累加次数；下一条不同的记录到达、或距第一次出现
+//! 超过超时时间后，输出第一次出现的那条，重复过的带上 `repeated: N`（N 为总次数）。
+//! 超时从第一次出现算起，重复持续不断时每个超时周期至少输出一条，之后的重复重新计数。
+//! 只折叠相邻的重复，与时间窗口内的去重不同，中间夹着任何其他记录都会断开。
+//!
+//! 每条记录都要等到下一条不同的记录或超时后才输出。超时由后台任务检查，
+//! 需在 tokio 运行时内产生第一条日志；没有运行时时只在下一条记录到达时检查。
+//! 折叠器登记为管线的 `Holdback`，`LogWriterGuard::flush_and_close`、`shutdown`、
+//! `signal::flush_on_signal` 等关闭路径会先输出暂存的记录。
+
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::pipeline::{Holdback, Pipeline};
+use crate::LogRecord;
+
+/// 重复次数所在的字段名
+pub const REPEATED_FIELD: &str = "repeated";
+
+/// 暂存的记录自第一次出现起最多保留多久
+pub const DEFAULT_COLLAPSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Held<R> {
+    log: R,
+    count: u64,
+    /// 第一次出现的时刻，超时从这里算起
+    since: Instant,
+}
+
+impl<R: LogRecord> Held<R> {
+    fn finish(self) -> R {
+        let mut log = self.log;
+        if self.count > 1 {
+            log.set_repeated(self.count);
+        }
+        log
+    }
+}
+
+/// 暂存最近一条记录并累加其重复次数，见模块文档
+pub(crate) struct Collapser<R> {
+    timeout: Duration,
+    held: Mutex<Option<Held<R>>>,
+    /// 检查超时的后台任务是否已启动
+    ticker: OnceLock<()>,
+    /// 是否已登记为管线的 `Holdback`
+    registered: OnceLock<()>,
+}
+
+impl<R: LogRecord> Collapser<R> {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            held: Mutex::new(None),
+            ticker: OnceLock::new(),
+            registered: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// 处理一条新记录：与暂存的相同且未超时时只计数，否则先输出暂存的，再暂存这一条
+    ///
+    /// 在锁内写入管线，保证与后台任务的输出不乱序。
+    pub(crate) fn push(self: &Arc<Self>, log: R, pipeline: &Pipeline<R>) {
+        self.registered.get_or_init(|| {
+            let holdback: Arc<dyn Holdback<R>> = self.clone();
+            pipeline.register_holdback(Arc::downgrade(&holdback));
+        });
+        self.start_ticker(pipeline);
+        let now = pipeline.clock().instant();
+        let mut held = self.lock();
+        match held.as_mut() {
+            Some(h) if h.log.same_content(&log) && now.duration_since(h.since) < self.timeout => {
+                h.count += 1;
+            }
+            _ => {
+                let previous = held.replace(Held {
+                    log,
+                    count: 1,
+                    since: now,
+                });
+                if let Some(previous) = previous {
+                    pipeline.ingest(previous.finish());
+                }
+            }
+        }
+    }
+
+    /// 输出已超时的暂存记录
+    pub(crate) fn flush_expired(&self, pipeline: &Pipeline<R>) {
+        let now = pipeline.clock().instant();
+        let mut held = self.lock();
+        if held
+            .as_ref()
+            .is_some_and(|h| now.duration_since(h.since) >= self.timeout)
+        {
+            if let Some(h) = held.take() {
+                pipeline.ingest(h.finish());
+            }
+        }
+    }
+
+    fn start_ticker(self: &Arc<Self>, pipeline: &Pipeline<R>) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        self.ticker.get_or_init(|| {
+            let collapser: Weak<Self> = Arc::downgrade(self);
+            let pipeline = pipeline.clone();
+            let period = self.timeout / 2;
+            runtime.spawn(async move {
+                let mut ticker = tokio::time::interval(period.max(Duration::from_millis(1)));
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    // Layer 被丢弃后退出
+                    let Some(collapser) = collapser.upgrade() else {
+                        break;
+                    };
+                    collapser.flush_expired(&pipeline);
+                }
+            });
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Held<R>>> {
+        self.held.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<R: LogRecord> Holdback<R> for Collapser<R> {
+    fn release(&self, pipeline: &Pipeline<R>) {
+        if let Some(h) = self.lock().take() {
+            pipeline.ingest(h.finish());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::tests::wait_for_cache;
+    use crate::clock::MockClock;
+    use crate::{BroadcastLogLayer, LogCache, LogEntry};
+    use serde_json::json;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[tokio::test]
+    async fn test_collapse_adjacent_duplicates() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_collapse_timeout(Duration::from_millis(50));
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        for _ in 0..3 {
+            tracing::warn!(attempt = 1, "retrying");
+        }
+        tracing::info!("connected");
+        // 字段不同不算重复
+        tracing::warn!(attempt = 1, "retrying");
+        tracing::warn!(attempt = 2, "retrying");
+
+        let logs = wait_for_cache(&cache, 4).await;
+        let summary: Vec<_> = logs
+            .iter()
+            .map(|log| (log.message.as_str(), log.fields.get(REPEATED_FIELD)))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("retrying", Some(&json!(3))),
+                ("connected", None),
+                ("retrying", None),
+                ("retrying", None),
+            ]
+        );
+        assert_eq!(logs[3].fields["attempt"], json!(2));
+    }
+
+    #[tokio::test]
+    async fn test_held_record_survives_close() {
+        skip_if_events_compiled_out!();
+        let path = std::env::temp_dir().join(format!(
+            "listen-tracing-collapse-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let clock = MockClock::new(chrono::Utc::now());
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let layer = BroadcastLogLayer::new(tx, LogCache::default())
+            .with_persist_path(&path)
+            .with_clock(clock.clone())
+            .with_collapse_timeout(Duration::from_secs(1));
+        let pipeline = layer.pipeline().clone();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        // 重复不断时，超时从第一次出现算起
+        for _ in 0..3 {
+            tracing::warn!("retrying");
+            clock.advance(Duration::from_millis(400));
+        }
+        tracing::warn!("retrying");
+        tracing::warn!("retrying");
+        pipeline.writer_guard().flush_and_close().await;
+
+        let repeated: Vec<_> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap())
+            .map(|log| (log.message, log.fields.get(REPEATED_FIELD).cloned()))
+            .collect();
+        assert_eq!(
+            repeated,
+            [
+                ("retrying".to_string(), Some(json!(3))),
+                ("retrying".to_string(), Some(json!(2))),
+            ]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,61 @@
+use std::future::Future;
+
+tokio::task_local! {
+    /// 当前异步作用域的关联 ID，由 `with_correlation_id` 设置
+    pub static CORRELATION_ID: String;
+}
+
+/// 在 `fut` 执行期间设置关联 ID，期间产生的每条 LogEntry 都会带上它
+pub async fn with_correlation_id<F: Future>(id: impl Into<String>, fut: F) -> F::Output {
+    CORRELATION_ID.scope(id.into(), fut).await
+}
+
+/// 读取当前任务的关联 ID，不在任何作用域内时返回 None
+pub fn current_correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogCache};
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[tokio::test]
+    async fn test_correlation_id_per_task() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        let a = tokio::spawn(with_correlation_id("req-a", async {
+            tracing::info!("from a");
+            tokio::task::yield_now().await;
+            tracing::info!("from a");
+        }));
+        let b = tokio::spawn(with_correlation_id("req-b", async {
+            tracing::info!("from b");
+            tokio::task::yield_now().await;
+            tracing::info!("from b");
+        }));
+        a.await.unwrap();
+        b.await.unwrap();
+        tracing::info!("outside");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let logs = cache.read().await;
+        assert_eq!(logs.len(), 5);
+        for entry in logs.iter() {
+            let expected = match entry.message.as_str() {
+                "from a" => Some("req-a"),
+                "from b" => Some("req-b"),
+                _ => None,
+            };
+            assert_eq!(entry.correlation_id.as_deref(), expected);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::clock::Clock;
+use crate::pipeline::Pipeline;
+use crate::stats::LogStatsSnapshot;
+use crate::{LogEntry, LogRecord};
+
+/// 摘要条目使用的 target
+pub const DIGEST_TARGET: &str = "listen_tracing::digest";
+
+/// 一个周期内没有任何活动时的行为
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdleDigest {
+    /// 不输出
+    #[default]
+    Skip,
+    /// 输出一条简短的 "pipeline idle" 标记
+    Marker,
+}
+
+#[derive(Clone, Debug)]
+pub struct DigestConfig {
+    pub enabled: bool,
+    pub interval: Duration,
+    pub idle: IdleDigest,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(60),
+            idle: IdleDigest::Skip,
+        }
+    }
+}
+
+impl DigestConfig {
+    /// 从环境变量读取：`LOG_DIGEST_INTERVAL_SECS`（0 表示关闭）、`LOG_DIGEST_IDLE=marker`
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(secs) = std::env::var("LOG_DIGEST_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            config.enabled = secs > 0;
+            config.interval = Duration::from_secs(secs.max(1));
+        }
+        if std::env::var("LOG_DIGEST_IDLE").is_ok_and(|v| v.eq_ignore_ascii_case("marker")) {
+            config.idle = IdleDigest::Marker;
+        }
+        config
+    }
+}
+
+/// 根据 LogStats 的增量生成管线健康摘要
+pub struct Digest<R = LogEntry> {
+    pipeline: Pipeline<R>,
+    config: DigestConfig,
+    last: LogStatsSnapshot,
+}
+
+impl<R: LogRecord> Digest<R> {
+    pub fn new(pipeline: Pipeline<R>, config: DigestConfig) -> Self {
+        let last = pipeline.stats().snapshot();
+        Self {
+            pipeline,
+            config,
+            last,
+        }
+    }
+
+    /// 计算自上次以来的摘要条目，空闲且配置为 Skip 时返回 None
+    pub async fn next_entry(&mut self) -> Option<LogEntry> {
+        let now = self.pipeline.stats().snapshot();
+        let delta = now.delta(&self.last);
+        self.last = now;
+
+        let interval_secs = self.config.interval.as_secs();
+        let mut entry = LogEntry {
+            timestamp: self.pipeline.clock().now().to_rfc3339().into(),
+            level: "INFO".to_string(),
+            target: DIGEST_TARGET.to_string(),
+            ..Default::default()
+        };
+        if delta.is_idle() {
+            if self.config.idle == IdleDigest::Skip {
+                return None;
+            }
+            entry.message = "pipeline idle".to_string();
+            entry
+                .fields
+                .insert("interval_secs".into(), json!(interval_secs));
+            return Some(entry);
+        }
+
+        let cache = self.pipeline.cache().read().await.stats();
+        entry.message = "pipeline digest".to_string();
+        entry.fields = [
+            ("interval_secs", json!(interval_secs)),
+            ("events_error", json!(delta.events.error)),
+            ("events_warn", json!(delta.events.warn)),
+            ("events_info", json!(delta.events.info)),
+            ("events_debug", json!(delta.events.debug)),
+            ("events_trace", json!(delta.events.trace)),
+            ("dropped", json!(delta.dropped)),
+            ("write_errors", json!(delta.write_errors)),
+            ("bytes_written", json!(delta.bytes_written)),
+            ("skipped_persistence", json!(delta.skipped_persistence)),
+            ("skipped_broadcast", json!(delta.skipped_broadcast)),
+            ("cache_entries", json!(cache.entries)),
+            ("cache_bytes", json!(cache.bytes)),
+            ("non_numeric_fields", json!(delta.non_numeric_fields)),
+            ("verbose_windows", json!(delta.verbose_windows)),
+            ("verbose_entries", json!(delta.verbose_entries)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        // 分位数自启动以来累计，不是本周期的增量
+        let percentiles = self.pipeline.stats().all_field_percentiles();
+        if !percentiles.is_empty() {
+            entry
+                .fields
+                .insert("field_percentiles".into(), json!(percentiles));
+        }
+        Some(entry)
+    }
+}
+
+/// 启动周期摘要任务，配置关闭时返回 None
+pub fn spawn_digest<R: LogRecord>(
+    pipeline: Pipeline<R>,
+    config: DigestConfig,
+) -> Option<JoinHandle<()>> {
+    if !config.enabled {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        ticker.tick().await;
+        let mut digest = Digest::new(pipeline.clone(), config);
+        loop {
+            ticker.tick().await;
+            if let Some(entry) = digest.next_entry().await {
+                pipeline.ingest(R::from(entry));
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogCache;
+    use tokio::sync::broadcast;
+
+    fn entry(level: &str) -> LogEntry {
+        LogEntry {
+            level: level.to_string(),
+            target: "digest_test".to_string(),
+            message: "work".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_digest_deltas_and_idle() {
+        let (tx, _rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(None);
+        pipeline
+            .stats()
+            .track_field_percentiles(["latency_ms"], ["digest_test"]);
+        let mut digest = Digest::new(pipeline.clone(), DigestConfig::default());
+
+        for (level, latency) in [
+            ("INFO", json!(12)),
+            ("INFO", json!("n/a")),
+            ("ERROR", json!(40)),
+        ] {
+            let mut entry = entry(level);
+            entry.fields.insert("latency_ms".into(), latency);
+            pipeline.ingest(entry);
+        }
+        crate::broadcast::tests::wait_for_cache(pipeline.cache(), 3).await;
+
+        let summary = digest.next_entry().await.unwrap();
+        assert_eq!(summary.target, DIGEST_TARGET);
+        assert_eq!(summary.fields["events_info"], 2);
+        assert_eq!(summary.fields["events_error"], 1);
+        assert_eq!(summary.fields["cache_entries"], 3);
+        assert_eq!(summary.fields["non_numeric_fields"], 1);
+        let percentiles = &summary.fields["field_percentiles"];
+        assert_eq!(percentiles[0]["target"], "");
+        assert_eq!(percentiles[1]["target"], "digest_test");
+        assert_eq!(percentiles[1]["field"], "latency_ms");
+        assert_eq!(percentiles[1]["count"], 2);
+        assert_eq!(percentiles[1]["p99"], 40.0);
+        let latency = pipeline
+            .stats()
+            .field_percentiles("latency_ms", "digest_test")
+            .unwrap();
+        assert_eq!(latency.p50, 12.0);
+
+        // 摘要自身进入管线但不计入下一周期的活动
+        pipeline.ingest(summary);
+        assert!(digest.next_entry().await.is_none());
+
+        let config = DigestConfig {
+            idle: IdleDigest::Marker,
+            ..Default::default()
+        };
+        let mut digest = Digest::new(pipeline, config);
+        assert_eq!(digest.next_entry().await.unwrap().message, "pipeline idle");
+    }
+}
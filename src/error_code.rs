@@ -0,0 +1,255 @@
+//! 各错误类型的稳定错误码
+//!
+//! 每个错误类型提供 `code(&self) -> &'static str`，调用方可据此映射 HTTP 状态或告警分类，
+//! 无需匹配错误文本。错误码一经发布不再改变含义，新增变体使用新的编号；
+//! 包装其他错误的变体（`InitError::Setup`、`SetupError::Lock`、`FollowError::Parse`）返回内层错误的错误码。
+//! sink 输出的诊断也以 `[LT-SNK-001]` 的形式带上错误码。
+
+/// 一个错误码的说明
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorCodeInfo {
+    pub code: &'static str,
+    /// 产生该错误码的类型与变体
+    pub error: &'static str,
+    pub description: &'static str,
+}
+
+const fn info(code: &'static str, error: &'static str, description: &'static str) -> ErrorCodeInfo {
+    ErrorCodeInfo {
+        code,
+        error,
+        description,
+    }
+}
+
+/// 全部错误码
+pub const ERROR_CODES: &[ErrorCodeInfo] = &[
+    info(
+        "LT-SET-001",
+        "SetupError::ForeignSubscriber",
+        "global subscriber was installed outside listen-tracing",
+    ),
+    info(
+        "LT-SET-002",
+        "SetupError::Reload",
+        "the subscriber owning the reload slot was dropped",
+    ),
+    info(
+        "LT-SET-003",
+        "SetupError::Init",
+        "installing the global subscriber failed",
+    ),
+    info(
+        "LT-SET-004",
+        "LockError::Held",
+        "the log file is locked by another process",
+    ),
+    info("LT-CFG-001", "ConfigError", "invalid configuration option"),
+    info(
+        "LT-CFG-002",
+        "InitError::Invalid",
+        "TracingBuilder validation found invalid options",
+    ),
+    info("LT-CFG-003", "RouteParseError", "invalid route rule"),
+    info(
+        "LT-CFG-004",
+        "SavedQueryError::TooLarge",
+        "saved queries file would exceed its size limit",
+    ),
+    info(
+        "LT-CFG-005",
+        "PinError::LimitReached",
+        "too many cache entries are pinned",
+    ),
+    #[cfg(feature = "tz")]
+    info(
+        "LT-CFG-006",
+        "TimezoneError",
+        "unknown display timezone name",
+    ),
+    info("LT-QRY-001", "QueryParseError", "invalid query expression"),
+    info(
+        "LT-QRY-002",
+        "PinError::NotFound",
+        "the cache entry to pin has been evicted or does not exist",
+    ),
+    info(
+        "LT-IO-001",
+        "FollowError::Io",
+        "reading the log file failed",
+    ),
+    info(
+        "LT-IO-002",
+        "SavedQueryError::Io",
+        "reading or writing the saved queries file failed",
+    ),
+    info(
+        "LT-IO-003",
+        "FlushError::Timeout",
+        "log writer did not finish flushing in time and was aborted",
+    ),
+    info(
+        "LT-IO-004",
+        "LockError::Io",
+        "opening or locking the log file's lock file failed",
+    ),
+    info(
+        "LT-PRS-001",
+        "MigrateError::NotAnObject",
+        "log entry is not a JSON object",
+    ),
+    info(
+        "LT-PRS-002",
+        "MigrateError::InvalidSchema",
+        "log entry schema is not a non-negative integer",
+    ),
+    info(
+        "LT-PRS-003",
+        "MigrateError::UnsupportedSchema",
+        "log entry schema is newer than this version",
+    ),
+    info(
+        "LT-PRS-004",
+        "MigrateError::Json",
+        "log entry does not match the current structure",
+    ),
+    info(
+        "LT-PRS-005",
+        "SavedQueryError::Json",
+        "saved queries file is not valid JSON",
+    ),
+    info(
+        "LT-PRS-006",
+        "DecodeError",
+        "persisted log record could not be split or decoded",
+    ),
+    info(
+        "LT-SNK-001",
+        "SinkError::Send",
+        "sink failed to deliver entries",
+    ),
+    info(
+        "LT-SNK-002",
+        "SinkError::TooLarge",
+        "entry exceeds the sink's size limit and was dropped",
+    ),
+    info(
+        "LT-SNK-003",
+        "SinkError::Lagged",
+        "sink fell behind the broadcast channel and skipped entries",
+    ),
+];
+
+/// 查找错误码的说明
+pub fn describe(code: &str) -> Option<&'static ErrorCodeInfo> {
+    ERROR_CODES.iter().find(|info| info.code == code)
+}
+
+#[cfg(all(test, feature = "broadcast"))]
+mod tests {
+    use super::*;
+    use crate::file_lock::LockError;
+    use crate::follow::FollowError;
+    use crate::pipeline::FlushError;
+    use crate::query::QueryExpr;
+    use crate::routing::RouteRules;
+    use crate::saved_query::SavedQueryError;
+    use crate::sink::SinkError;
+    use crate::{ConfigError, InitError, LogEntry, PinError, SetupError};
+    use std::collections::BTreeSet;
+    use std::io;
+
+    #[test]
+    fn test_codes_unique_and_documented() {
+        let io_error = || io::Error::other("boom");
+        let migrate = || LogEntry::migrate(serde_json::json!([])).unwrap_err();
+        let reload = tracing_subscriber::reload::Layer::<
+            tracing_subscriber::filter::LevelFilter,
+            tracing_subscriber::Registry,
+        >::new(tracing_subscriber::filter::LevelFilter::INFO);
+        let handle = reload.1;
+        drop(reload.0);
+        let codes = [
+            SetupError::ForeignSubscriber.code(),
+            SetupError::Reload(handle.reload(tracing::Level::DEBUG).unwrap_err()).code(),
+            SetupError::Init("taken".to_string()).code(),
+            ConfigError::new("level", "loud", "unknown level").code(),
+            InitError::Invalid(Vec::new()).code(),
+            RouteRules::parse("???").unwrap_err().code(),
+            QueryExpr::parse("level >").unwrap_err().code(),
+            FollowError::Io(io_error()).code(),
+            migrate().code(),
+            LogEntry::migrate(serde_json::json!({"schema": "x"}))
+                .unwrap_err()
+                .code(),
+            LogEntry::migrate(serde_json::json!({"schema": 255}))
+                .unwrap_err()
+                .code(),
+            LogEntry::migrate(serde_json::json!({"schema": 1}))
+                .unwrap_err()
+                .code(),
+            SinkError::Send {
+                sink: "gelf",
+                operation: "send",
+                source: Box::new(io_error()),
+            }
+            .code(),
+            SinkError::TooLarge {
+                sink: "gelf",
+                bytes: 1 << 20,
+            }
+            .code(),
+            SinkError::Lagged {
+                sink: "sink",
+                skipped: 3,
+            }
+            .code(),
+            SavedQueryError::Io(io_error()).code(),
+            SavedQueryError::Json(serde_json::from_str::<u8>("x").unwrap_err()).code(),
+            SavedQueryError::TooLarge { bytes: 2, max: 1 }.code(),
+            PinError::NotFound { seq: 1 }.code(),
+            PinError::LimitReached { max: 100 }.code(),
+            FlushError::Timeout {
+                timeout: std::time::Duration::from_secs(1),
+                abandoned: 0,
+            }
+            .code(),
+            LockError::Held {
+                path: "logs.jsonl.lock".into(),
+                holder: None,
+            }
+            .code(),
+            LockError::Io(io_error()).code(),
+            crate::serializer::split_records(
+                &[0, 0, 0, 9],
+                crate::serializer::Framing::LengthPrefixed,
+            )
+            .unwrap_err()
+            .code(),
+            #[cfg(feature = "tz")]
+            crate::timezone::parse_timezone("Mars/Olympus")
+                .unwrap_err()
+                .code(),
+        ];
+        let unique: BTreeSet<&str> = codes.iter().copied().collect();
+        assert_eq!(unique.len(), codes.len(), "{:?}", codes);
+        let documented: BTreeSet<&str> = ERROR_CODES.iter().map(|info| info.code).collect();
+        assert_eq!(unique, documented);
+        assert!(ERROR_CODES
+            .iter()
+            .all(|info| info.code.starts_with("LT-") && !info.description.is_empty()));
+
+        // 包装变体沿用内层错误码
+        assert_eq!(
+            InitError::Setup(SetupError::ForeignSubscriber).code(),
+            "LT-SET-001"
+        );
+        let parse = FollowError::Parse {
+            line: "[]".to_string(),
+            error: migrate(),
+        };
+        assert_eq!(parse.code(), "LT-PRS-001");
+        assert_eq!(describe("LT-IO-001").unwrap().error, "FollowError::Io");
+        assert!(describe("LT-XXX-999").is_none());
+    }
+}
@@ -0,0 +1,387 @@
+//! 按字段取值提升日志级别
+//!
+//! 例如 INFO 日志中 `latency_ms` 超过 5000 时按 WARN 处理，无需修改各处调用点。
+//! 规则在字段捕获之后、广播/缓存/持久化之前生效，被提升的条目在 `fields` 中
+//! 以 `original_level` 记录原级别。
+//!
+//! 规则文本每行一条，`#` 开头为注释：
+//!
+//! ```text
+//! escalate "*" latency_ms gt 5000 => WARN
+//! escalate "payments::*" status eq "declined" => ERROR
+//! ```
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use tracing::Level;
+
+use crate::routing::target_matches;
+use crate::{ConfigError, LogEntry};
+
+/// 被提升的条目记录原级别的字段名
+pub const ORIGINAL_LEVEL_FIELD: &str = "original_level";
+
+/// 字段取值的判断条件
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "op", content = "value", rename_all = "snake_case")]
+pub enum Predicate {
+    /// 数值大于
+    Gt(f64),
+    /// 数值小于
+    Lt(f64),
+    /// 去掉 Debug 引号后与字符串相等
+    Eq(String),
+    /// 去掉 Debug 引号后包含子串
+    Contains(String),
+}
+
+impl Predicate {
+    /// 由运算符名（`gt` / `lt` / `eq` / `contains`）和比较值构造，`gt` / `lt` 要求比较值为数字
+    pub fn parse(op: &str, value: &str) -> Result<Self, String> {
+        let number = || {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("`{}` needs a numeric value, got {:?}", op, value))
+        };
+        match op {
+            "gt" => number().map(Predicate::Gt),
+            "lt" => number().map(Predicate::Lt),
+            "eq" => Ok(Predicate::Eq(value.to_string())),
+            "contains" => Ok(Predicate::Contains(value.to_string())),
+            _ => Err(format!(
+                "unknown predicate `{}` (expected gt, lt, eq or contains)",
+                op
+            )),
+        }
+    }
+
+    /// 字段值按 Debug 捕获时可能带引号；数值比较时非数字的值视为不匹配
+    pub fn test(&self, value: &Value) -> bool {
+        let text = match value {
+            Value::String(s) => s
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(s),
+            other => return self.test_text(&other.to_string()),
+        };
+        self.test_text(text)
+    }
+
+    fn test_text(&self, text: &str) -> bool {
+        match self {
+            Predicate::Gt(n) => text.trim().parse::<f64>().is_ok_and(|v| v > *n),
+            Predicate::Lt(n) => text.trim().parse::<f64>().is_ok_and(|v| v < *n),
+            Predicate::Eq(s) => text == s,
+            Predicate::Contains(s) => text.contains(s.as_str()),
+        }
+    }
+}
+
+/// 一条提升规则：target 匹配 `pattern`（通配语法同 `RouteRule`）且字段 `field` 满足 `predicate` 时，
+/// 级别提升为 `level`；只会提升，不会降低
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct EscalationRule {
+    pub pattern: String,
+    pub field: String,
+    #[serde(flatten)]
+    pub predicate: Predicate,
+    #[serde(serialize_with = "serialize_level")]
+    pub level: Level,
+}
+
+pub(crate) fn serialize_level<S: Serializer>(
+    level: &Level,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(level.as_str())
+}
+
+impl EscalationRule {
+    /// 由字符串形式的各部分构造并校验，错误信息说明哪一部分无效
+    pub fn new(
+        pattern: &str,
+        field: &str,
+        op: &str,
+        value: &str,
+        level: &str,
+    ) -> Result<Self, String> {
+        if field.is_empty() {
+            return Err("empty field name".to_string());
+        }
+        let predicate = Predicate::parse(op, value)?;
+        let level = level
+            .parse::<Level>()
+            .map_err(|_| format!("unknown level `{}`", level))?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            field: field.to_string(),
+            predicate,
+            level,
+        })
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        target_matches(&self.pattern, &entry.target)
+            && entry
+                .fields
+                .get(&self.field)
+                .is_some_and(|value| self.predicate.test(value))
+    }
+}
+
+/// 单条规则及其命中次数
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EscalationRuleStats {
+    #[serde(flatten)]
+    pub rule: EscalationRule,
+    pub matched: u64,
+}
+
+/// 提升规则表；多条规则同时匹配时取其中最严重的级别
+#[derive(Debug, Default)]
+pub struct EscalationRules {
+    rules: Vec<(EscalationRule, AtomicU64)>,
+}
+
+impl EscalationRules {
+    pub fn new(rules: impl IntoIterator<Item = EscalationRule>) -> Self {
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| (rule, AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// 解析规则文本，语法见模块文档；出错时 `ConfigError::option` 为 `escalation:<行号>`
+    pub fn parse(input: &str) -> Result<Self, ConfigError> {
+        let mut rules = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error =
+                |reason: String| ConfigError::new(format!("escalation:{}", i + 1), line, reason);
+            let words = split_words(line).map_err(error)?;
+            let rule = match words.as_slice() {
+                [keyword, pattern, field, op, value, arrow, level]
+                    if keyword == "escalate" && arrow == "=>" =>
+                {
+                    EscalationRule::new(pattern, field, op, value, level).map_err(error)?
+                }
+                _ => {
+                    return Err(error(
+                        "expected `escalate \"<target>\" <field> <op> <value> => <LEVEL>`"
+                            .to_string(),
+                    ))
+                }
+            };
+            rules.push(rule);
+        }
+        Ok(Self::new(rules))
+    }
+
+    /// 读取并解析规则文件
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::new("escalation_file", path.display().to_string(), e))?;
+        Self::parse(&input)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &EscalationRule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// 对条目应用全部规则，计入每条匹配规则的命中次数；级别被提升时返回 true
+    pub fn apply(&self, entry: &mut LogEntry) -> bool {
+        let Some(current) = entry.level_value() else {
+            return false;
+        };
+        let mut escalated: Option<Level> = None;
+        for (rule, matched) in &self.rules {
+            if !rule.matches(entry) {
+                continue;
+            }
+            matched.fetch_add(1, Ordering::Relaxed);
+            // tracing 中越严重的级别越小
+            if rule.level < escalated.unwrap_or(current) {
+                escalated = Some(rule.level);
+            }
+        }
+        let Some(level) = escalated else {
+            return false;
+        };
+        let original = std::mem::replace(&mut entry.level, level.as_str().to_string());
+        entry
+            .fields
+            .insert(ORIGINAL_LEVEL_FIELD.to_string(), Value::String(original));
+        true
+    }
+
+    /// 各规则及命中次数，按书写顺序排列
+    pub fn stats(&self) -> Vec<EscalationRuleStats> {
+        self.rules
+            .iter()
+            .map(|(rule, matched)| EscalationRuleStats {
+                rule: rule.clone(),
+                matched: matched.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// 按空白切分，双引号内的空白保留
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| "unterminated string".to_string())?;
+            words.push(quoted[..end].to_string());
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            words.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(target: &str, level: &str, fields: &[(&str, &str)]) -> LogEntry {
+        LogEntry {
+            level: level.to_string(),
+            target: target.to_string(),
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_most_severe_match_wins() {
+        let rules = EscalationRules::parse(
+            r#"
+            # 慢请求
+            escalate "*" latency_ms gt 5000 => WARN
+            escalate "payments::*" latency_ms gt 10000 => ERROR
+            escalate "payments::*" status eq "card declined" => WARN
+            escalate "*" note contains debug => DEBUG
+            "#,
+        )
+        .unwrap();
+
+        let mut slow = entry("payments::card", "INFO", &[("latency_ms", "12000")]);
+        assert!(rules.apply(&mut slow));
+        assert_eq!(slow.level, "ERROR");
+        assert_eq!(slow.fields[ORIGINAL_LEVEL_FIELD], "INFO");
+
+        let mut declined = entry(
+            "payments::card",
+            "INFO",
+            &[("latency_ms", "6000"), ("status", "\"card declined\"")],
+        );
+        assert!(rules.apply(&mut declined));
+        assert_eq!(declined.level, "WARN");
+
+        // 已经是 ERROR 的条目不会被降级
+        let mut error = entry("feed", "ERROR", &[("latency_ms", "9000")]);
+        assert!(!rules.apply(&mut error));
+        assert_eq!(error.level, "ERROR");
+        assert!(!error.fields.contains_key(ORIGINAL_LEVEL_FIELD));
+
+        let mut fast = entry("feed", "INFO", &[("latency_ms", "12"), ("note", "debug")]);
+        assert!(!rules.apply(&mut fast));
+        let mut text = entry("feed", "INFO", &[("latency_ms", "n/a")]);
+        assert!(!rules.apply(&mut text));
+
+        let matched: Vec<u64> = rules.stats().iter().map(|s| s.matched).collect();
+        assert_eq!(matched, [3, 1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_layer_escalates_before_fan_out() {
+        skip_if_events_compiled_out!();
+        use crate::{BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_escalations(
+                EscalationRules::parse("escalate \"*\" latency_ms gt 5000 => WARN").unwrap(),
+            );
+        let rules = layer.escalations();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::info!(latency_ms = 5200, "slow request");
+        tracing::info!(latency_ms = 80, "fast request");
+
+        let broadcast = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no broadcast entry")
+            .unwrap();
+        assert_eq!(broadcast.level, "WARN");
+        assert_eq!(broadcast.fields[ORIGINAL_LEVEL_FIELD], "INFO");
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        assert_eq!(logs[0].level, "WARN");
+        assert_eq!(logs[1].level, "INFO");
+        assert_eq!(rules.stats()[0].matched, 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (input, option, reason) in [
+            (
+                "escalate \"*\" latency_ms above 5000 => WARN",
+                "escalation:1",
+                "unknown predicate `above` (expected gt, lt, eq or contains)",
+            ),
+            (
+                "\nescalate \"*\" latency_ms gt slow => WARN",
+                "escalation:2",
+                "`gt` needs a numeric value, got \"slow\"",
+            ),
+            (
+                "escalate \"*\" latency_ms gt 1 => LOUD",
+                "escalation:1",
+                "unknown level `LOUD`",
+            ),
+            (
+                "escalate \"*\" latency_ms gt 1 WARN",
+                "escalation:1",
+                "expected `escalate \"<target>\" <field> <op> <value> => <LEVEL>`",
+            ),
+            (
+                "escalate \"* latency_ms gt 1 => WARN",
+                "escalation:1",
+                "unterminated string",
+            ),
+        ] {
+            let err = EscalationRules::parse(input).err().unwrap();
+            assert_eq!((err.option.as_str(), err.reason.as_str()), (option, reason));
+        }
+        assert!(EscalationRules::parse("# none").unwrap().is_empty());
+    }
+}
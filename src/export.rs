@@ -0,0 +1,62 @@
+use std::io;
+use std::path::Path;
+
+use crate::store::LogStore;
+use crate::LogCache;
+
+/// 把当前缓存整体导出为一个 JSON 数组（非 JSONL），用于附在问题报告里
+pub async fn export_cache_json(cache: &LogCache, pretty: bool) -> String {
+    let entries = cache.snapshot().await;
+    let json = if pretty {
+        serde_json::to_string_pretty(&entries)
+    } else {
+        serde_json::to_string(&entries)
+    };
+    json.unwrap_or_else(|_| "[]".to_string())
+}
+
+/// 导出缓存快照并写入文件，已存在的文件会被覆盖
+pub async fn export_cache_to_file(
+    cache: &LogCache,
+    path: impl AsRef<Path>,
+    pretty: bool,
+) -> io::Result<()> {
+    let json = export_cache_json(cache, pretty).await;
+    tokio::fs::write(path, json).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogEntry;
+
+    #[tokio::test]
+    async fn test_export_round_trip() {
+        let cache = LogCache::default();
+        for (level, message) in [("INFO", "started"), ("ERROR", "failed")] {
+            cache
+                .append(LogEntry {
+                    timestamp: "2024-06-01T00:00:00+00:00".into(),
+                    level: level.to_string(),
+                    target: "export_test".to_string(),
+                    message: message.to_string(),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        let pretty = export_cache_json(&cache, true).await;
+        assert!(pretty.starts_with("[\n"));
+        let decoded: Vec<LogEntry> = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(decoded, cache.snapshot().await);
+
+        let path =
+            std::env::temp_dir().join(format!("listen_tracing_export_{}.json", std::process::id()));
+        export_cache_to_file(&cache, &path, false).await.unwrap();
+        let decoded: Vec<LogEntry> =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].message, "failed");
+    }
+}
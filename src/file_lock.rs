@@ -0,0 +1,264 @@
+//! 持久化文件的独占锁，防止两个进程交错写同一个文件
+//!
+//! 默认不取锁。以 `BroadcastLogLayer::with_file_lock` 开启后，安装 Layer 时（`setup_tracing_with_layer`、
+//! `TracingBuilder::init` 等，或手动调用 `BroadcastLogLayer::lock_persist_path`）对旁路文件
+//! `<path>.lock` 取建议锁：Unix 上为 `flock`，Windows 上为 `LockFileEx`，均经由 `File::try_lock`。
+//! 取得锁后写入持有者的 pid 与实例 ID，冲突时可据此报告是谁占用了文件。
+//!
+//! - 锁随文件句柄存在，进程崩溃时由操作系统释放；残留的 `.lock` 文件不影响下次启动，内容会被覆盖
+//! - `LogWriterGuard::flush_and_close` / `shutdown` 完成后主动释放；`.lock` 文件本身保留，
+//!   避免删除与另一进程打开之间的竞争
+//! - 锁已被持有时按 `LockConflict` 处理，同一进程内再次对同一路径取锁同样视为冲突
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 持久化文件 `path` 对应的锁文件路径：`<path>.lock`
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// 冲突时改用的路径：在扩展名前插入 pid，`logs.jsonl` -> `logs.<pid>.jsonl`
+pub fn fallback_path(path: &Path, pid: u32) -> PathBuf {
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => {
+            let mut name = OsString::from(stem);
+            name.push(format!(".{}.", pid));
+            name.push(ext);
+            path.with_file_name(name)
+        }
+        _ => {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!(".{}", pid));
+            PathBuf::from(name)
+        }
+    }
+}
+
+/// 本进程的实例 ID：pid 加启动时刻的 epoch 纳秒，重启或 pid 复用后不会重复
+pub fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    INSTANCE_ID.get_or_init(|| {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        format!("{:x}-{:x}", std::process::id(), nanos)
+    })
+}
+
+/// 持久化文件已被锁定时的处理方式
+#[cfg_attr(feature = "broadcast", derive(serde::Serialize))]
+#[cfg_attr(feature = "broadcast", serde(rename_all = "snake_case"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockConflict {
+    /// 安装失败，返回 `SetupError::Lock`
+    #[default]
+    Fail,
+    /// 改写到 `fallback_path` 给出的带 pid 后缀的路径，并记录一条 WARN 诊断
+    Fallback,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LockError {
+    /// 锁已被其他进程（或本进程的另一个 Layer）持有；`holder` 为锁文件中记录的持有者
+    Held {
+        path: PathBuf,
+        holder: Option<String>,
+    },
+    Io(io::Error),
+}
+
+impl LockError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        match self {
+            LockError::Held { .. } => "LT-SET-004",
+            LockError::Io(_) => "LT-IO-004",
+        }
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Held { path, holder } => {
+                write!(
+                    f,
+                    "log file is locked by another process ({}",
+                    path.display()
+                )?;
+                if let Some(holder) = holder {
+                    write!(f, ", held by {}", holder)?;
+                }
+                write!(f, ")")
+            }
+            LockError::Io(e) => write!(f, "failed to lock log file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<io::Error> for LockError {
+    fn from(e: io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+/// 因冲突改用了带后缀的路径
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockFallback {
+    pub original: PathBuf,
+    pub fallback: PathBuf,
+    /// 原路径锁文件中记录的持有者
+    pub holder: Option<String>,
+}
+
+/// 已取得的锁，drop 时释放
+#[derive(Debug)]
+pub struct PersistLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl PersistLock {
+    /// 对持久化文件 `data_path` 的锁文件取锁，不等待
+    pub fn acquire(data_path: &Path) -> Result<Self, LockError> {
+        let path = lock_path(data_path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                // Windows 上被锁定的区域不可读，此时不报告持有者
+                let mut holder = String::new();
+                let holder = file
+                    .read_to_string(&mut holder)
+                    .ok()
+                    .map(|_| holder.trim().to_string())
+                    .filter(|holder| !holder.is_empty());
+                return Err(LockError::Held { path, holder });
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        // 覆盖崩溃进程留下的旧内容
+        file.set_len(0)?;
+        writeln!(
+            file,
+            "pid={} instance={}",
+            std::process::id(),
+            instance_id()
+        )?;
+        Ok(Self { path, _file: file })
+    }
+
+    /// 锁文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(all(test, feature = "broadcast"))]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogCache};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "listen-tracing-lock-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(lock_path(&path));
+        path
+    }
+
+    #[test]
+    fn test_acquire_conflict_and_stale_lock() {
+        let path = temp_path("acquire");
+        let first = PersistLock::acquire(&path).unwrap();
+        assert_eq!(first.path(), lock_path(&path));
+        let err = PersistLock::acquire(&path).unwrap_err();
+        assert_eq!(err.code(), "LT-SET-004");
+        let LockError::Held { holder, .. } = &err else {
+            panic!("expected a held lock, got {:?}", err);
+        };
+        #[cfg(unix)]
+        assert_eq!(
+            holder.as_deref(),
+            Some(format!("pid={} instance={}", std::process::id(), instance_id()).as_str())
+        );
+        #[cfg(not(unix))]
+        let _ = holder;
+
+        // 残留的锁文件（如进程崩溃后）不妨碍再次取锁
+        drop(first);
+        std::fs::write(lock_path(&path), "pid=1 instance=stale\n").unwrap();
+        let again = PersistLock::acquire(&path).unwrap();
+        let content = std::fs::read_to_string(lock_path(&path)).unwrap();
+        assert!(content.ends_with(&format!("instance={}\n", instance_id())));
+        drop(again);
+        let _ = std::fs::remove_file(lock_path(&path));
+
+        assert_eq!(
+            fallback_path(Path::new("/var/log/logs.jsonl"), 42),
+            Path::new("/var/log/logs.42.jsonl")
+        );
+        assert_eq!(fallback_path(Path::new("logs"), 42), Path::new("logs.42"));
+    }
+
+    #[tokio::test]
+    async fn test_layer_fail_and_fallback() {
+        let path = temp_path("layer");
+        let layer = |conflict| {
+            let (tx, _rx) = tokio::sync::broadcast::channel(16);
+            BroadcastLogLayer::new(tx, LogCache::default())
+                .with_persist_path(&path)
+                .with_file_lock(Some(conflict))
+        };
+        let mut owner = layer(LockConflict::Fail);
+        assert_eq!(owner.lock_persist_path().unwrap(), None);
+        // 重复调用不会与自己冲突
+        assert_eq!(owner.lock_persist_path().unwrap(), None);
+
+        let err = layer(LockConflict::Fail).lock_persist_path().unwrap_err();
+        assert_eq!(err.code(), "LT-SET-004");
+        assert_eq!(
+            crate::SetupError::Lock(err).code(),
+            "LT-SET-004",
+            "SetupError::Lock reports the inner code"
+        );
+
+        let mut fallback = layer(LockConflict::Fallback);
+        let moved = fallback.lock_persist_path().unwrap().unwrap();
+        let expected = fallback_path(&path, std::process::id());
+        assert_eq!(moved.original, path);
+        assert_eq!(moved.fallback, expected);
+        assert_eq!(fallback.pipeline().persist_path(), Some(expected.as_path()));
+        assert_eq!(fallback.active_log_paths(), [expected.as_path()]);
+
+        // 默认不取锁，照旧追加
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let mut unlocked = BroadcastLogLayer::new(tx, LogCache::default()).with_persist_path(&path);
+        assert_eq!(unlocked.lock_persist_path().unwrap(), None);
+
+        // 正常关闭后释放
+        owner.pipeline().writer_guard().flush_and_close().await;
+        assert_eq!(layer(LockConflict::Fail).lock_persist_path().unwrap(), None);
+        drop(fallback);
+        let _ = std::fs::remove_file(lock_path(&path));
+        let _ = std::fs::remove_file(lock_path(&expected));
+    }
+}
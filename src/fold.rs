@@ -0,0 +1,269 @@
+//! 外部输入的多行消息折叠
+//!
+//! 经 UDS / TCP 接入的外部程序输出里，堆栈等多行内容按行到达；逐行生成 `LogEntry`
+//! 会把一段堆栈拆散。`LineFolder` 按连接把续行追加到上一条的 message 中，
+//! 由接入端在每读到一行时调用，只用于接入路径，tracing 事件不经过这里。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{rfc3339_now, FieldLimits, LogEntry};
+
+/// 接入端生成的纯文本条目默认使用的 target
+pub const INGEST_TARGET: &str = "listen_tracing::ingest";
+
+const PYTHON_TRACEBACK: &str = "Traceback (most recent call last):";
+
+/// 自定义续行判断，参数为（已折叠的 message, 新行）
+pub type ContinuationFn = dyn Fn(&str, &str) -> bool + Send + Sync;
+
+/// 判断一行是否为上一条的续行
+#[derive(Clone, Default)]
+pub enum Continuation {
+    /// 以空白开头的行
+    Indented,
+    /// 在 `Indented` 之外，识别 Java 的 `Caused by:` 以及 Python traceback 末尾的异常行
+    #[default]
+    Traceback,
+    /// 自定义判断，可在其中使用正则
+    Custom(Arc<ContinuationFn>),
+}
+
+impl fmt::Debug for Continuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Continuation::Indented => write!(f, "Indented"),
+            Continuation::Traceback => write!(f, "Traceback"),
+            Continuation::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+impl Continuation {
+    pub fn is_continuation(&self, message: &str, line: &str) -> bool {
+        let indented = line.starts_with(char::is_whitespace);
+        match self {
+            Continuation::Indented => indented,
+            Continuation::Traceback => {
+                indented
+                    || line.starts_with("Caused by:")
+                    // Python 的 traceback 以一行不缩进的 `ValueError: ...` 结束
+                    || (message.starts_with(PYTHON_TRACEBACK)
+                        && message
+                            .lines()
+                            .last()
+                            .is_some_and(|last| last.starts_with(char::is_whitespace)))
+            }
+            Continuation::Custom(detect) => detect(message, line),
+        }
+    }
+}
+
+/// 折叠配置
+#[derive(Clone, Debug)]
+pub struct FoldConfig {
+    pub continuation: Continuation,
+    /// 折叠后 message 的字节上限，再追加会超出时结束当前条目，该行另起一条
+    pub max_bytes: usize,
+    /// 一条消息从首行起最多等待续行的时长，超时后由 `flush_expired` 输出
+    pub max_wait: Duration,
+    /// 纯文本行生成条目时使用的 target
+    pub target: String,
+    /// JSON 条目携带的字段上限
+    pub field_limits: FieldLimits,
+}
+
+impl Default for FoldConfig {
+    fn default() -> Self {
+        Self {
+            continuation: Continuation::default(),
+            max_bytes: 64 * 1024,
+            max_wait: Duration::from_millis(500),
+            target: INGEST_TARGET.to_string(),
+            field_limits: FieldLimits::default(),
+        }
+    }
+}
+
+struct Pending {
+    entry: LogEntry,
+    started: Instant,
+}
+
+/// 按连接折叠多行消息；不同连接的行互不影响
+#[derive(Default)]
+pub struct LineFolder {
+    config: FoldConfig,
+    pending: HashMap<u64, Pending>,
+}
+
+impl LineFolder {
+    pub fn new(config: FoldConfig) -> Self {
+        Self {
+            config,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// 接入连接 `conn` 的一行，返回因此结束的上一条（若有）
+    ///
+    /// 整行是 JSON 格式的 `LogEntry`（任意历史 schema）时总是另起一条；
+    /// 其余按纯文本处理，续行以换行符追加到上一条的 message。
+    pub fn push(&mut self, conn: u64, line: &str, now: Instant) -> Option<LogEntry> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let structured = parse_entry(line).map(|mut entry| {
+            self.config.field_limits.apply(&mut entry.fields);
+            entry
+        });
+        if structured.is_none() {
+            if let Some(pending) = self.pending.get_mut(&conn) {
+                let message = &mut pending.entry.message;
+                if now.duration_since(pending.started) <= self.config.max_wait
+                    && message.len() + 1 + line.len() <= self.config.max_bytes
+                    && self.config.continuation.is_continuation(message, line)
+                {
+                    message.push('\n');
+                    message.push_str(line);
+                    return None;
+                }
+            }
+        }
+        let entry = structured.unwrap_or_else(|| LogEntry {
+            timestamp: rfc3339_now().into(),
+            level: "INFO".to_string(),
+            target: self.config.target.clone(),
+            message: line.to_string(),
+            ..Default::default()
+        });
+        self.pending
+            .insert(
+                conn,
+                Pending {
+                    entry,
+                    started: now,
+                },
+            )
+            .map(|pending| pending.entry)
+    }
+
+    /// 输出等待续行超过 `max_wait` 的条目，接入端应定期调用
+    pub fn flush_expired(&mut self, now: Instant) -> Vec<LogEntry> {
+        let max_wait = self.config.max_wait;
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.started) > max_wait)
+            .map(|(conn, _)| *conn)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|conn| self.close(conn))
+            .collect()
+    }
+
+    /// 连接断开时输出其未完成的条目
+    pub fn close(&mut self, conn: u64) -> Option<LogEntry> {
+        self.pending.remove(&conn).map(|pending| pending.entry)
+    }
+}
+
+fn parse_entry(line: &str) -> Option<LogEntry> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let value = serde_json::from_str(line).ok()?;
+    LogEntry::migrate(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(folder: &mut LineFolder, conn: u64, input: &str, now: Instant) -> Vec<LogEntry> {
+        input
+            .lines()
+            .filter_map(|line| folder.push(conn, line, now))
+            .collect()
+    }
+
+    #[test]
+    fn test_java_and_python_tracebacks() {
+        let mut folder = LineFolder::default();
+        let now = Instant::now();
+        let java = "Exception in thread \"main\" java.lang.IllegalStateException: boom\n\
+                    \tat com.example.Order.place(Order.java:42)\n\
+                    \tat com.example.Main.main(Main.java:7)\n\
+                    Caused by: java.io.IOException: disk full\n\
+                    \t... 2 more";
+        let python = "Traceback (most recent call last):\n  \
+                      File \"app.py\", line 3, in <module>\n    \
+                      main()\n\
+                      ValueError: bad input";
+        let input = format!("{}\n{}\nstarted worker\nready\n", java, python);
+
+        let mut entries = feed(&mut folder, 1, &input, now);
+        entries.extend(folder.close(1));
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, [java, python, "started worker", "ready"]);
+        assert_eq!(entries[0].target, INGEST_TARGET);
+    }
+
+    #[test]
+    fn test_interleaved_connections() {
+        let mut folder = LineFolder::default();
+        let now = Instant::now();
+        let json = r#"{"timestamp":"2024-06-01T00:00:00Z","level":"WARN","target":"svc","message":"structured"}"#;
+        let mut out = Vec::new();
+        for (conn, line) in [
+            (1, "request failed"),
+            (2, "other service"),
+            (1, "  at handler (server.js:10:5)"),
+            (2, json),
+            (1, "  at process (node:internal:1:1)"),
+            (2, "  continues the JSON entry"),
+            (1, "next"),
+        ] {
+            out.extend(folder.push(conn, line, now));
+        }
+        out.extend(folder.close(1));
+        out.extend(folder.close(2));
+        let messages: Vec<&str> = out.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "other service",
+                "request failed\n  at handler (server.js:10:5)\n  at process (node:internal:1:1)",
+                "next",
+                "structured\n  continues the JSON entry",
+            ]
+        );
+        assert_eq!(out[3].level, "WARN");
+    }
+
+    #[test]
+    fn test_size_and_time_limits() {
+        let mut folder = LineFolder::new(FoldConfig {
+            continuation: Continuation::Indented,
+            max_bytes: 20,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(folder.push(1, "head", now).is_none());
+        assert!(folder.push(1, "  line one", now).is_none());
+        // 追加后会超过 20 字节，另起一条
+        let done = folder.push(1, "  line two", now).unwrap();
+        assert_eq!(done.message, "head\n  line one");
+
+        assert!(folder.flush_expired(now).is_empty());
+        let later = now + Duration::from_secs(1);
+        let flushed = folder.flush_expired(later);
+        assert_eq!(flushed[0].message, "  line two");
+        assert!(folder.close(1).is_none());
+
+        assert!(folder.push(2, "head", now).is_none());
+        // 超过 max_wait 的续行不再并入
+        assert_eq!(folder.push(2, "  late", later).unwrap().message, "head");
+    }
+}
@@ -0,0 +1,299 @@
+//! 跟随读取 JSONL 持久化文件（类似 `tail -f`），供独立进程消费 `logs.jsonl`
+//!
+//! 通过轮询文件元数据发现追加内容；文件被截断（长度变小）时从头重读，
+//! 被轮转（路径指向新的 inode）时先读完旧文件剩余内容，再从新文件开头读起。
+//! 未以换行结尾的最后一行会暂存，等写完整后再解析。
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{LogEntry, MigrateError};
+
+/// 默认轮询间隔
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FollowError {
+    Io(io::Error),
+    /// 某一行不是合法的 LogEntry JSON，跳过该行后可继续读取
+    Parse {
+        line: String,
+        error: MigrateError,
+    },
+}
+
+impl fmt::Display for FollowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FollowError::Io(e) => write!(f, "failed to read log file: {}", e),
+            FollowError::Parse { line, error } => {
+                write!(f, "invalid log line {:?}: {}", line, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FollowError {}
+
+impl FollowError {
+    /// 稳定的错误码；`Parse` 返回内层 `MigrateError` 的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            FollowError::Io(_) => "LT-IO-001",
+            FollowError::Parse { error, .. } => error.code(),
+        }
+    }
+}
+
+impl From<io::Error> for FollowError {
+    fn from(e: io::Error) -> Self {
+        FollowError::Io(e)
+    }
+}
+
+/// 从文件末尾开始跟随，只返回之后追加的日志
+pub fn follow_log_file(path: &Path) -> LogFollower {
+    LogFollower::new(path, None)
+}
+
+/// 跟随状态，通过 `next().await` 逐条取出日志
+pub struct LogFollower {
+    path: PathBuf,
+    start: Option<u64>,
+    file: Option<File>,
+    identity: Option<u64>,
+    pos: u64,
+    partial: Vec<u8>,
+    pending: VecDeque<Result<LogEntry, FollowError>>,
+    poll_interval: Duration,
+}
+
+/// 用于识别轮转的文件标识，非 unix 平台只能依赖长度变化判断
+#[cfg(unix)]
+fn identity(meta: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.ino())
+}
+
+#[cfg(not(unix))]
+fn identity(_meta: &Metadata) -> Option<u64> {
+    None
+}
+
+impl LogFollower {
+    /// `start` 为 None 时从当前文件末尾开始，否则从给定字节偏移开始（0 表示读全部历史）
+    pub fn new(path: &Path, start: Option<u64>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            start,
+            file: None,
+            identity: None,
+            pos: 0,
+            partial: Vec::new(),
+            pending: VecDeque::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// 已消费到的字节偏移，可保存下来供下次 `LogFollower::new(path, Some(offset))` 续读
+    pub fn offset(&self) -> u64 {
+        self.pos - self.partial.len() as u64
+    }
+
+    /// 等待下一条日志；文件暂不存在时持续等待其出现
+    pub async fn next(&mut self) -> Result<LogEntry, FollowError> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return item;
+            }
+            if let Err(e) = self.poll() {
+                return Err(e.into());
+            }
+            if self.pending.is_empty() {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    /// 检查一次文件状态，把新出现的完整行放入 pending
+    fn poll(&mut self) -> io::Result<()> {
+        let meta = match std::fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            // 轮转过程中路径可能短暂不存在
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if self.file.is_none() {
+            self.open(&meta)?;
+        } else if identity(&meta) != self.identity {
+            self.read_available()?;
+            self.flush_partial();
+            self.start = Some(0);
+            self.open(&meta)?;
+        } else if meta.len() < self.pos {
+            // 只截掉了尚未读完的半行（写入方修复崩溃残留）时从该行行首续读，否则视为整体截断
+            self.pos = if meta.len() >= self.offset() {
+                self.offset()
+            } else {
+                0
+            };
+            self.partial.clear();
+        }
+        self.read_available()
+    }
+
+    fn open(&mut self, meta: &Metadata) -> io::Result<()> {
+        let file = File::open(&self.path)?;
+        self.pos = match self.start.take() {
+            Some(offset) => offset.min(meta.len()),
+            None => meta.len(),
+        };
+        self.identity = identity(meta);
+        self.partial.clear();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn read_available(&mut self) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.seek(SeekFrom::Start(self.pos))?;
+        let read = file.read_to_end(&mut self.partial)?;
+        self.pos += read as u64;
+
+        let Some(last_newline) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Ok(());
+        };
+        let rest = self.partial.split_off(last_newline + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        for line in complete.split(|&b| b == b'\n') {
+            self.push_line(line);
+        }
+        Ok(())
+    }
+
+    /// 旧文件轮转走后，最后一行即使没有换行也不会再被补全
+    fn flush_partial(&mut self) {
+        let line = std::mem::take(&mut self.partial);
+        self.push_line(&line);
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.pending.push_back(
+            serde_json::from_str(line)
+                .map_err(MigrateError::from)
+                .and_then(LogEntry::migrate)
+                .map_err(|error| FollowError::Parse {
+                    line: line.to_string(),
+                    error,
+                }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn line(message: &str) -> String {
+        let entry = LogEntry {
+            level: "INFO".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        };
+        format!("{}\n", serde_json::to_string(&entry).unwrap())
+    }
+
+    fn append(path: &Path, data: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    async fn next_message(follower: &mut LogFollower) -> String {
+        tokio::time::timeout(Duration::from_secs(2), follower.next())
+            .await
+            .expect("timed out waiting for a line")
+            .unwrap()
+            .message
+    }
+
+    #[tokio::test]
+    async fn test_follow_appends_truncation_and_rotation() {
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_follow_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        append(&path, &line("history"));
+
+        let mut follower = follow_log_file(&path).with_poll_interval(Duration::from_millis(5));
+        follower.poll().unwrap();
+
+        // 半行暂存，写完整后才返回
+        let partial = line("third");
+        let (head, tail) = partial.split_at(10);
+        append(
+            &path,
+            &format!("{}{}{}", line("first"), line("second"), head),
+        );
+        assert_eq!(next_message(&mut follower).await, "first");
+        assert_eq!(next_message(&mut follower).await, "second");
+        follower.poll().unwrap();
+        assert!(follower.pending.is_empty());
+        append(&path, tail);
+        assert_eq!(next_message(&mut follower).await, "third");
+
+        append(&path, "not json\n");
+        assert!(matches!(
+            follower.next().await,
+            Err(FollowError::Parse { .. })
+        ));
+
+        // 写入方修复崩溃留下的半行后，从该行行首续读而不是从头重读
+        append(&path, &line("crashed")[..12]);
+        follower.poll().unwrap();
+        assert!(follower.pending.is_empty());
+        crate::pipeline::repair_partial_line(&path).unwrap();
+        append(&path, &line("after repair"));
+        assert_eq!(next_message(&mut follower).await, "after repair");
+        let _ = std::fs::remove_file(crate::pipeline::partial_path(&path));
+
+        // 截断后从头读
+        std::fs::write(&path, line("after truncate")).unwrap();
+        assert_eq!(next_message(&mut follower).await, "after truncate");
+
+        // 轮转：先读完旧文件，再从新文件开头读
+        let rotated = path.with_extension("jsonl.1");
+        append(&path, &line("last in old"));
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&path, &line("first in new"));
+        assert_eq!(next_message(&mut follower).await, "last in old");
+        assert_eq!(next_message(&mut follower).await, "first in new");
+        assert_eq!(follower.offset(), line("first in new").len() as u64);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&rotated);
+    }
+}
@@ -0,0 +1,286 @@
+//! GELF over UDP 输出（需开启 `gelf` feature），对接 Graylog
+//!
+//! 超过分片大小的数据报按 GELF 分片协议拆分（最多 128 片），可选 gzip 压缩。
+
+use std::sync::Arc;
+
+use serde_json::{json, Map, Value};
+use tokio::net::UdpSocket;
+
+use crate::gzip::gzip;
+use crate::sink::{syslog_severity, LogSink, SinkError, SinkStats};
+use crate::LogEntry;
+
+/// 局域网推荐的分片大小，跨公网时建议改为 1420
+pub const DEFAULT_CHUNK_SIZE: usize = 8192;
+const CHUNK_HEADER_LEN: usize = 12;
+const MAX_CHUNKS: usize = 128;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GelfCompression {
+    #[default]
+    None,
+    Gzip,
+}
+
+/// 把字段名转换为 GELF 附加字段名：加 `_` 前缀，非 `[\w.-]` 字符替换为 `_`，`_id` 为保留字
+pub fn gelf_field_name(key: &str) -> String {
+    let sanitized: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match sanitized.as_str() {
+        "id" => "_id_".to_string(),
+        _ => format!("_{}", sanitized),
+    }
+}
+
+/// 构建 GELF 1.1 JSON 负载
+pub fn gelf_payload(entry: &LogEntry, host: &str) -> Value {
+    let timestamp = entry
+        .timestamp
+        .epoch_millis()
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis()) as f64
+        / 1000.0;
+    let short_message = if entry.message.is_empty() {
+        "<no message>"
+    } else {
+        entry.message.as_str()
+    };
+
+    // 用户字段先写入，与下面的保留字段（`_target`、`_correlation_id`）同名时被覆盖
+    let mut payload: Map<String, Value> = entry
+        .fields
+        .iter()
+        .map(|(key, value)| (gelf_field_name(key), value.clone()))
+        .collect();
+    payload.insert("version".into(), json!("1.1"));
+    payload.insert("host".into(), json!(host));
+    payload.insert("short_message".into(), json!(short_message));
+    payload.insert("timestamp".into(), json!(timestamp));
+    payload.insert("level".into(), json!(syslog_severity(&entry.level)));
+    payload.insert(gelf_field_name("target"), json!(entry.target));
+    if let Some(id) = &entry.correlation_id {
+        payload.insert(gelf_field_name("correlation_id"), json!(id));
+    }
+    Value::Object(payload)
+}
+
+/// 按 GELF 分片协议拆分数据报；超过 128 片时返回 None
+pub fn gelf_chunks(data: &[u8], chunk_size: usize, message_id: [u8; 8]) -> Option<Vec<Vec<u8>>> {
+    if data.len() <= chunk_size {
+        return Some(vec![data.to_vec()]);
+    }
+    let body = chunk_size.saturating_sub(CHUNK_HEADER_LEN).max(1);
+    let count = data.len().div_ceil(body);
+    if count > MAX_CHUNKS {
+        return None;
+    }
+    Some(
+        data.chunks(body)
+            .enumerate()
+            .map(|(seq, part)| {
+                let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + part.len());
+                chunk.extend_from_slice(&[0x1e, 0x0f]);
+                chunk.extend_from_slice(&message_id);
+                chunk.push(seq as u8);
+                chunk.push(count as u8);
+                chunk.extend_from_slice(part);
+                chunk
+            })
+            .collect(),
+    )
+}
+
+/// 通过 UDP 发送 GELF 消息的 sink
+pub struct GelfSink {
+    addr: String,
+    host: String,
+    chunk_size: usize,
+    compression: GelfCompression,
+    socket: Option<UdpSocket>,
+    next_id: u64,
+    stats: Arc<SinkStats>,
+}
+
+impl GelfSink {
+    /// `host` 为 GELF 的 host 字段，通常是本机主机名
+    pub fn new(addr: impl Into<String>, host: impl Into<String>) -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self {
+            addr: addr.into(),
+            host: host.into(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            compression: GelfCompression::None,
+            socket: None,
+            next_id: seed ^ std::process::id() as u64,
+            stats: Arc::new(SinkStats::default()),
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(CHUNK_HEADER_LEN + 1);
+        self
+    }
+
+    pub fn with_compression(mut self, compression: GelfCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn stats(&self) -> Arc<SinkStats> {
+        self.stats.clone()
+    }
+
+    async fn socket(&mut self) -> std::io::Result<&UdpSocket> {
+        if self.socket.is_none() {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(&self.addr).await?;
+            self.socket = Some(socket);
+        }
+        Ok(self.socket.as_ref().expect("bound above"))
+    }
+}
+
+impl LogSink for GelfSink {
+    async fn send(&mut self, entry: &LogEntry) {
+        let json = gelf_payload(entry, &self.host).to_string();
+        let data = match self.compression {
+            GelfCompression::None => json.into_bytes(),
+            GelfCompression::Gzip => gzip(json.as_bytes()),
+        };
+        self.next_id = self.next_id.wrapping_add(1);
+        let Some(chunks) = gelf_chunks(&data, self.chunk_size, self.next_id.to_be_bytes()) else {
+            SinkError::TooLarge {
+                sink: "gelf",
+                bytes: data.len(),
+            }
+            .report();
+            self.stats.record_dropped(1);
+            return;
+        };
+
+        let result = async {
+            let socket = self.socket().await?;
+            for chunk in &chunks {
+                socket.send(chunk).await?;
+            }
+            Ok::<_, std::io::Error>(())
+        }
+        .await;
+        match result {
+            Ok(()) => self.stats.record_sent(1),
+            Err(e) => {
+                SinkError::send("gelf", "send", e).report();
+                self.socket = None;
+                self.stats.record_failed(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::tests::xorshift;
+    use crate::gzip::tests::gunzip;
+
+    fn entry(message: String) -> LogEntry {
+        LogEntry {
+            timestamp: "2024-06-01T12:00:00.123+00:00".into(),
+            level: "WARN".to_string(),
+            target: "gelf_test".to_string(),
+            message,
+            correlation_id: Some("abc".to_string()),
+            fields: [("order id".to_string(), json!("42"))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn recv(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = vec![0; 65536];
+        let n = socket.recv(&mut buf).await.unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_gelf_over_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+
+        let mut sink = GelfSink::new(addr.clone(), "test-host");
+        sink.send(&entry("hello".to_string())).await;
+        let payload: Value = serde_json::from_slice(&recv(&server).await).unwrap();
+        assert_eq!(payload["version"], "1.1");
+        assert_eq!(payload["host"], "test-host");
+        assert_eq!(payload["short_message"], "hello");
+        assert_eq!(payload["timestamp"], 1717243200.123);
+        assert_eq!(payload["level"], 4);
+        assert_eq!(payload["_target"], "gelf_test");
+        assert_eq!(payload["_correlation_id"], "abc");
+        assert_eq!(payload["_order_id"], "42");
+
+        // 无规律的长消息在压缩后仍需分片
+        let mut next = xorshift(0x2545_f491_4f6c_dd1d);
+        let long: String = (0..20_000)
+            .map(|_| char::from(b'a' + next(26) as u8))
+            .collect();
+        let mut sink = GelfSink::new(addr, "test-host")
+            .with_chunk_size(1420)
+            .with_compression(GelfCompression::Gzip);
+        sink.send(&entry(long.clone())).await;
+
+        let first = recv(&server).await;
+        assert_eq!(&first[..2], &[0x1e, 0x0f]);
+        let count = first[11] as usize;
+        assert!(count > 1);
+        let mut parts = vec![Vec::new(); count];
+        parts[first[10] as usize] = first[12..].to_vec();
+        for _ in 1..count {
+            let chunk = recv(&server).await;
+            assert_eq!(&chunk[2..10], &first[2..10]);
+            assert!(chunk.len() <= 1420);
+            parts[chunk[10] as usize] = chunk[12..].to_vec();
+        }
+        let payload: Value = serde_json::from_slice(&gunzip(&parts.concat())).unwrap();
+        assert_eq!(payload["short_message"], long.as_str());
+        assert_eq!(sink.stats().snapshot().sent, 1);
+    }
+
+    #[test]
+    fn test_field_name_sanitization() {
+        assert_eq!(gelf_field_name("user id"), "_user_id");
+        assert_eq!(gelf_field_name("a.b-c"), "_a.b-c");
+        assert_eq!(gelf_field_name("id"), "_id_");
+    }
+
+    #[test]
+    fn test_payload_reserved_fields() {
+        let mut entry = entry("hello".to_string());
+        entry.fields.insert("target".into(), json!("spoofed"));
+        entry
+            .fields
+            .insert("correlation_id".into(), json!("spoofed"));
+        entry.timestamp = "not a timestamp".into();
+
+        let before = chrono::Utc::now().timestamp_millis() as f64 / 1000.0;
+        let payload = gelf_payload(&entry, "test-host");
+        assert_eq!(payload["_target"], "gelf_test");
+        assert_eq!(payload["_correlation_id"], "abc");
+        // 无法解析的时间戳退回到当前时间，而不是 1970 年
+        let timestamp = payload["timestamp"].as_f64().unwrap();
+        assert!(timestamp >= before.floor() && timestamp - before < 60.0);
+    }
+}
@@ -0,0 +1,278 @@
+//! 极简 gzip 编码：LZ77 + 固定 Huffman 表的单块 DEFLATE（RFC 1951/1952）
+//!
+//! 压缩率不及 zlib，但对重复度高的 JSON 日志已经足够，且不引入额外依赖。
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 32;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// gzip 压缩整段数据
+pub(crate) fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    let mut crc = !0u32;
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, n: u32) {
+        self.acc |= (value as u64) << self.nbits;
+        self.nbits += n;
+        while self.nbits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    /// Huffman 码按高位在前写出
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn write_literal(&mut self, symbol: u16) {
+        let s = symbol as u32;
+        match s {
+            0..=143 => self.write_code(0x30 + s, 8),
+            144..=255 => self.write_code(0x190 + s - 144, 9),
+            256..=279 => self.write_code(s - 256, 7),
+            _ => self.write_code(0xC0 + s - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) {
+        let li = LENGTH_BASE
+            .iter()
+            .rposition(|&b| b as usize <= length)
+            .unwrap();
+        self.write_literal(257 + li as u16);
+        self.write_bits(
+            (length - LENGTH_BASE[li] as usize) as u32,
+            LENGTH_EXTRA[li] as u32,
+        );
+        let di = DIST_BASE
+            .iter()
+            .rposition(|&b| b as usize <= distance)
+            .unwrap();
+        self.write_code(di as u32, 5);
+        self.write_bits(
+            (distance - DIST_BASE[di] as usize) as u32,
+            DIST_EXTRA[di] as u32,
+        );
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+fn hash3(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+fn insert_hash(head: &mut [usize], prev: &mut [usize], data: &[u8], i: usize) {
+    let h = hash3(data, i);
+    prev[i] = head[h];
+    head[h] = i;
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        out: Vec::with_capacity(data.len() / 2 + 16),
+        acc: 0,
+        nbits: 0,
+    };
+    // BFINAL = 1, BTYPE = 01（固定 Huffman）
+    w.write_bits(1, 1);
+    w.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+        if i + MIN_MATCH <= data.len() {
+            let h = hash3(data, i);
+            let mut candidate = head[h];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let max = (data.len() - i).min(MAX_MATCH);
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - candidate;
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+            insert_hash(&mut head, &mut prev, data, i);
+        }
+
+        if best_len >= MIN_MATCH {
+            w.write_match(best_len, best_dist);
+            for j in i + 1..(i + best_len).min(data.len() + 1 - MIN_MATCH) {
+                insert_hash(&mut head, &mut prev, data, j);
+            }
+            i += best_len;
+        } else {
+            w.write_literal(data[i] as u16);
+            i += 1;
+        }
+    }
+    w.write_literal(256);
+    w.finish()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, n: u32) -> u32 {
+            let mut v = 0;
+            for k in 0..n {
+                let b = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+                v |= (b as u32) << k;
+                self.pos += 1;
+            }
+            v
+        }
+
+        /// Huffman 码高位在前，逐位读取
+        fn code(&mut self, n: u32, start: u32) -> u32 {
+            (0..n).fold(start, |code, _| (code << 1) | self.bits(1))
+        }
+    }
+
+    /// 仅支持固定 Huffman 块的解码器，用于验证编码结果
+    pub(crate) fn gunzip(data: &[u8]) -> Vec<u8> {
+        assert_eq!(&data[..3], &[0x1f, 0x8b, 8]);
+        let mut r = BitReader {
+            data: &data[10..data.len() - 8],
+            pos: 0,
+        };
+        assert_eq!(r.bits(3), 0b011);
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let code = r.code(7, 0);
+            let symbol = if code <= 23 {
+                256 + code
+            } else {
+                let code = r.code(1, code);
+                match code {
+                    0x30..=0xBF => code - 0x30,
+                    0xC0..=0xC7 => 280 + code - 0xC0,
+                    _ => r.code(1, code) - 0x190 + 144,
+                }
+            };
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let li = (symbol - 257) as usize;
+                    let length =
+                        LENGTH_BASE[li] as usize + r.bits(LENGTH_EXTRA[li] as u32) as usize;
+                    let di = r.code(5, 0) as usize;
+                    let distance = DIST_BASE[di] as usize + r.bits(DIST_EXTRA[di] as u32) as usize;
+                    let start = out.len() - distance;
+                    for k in 0..length {
+                        out.push(out[start + k]);
+                    }
+                }
+            }
+        }
+        let trailer = &data[data.len() - 8..];
+        assert_eq!(
+            u32::from_le_bytes(trailer[..4].try_into().unwrap()),
+            crc32(&out)
+        );
+        assert_eq!(
+            u32::from_le_bytes(trailer[4..].try_into().unwrap()) as usize,
+            out.len()
+        );
+        out
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let repetitive = r#"{"level":"INFO","target":"app","message":"tick"}"#.repeat(50);
+        let compressed = gzip(repetitive.as_bytes());
+        assert!(compressed.len() < repetitive.len() / 4);
+        assert_eq!(gunzip(&compressed), repetitive.as_bytes());
+
+        let mixed: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(gunzip(&gzip(&mixed)), mixed);
+        assert_eq!(gunzip(&gzip(b"")), b"");
+    }
+}
@@ -0,0 +1,547 @@
+//! JSONL 持久化文件的二进制索引旁路文件（`logs.jsonl.idx`）
+//!
+//! 写入任务每隔 N 条日志追加一条 `(字节偏移, 时间戳毫秒, 序号)`，
+//! `query_log_files` 按时间范围查询时在索引上二分，直接 seek 到范围起点附近，而不必从头扫描。
+//! 索引缺失、被截断或与数据文件不一致时会从数据文件重建。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::json_schema::timestamp_millis;
+use crate::LogEntry;
+
+/// 默认每 1024 条日志记录一个索引点
+pub const DEFAULT_INDEX_INTERVAL: u64 = 1024;
+
+const MAGIC: &[u8; 8] = b"LTIDX\0\0\x01";
+const RECORD_LEN: usize = 24;
+
+/// 一个索引点：第 `seq` 条日志（从 0 开始）位于数据文件的 `offset` 处
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexRecord {
+    pub offset: u64,
+    pub timestamp_ms: i64,
+    pub seq: u64,
+}
+
+impl IndexRecord {
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut buf = [0; RECORD_LEN];
+        buf[..8].copy_from_slice(&self.offset.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        buf[16..].copy_from_slice(&self.seq.to_le_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8]) -> Self {
+        let word = |i: usize| buf[i..i + 8].try_into().unwrap();
+        Self {
+            offset: u64::from_le_bytes(word(0)),
+            timestamp_ms: i64::from_le_bytes(word(8)),
+            seq: u64::from_le_bytes(word(16)),
+        }
+    }
+}
+
+/// 数据文件对应的索引路径：`logs.jsonl` -> `logs.jsonl.idx`
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".idx");
+    PathBuf::from(name)
+}
+
+#[derive(Deserialize)]
+struct TimestampOnly {
+    timestamp: Option<serde_json::Value>,
+}
+
+/// 行内的时间，接受 `TimestampFormat` 中除 `Custom` 以外的各种写法
+fn line_timestamp_ms(line: &[u8]) -> Option<i64> {
+    let parsed: TimestampOnly = serde_json::from_slice(line).ok()?;
+    timestamp_millis(&parsed.timestamp?)
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(u8::is_ascii_whitespace)
+}
+
+/// 逐行读取，回调参数为 (行首偏移, 行内容)；最后一行没有换行时同样返回
+fn for_each_line(
+    file: &mut File,
+    start: u64,
+    end: Option<u64>,
+    mut f: impl FnMut(u64, &[u8]),
+) -> io::Result<()> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(file);
+    let mut offset = start;
+    let mut line = Vec::new();
+    while end.is_none_or(|end| offset < end) {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        f(offset, &line);
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+/// 读取并校验索引，文件缺失或与数据文件不一致时返回 None
+pub fn load_index(path: &Path) -> io::Result<Option<Vec<IndexRecord>>> {
+    let bytes = match fs::read(index_path(path)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if bytes.len() < MAGIC.len()
+        || &bytes[..MAGIC.len()] != MAGIC
+        || !(bytes.len() - MAGIC.len()).is_multiple_of(RECORD_LEN)
+    {
+        return Ok(None);
+    }
+    let records: Vec<IndexRecord> = bytes[MAGIC.len()..]
+        .chunks_exact(RECORD_LEN)
+        .map(IndexRecord::from_bytes)
+        .collect();
+    let ordered = records
+        .windows(2)
+        .all(|w| w[0].offset < w[1].offset && w[0].seq < w[1].seq);
+    if !ordered {
+        return Ok(None);
+    }
+    let Some(last) = records.last() else {
+        return Ok(Some(records));
+    };
+
+    // 抽查最后一个索引点：必须位于行首，且该行的时间戳一致
+    let mut data = match File::open(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if last.offset >= data.metadata()?.len() {
+        return Ok(None);
+    }
+    if last.offset > 0 {
+        let mut prev = [0u8];
+        data.seek(SeekFrom::Start(last.offset - 1))?;
+        data.read_exact(&mut prev)?;
+        if prev[0] != b'\n' {
+            return Ok(None);
+        }
+    }
+    let mut line = Vec::new();
+    data.seek(SeekFrom::Start(last.offset))?;
+    BufReader::new(&mut data).read_until(b'\n', &mut line)?;
+    if line_timestamp_ms(&line) != Some(last.timestamp_ms) {
+        return Ok(None);
+    }
+    Ok(Some(records))
+}
+
+/// 按默认间隔从数据文件重建索引
+pub fn rebuild_index(path: &Path) -> io::Result<Vec<IndexRecord>> {
+    rebuild_index_with_interval(path, DEFAULT_INDEX_INTERVAL)
+}
+
+/// 扫描整个数据文件重建索引，先写临时文件再原子替换
+pub fn rebuild_index_with_interval(path: &Path, interval: u64) -> io::Result<Vec<IndexRecord>> {
+    let interval = interval.max(1);
+    let mut records = Vec::new();
+    let mut data = match File::open(path) {
+        Ok(data) => Some(data),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    if let Some(data) = &mut data {
+        let mut seq = 0;
+        let mut last_indexed: Option<u64> = None;
+        for_each_line(data, 0, None, |offset, line| {
+            if is_blank(line) {
+                return;
+            }
+            if last_indexed.is_none_or(|last| seq - last >= interval) {
+                if let Some(timestamp_ms) = line_timestamp_ms(line) {
+                    records.push(IndexRecord {
+                        offset,
+                        timestamp_ms,
+                        seq,
+                    });
+                    last_indexed = Some(seq);
+                }
+            }
+            seq += 1;
+        })?;
+    }
+
+    let target = index_path(path);
+    let mut tmp = target.clone().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut bytes = MAGIC.to_vec();
+    for record in &records {
+        bytes.extend_from_slice(&record.to_bytes());
+    }
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, &target)?;
+    Ok(records)
+}
+
+/// 读取索引，不可用时重建
+fn load_or_rebuild(path: &Path, interval: u64) -> io::Result<Vec<IndexRecord>> {
+    match load_index(path)? {
+        Some(records) => Ok(records),
+        None => rebuild_index_with_interval(path, interval),
+    }
+}
+
+/// 把数据文件连同索引一起重命名（轮转），写入任务在下一批日志时会为新文件建立新索引
+pub fn rotate_log_file(path: &Path, rotated: &Path) -> io::Result<()> {
+    fs::rename(path, rotated)?;
+    match fs::rename(index_path(path), index_path(rotated)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 写入任务持有的索引状态，跟踪数据文件的写入偏移和日志序号
+pub(crate) struct IndexWriter {
+    index: File,
+    interval: u64,
+    offset: u64,
+    next_seq: u64,
+    last_indexed: Option<u64>,
+}
+
+impl IndexWriter {
+    /// 按数据文件当前内容恢复状态，索引不可用时先重建
+    pub(crate) fn open(path: &Path, interval: u64) -> io::Result<Self> {
+        let interval = interval.max(1);
+        let records = load_or_rebuild(path, interval)?;
+        let offset = fs::metadata(path).map_or(0, |m| m.len());
+
+        let last = records.last().copied();
+        let mut next_seq = last.map_or(0, |last| last.seq);
+        if offset > 0 {
+            let mut data = File::open(path)?;
+            let mut lines = 0;
+            for_each_line(
+                &mut data,
+                last.map_or(0, |last| last.offset),
+                None,
+                |_, line| {
+                    if !is_blank(line) {
+                        lines += 1;
+                    }
+                },
+            )?;
+            next_seq += lines;
+        }
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_path(path))?;
+        Ok(Self {
+            index,
+            interval,
+            offset,
+            next_seq,
+            last_indexed: last.map(|last| last.seq),
+        })
+    }
+
+    /// 数据文件长度与记录的偏移不一致（被截断、轮转或有其他写入者）时需要重新 open
+    pub(crate) fn in_sync(&self, data_len: u64) -> bool {
+        self.offset == data_len
+    }
+
+    /// 一行（含换行符，共 `len` 字节）已写入数据文件
+    pub(crate) fn written(&mut self, len: u64, timestamp_ms: Option<i64>) -> io::Result<()> {
+        let offset = self.offset;
+        let seq = self.next_seq;
+        self.offset += len;
+        self.next_seq += 1;
+        if self
+            .last_indexed
+            .is_some_and(|last| seq - last < self.interval)
+        {
+            return Ok(());
+        }
+        let Some(timestamp_ms) = timestamp_ms else {
+            return Ok(());
+        };
+        self.last_indexed = Some(seq);
+        let record = IndexRecord {
+            offset,
+            timestamp_ms,
+            seq,
+        };
+        self.index.write_all(&record.to_bytes())
+    }
+}
+
+/// 需要扫描的字节区间 `[start, end)`，`end` 为 None 表示到文件末尾
+///
+/// 假设文件内时间大致有序：两端各多留一个索引间隔，容忍多线程写入造成的轻微乱序。
+/// 索引点的时间戳出现倒退（墙上时钟回拨）时无法二分，退化为扫描整个文件。
+pub(crate) fn scan_range(
+    records: &[IndexRecord],
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+) -> (u64, Option<u64>) {
+    if records
+        .windows(2)
+        .any(|w| w[1].timestamp_ms < w[0].timestamp_ms)
+    {
+        return (0, None);
+    }
+    let start = from_ms.map_or(0, |from| {
+        let before = records.partition_point(|r| r.timestamp_ms < from);
+        before.checked_sub(2).map_or(0, |i| records[i].offset)
+    });
+    let end = to_ms.and_then(|to| {
+        let after = records.partition_point(|r| r.timestamp_ms <= to);
+        records.get(after + 1).map(|r| r.offset)
+    });
+    (start, end)
+}
+
+/// 按时间范围（epoch 毫秒，闭区间）查询一个或多个 JSONL 文件，例如当前文件和轮转出的旧文件
+///
+/// 每个文件借助各自的索引定位扫描区间，索引不可用时先重建；返回的条目按文件顺序（即写入顺序）排列，
+/// 不按时间戳重排，时钟回拨后也保持因果顺序；无法解析的行被跳过。
+pub fn query_log_files<P: AsRef<Path>>(
+    paths: &[P],
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+) -> io::Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let mut data = match File::open(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let records = load_or_rebuild(path, DEFAULT_INDEX_INTERVAL)?;
+        let (start, end) = scan_range(&records, from_ms, to_ms);
+        for_each_line(&mut data, start, end, |_, line| {
+            let Some(ts) = line_timestamp_ms(line) else {
+                return;
+            };
+            if from_ms.is_some_and(|from| ts < from) || to_ms.is_some_and(|to| ts > to) {
+                return;
+            }
+            let entry = serde_json::from_slice(line)
+                .ok()
+                .and_then(|value| LogEntry::migrate(value).ok());
+            entries.extend(entry);
+        })?;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+    use crate::tracing_utils::epoch_millis_to_rfc3339;
+    use crate::LogCache;
+    use tokio::sync::broadcast;
+
+    const BASE_MS: i64 = 1_717_200_000_000;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "listen-tracing-index-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(index_path(&path));
+        path
+    }
+
+    /// 经管线写入第 `range` 条日志，第 i 条的时间为 BASE_MS + i 秒
+    async fn write(path: &Path, range: std::ops::Range<i64>) {
+        let (tx, _rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.to_path_buf()));
+        pipeline.set_index_interval(10);
+        for i in range {
+            pipeline.ingest(LogEntry {
+                timestamp: epoch_millis_to_rfc3339(BASE_MS + i * 1000).into(),
+                level: "INFO".to_string(),
+                message: format!("event {}", i),
+                ..Default::default()
+            });
+        }
+        pipeline.writer_guard().flush_and_close().await;
+    }
+
+    fn query(paths: &[&Path], from: i64, to: i64) -> Vec<String> {
+        query_log_files(
+            paths,
+            Some(BASE_MS + from * 1000),
+            Some(BASE_MS + to * 1000),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.message)
+        .collect()
+    }
+
+    fn expected(range: std::ops::RangeInclusive<i64>) -> Vec<String> {
+        range.map(|i| format!("event {}", i)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_writer_maintains_index() {
+        let path = temp_path("writer");
+        write(&path, 0..150).await;
+        // 重新打开时序号接续
+        write(&path, 150..200).await;
+
+        let records = load_index(&path).unwrap().unwrap();
+        assert_eq!(records.len(), 20);
+        assert!(records
+            .iter()
+            .enumerate()
+            .all(|(i, r)| r.seq == i as u64 * 10));
+        assert_eq!(records[15].timestamp_ms, BASE_MS + 150_000);
+        assert_eq!(records, rebuild_index_with_interval(&path, 10).unwrap());
+
+        // 后段的查询从中间开始扫描，并在范围之后提前结束
+        let (start, end) = scan_range(&records, Some(BASE_MS + 120_000), Some(BASE_MS + 130_000));
+        assert_eq!(start, records[10].offset);
+        assert_eq!(end, Some(records[15].offset));
+        assert_eq!(query(&[&path], 120, 130), expected(120..=130));
+        assert_eq!(query(&[&path], 0, 0), expected(0..=0));
+        assert_eq!(query(&[&path], 195, 300), expected(195..=199));
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_index_is_rebuilt() {
+        let path = temp_path("corrupt");
+        write(&path, 0..100).await;
+        let idx = index_path(&path);
+        let good = fs::read(&idx).unwrap();
+
+        let corruptions: Vec<Vec<u8>> = vec![
+            // 截断在记录中间
+            good[..good.len() - 5].to_vec(),
+            // 头部损坏
+            [b"garbage!".as_slice(), &good[8..]].concat(),
+            // 偏移指向行中间
+            {
+                let mut bad = good.clone();
+                let at = bad.len() - RECORD_LEN;
+                let mut record = IndexRecord::from_bytes(&bad[at..]);
+                record.offset += 1;
+                bad[at..].copy_from_slice(&record.to_bytes());
+                bad
+            },
+            // 乱序
+            [
+                &good[..8],
+                &good[8 + RECORD_LEN..8 + 2 * RECORD_LEN],
+                &good[8..8 + RECORD_LEN],
+            ]
+            .concat(),
+        ];
+        for corrupted in corruptions {
+            fs::write(&idx, corrupted).unwrap();
+            assert_eq!(load_index(&path).unwrap(), None);
+            assert_eq!(query(&[&path], 40, 45), expected(40..=45));
+            // 查询时已按默认间隔重建
+            assert!(load_index(&path).unwrap().is_some());
+        }
+
+        fs::remove_file(&idx).unwrap();
+        assert_eq!(query(&[&path], 90, 200), expected(90..=99));
+
+        // 数据文件被截断后，索引指向文件之外
+        write(&path, 100..130).await;
+        let data = fs::read(&path).unwrap();
+        let cut = data[..data.len() / 2]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .unwrap()
+            + 1;
+        fs::write(&path, &data[..cut]).unwrap();
+        assert!(load_index(&path).unwrap().is_none());
+        let kept = query(&[&path], 0, 1000).len() as i64;
+        assert_eq!(query(&[&path], 0, 1000), expected(0..=kept - 1));
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&idx);
+    }
+
+    #[tokio::test]
+    async fn test_clock_regression_scans_in_write_order() {
+        let path = temp_path("regression");
+        // 第 0..40 条后时钟回拨 30 秒，再写 40 条
+        write(&path, 0..40).await;
+        {
+            let (tx, _rx) = broadcast::channel(16);
+            let mut pipeline = Pipeline::new(tx, LogCache::default());
+            pipeline.set_persist_path(Some(path.to_path_buf()));
+            pipeline.set_index_interval(10);
+            for i in 10..50 {
+                pipeline.ingest(LogEntry {
+                    timestamp: epoch_millis_to_rfc3339(BASE_MS + i * 1000).into(),
+                    level: "INFO".to_string(),
+                    message: format!("after step {}", i),
+                    ..Default::default()
+                });
+            }
+            pipeline.writer_guard().flush_and_close().await;
+        }
+
+        let records = load_index(&path).unwrap().unwrap();
+        assert_eq!(
+            scan_range(&records, Some(BASE_MS + 30_000), None),
+            (0, None)
+        );
+        let mut want = expected(30..=35);
+        want.extend((10..=35).map(|i| format!("after step {}", i)).filter(|m| {
+            let i: i64 = m.rsplit(' ').next().unwrap().parse().unwrap();
+            i >= 30
+        }));
+        assert_eq!(query(&[&path], 30, 35), want);
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_moves_index() {
+        let path = temp_path("rotate");
+        let rotated = path.with_extension("jsonl.1");
+        let _ = fs::remove_file(&rotated);
+        let _ = fs::remove_file(index_path(&rotated));
+
+        write(&path, 0..50).await;
+        rotate_log_file(&path, &rotated).unwrap();
+        assert!(!index_path(&path).exists());
+        assert!(load_index(&rotated).unwrap().is_some());
+
+        write(&path, 50..80).await;
+        let records = load_index(&path).unwrap().unwrap();
+        assert_eq!((records[0].offset, records[0].seq), (0, 0));
+        assert_eq!(query(&[&rotated, &path], 45, 55), expected(45..=55));
+
+        for p in [&path, &rotated] {
+            let _ = fs::remove_file(p);
+            let _ = fs::remove_file(index_path(p));
+        }
+    }
+}
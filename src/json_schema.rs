@@ -0,0 +1,364 @@
+use std::fmt::Write as _;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::ser::{Error as _, SerializeMap};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+/// ECS 输出中 `ecs.version` 的取值
+pub const ECS_VERSION: &str = "8.11.0";
+
+/// 原生键名与 ECS 键名的对应，未列出的键（message、fields 等）保持原样
+const ECS_KEYS: [(&str, &str); 7] = [
+    ("timestamp", "@timestamp"),
+    ("level", "log.level"),
+    ("target", "log.logger"),
+    ("correlation_id", "trace.id"),
+    ("seq", "event.sequence"),
+    ("fingerprint", "event.hash"),
+    ("name", "event.action"),
+];
+
+/// 持久化 JSONL 的字段命名
+///
+/// 只影响写出的键名，`LogEntry` 本身以及广播、缓存中的条目不变；
+/// `LogEntry::migrate` 能读回两种命名的文件。
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonSchema {
+    /// 与 `LogEntry` 的字段名一致
+    #[default]
+    Native,
+    /// Elastic Common Schema：`@timestamp`、`log.level`、`log.logger` 等，另加 `ecs.version`
+    Ecs,
+}
+
+impl JsonSchema {
+    /// 按该命名把一条记录序列化为单行 JSON
+    pub fn to_line<T: Serialize>(self, record: &T) -> serde_json::Result<String> {
+        match self {
+            JsonSchema::Native => serde_json::to_string(record),
+            JsonSchema::Ecs => serde_json::to_string(&Ecs(record)),
+        }
+    }
+
+    /// 按该命名把一条记录转为 JSON 值，供 `LogSerializer` 编码
+    pub fn to_value<T: Serialize>(self, record: &T) -> serde_json::Result<Value> {
+        match self {
+            JsonSchema::Native => serde_json::to_value(record),
+            JsonSchema::Ecs => serde_json::to_value(Ecs(record)),
+        }
+    }
+}
+
+/// 序列化输出中 `timestamp` 的格式
+///
+/// 只影响写出的 JSON（持久化文件、`RedisSink`、经 `Timestamps` 包装的查询结果），
+/// `LogEntry::timestamp` 本身不变。`LogEntry::migrate` 能读回除 `Custom` 以外的全部格式，
+/// `Custom` 写出的值需用 `TimestampFormat::parse` 按同一格式解析。
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// 保持记录中的原样：`+00:00` 偏移，秒以下按需输出
+    #[default]
+    Native,
+    /// `2024-06-01T12:00:00.123Z`
+    Rfc3339Millis,
+    /// `2024-06-01T12:00:00.123456789Z`
+    Rfc3339Nanos,
+    /// epoch 毫秒，JSON 数字
+    EpochMillis,
+    /// epoch 秒（截断），JSON 数字
+    EpochSeconds,
+    /// chrono 的 strftime 格式，按 UTC 输出；格式串无效时按 `Native` 输出
+    Custom(&'static str),
+}
+
+/// 不小于该值的 epoch 数字视为毫秒，否则视为秒（1e11 秒已在公元 5000 年之后）
+const EPOCH_MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+impl TimestampFormat {
+    /// 按该格式输出一个 RFC 3339 时间，无法解析时原样输出
+    pub fn format(self, timestamp: &str) -> Value {
+        let Ok(t) = DateTime::parse_from_rfc3339(timestamp) else {
+            return timestamp.into();
+        };
+        let t = t.with_timezone(&Utc);
+        match self {
+            TimestampFormat::Native => timestamp.into(),
+            TimestampFormat::Rfc3339Millis => t.to_rfc3339_opts(SecondsFormat::Millis, true).into(),
+            TimestampFormat::Rfc3339Nanos => t.to_rfc3339_opts(SecondsFormat::Nanos, true).into(),
+            TimestampFormat::EpochMillis => t.timestamp_millis().into(),
+            TimestampFormat::EpochSeconds => t.timestamp().into(),
+            TimestampFormat::Custom(format) => {
+                let mut out = String::new();
+                match write!(out, "{}", t.format(format)) {
+                    Ok(()) => out.into(),
+                    Err(_) => timestamp.into(),
+                }
+            }
+        }
+    }
+
+    /// 把该格式写出的值解析回 `LogEntry::timestamp` 使用的 RFC 3339 文本
+    ///
+    /// `Custom` 之外的格式不依赖 `self`：RFC 3339 文本与 epoch 数字（按量级区分毫秒与秒）都能识别。
+    pub fn parse(self, value: &Value) -> Option<String> {
+        let t = match (self, value) {
+            (TimestampFormat::Custom(format), Value::String(s)) => {
+                DateTime::parse_from_str(s, format)
+                    .map(|t| t.with_timezone(&Utc))
+                    .or_else(|_| {
+                        chrono::NaiveDateTime::parse_from_str(s, format).map(|t| t.and_utc())
+                    })
+                    .ok()?
+            }
+            _ => parse_timestamp(value)?,
+        };
+        Some(t.to_rfc3339())
+    }
+}
+
+/// RFC 3339 文本或 epoch 数字
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+        Value::Number(n) => match n.as_i64() {
+            Some(ms) if ms.abs() >= EPOCH_MILLIS_THRESHOLD => DateTime::from_timestamp_millis(ms),
+            Some(secs) => DateTime::from_timestamp(secs, 0),
+            None => DateTime::from_timestamp_millis((n.as_f64()? * 1000.0) as i64),
+        },
+        _ => None,
+    }
+}
+
+/// 序列化后的 `timestamp` 值对应的 epoch 毫秒，供读取持久化文件时使用
+pub(crate) fn timestamp_millis(value: &Value) -> Option<i64> {
+    parse_timestamp(value).map(|t| t.timestamp_millis())
+}
+
+/// 把 epoch 数字与 `Z` 结尾的 RFC 3339 时间改回原生写法，其余保持不变
+pub(crate) fn native_timestamp(map: &mut Map<String, Value>) {
+    let Some(value) = map.get_mut("timestamp") else {
+        return;
+    };
+    let foreign = match value {
+        Value::Number(_) => true,
+        Value::String(s) => s.ends_with('Z') || s.ends_with('z'),
+        _ => false,
+    };
+    if let Some(t) = parse_timestamp(value).filter(|_| foreign) {
+        *value = t.to_rfc3339().into();
+    }
+}
+
+/// 以指定时间格式序列化记录的包装
+///
+/// 递归改写各层对象中的 `timestamp` 键（`fields` 之内除外），因此 `LogEntry`、`store::LogPage`、
+/// `Vec<LogEntry>` 等查询结果都可直接包装后返回给 HTTP 客户端。非 `Native` 格式经 `Value` 中转，键按字典序输出。
+pub struct Timestamps<'a, T>(pub &'a T, pub TimestampFormat);
+
+impl<T: Serialize> Serialize for Timestamps<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.1 == TimestampFormat::Native {
+            return self.0.serialize(serializer);
+        }
+        let mut value = serde_json::to_value(self.0).map_err(S::Error::custom)?;
+        rewrite_timestamps(&mut value, self.1);
+        value.serialize(serializer)
+    }
+}
+
+fn rewrite_timestamps(value: &mut Value, format: TimestampFormat) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match (key.as_str(), &*value) {
+                    ("fields", _) => {}
+                    ("timestamp", Value::String(s)) => *value = format.format(s),
+                    _ => rewrite_timestamps(value, format),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite_timestamps(item, format);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 以 ECS 键名序列化内部记录的包装，记录须序列化为 JSON 对象
+pub struct Ecs<'a, T>(pub &'a T);
+
+impl<T: Serialize> Serialize for Ecs<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Value::Object(map) = serde_json::to_value(self.0).map_err(S::Error::custom)? else {
+            return Err(S::Error::custom("ECS layout needs a JSON object"));
+        };
+        let mut out = serializer.serialize_map(Some(map.len() + 1))?;
+        out.serialize_entry("ecs.version", ECS_VERSION)?;
+        for (key, value) in &map {
+            let key = ECS_KEYS
+                .iter()
+                .find(|(native, _)| native == key)
+                .map_or(key.as_str(), |(_, ecs)| ecs);
+            out.serialize_entry(key, value)?;
+        }
+        out.end()
+    }
+}
+
+/// 把 ECS 键名改回原生键名并去掉 `ecs.version`；不含 `@timestamp` 的对象不做修改
+pub(crate) fn ecs_to_native(map: &mut Map<String, Value>) {
+    if !map.contains_key("@timestamp") {
+        return;
+    }
+    map.remove("ecs.version");
+    for (native, ecs) in ECS_KEYS {
+        if let Some(value) = map.remove(ecs) {
+            map.insert(native.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{LogHit, LogPage};
+    use crate::{BroadcastLogLayer, LogCache, LogEntry};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn test_ecs_persisted_keys() {
+        skip_if_events_compiled_out!();
+        let path =
+            std::env::temp_dir().join(format!("listen-tracing-ecs-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .with_persist_path(&path)
+            .with_index_interval(0)
+            .with_json_schema(JsonSchema::Ecs)
+            .with_timestamp_format(TimestampFormat::Rfc3339Nanos);
+        let pipeline = layer.pipeline().clone();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::warn!(order = 7, "order rejected");
+        pipeline.writer_guard().flush_and_close().await;
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        let value: Value = serde_json::from_str(line.trim_end()).unwrap();
+        let object = value.as_object().unwrap();
+        assert_eq!(object["log.level"], "WARN");
+        assert_eq!(object["log.logger"], module_path!());
+        assert_eq!(object["ecs.version"], ECS_VERSION);
+        assert_eq!(object["message"], "order rejected");
+        assert_eq!(object["fields"]["order"], 7);
+        assert!(object["@timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(!object.contains_key("timestamp") && !object.contains_key("level"));
+
+        // 缓存中的条目不受影响，文件可经 migrate 读回
+        let cached = cache.read().await.to_vec();
+        let mut migrated = LogEntry::migrate(value).unwrap();
+        assert_eq!(migrated.seq, None);
+        migrated.seq = cached[0].seq;
+        assert_eq!(migrated, cached[0]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let nanos = "2024-06-01T12:00:00.123456789+00:00";
+        let entry = LogEntry {
+            timestamp: nanos.into(),
+            level: "INFO".to_string(),
+            target: "app".to_string(),
+            message: "filled".to_string(),
+            ..Default::default()
+        };
+        let line = |format| serde_json::to_string(&Timestamps(&entry, format)).unwrap();
+        let sorted = |timestamp: &str| {
+            format!(
+                r#"{{"level":"INFO","message":"filled","schema":7,"target":"app","timestamp":{}}}"#,
+                timestamp
+            )
+        };
+        let custom = TimestampFormat::Custom("%Y/%m/%d %H:%M:%S%.3f");
+        assert_eq!(
+            line(TimestampFormat::Native),
+            r#"{"schema":7,"timestamp":"2024-06-01T12:00:00.123456789+00:00","level":"INFO","target":"app","message":"filled"}"#
+        );
+        // (格式, 写出的值, migrate 读回的 timestamp, 按同一格式 parse 的结果)
+        let millis = "2024-06-01T12:00:00.123+00:00";
+        let cases = [
+            (
+                TimestampFormat::Rfc3339Millis,
+                r#""2024-06-01T12:00:00.123Z""#,
+                millis,
+                millis,
+            ),
+            (
+                TimestampFormat::Rfc3339Nanos,
+                r#""2024-06-01T12:00:00.123456789Z""#,
+                nanos,
+                nanos,
+            ),
+            (
+                TimestampFormat::EpochMillis,
+                "1717243200123",
+                millis,
+                millis,
+            ),
+            (
+                TimestampFormat::EpochSeconds,
+                "1717243200",
+                "2024-06-01T12:00:00+00:00",
+                "2024-06-01T12:00:00+00:00",
+            ),
+            // Custom 无法自动识别，migrate 保留原文
+            (
+                custom,
+                r#""2024/06/01 12:00:00.123""#,
+                "2024/06/01 12:00:00.123",
+                millis,
+            ),
+        ];
+        for (format, written, migrated, parsed) in cases {
+            let line = line(format);
+            assert_eq!(line, sorted(written), "{:?}", format);
+            let value: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(format.parse(&value["timestamp"]).as_deref(), Some(parsed));
+            let read_back = LogEntry::migrate(value).unwrap();
+            assert_eq!(read_back.timestamp, migrated, "{:?}", format);
+            assert_eq!(read_back.message, entry.message);
+        }
+
+        // 查询结果中的嵌套条目同样改写，字段里的同名键保持原样
+        let page = LogPage {
+            total: 1,
+            page: 1,
+            page_size: 50,
+            items: vec![LogHit {
+                entry: LogEntry {
+                    fields: [("timestamp".to_string(), "2024-06-01T00:00:00+00:00".into())]
+                        .into_iter()
+                        .collect(),
+                    ..entry.clone()
+                },
+                highlights: None,
+                pinned: false,
+            }],
+        };
+        let value = serde_json::to_value(Timestamps(&page, TimestampFormat::EpochSeconds)).unwrap();
+        assert_eq!(value["items"][0]["timestamp"], 1717243200);
+        assert_eq!(
+            value["items"][0]["fields"]["timestamp"],
+            "2024-06-01T00:00:00+00:00"
+        );
+    }
+}
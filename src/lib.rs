@@ -1,118 +1,218 @@
+/// 启用 `max_level_*` / `release_max_level_*` 时部分事件在编译期被移除（`--all-features`
+/// 会带上 `max_level_off`），断言事件内容的测试以此开头，直接跳过
+#[cfg(all(test, feature = "setup"))]
+macro_rules! skip_if_events_compiled_out {
+    () => {
+        if tracing::level_filters::STATIC_MAX_LEVEL != tracing::level_filters::LevelFilter::TRACE {
+            return;
+        }
+    };
+}
+
+#[cfg(feature = "utils")]
 pub mod tracing_utils;
+#[cfg(feature = "broadcast")]
+pub mod audit;
+#[cfg(feature = "broadcast")]
+mod broadcast;
+#[cfg(feature = "broadcast")]
+pub use broadcast::*;
+#[cfg(feature = "broadcast")]
+mod broadcaster;
+#[cfg(feature = "broadcast")]
+pub use broadcaster::*;
+#[cfg(feature = "broadcast")]
+mod cache;
+#[cfg(feature = "broadcast")]
+pub use cache::*;
+#[cfg(feature = "broadcast")]
+mod builder;
+#[cfg(feature = "broadcast")]
+pub use builder::*;
+#[cfg(feature = "broadcast")]
+pub mod clock;
+#[cfg(feature = "broadcast")]
+pub mod collapse;
+#[cfg(feature = "broadcast")]
+mod config;
+#[cfg(feature = "broadcast")]
+pub use config::*;
+#[cfg(feature = "broadcast")]
+pub mod correlation;
+#[cfg(feature = "broadcast")]
+pub mod digest;
+#[cfg(feature = "broadcast")]
+pub mod escalation;
+#[cfg(feature = "broadcast")]
+pub mod export;
+#[cfg(feature = "broadcast")]
+pub mod fold;
+#[cfg(feature = "broadcast")]
+pub mod follow;
+#[cfg(feature = "broadcast")]
+pub mod index;
+#[cfg(feature = "broadcast")]
+mod json_schema;
+#[cfg(feature = "broadcast")]
+pub use json_schema::*;
+#[cfg(feature = "broadcast")]
+mod limits;
+#[cfg(feature = "broadcast")]
+pub use limits::*;
+#[cfg(feature = "broadcast")]
+pub mod pipeline;
+#[cfg(feature = "broadcast")]
+mod prometheus;
+#[cfg(feature = "broadcast")]
+pub mod quantile;
+#[cfg(feature = "broadcast")]
+pub mod query;
+#[cfg(feature = "broadcast")]
+mod record;
+#[cfg(feature = "broadcast")]
+pub use record::*;
+#[cfg(feature = "broadcast")]
+pub mod request_log;
+#[cfg(feature = "broadcast")]
+mod routed;
+#[cfg(feature = "broadcast")]
+pub use routed::*;
+#[cfg(feature = "broadcast")]
+pub mod sampling;
+#[cfg(feature = "broadcast")]
+pub mod saved_query;
+#[cfg(feature = "broadcast")]
+pub mod sanitize;
+#[cfg(feature = "broadcast")]
+pub mod serializer;
+#[cfg(feature = "broadcast")]
+pub mod schema;
+#[cfg(feature = "broadcast")]
+mod sha256;
+#[cfg(feature = "broadcast")]
+mod span_cache;
+#[cfg(feature = "broadcast")]
+pub use span_cache::*;
+#[cfg(feature = "broadcast")]
+pub mod span_events;
+#[cfg(feature = "broadcast")]
+pub mod store;
+#[cfg(feature = "broadcast")]
+pub mod sink;
+#[cfg(feature = "broadcast")]
+mod stats;
+#[cfg(feature = "broadcast")]
+pub use stats::*;
+#[cfg(feature = "broadcast")]
+pub mod target_levels;
+#[cfg(feature = "broadcast")]
+mod timestamp;
+#[cfg(feature = "tz")]
+pub mod timezone;
+#[cfg(feature = "broadcast")]
+pub use timestamp::*;
+#[cfg(feature = "broadcast")]
+pub mod verbosity;
+#[cfg(feature = "broadcast")]
+pub mod viewer;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "gelf")]
+pub mod gelf;
+#[cfg(feature = "gelf")]
+mod gzip;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "notify")]
+pub mod notify;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod error_code;
+pub mod file_lock;
+#[cfg(feature = "setup")]
+pub mod routing;
+#[cfg(all(unix, feature = "signal"))]
+pub mod signal;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+#[cfg(feature = "tcp_sink")]
+pub mod tcp_sink;
+#[cfg(feature = "setup")]
+mod setup;
+#[cfg(feature = "setup")]
+pub use setup::*;
+#[cfg(feature = "setup")]
+mod style;
+#[cfg(feature = "setup")]
+pub use style::*;
+
+#[cfg(feature = "setup")]
+use tracing_subscriber::{layer::SubscriberExt,util::SubscriberInitExt, EnvFilter, Layer};
+
+/// 安装全局 subscriber，并预留 reload 槽位供之后的 `setup_tracing_with_broadcast` / `attach_layer` 使用。
+/// 已有全局 subscriber 时只打印提示，不会 panic。
+/// 设置了 `IS_SYSTEMD_SERVICE` 时输出到 journald；journald 不可用时回退到标准输出，并记录一条 WARN 说明原因。
+#[cfg(feature = "setup")]
+pub fn setup_tracing() {
+    init_global(None);
+}
 
-use chrono::Utc;
-use serde::{Deserialize, Serialize};
-use std::{fs::OpenOptions, io::Write, sync::Arc};
-use tokio::sync::{broadcast, RwLock};
-use tracing::{Event, Subscriber};
-use tracing_subscriber::{layer::SubscriberExt,util::SubscriberInitExt, EnvFilter, Layer, Registry};
+/// 同 `setup_tracing`，但终端输出按 `style` 定制（级别图标、颜色、时间戳、target、紧凑布局）。
+/// 以 systemd 服务运行时仍输出到 journald，`style` 不生效；journald 不可用时回退到终端输出。
+#[cfg(feature = "setup")]
+pub fn setup_tracing_styled(style: LogStyle) {
+    init_global(Some(style));
+}
 
-pub fn setup_tracing() {
+#[cfg(feature = "setup")]
+fn init_global(style: Option<LogStyle>) {
     // Create an EnvFilter that reads from RUST_LOG with INFO as default
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (slot, handle) = setup::extra_layers_slot();
 
     // Configure logging based on environment
-    if std::env::var("IS_SYSTEMD_SERVICE").is_ok() {
+    let systemd = std::env::var("IS_SYSTEMD_SERVICE").is_ok();
+    let (result, journald_error) = match setup::console_output(systemd, tracing_journald::layer) {
         // Use systemd formatting when running as a service
-        let journald_layer = tracing_journald::layer().expect("Failed to create journald layer");
-        tracing_subscriber::registry()
-            .with(journald_layer)
-            .with(env_filter)
-            .init();
-    } else {
-        // Use standard formatting for non-systemd environments
-        tracing_subscriber::fmt()
-            .with_ansi(true)
-            .with_target(true)
-            .with_env_filter(env_filter)
-            .init();
-    }
-}
-
-
-#[derive(Serialize, Clone, Debug)]
-pub struct LogEntry {
-    pub timestamp: String,
-    pub level: String,
-    pub target: String,
-    pub message: String,
-}
-
-pub type LogCache = Arc<RwLock<Vec<LogEntry>>>;
-
-#[derive(Deserialize)]
-pub struct LogQuery {
-    pub level: Option<String>,
-    pub keyword: Option<String>,
-    pub page: Option<usize>,
-    pub page_size: Option<usize>,
-}
-
-pub fn setup_tracing_with_broadcast(tx: broadcast::Sender<LogEntry>, cache: LogCache) {
-    let layer = BroadcastLogLayer { tx, cache };
-    let subscriber = Registry::default()
-        .with(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
-        .with(tracing_subscriber::fmt::layer().json())
-        .with(layer);
-    tracing::subscriber::set_global_default(subscriber).unwrap();
-}
-
-struct BroadcastLogLayer {
-    tx: broadcast::Sender<LogEntry>,
-    cache: LogCache,
-}
-
-impl<S: Subscriber> Layer<S> for BroadcastLogLayer {
-    fn on_event(&self, event: &Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
-        let mut visitor = TracingVisitor::default();
-        event.record(&mut visitor);
-
-        // 构建 Arc 包裹的日志对象
-        let log = Arc::new(LogEntry {
-            timestamp: Utc::now().to_rfc3339(),
-            level: event.metadata().level().to_string(),
-            target: event.metadata().target().to_string(),
-            message: visitor.message.unwrap_or_else(|| "<no message>".to_string()),
-        });
-
-        // 广播日志副本（需要 LogEntry 实现 Clone）
-        let _ = self.tx.send((*log).clone());
-
-        let cache = self.cache.clone();
-        let log_clone = log.clone();
-
-        // 异步缓存 + 持久化
-        tokio::spawn(async move {
-            {
-                let mut logs = cache.write().await;
-                logs.push((*log_clone).clone());
-                if logs.len() > 1000 {
-                    let len = logs.len();
-                    logs.drain(0..(len - 1000));
-                }
-            }
-
-            if let Ok(mut file) = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("logs.jsonl")
-            {
-                let _ = writeln!(file, "{}", serde_json::to_string(&*log_clone).unwrap());
+        setup::ConsoleOutput::Journald(journald_layer) => (
+            tracing_subscriber::registry()
+                .with(slot)
+                .with(journald_layer)
+                .with(env_filter)
+                .try_init(),
+            None,
+        ),
+        setup::ConsoleOutput::Stdout(journald_error) => {
+            // Use standard formatting for non-systemd environments
+            let default_fmt = style
+                .is_none()
+                .then(|| tracing_subscriber::fmt::layer().with_ansi(true).with_target(true));
+            // `console = false` 的路由规则只作用于终端输出
+            let result = tracing_subscriber::registry()
+                .with(slot)
+                .with(env_filter)
+                .with(default_fmt.with_filter(routing::env_routes().console_filter()))
+                .with(style.map(styled_layer).with_filter(routing::env_routes().console_filter()))
+                .try_init();
+            (result, journald_error)
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            setup::register_extra_layers(handle);
+            if let Some(e) = journald_error {
+                tracing::warn!(
+                    error = %e,
+                    "IS_SYSTEMD_SERVICE is set but journald is unavailable, logging to stdout"
+                );
             }
-        });
-    }
-}
-
-#[derive(Default)]
-pub struct TracingVisitor {
-    message: Option<String>,
-}
-
-impl tracing::field::Visit for TracingVisitor {
-    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        if field.name() == "message" {
-            self.message = Some(format!("{:?}", value));
+        }
+        Err(e) => {
+            let e = SetupError::Init(e.to_string());
+            eprintln!("listen-tracing: [{}] setup_tracing skipped: {}", e.code(), e);
         }
     }
 }
-
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cache::ByteCounter;
+
+/// 超出限制时记录被丢弃字段数的标记字段
+pub const TRUNCATED_FIELDS: &str = "_truncated_fields";
+
+/// 单条日志结构化字段的数量、键长、值长度与嵌套深度上限
+///
+/// 超出任一上限的字段整体丢弃，条目上以 `_truncated_fields` 记录丢弃的个数，
+/// 防止偶发的大对象日志拖垮内存与磁盘。只想截断过长的字符串时用
+/// `BroadcastLogLayer::with_max_field_bytes`。
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldLimits {
+    /// 每条日志最多保留的字段数，按捕获顺序保留前面的
+    pub max_fields: usize,
+    /// 字段名的最大字节数
+    pub max_key_bytes: usize,
+    /// 字段值 JSON 序列化后的最大字节数，None 表示不限
+    pub max_value_bytes: Option<usize>,
+    /// 值中对象 / 数组的最大嵌套层数，标量为 0
+    pub max_depth: usize,
+}
+
+impl Default for FieldLimits {
+    fn default() -> Self {
+        Self {
+            max_fields: 32,
+            max_key_bytes: 128,
+            max_value_bytes: None,
+            max_depth: 4,
+        }
+    }
+}
+
+impl FieldLimits {
+    /// 新字段能否加入 `fields`；同名字段覆盖不占新的名额
+    pub fn admits(&self, fields: &BTreeMap<String, Value>, key: &str, value: &Value) -> bool {
+        (fields.len() < self.max_fields || fields.contains_key(key))
+            && key.len() <= self.max_key_bytes
+            && depth(value) <= self.max_depth
+            && self
+                .max_value_bytes
+                .is_none_or(|max| serialized_len(value) <= max)
+    }
+
+    /// 对已构造好的字段表（例如外部接入的条目）应用限制，返回丢弃的字段数；
+    /// 有丢弃时写入 `_truncated_fields`
+    pub fn apply(&self, fields: &mut BTreeMap<String, Value>) -> u64 {
+        let mut kept = BTreeMap::new();
+        let mut dropped = 0;
+        for (key, value) in std::mem::take(fields) {
+            if key == TRUNCATED_FIELDS {
+                dropped += value.as_u64().unwrap_or_default();
+            } else if self.admits(&kept, &key, &value) {
+                kept.insert(key, value);
+            } else {
+                dropped += 1;
+            }
+        }
+        *fields = kept;
+        mark_truncated(fields, dropped);
+        dropped
+    }
+}
+
+/// 有字段被丢弃时写入标记
+pub(crate) fn mark_truncated(fields: &mut BTreeMap<String, Value>, dropped: u64) {
+    if dropped > 0 {
+        fields.insert(TRUNCATED_FIELDS.to_string(), Value::from(dropped));
+    }
+}
+
+fn depth(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+        Value::Object(map) => 1 + map.values().map(depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn serialized_len(value: &Value) -> usize {
+    let mut counter = ByteCounter(0);
+    serde_json::to_writer(&mut counter, value)
+        .map(|_| counter.0)
+        .unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_adversarial_fields() {
+        let limits = FieldLimits {
+            max_value_bytes: Some(1024),
+            ..Default::default()
+        };
+        let mut nested = json!("leaf");
+        for _ in 0..64 {
+            nested = json!({ "child": nested });
+        }
+        let mut fields: BTreeMap<String, Value> =
+            (0..500).map(|i| (format!("k{:03}", i), json!(i))).collect();
+        fields.insert("a_nested".to_string(), nested);
+        fields.insert("a_huge".to_string(), json!("x".repeat(1 << 20)));
+        fields.insert("a".repeat(200), json!(1));
+        fields.insert("a_shallow".to_string(), json!({"x": [1, {"y": 2}]}));
+
+        let dropped = limits.apply(&mut fields);
+        // 保留 a_shallow 与 k000..k030，其余 472 个丢弃
+        assert_eq!(dropped, 472);
+        assert_eq!(fields.len(), 33);
+        assert_eq!(fields[TRUNCATED_FIELDS], 472);
+        assert!(fields.contains_key("a_shallow"));
+        assert!(fields.contains_key("k030"));
+        assert!(!fields.contains_key("k031"));
+
+        // 再次应用时累加已有的标记，不重复丢弃
+        assert_eq!(limits.apply(&mut fields), 472);
+        assert_eq!(fields.len(), 33);
+    }
+
+    #[tokio::test]
+    async fn test_layer_drops_fields_over_limits() {
+        skip_if_events_compiled_out!();
+        use crate::{BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_field_limits(FieldLimits {
+                max_fields: 3,
+                max_key_bytes: 12,
+                max_value_bytes: Some(64),
+                max_depth: 0,
+            });
+        let stats = layer.stats();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        let huge = "x".repeat(1 << 20);
+        tracing::info!(
+            a = 1,
+            huge = %huge,
+            an_excessively_long_key = 2,
+            b = 3,
+            c = 4,
+            d = 5,
+            "adversarial"
+        );
+        tracing::info!(a = 1, "within limits");
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        let keys: Vec<&str> = logs[0].fields.keys().map(String::as_str).collect();
+        assert_eq!(keys, [TRUNCATED_FIELDS, "a", "b", "c"]);
+        assert_eq!(logs[0].fields[TRUNCATED_FIELDS], 3);
+        assert_eq!(logs[0].message, "adversarial");
+        assert!(!logs[1].fields.contains_key(TRUNCATED_FIELDS));
+        assert_eq!(stats.snapshot().truncated_fields, 3);
+    }
+}
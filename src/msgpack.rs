@@ -0,0 +1,228 @@
+//! 极简 MessagePack 编解码，只覆盖 JSON 数据模型（nil / bool / 整数 / float64 / str / array / map）
+//!
+//! 编码总是选用最短的表示；解码另外接受 float32，拒绝 bin / ext 等 JSON 中没有对应的类型。
+
+use serde_json::{Map, Number, Value};
+
+use crate::serializer::{DecodeError, Reader, MAX_DEPTH};
+
+pub(crate) fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => encode_number(n, out),
+        Value::String(s) => encode_str(s, out),
+        Value::Array(items) => {
+            encode_len(items.len(), 0x90, 0xdc, out);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Object(map) => {
+            encode_len(map.len(), 0x80, 0xde, out);
+            for (key, value) in map {
+                encode_str(key, out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    let len = s.len();
+    if len < 32 {
+        out.push(0xa0 | len as u8);
+    } else if len <= 0xff {
+        out.extend_from_slice(&[0xd9, len as u8]);
+    } else if len <= 0xffff {
+        out.push(0xda);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xdb);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// array / map 的长度头：fix 形式不足 16，否则 16 / 32 位（`marker16` + 1）
+fn encode_len(len: usize, fix: u8, marker16: u8, out: &mut Vec<u8>) {
+    if len < 16 {
+        out.push(fix | len as u8);
+    } else if len <= 0xffff {
+        out.push(marker16);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(marker16 + 1);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn encode_number(n: &Number, out: &mut Vec<u8>) {
+    if let Some(u) = n.as_u64() {
+        if u < 0x80 {
+            out.push(u as u8);
+        } else if u <= 0xff {
+            out.extend_from_slice(&[0xcc, u as u8]);
+        } else if u <= 0xffff {
+            out.push(0xcd);
+            out.extend_from_slice(&(u as u16).to_be_bytes());
+        } else if u <= 0xffff_ffff {
+            out.push(0xce);
+            out.extend_from_slice(&(u as u32).to_be_bytes());
+        } else {
+            out.push(0xcf);
+            out.extend_from_slice(&u.to_be_bytes());
+        }
+    } else if let Some(i) = n.as_i64() {
+        // 非负数已由 as_u64 处理
+        if i >= -32 {
+            out.push(i as i8 as u8);
+        } else if i >= i8::MIN as i64 {
+            out.extend_from_slice(&[0xd0, i as i8 as u8]);
+        } else if i >= i16::MIN as i64 {
+            out.push(0xd1);
+            out.extend_from_slice(&(i as i16).to_be_bytes());
+        } else if i >= i32::MIN as i64 {
+            out.push(0xd2);
+            out.extend_from_slice(&(i as i32).to_be_bytes());
+        } else {
+            out.push(0xd3);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+    } else {
+        out.push(0xcb);
+        out.extend_from_slice(&n.as_f64().unwrap_or_default().to_be_bytes());
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Value, DecodeError> {
+    let mut reader = Reader::new(bytes);
+    let value = decode_value(&mut reader, 0)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+fn decode_value(r: &mut Reader<'_>, depth: usize) -> Result<Value, DecodeError> {
+    if depth > MAX_DEPTH {
+        return Err(r.error("nesting too deep"));
+    }
+    let marker = r.byte()?;
+    let value = match marker {
+        0x00..=0x7f => Value::from(marker),
+        0x80..=0x8f => decode_map(r, (marker & 0x0f) as usize, depth)?,
+        0x90..=0x9f => decode_array(r, (marker & 0x0f) as usize, depth)?,
+        0xa0..=0xbf => r.string((marker & 0x1f) as usize)?,
+        0xc0 => Value::Null,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xca => {
+            let f = f32::from_be_bytes(r.array()?) as f64;
+            float(r, f)?
+        }
+        0xcb => {
+            let f = f64::from_be_bytes(r.array()?);
+            float(r, f)?
+        }
+        0xcc => Value::from(r.byte()?),
+        0xcd => Value::from(u16::from_be_bytes(r.array()?)),
+        0xce => Value::from(u32::from_be_bytes(r.array()?)),
+        0xcf => Value::from(u64::from_be_bytes(r.array()?)),
+        0xd0 => Value::from(r.byte()? as i8),
+        0xd1 => Value::from(i16::from_be_bytes(r.array()?)),
+        0xd2 => Value::from(i32::from_be_bytes(r.array()?)),
+        0xd3 => Value::from(i64::from_be_bytes(r.array()?)),
+        0xd9 => {
+            let len = r.byte()? as usize;
+            r.string(len)?
+        }
+        0xda => {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            r.string(len)?
+        }
+        0xdb => {
+            let len = u32::from_be_bytes(r.array()?) as usize;
+            r.string(len)?
+        }
+        0xdc => {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            decode_array(r, len, depth)?
+        }
+        0xdd => {
+            let len = u32::from_be_bytes(r.array()?) as usize;
+            decode_array(r, len, depth)?
+        }
+        0xde => {
+            let len = u16::from_be_bytes(r.array()?) as usize;
+            decode_map(r, len, depth)?
+        }
+        0xdf => {
+            let len = u32::from_be_bytes(r.array()?) as usize;
+            decode_map(r, len, depth)?
+        }
+        0xe0..=0xff => Value::from(marker as i8),
+        _ => return Err(r.error("unsupported MessagePack type")),
+    };
+    Ok(value)
+}
+
+fn float(r: &Reader<'_>, f: f64) -> Result<Value, DecodeError> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| r.error("NaN or infinite float"))
+}
+
+fn decode_array(r: &mut Reader<'_>, len: usize, depth: usize) -> Result<Value, DecodeError> {
+    // 每个元素至少 1 字节，长度头不可信时不预先分配
+    let mut items = Vec::with_capacity(len.min(r.remaining()));
+    for _ in 0..len {
+        items.push(decode_value(r, depth + 1)?);
+    }
+    Ok(Value::Array(items))
+}
+
+fn decode_map(r: &mut Reader<'_>, len: usize, depth: usize) -> Result<Value, DecodeError> {
+    let mut map = Map::new();
+    for _ in 0..len {
+        let Value::String(key) = decode_value(r, depth + 1)? else {
+            return Err(r.error("map key is not a string"));
+        };
+        let value = decode_value(r, depth + 1)?;
+        map.insert(key, value);
+    }
+    Ok(Value::Object(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_value_round_trip_and_sizes() {
+        let value = json!({
+            "small": 5,
+            "byte": 200,
+            "wide": 70000,
+            "huge": u64::MAX,
+            "neg": -5,
+            "neg8": -100,
+            "neg64": i64::MIN,
+            "float": 1.5,
+            "text": "日志".repeat(20),
+            "list": (0..20).collect::<Vec<_>>(),
+            "nested": {"ok": true, "none": null},
+        });
+        let mut out = Vec::new();
+        encode(&value, &mut out);
+        assert_eq!(decode(&out).unwrap(), value);
+
+        // 最短表示：正负 fixint 各 1 字节
+        let mut out = Vec::new();
+        encode(&json!([1, -1]), &mut out);
+        assert_eq!(out, [0x92, 0x01, 0xff]);
+
+        assert!(decode(&[0xc4, 0x01, 0x00]).is_err());
+        assert!(decode(&[0x92, 0x01]).is_err());
+        assert!(decode(&[0x01, 0x01]).is_err());
+    }
+}
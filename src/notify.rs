@@ -0,0 +1,329 @@
+//! Telegram / Discord 告警推送（需开启 `notify` feature）
+//!
+//! `NotifierSink` 负责级别/正则过滤、限流与摘要、按平台长度截断，
+//! HTTP 请求通过调用方实现的 `HttpPost` 发出（例如包装 reqwest::Client）。
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde_json::{json, Value};
+use tracing::Level;
+
+use crate::clock::{Clock, SharedClock};
+use crate::sink::{LogSink, SinkError, SinkStats};
+use crate::LogEntry;
+
+pub type NotifyError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 发送 JSON POST 请求的最小接口
+pub trait HttpPost: Send + Sync + 'static {
+    fn post_json(
+        &self,
+        url: &str,
+        body: &Value,
+    ) -> impl Future<Output = Result<(), NotifyError>> + Send;
+}
+
+/// 推送目标
+#[derive(Clone, Debug)]
+pub enum NotifyTarget {
+    Telegram { bot_token: String, chat_id: String },
+    Discord { webhook_url: String },
+}
+
+impl NotifyTarget {
+    /// 平台单条消息的最大字符数
+    pub fn max_len(&self) -> usize {
+        match self {
+            NotifyTarget::Telegram { .. } => 4096,
+            NotifyTarget::Discord { .. } => 2000,
+        }
+    }
+
+    /// 生成请求地址和 JSON 请求体
+    pub fn request(&self, text: &str) -> (String, Value) {
+        match self {
+            NotifyTarget::Telegram { bot_token, chat_id } => (
+                format!("https://api.telegram.org/bot{}/sendMessage", bot_token),
+                json!({ "chat_id": chat_id, "text": text }),
+            ),
+            NotifyTarget::Discord { webhook_url } => {
+                (webhook_url.clone(), json!({ "content": text }))
+            }
+        }
+    }
+}
+
+/// 按字符数截断，超出时以 `…` 结尾
+fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(max.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// 把严重错误推送到 Telegram 群或 Discord webhook 的 sink
+///
+/// 每个时间窗口（默认 1 分钟）最多推送 `max_per_window` 条，其余只计数，
+/// 窗口结束时补发一条 "N more errors suppressed" 摘要。推送失败只记入 stats。
+pub struct NotifierSink<H: HttpPost> {
+    target: NotifyTarget,
+    http: H,
+    min_level: Level,
+    filter: Option<Regex>,
+    max_fields: usize,
+    max_per_window: u32,
+    window: Duration,
+    window_start: Instant,
+    sent_in_window: u32,
+    suppressed: u64,
+    clock: SharedClock,
+    stats: Arc<SinkStats>,
+}
+
+impl<H: HttpPost> NotifierSink<H> {
+    pub fn new(target: NotifyTarget, http: H) -> Self {
+        let clock = SharedClock::default();
+        Self {
+            target,
+            http,
+            min_level: Level::ERROR,
+            filter: None,
+            max_fields: 5,
+            max_per_window: 10,
+            window: Duration::from_secs(60),
+            window_start: clock.instant(),
+            sent_in_window: 0,
+            suppressed: 0,
+            clock,
+            stats: Arc::new(SinkStats::default()),
+        }
+    }
+
+    pub fn with_min_level(mut self, level: Level) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    /// 只推送 message 匹配该正则的条目
+    pub fn with_filter(mut self, filter: Regex) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// 消息中最多附带的结构化字段数
+    pub fn with_max_fields(mut self, max_fields: usize) -> Self {
+        self.max_fields = max_fields;
+        self
+    }
+
+    /// 每个窗口最多推送的条数，默认每分钟 10 条
+    pub fn with_rate_limit(mut self, max_per_window: u32, window: Duration) -> Self {
+        self.max_per_window = max_per_window.max(1);
+        self.window = window;
+        self
+    }
+
+    /// 限流窗口使用的时钟，默认为系统时钟
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        self.clock = SharedClock::new(clock);
+        self.window_start = self.clock.instant();
+        self
+    }
+
+    /// sent 为成功推送的消息数（含摘要），dropped 为被限流压下的条目数
+    pub fn stats(&self) -> Arc<SinkStats> {
+        self.stats.clone()
+    }
+
+    pub fn format(&self, entry: &LogEntry) -> String {
+        let mut text = format!(
+            "[{}] {} {}\n{}",
+            entry.level, entry.timestamp, entry.target, entry.message
+        );
+        for (key, value) in entry.fields.iter().take(self.max_fields) {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            text.push_str(&format!("\n{}={}", key, value));
+        }
+        truncate_chars(&text, self.target.max_len())
+    }
+
+    async fn deliver(&self, text: &str) {
+        let (url, body) = self.target.request(text);
+        match self.http.post_json(&url, &body).await {
+            Ok(()) => self.stats.record_sent(1),
+            Err(e) => {
+                SinkError::send("notifier", "delivery", e).report();
+                self.stats.record_failed(1);
+            }
+        }
+    }
+
+    /// 窗口到期时补发摘要并开启新窗口
+    async fn roll_window(&mut self) {
+        let now = self.clock.instant();
+        if now.saturating_duration_since(self.window_start) < self.window {
+            return;
+        }
+        if self.suppressed > 0 {
+            let digest = format!(
+                "{} more errors suppressed in the last {:?}",
+                self.suppressed, self.window
+            );
+            self.deliver(&digest).await;
+        }
+        self.window_start = now;
+        self.sent_in_window = 0;
+        self.suppressed = 0;
+    }
+}
+
+impl<H: HttpPost> LogSink for NotifierSink<H> {
+    async fn send(&mut self, entry: &LogEntry) {
+        if !entry.is_at_least(self.min_level) {
+            return;
+        }
+        if let Some(filter) = &self.filter {
+            if !filter.is_match(&entry.message) {
+                return;
+            }
+        }
+
+        self.roll_window().await;
+        if self.sent_in_window < self.max_per_window {
+            self.sent_in_window += 1;
+            let text = self.format(entry);
+            self.deliver(&text).await;
+        } else {
+            self.suppressed += 1;
+            self.stats.record_dropped(1);
+        }
+    }
+
+    async fn flush(&mut self) {
+        self.roll_window().await;
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(self.window.min(Duration::from_secs(10)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockHttp {
+        requests: Mutex<Vec<(String, Value)>>,
+    }
+
+    impl HttpPost for Arc<MockHttp> {
+        async fn post_json(&self, url: &str, body: &Value) -> Result<(), NotifyError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.to_string(), body.clone()));
+            Ok(())
+        }
+    }
+
+    fn error(message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: "2024-06-01T00:00:00+00:00".into(),
+            level: "ERROR".to_string(),
+            target: "bot::executor".to_string(),
+            message: message.to_string(),
+            fields: [("symbol".to_string(), json!("BTC"))].into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filters_rate_limits_and_digests() {
+        let http = Arc::new(MockHttp::default());
+        let target = NotifyTarget::Telegram {
+            bot_token: "token".to_string(),
+            chat_id: "42".to_string(),
+        };
+        let mut sink = NotifierSink::new(target, http.clone())
+            .with_filter(Regex::new("order").unwrap())
+            .with_rate_limit(2, Duration::from_millis(50));
+
+        let mut info = error("order ok");
+        info.level = "INFO".to_string();
+        sink.send(&info).await;
+        sink.send(&error("connection reset")).await;
+        for _ in 0..5 {
+            sink.send(&error("order rejected")).await;
+        }
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        sink.flush().await;
+
+        let requests = http.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0].0,
+            "https://api.telegram.org/bottoken/sendMessage"
+        );
+        assert_eq!(requests[0].1["chat_id"], "42");
+        assert_eq!(
+            requests[0].1["text"],
+            "[ERROR] 2024-06-01T00:00:00+00:00 bot::executor\norder rejected\nsymbol=BTC"
+        );
+        assert_eq!(
+            requests[2].1["text"],
+            "3 more errors suppressed in the last 50ms"
+        );
+        assert_eq!(sink.stats().snapshot().dropped, 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_window_resets_at_boundary() {
+        let http = Arc::new(MockHttp::default());
+        let clock = crate::clock::MockClock::new(chrono::Utc::now());
+        let target = NotifyTarget::Discord {
+            webhook_url: "https://discord.test/hook".to_string(),
+        };
+        let mut sink = NotifierSink::new(target, http.clone())
+            .with_clock(clock.clone())
+            .with_rate_limit(1, Duration::from_secs(60));
+
+        sink.send(&error("first")).await;
+        sink.send(&error("suppressed")).await;
+        clock.advance(Duration::from_secs(60) - Duration::from_nanos(1));
+        sink.send(&error("still suppressed")).await;
+        assert_eq!(http.requests.lock().unwrap().len(), 1);
+
+        // 恰好到达窗口长度时开启新窗口
+        clock.advance(Duration::from_nanos(1));
+        sink.send(&error("next window")).await;
+        let requests = http.requests.lock().unwrap();
+        let texts: Vec<&str> = requests
+            .iter()
+            .map(|(_, body)| body["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(texts.len(), 3);
+        assert_eq!(texts[1], "2 more errors suppressed in the last 60s");
+        assert!(texts[2].ends_with("next window\nsymbol=BTC"));
+    }
+
+    #[test]
+    fn test_discord_truncation() {
+        let target = NotifyTarget::Discord {
+            webhook_url: "https://discord.test/hook".to_string(),
+        };
+        let sink = NotifierSink::new(target, Arc::new(MockHttp::default()));
+        let text = sink.format(&error(&"错".repeat(3000)));
+        assert_eq!(text.chars().count(), 2000);
+        assert!(text.ends_with('…'));
+    }
+}
@@ -0,0 +1,1643 @@
+use std::any::Any;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, Weak};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::Level;
+
+use crate::clock::{Clock, SharedClock};
+use crate::digest::DIGEST_TARGET;
+use crate::file_lock::{fallback_path, LockConflict, LockError, LockFallback, PersistLock};
+use crate::index::{IndexWriter, DEFAULT_INDEX_INTERVAL};
+use crate::json_schema::{JsonSchema, TimestampFormat, Timestamps};
+use crate::routing::{env_routes, RouteRuleStats, RouteRules};
+use crate::serializer::{encode_record, Framing, LogSerializer};
+use crate::{
+    LogCache, LogEntry, LogRecord, LogStats, LogStatsSnapshot, PinError, DEFAULT_LOG_FILE,
+    LOG_ENTRY_SCHEMA,
+};
+
+/// 缓存写入通道的默认容量
+pub const DEFAULT_WRITER_CAPACITY: usize = 4096;
+
+/// 具名广播通道的默认容量
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// 写入任务单次从通道取出的最大条目数
+const WRITER_BATCH: usize = 256;
+
+/// 暂停 / 恢复与关闭记录使用的 target，这些条目不受暂停影响，保证数据缺口有据可查
+pub const ADMIN_TARGET: &str = "listen_tracing::admin";
+
+/// 全局 subscriber 使用的管线，供 `tracing_status` 读取
+static GLOBAL_PIPELINE: OnceLock<Pipeline> = OnceLock::new();
+
+/// 日志的公共出口：广播、写入缓存、追加到 JSONL 文件
+///
+/// `BroadcastLogLayer` 和后台任务（如周期摘要）共用同一个 Pipeline，
+/// 因此合成的条目与 tracing 事件走完全相同的路径。
+/// 广播在调用方同步完成；缓存和持久化由唯一的写入任务经有界通道消费，
+/// 通道满时直接丢弃并计入 `LogStats::dropped`。
+///
+/// 克隆出的 Pipeline 共享同一组暂停开关，可在任意线程或任务中调用 `pause_*` / `resume_*`。
+/// 每条日志在分发前按 `RouteRules`（默认取 `routing::env_routes`）决定是否广播、持久化。
+/// 广播、缓存、持久化各有一个最低级别（`set_*_level`），可在运行时调整，例如 DEBUG 只落盘、不推送给订阅方。
+/// 记录类型 `R` 见 `LogRecord`，默认为 `LogEntry`。
+#[derive(Clone)]
+pub struct Pipeline<R = LogEntry> {
+    tx: broadcast::Sender<R>,
+    cache: LogCache<R>,
+    persist_path: Option<PathBuf>,
+    stats: Arc<LogStats>,
+    /// 缓存容量为 0，条目写入后立即被淘汰
+    cache_disabled: bool,
+    writer_capacity: usize,
+    index_interval: u64,
+    json_schema: JsonSchema,
+    timestamp_format: TimestampFormat,
+    /// None 时直接写出 JSONL
+    serializer: Option<Arc<dyn LogSerializer>>,
+    routes: Arc<RouteRules>,
+    clock: SharedClock,
+    channels: Arc<RwLock<Vec<NamedChannel<R>>>>,
+    writer: Arc<OnceLock<WriterTask<R>>>,
+    control: Arc<PipelineControl>,
+    /// 持久化文件的锁，见 `file_lock`
+    persist_lock: Arc<Mutex<Option<PersistLock>>>,
+    /// 关闭前需先交出暂存记录的组件，见 `Holdback`
+    holdbacks: Arc<Mutex<Vec<Weak<dyn Holdback<R>>>>>,
+}
+
+/// 在记录进入管线之前暂存它的组件（如 `collapse::Collapser`）
+///
+/// 通过 `Pipeline::register_holdback` 登记后，`LogWriterGuard` 的各个关闭路径
+/// 会在关闭写入任务之前调用 `release`，暂存的记录不会因关闭而丢失。
+pub(crate) trait Holdback<R>: Send + Sync {
+    /// 把暂存的记录写入 `pipeline`
+    fn release(&self, pipeline: &Pipeline<R>);
+}
+
+/// 写入任务的入口与中止句柄
+struct WriterTask<R> {
+    tx: mpsc::Sender<WriterMsg<R>>,
+    abort: tokio::task::AbortHandle,
+}
+
+/// 具名广播通道的过滤条件
+pub type ChannelFilter<R> = dyn Fn(&R) -> bool + Send + Sync;
+
+/// `Pipeline::add_channel` 登记的广播通道，容量与落后互不影响
+struct NamedChannel<R> {
+    name: String,
+    tx: broadcast::Sender<R>,
+    filter: Box<ChannelFilter<R>>,
+}
+
+enum WriterMsg<R> {
+    /// 日志及其去向；暂停状态与级别在入队时确定，避免与写入任务的时序竞争
+    Entry(R, Targets),
+    /// 之前入队的日志全部写完后回复，随后写入任务退出
+    Close(oneshot::Sender<()>),
+    /// 之前入队的日志全部写完后回复，写入任务继续运行
+    Flush(oneshot::Sender<()>),
+}
+
+/// 条目交给写入任务后的去向
+#[derive(Clone, Copy, Debug)]
+struct Targets {
+    cache: bool,
+    persist: bool,
+}
+
+/// 运行时开关，每条日志进入管线时检查一次
+#[derive(Debug, Default)]
+struct PipelineControl {
+    persistence: AtomicBool,
+    broadcast: AtomicBool,
+    /// 各去向的最低级别，编码见 `encode_level`
+    persist_level: AtomicU8,
+    broadcast_level: AtomicU8,
+    cache_level: AtomicU8,
+    /// `LogWriterGuard::flush_and_close` 之后不再接收新日志进缓存和文件
+    closed: AtomicBool,
+    /// `LogWriterGuard::shutdown` 的宽限期内，仍正常接收日志
+    draining: AtomicBool,
+}
+
+/// 写入任务的关闭句柄，用于进程退出前确保已入队的日志全部落盘
+///
+/// 通过 `Pipeline::writer_guard` 获取；`flush_and_close` 与 `shutdown` 会消耗 guard。
+pub struct LogWriterGuard<R = LogEntry> {
+    pipeline: Pipeline<R>,
+}
+
+impl<R: LogRecord> LogWriterGuard<R> {
+    /// 停止接收新日志（之后的日志只广播，计入 dropped），等待写入任务处理完此前入队的全部日志后退出
+    ///
+    /// 暂存中的记录（如 `with_collapse_consecutive` 折叠中的重复）先写入管线，见 `Holdback`。
+    pub async fn flush_and_close(self) {
+        self.pipeline.release_holdbacks();
+        self.pipeline.control.closed.store(true, Ordering::SeqCst);
+        if let Some(writer) = self.pipeline.writer.get() {
+            let (ack, done) = oneshot::channel();
+            if writer.tx.send(WriterMsg::Close(ack)).await.is_ok() {
+                let _ = done.await;
+            }
+        }
+        self.pipeline.release_lock();
+    }
+
+    /// 同 `flush_and_close`，但最多等待 `timeout`（例如磁盘卡住时），让关闭流程可以继续
+    ///
+    /// 超时后中止写入任务，仍在通道中的条目计入 `LogStats::dropped` 并在错误中给出；
+    /// 写入任务已取出、正在写的那一批可能只写了一部分。管线此后同样不再接收新日志。
+    pub async fn flush_and_close_timeout(self, timeout: Duration) -> Result<(), FlushError> {
+        self.pipeline.release_holdbacks();
+        self.pipeline.control.closed.store(true, Ordering::SeqCst);
+        let Some(writer) = self.pipeline.writer.get() else {
+            self.pipeline.release_lock();
+            return Ok(());
+        };
+        let (ack, done) = oneshot::channel();
+        let close_queued = AtomicBool::new(false);
+        let close = async {
+            if writer.tx.send(WriterMsg::Close(ack)).await.is_ok() {
+                close_queued.store(true, Ordering::SeqCst);
+                let _ = done.await;
+            }
+        };
+        if tokio::time::timeout(timeout, close).await.is_ok() {
+            self.pipeline.release_lock();
+            return Ok(());
+        }
+        writer.abort.abort();
+        self.pipeline.release_lock();
+        // 关闭消息之后不会再有条目入队：它仍在通道中时不计入，已被取出时通道为空
+        let backlog = writer.tx.max_capacity() - writer.tx.capacity();
+        let abandoned = backlog.saturating_sub(close_queued.load(Ordering::SeqCst) as usize);
+        self.pipeline.stats.record_dropped(abandoned as u64);
+        Err(FlushError::Timeout { timeout, abandoned })
+    }
+
+    /// 有序关闭：在 `grace` 内照常接收日志，让其他组件的关闭过程仍被记录，
+    /// 随后写入一条 `pipeline shutting down` 管理日志，再 `flush_and_close`
+    ///
+    /// 这条日志同样会广播，sink 可据此得知上游已结束。已在关闭中或已关闭时直接返回。
+    pub async fn shutdown(self, grace: Duration) {
+        let control = self.pipeline.control.clone();
+        if control.closed.load(Ordering::SeqCst) || control.draining.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        tokio::time::sleep(grace).await;
+        // 暂存的记录排在关闭记录之前
+        self.pipeline.release_holdbacks();
+        let stats = self.pipeline.stats.snapshot();
+        self.pipeline.ingest(R::from(LogEntry {
+            schema: LOG_ENTRY_SCHEMA,
+            timestamp: self.pipeline.clock.now().to_rfc3339().into(),
+            level: "INFO".to_string(),
+            target: ADMIN_TARGET.to_string(),
+            message: "pipeline shutting down".to_string(),
+            fields: [
+                ("action", serde_json::json!("shutdown")),
+                ("grace_ms", serde_json::json!(grace.as_millis() as u64)),
+                ("dropped_total", serde_json::json!(stats.dropped)),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+            ..Default::default()
+        }));
+        self.flush_and_close().await;
+        control.draining.store(false, Ordering::SeqCst);
+    }
+
+    /// 等待 `trigger`（例如 `CancellationToken::cancelled_owned()`）完成后执行 `shutdown`
+    pub async fn shutdown_on<F: Future>(self, trigger: F, grace: Duration) {
+        trigger.await;
+        self.shutdown(grace).await;
+    }
+}
+
+/// `LogWriterGuard::flush_and_close_timeout` 的失败原因
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FlushError {
+    /// 写入任务未能在限定时间内写完，已被中止；`abandoned` 为中止时仍在通道中的条目数
+    Timeout { timeout: Duration, abandoned: usize },
+}
+
+impl FlushError {
+    /// 稳定的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            FlushError::Timeout { .. } => "LT-IO-003",
+        }
+    }
+}
+
+impl std::fmt::Display for FlushError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlushError::Timeout { timeout, abandoned } => write!(
+                f,
+                "log writer did not finish within {:?}, aborted with {} entries unwritten",
+                timeout, abandoned
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FlushError {}
+
+/// 是否应跳过该条目：管理记录本身永远放行
+fn skip<R: LogRecord>(paused: &AtomicBool, log: &R) -> bool {
+    paused.load(Ordering::Relaxed) && log.target() != ADMIN_TARGET
+}
+
+/// 级别编码为 1 (ERROR) ..= 5 (TRACE)，0 表示不限制
+fn encode_level(level: Option<Level>) -> u8 {
+    match level {
+        None => 0,
+        Some(Level::ERROR) => 1,
+        Some(Level::WARN) => 2,
+        Some(Level::INFO) => 3,
+        Some(Level::DEBUG) => 4,
+        Some(Level::TRACE) => 5,
+    }
+}
+
+fn decode_level(code: u8) -> Option<Level> {
+    match code {
+        1 => Some(Level::ERROR),
+        2 => Some(Level::WARN),
+        3 => Some(Level::INFO),
+        4 => Some(Level::DEBUG),
+        5 => Some(Level::TRACE),
+        _ => None,
+    }
+}
+
+/// 条目级别是否达到去向的最低级别；无法识别的级别与管理记录总是放行
+fn admits<R: LogRecord>(min: &AtomicU8, log: &R) -> bool {
+    let min = min.load(Ordering::Relaxed);
+    min == 0
+        || log.target() == ADMIN_TARGET
+        || log
+            .level()
+            .parse::<Level>()
+            .map_or(true, |level| encode_level(Some(level)) <= min)
+}
+
+/// 管线运行状态
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TracingStatus {
+    pub stats: LogStatsSnapshot,
+    /// 写入通道容量
+    pub writer_capacity: usize,
+    /// 当前等待写入缓存的条目数
+    pub writer_backlog: usize,
+    /// 持久化是否暂停，暂停期间跳过的条目数见 `stats.skipped_persistence`
+    pub persistence_paused: bool,
+    /// 广播是否暂停，暂停期间跳过的条目数见 `stats.skipped_broadcast`
+    pub broadcast_paused: bool,
+    /// 是否处于 `LogWriterGuard::shutdown` 的宽限期
+    pub draining: bool,
+}
+
+impl Pipeline {
+    pub fn new(tx: broadcast::Sender<LogEntry>, cache: LogCache) -> Self {
+        Self::for_record(tx, cache)
+    }
+}
+
+impl<R: LogRecord> Pipeline<R> {
+    /// 使用自定义记录类型构造，见 `LogRecord`
+    pub fn for_record(tx: broadcast::Sender<R>, cache: LogCache<R>) -> Self {
+        // 构造时缓存尚未被写入任务持有，读不到时按有容量处理
+        let cache_disabled = cache
+            .try_read()
+            .is_ok_and(|cache| cache.stats().max_entries == 0);
+        Self {
+            tx,
+            cache,
+            cache_disabled,
+            persist_path: Some(PathBuf::from(DEFAULT_LOG_FILE)),
+            stats: Arc::default(),
+            writer_capacity: DEFAULT_WRITER_CAPACITY,
+            index_interval: DEFAULT_INDEX_INTERVAL,
+            json_schema: JsonSchema::default(),
+            timestamp_format: TimestampFormat::default(),
+            serializer: None,
+            routes: env_routes(),
+            clock: SharedClock::default(),
+            channels: Arc::default(),
+            writer: Arc::default(),
+            control: Arc::default(),
+            persist_lock: Arc::default(),
+            holdbacks: Arc::default(),
+        }
+    }
+
+    pub fn sender(&self) -> &broadcast::Sender<R> {
+        &self.tx
+    }
+
+    /// 登记具名广播通道，只接收 `filter` 为真的条目，返回其接收端
+    ///
+    /// 各通道有独立的容量，某个通道的订阅方落后（`RecvError::Lagged`）不影响其他通道和主通道。
+    /// 同名通道已存在时返回它的新接收端，容量和过滤条件以首次登记为准。
+    pub fn add_channel(
+        &self,
+        name: &str,
+        capacity: usize,
+        filter: impl Fn(&R) -> bool + Send + Sync + 'static,
+    ) -> broadcast::Receiver<R> {
+        let mut channels = self
+            .channels
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(channel) = channels.iter().find(|channel| channel.name == name) {
+            return channel.tx.subscribe();
+        }
+        let (tx, rx) = broadcast::channel(capacity);
+        channels.push(NamedChannel {
+            name: name.to_string(),
+            tx,
+            filter: Box::new(filter),
+        });
+        rx
+    }
+
+    /// 是否有去向需要条目：主通道或具名通道有订阅方、缓存容量非零或开启了持久化
+    ///
+    /// 为 false 时 `BroadcastLogLayer` 不再捕获字段、构造记录，只计入事件统计。
+    pub fn has_consumers(&self) -> bool {
+        self.tx.receiver_count() > 0
+            || !self.cache_disabled
+            || self.persist_path.is_some()
+            || self
+                .channels
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .any(|channel| channel.tx.receiver_count() > 0)
+    }
+
+    /// 具名通道的发送端，可用于追加订阅或交给 `LogBroadcaster::from_sender` 统计落后
+    pub fn channel(&self, name: &str) -> Option<broadcast::Sender<R>> {
+        let channels = self.channels.read().unwrap_or_else(PoisonError::into_inner);
+        channels
+            .iter()
+            .find(|channel| channel.name == name)
+            .map(|channel| channel.tx.clone())
+    }
+
+    pub fn cache(&self) -> &LogCache<R> {
+        &self.cache
+    }
+
+    /// 钉住缓存中序号为 `seq` 的条目，使其不再被淘汰或清空，见 `LogBuffer::pin`
+    pub async fn pin_entry(&self, seq: u64) -> Result<bool, PinError> {
+        self.cache.write().await.pin(seq)
+    }
+
+    /// 取消钉住，返回被取消的条目
+    pub async fn unpin_entry(&self, seq: u64) -> Option<R> {
+        self.cache.write().await.unpin(seq)
+    }
+
+    /// 钉住的条目，按时间顺序
+    pub async fn list_pinned(&self) -> Vec<R> {
+        self.cache.read().await.pinned().cloned().collect()
+    }
+
+    pub fn stats(&self) -> &Arc<LogStats> {
+        &self.stats
+    }
+
+    pub fn persist_path(&self) -> Option<&Path> {
+        self.persist_path.as_deref()
+    }
+
+    /// 写入任务在第一条日志到达时启动，之后的修改不再生效
+    pub(crate) fn set_persist_path(&mut self, path: Option<PathBuf>) {
+        self.persist_path = path;
+    }
+
+    /// 对持久化路径取锁，见 `file_lock`；不持久化或已持有锁时不做任何事
+    ///
+    /// 冲突且 `conflict` 为 `Fallback` 时改写到 `fallback_path` 给出的路径并返回改用的路径。
+    /// 写入任务在第一条日志到达时启动，应在此之前调用。
+    pub(crate) fn lock_persist_path(
+        &mut self,
+        conflict: LockConflict,
+    ) -> Result<Option<LockFallback>, LockError> {
+        let persist_lock = self.persist_lock.clone();
+        let mut held = persist_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(path) = self.persist_path.clone() else {
+            return Ok(None);
+        };
+        if held.is_some() {
+            return Ok(None);
+        }
+        match PersistLock::acquire(&path) {
+            Ok(lock) => {
+                *held = Some(lock);
+                Ok(None)
+            }
+            Err(LockError::Held { holder, .. }) if conflict == LockConflict::Fallback => {
+                let fallback = fallback_path(&path, std::process::id());
+                *held = Some(PersistLock::acquire(&fallback)?);
+                self.persist_path = Some(fallback.clone());
+                Ok(Some(LockFallback {
+                    original: path,
+                    fallback,
+                    holder,
+                }))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 登记暂存组件，见 `Holdback`；只保留弱引用，组件被丢弃后自动移除
+    pub(crate) fn register_holdback(&self, holdback: Weak<dyn Holdback<R>>) {
+        self.holdbacks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(holdback);
+    }
+
+    /// 让各暂存组件交出记录，须在设置 `closed` 之前调用
+    fn release_holdbacks(&self) {
+        let holdbacks: Vec<_> = {
+            let mut holdbacks = self
+                .holdbacks
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            holdbacks.retain(|holdback| holdback.strong_count() > 0);
+            holdbacks.iter().filter_map(Weak::upgrade).collect()
+        };
+        for holdback in holdbacks {
+            holdback.release(self);
+        }
+    }
+
+    /// 释放持久化文件的锁，写入任务结束后调用
+    fn release_lock(&self) {
+        self.persist_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    /// 调整缓存容量；写入任务启动后缓存可能被占用，此时不做修改
+    pub(crate) fn set_cache_limits(&mut self, max_entries: usize, max_bytes: Option<usize>) {
+        if let Ok(mut cache) = self.cache.try_write() {
+            cache.set_limits(max_entries, max_bytes);
+            self.cache_disabled = max_entries == 0;
+        }
+    }
+
+    pub(crate) fn set_writer_capacity(&mut self, capacity: usize) {
+        self.writer_capacity = capacity.max(1);
+    }
+
+    /// 0 表示不维护索引
+    pub(crate) fn set_index_interval(&mut self, interval: u64) {
+        self.index_interval = interval;
+    }
+
+    pub fn index_interval(&self) -> u64 {
+        self.index_interval
+    }
+
+    /// 写入任务在第一条日志到达时启动，之后的修改不再生效
+    pub(crate) fn set_json_schema(&mut self, schema: JsonSchema) {
+        self.json_schema = schema;
+    }
+
+    /// 持久化文件的字段命名
+    pub fn json_schema(&self) -> JsonSchema {
+        self.json_schema
+    }
+
+    /// 写入任务在第一条日志到达时启动，之后的修改不再生效
+    pub(crate) fn set_timestamp_format(&mut self, format: TimestampFormat) {
+        self.timestamp_format = format;
+    }
+
+    /// 持久化文件中 `timestamp` 的格式
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.timestamp_format
+    }
+
+    /// 写入任务在第一条日志到达时启动，之后的修改不再生效
+    pub(crate) fn set_serializer(&mut self, serializer: Arc<dyn LogSerializer>) {
+        self.serializer = Some(serializer);
+    }
+
+    /// 持久化文件的编码，None 表示默认的 JSONL
+    pub fn serializer(&self) -> Option<&dyn LogSerializer> {
+        self.serializer.as_deref()
+    }
+
+    pub(crate) fn set_routes(&mut self, routes: RouteRules) {
+        self.routes = Arc::new(routes);
+    }
+
+    pub fn routes(&self) -> &Arc<RouteRules> {
+        &self.routes
+    }
+
+    /// 生效中的路由规则及各自命中次数
+    pub fn route_stats(&self) -> Vec<RouteRuleStats> {
+        self.routes.stats()
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    /// 管线使用的时钟，见 `BroadcastLogLayer::with_clock`
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    pub fn status(&self) -> TracingStatus {
+        let writer_backlog = self
+            .writer
+            .get()
+            .map_or(0, |writer| writer.tx.max_capacity() - writer.tx.capacity());
+        TracingStatus {
+            stats: self.stats.snapshot(),
+            writer_capacity: self.writer_capacity,
+            writer_backlog,
+            persistence_paused: self.is_persistence_paused(),
+            broadcast_paused: self.is_broadcast_paused(),
+            draining: self.control.draining.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 暂停写入持久化文件（缓存、广播和控制台输出不受影响），`by` 记录操作者
+    pub fn pause_persistence(&self, by: &str) {
+        self.set_paused(&self.control.persistence, true, "persistence", by);
+    }
+
+    pub fn resume_persistence(&self, by: &str) {
+        self.set_paused(&self.control.persistence, false, "persistence", by);
+    }
+
+    /// 暂停广播，订阅方（sink、WebSocket 等）在恢复前收不到新日志
+    pub fn pause_broadcast(&self, by: &str) {
+        self.set_paused(&self.control.broadcast, true, "broadcast", by);
+    }
+
+    pub fn resume_broadcast(&self, by: &str) {
+        self.set_paused(&self.control.broadcast, false, "broadcast", by);
+    }
+
+    /// 持久化或广播任一处于暂停状态
+    pub fn is_paused(&self) -> bool {
+        self.is_persistence_paused() || self.is_broadcast_paused()
+    }
+
+    pub fn is_persistence_paused(&self) -> bool {
+        self.control.persistence.load(Ordering::Relaxed)
+    }
+
+    pub fn is_broadcast_paused(&self) -> bool {
+        self.control.broadcast.load(Ordering::Relaxed)
+    }
+
+    /// 持久化文件只写入不低于 `level` 的条目，None 表示不限制；可在运行时调整
+    pub fn set_persist_level(&self, level: Option<Level>) {
+        self.control
+            .persist_level
+            .store(encode_level(level), Ordering::Relaxed);
+    }
+
+    pub fn persist_level(&self) -> Option<Level> {
+        decode_level(self.control.persist_level.load(Ordering::Relaxed))
+    }
+
+    /// 只广播不低于 `level` 的条目（主通道与具名通道），None 表示不限制；可在运行时调整
+    pub fn set_broadcast_level(&self, level: Option<Level>) {
+        self.control
+            .broadcast_level
+            .store(encode_level(level), Ordering::Relaxed);
+    }
+
+    pub fn broadcast_level(&self) -> Option<Level> {
+        decode_level(self.control.broadcast_level.load(Ordering::Relaxed))
+    }
+
+    /// 缓存只保留不低于 `level` 的条目，None 表示不限制；可在运行时调整
+    pub fn set_cache_level(&self, level: Option<Level>) {
+        self.control
+            .cache_level
+            .store(encode_level(level), Ordering::Relaxed);
+    }
+
+    pub fn cache_level(&self) -> Option<Level> {
+        decode_level(self.control.cache_level.load(Ordering::Relaxed))
+    }
+
+    /// 切换开关，状态确实改变时记录一条管理日志（含操作者与累计跳过数）
+    fn set_paused(&self, flag: &AtomicBool, paused: bool, what: &str, by: &str) {
+        if flag.swap(paused, Ordering::SeqCst) == paused {
+            return;
+        }
+        let stats = self.stats.snapshot();
+        let action = if paused { "paused" } else { "resumed" };
+        self.ingest(R::from(LogEntry {
+            schema: LOG_ENTRY_SCHEMA,
+            timestamp: self.clock.now().to_rfc3339().into(),
+            level: "WARN".to_string(),
+            target: ADMIN_TARGET.to_string(),
+            message: format!("{} {}", what, action),
+            correlation_id: None,
+            fields: [
+                ("action", serde_json::json!(action)),
+                ("component", serde_json::json!(what)),
+                ("by", serde_json::json!(by)),
+                (
+                    "skipped_total",
+                    serde_json::json!(match what {
+                        "persistence" => stats.skipped_persistence,
+                        _ => stats.skipped_broadcast,
+                    }),
+                ),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+            seq: None,
+            fingerprint: None,
+            mono_ns: None,
+            date_bucket: None,
+            name: None,
+        }));
+    }
+
+    /// 注入一条构造好的日志（例如测试 SSE / WebSocket / sink 等下游消费者），
+    /// 等写入任务把它写入缓存和持久化文件后返回
+    ///
+    /// 与 Layer 产生的日志一样经过 `ingest`：计入统计，遵守暂停开关、路由规则与各去向的最低级别。
+    /// 全局安装后可经 `global_pipeline()` 调用。
+    pub async fn emit_entry(&self, log: R) {
+        self.ingest(log);
+        let Some(writer) = self.writer.get() else {
+            return;
+        };
+        let (ack, done) = oneshot::channel();
+        if writer.tx.send(WriterMsg::Flush(ack)).await.is_ok() {
+            let _ = done.await;
+        }
+    }
+
+    /// 获取写入任务的关闭句柄，见 `LogWriterGuard`
+    pub fn writer_guard(&self) -> LogWriterGuard<R> {
+        LogWriterGuard {
+            pipeline: self.clone(),
+        }
+    }
+
+    /// 注册为全局管线，仅第一次调用生效；只有记录类型为 `LogEntry` 的管线会被注册
+    pub(crate) fn install_global(&self) {
+        if let Some(pipeline) = (self as &dyn Any).downcast_ref::<Pipeline>() {
+            let _ = GLOBAL_PIPELINE.set(pipeline.clone());
+        }
+    }
+
+    /// 广播，并交给写入任务更新缓存和持久化文件
+    pub fn ingest(&self, log: R) {
+        // 摘要条目本身不计入统计，否则空闲时也永远有"活动"
+        if log.target() != DIGEST_TARGET {
+            self.stats.record_event(log.level());
+            self.stats
+                .record_field_values(log.target(), |name| log.field(name));
+        }
+
+        let route = self.routes.route(log.target());
+
+        // 有订阅者时才广播副本，避免无人接收时的整条拷贝
+        if skip(&self.control.broadcast, &log) {
+            self.stats.record_skipped_broadcast();
+        } else if route.is_none_or(|rule| rule.broadcast)
+            && admits(&self.control.broadcast_level, &log)
+        {
+            self.stats.record_broadcast();
+            if self.tx.receiver_count() > 0 {
+                let _ = self.tx.send(log.clone());
+            }
+            for channel in self
+                .channels
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+            {
+                if channel.tx.receiver_count() > 0 && (channel.filter)(&log) {
+                    let _ = channel.tx.send(log.clone());
+                }
+            }
+        }
+
+        let persist = !skip(&self.control.persistence, &log);
+        if !persist && self.persist_path.is_some() {
+            self.stats.record_skipped_persistence();
+        }
+        let targets = Targets {
+            cache: admits(&self.control.cache_level, &log),
+            persist: persist
+                && route.is_none_or(|rule| rule.persist)
+                && admits(&self.control.persist_level, &log),
+        };
+        if !targets.cache && !targets.persist {
+            return;
+        }
+        if self.control.closed.load(Ordering::Relaxed) {
+            self.stats.record_dropped(1);
+            return;
+        }
+        let Some(writer) = self.writer() else {
+            self.stats.record_dropped(1);
+            return;
+        };
+        if writer.try_send(WriterMsg::Entry(log, targets)).is_err() {
+            self.stats.record_dropped(1);
+        }
+    }
+
+    /// 取得写入通道，首次调用时在当前 tokio 运行时中启动写入任务
+    fn writer(&self) -> Option<&mpsc::Sender<WriterMsg<R>>> {
+        if let Some(writer) = self.writer.get() {
+            return Some(&writer.tx);
+        }
+        let handle = tokio::runtime::Handle::try_current().ok()?;
+        let writer = self.writer.get_or_init(|| {
+            let (tx, rx) = mpsc::channel(self.writer_capacity);
+            let task = handle.spawn(run_writer(
+                rx,
+                self.cache.clone(),
+                self.persist_path.clone(),
+                self.index_interval,
+                PersistFormat {
+                    json_schema: self.json_schema,
+                    timestamp_format: self.timestamp_format,
+                    serializer: self.serializer.clone(),
+                },
+                self.stats.clone(),
+            ));
+            WriterTask {
+                tx,
+                abort: task.abort_handle(),
+            }
+        });
+        Some(&writer.tx)
+    }
+}
+
+/// 唯一持有缓存写锁的任务：按批取出日志，一次加锁写入后追加到文件
+async fn run_writer<R: LogRecord>(
+    mut rx: mpsc::Receiver<WriterMsg<R>>,
+    cache: LogCache<R>,
+    persist_path: Option<PathBuf>,
+    index_interval: u64,
+    format: PersistFormat,
+    stats: Arc<LogStats>,
+) {
+    if let Some(path) = &persist_path {
+        let repaired = match format.framing() {
+            Framing::Newline => repair_partial_line(path),
+            Framing::LengthPrefixed => repair_partial_frame(path),
+        };
+        if repaired.is_err() {
+            stats.record_write_error();
+        }
+    }
+    let mut index = None;
+    let mut batch = Vec::with_capacity(WRITER_BATCH);
+    let mut entries = Vec::with_capacity(WRITER_BATCH);
+    let mut acks = Vec::new();
+    let mut flushes = Vec::new();
+    while rx.recv_many(&mut batch, WRITER_BATCH).await > 0 {
+        for msg in batch.drain(..) {
+            match msg {
+                WriterMsg::Entry(log, targets) => entries.push((log, targets)),
+                WriterMsg::Close(ack) => acks.push(ack),
+                WriterMsg::Flush(ack) => flushes.push(ack),
+            }
+        }
+        if let Some(path) = &persist_path {
+            persist(path, &entries, &stats, index_interval, &format, &mut index);
+        }
+        {
+            let mut cache = cache.write().await;
+            let mut cached = 0;
+            for (log, targets) in entries.drain(..) {
+                if targets.cache {
+                    cache.push(log);
+                    cached += 1;
+                }
+            }
+            stats.record_cached(cached);
+            let size = cache.stats();
+            stats.record_cache_size(size.entries as u64, size.bytes as u64);
+        }
+        for ack in flushes.drain(..) {
+            let _ = ack.send(());
+        }
+        if !acks.is_empty() {
+            for ack in acks.drain(..) {
+                let _ = ack.send(());
+            }
+            return;
+        }
+    }
+}
+
+/// 持久化文件中一条记录的写法
+struct PersistFormat {
+    json_schema: JsonSchema,
+    timestamp_format: TimestampFormat,
+    serializer: Option<Arc<dyn LogSerializer>>,
+}
+
+impl PersistFormat {
+    fn framing(&self) -> Framing {
+        self.serializer
+            .as_ref()
+            .map_or(Framing::Newline, |serializer| serializer.framing())
+    }
+
+    /// 编码一条记录（含分隔）追加到 `out`；未配置 serializer 时不经过中间的 JSON 值
+    fn encode<R: LogRecord>(&self, log: &R, out: &mut Vec<u8>) -> serde_json::Result<()> {
+        let record = Timestamps(log, self.timestamp_format);
+        match &self.serializer {
+            None => {
+                out.extend_from_slice(self.json_schema.to_line(&record)?.as_bytes());
+                out.push(b'\n');
+            }
+            Some(serializer) => {
+                let value = self.json_schema.to_value(&record)?;
+                encode_record(serializer.as_ref(), &value, out);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 追加写入一批日志；整批都被暂停跳过时不打开（也不创建）文件
+///
+/// `index_interval` 非 0 时同步维护索引旁路文件（见 `index` 模块），
+/// 数据文件长度与索引记录的不一致（被截断、轮转）时重新建立索引状态。
+fn persist<R: LogRecord>(
+    path: &Path,
+    batch: &[(R, Targets)],
+    stats: &LogStats,
+    index_interval: u64,
+    format: &PersistFormat,
+    index: &mut Option<IndexWriter>,
+) {
+    let mut file = None;
+    let mut buf = Vec::new();
+    for (log, _) in batch.iter().filter(|(_, targets)| targets.persist) {
+        let file = match &mut file {
+            Some(file) => file,
+            None => match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(opened) => {
+                    // 索引按行定位，只用于换行分隔的文件
+                    if index_interval > 0 && format.framing() == Framing::Newline {
+                        sync_index(path, &opened, index_interval, index);
+                    }
+                    file.insert(opened)
+                }
+                Err(_) => {
+                    stats.record_write_error();
+                    return;
+                }
+            },
+        };
+        // 自定义记录类型可能无法序列化为 JSON 对象，计为写入失败并跳过该条
+        buf.clear();
+        if format.encode(log, &mut buf).is_err() {
+            stats.record_write_error();
+            continue;
+        }
+        match file.write_all(&buf) {
+            Ok(()) => {
+                stats.record_write(buf.len() as u64);
+                stats.record_persisted();
+                if let Some(writer) = index {
+                    if writer
+                        .written(buf.len() as u64, log.timestamp_millis())
+                        .is_err()
+                    {
+                        stats.record_write_error();
+                        *index = None;
+                    }
+                }
+            }
+            Err(_) => {
+                stats.record_write_error();
+                *index = None;
+            }
+        }
+    }
+}
+
+/// 末尾不完整行移入的旁路文件：`logs.jsonl` -> `logs.jsonl.partial`
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// 修复进程中途被杀留下的半行：写入任务启动时调用一次
+///
+/// 文件不以换行结尾时检查最后一行：是完整的 JSON 对象则只补上换行；
+/// 否则把这段字节原样追加到 `partial_path`（另加一个换行分隔），并把数据文件截断到最后一个换行之后。
+/// 返回移走的字节数，文件不存在或无需移走时返回 None。
+pub fn repair_partial_line(path: &Path) -> io::Result<Option<u64>> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+
+    // 从末尾向前按块查找最后一个换行
+    let mut line_start = len;
+    let mut chunk = [0u8; 4096];
+    while line_start > 0 {
+        let n = chunk.len().min(line_start as usize);
+        file.seek(SeekFrom::Start(line_start - n as u64))?;
+        file.read_exact(&mut chunk[..n])?;
+        match chunk[..n].iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                line_start -= (n - i - 1) as u64;
+                break;
+            }
+            None => line_start -= n as u64,
+        }
+    }
+    if line_start == len {
+        return Ok(None);
+    }
+
+    let mut tail = Vec::with_capacity((len - line_start) as usize);
+    file.seek(SeekFrom::Start(line_start))?;
+    file.read_to_end(&mut tail)?;
+    if serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&tail).is_ok() {
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(b"\n")?;
+        return Ok(None);
+    }
+
+    let mut side = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(partial_path(path))?;
+    tail.push(b'\n');
+    side.write_all(&tail)?;
+    file.set_len(line_start)?;
+    Ok(Some(tail.len() as u64 - 1))
+}
+
+/// 同 `repair_partial_line`，用于长度前缀分隔的文件（见 `serializer::Framing`）
+///
+/// 从头按长度前缀逐条跳过，末尾不完整的记录原样追加到 `partial_path`，并把数据文件截断到最后一条完整记录之后。
+pub fn repair_partial_frame(path: &Path) -> io::Result<Option<u64>> {
+    let file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    let mut reader = io::BufReader::with_capacity(64 * 1024, file);
+    let mut complete = 0;
+    let mut header = [0u8; 4];
+    while len - complete >= 4 {
+        reader.read_exact(&mut header)?;
+        let record = u32::from_be_bytes(header) as u64;
+        if complete + 4 + record > len {
+            break;
+        }
+        reader.seek_relative(record as i64)?;
+        complete += 4 + record;
+    }
+    if complete == len {
+        return Ok(None);
+    }
+
+    let mut file = reader.into_inner();
+    let mut tail = Vec::with_capacity((len - complete) as usize);
+    file.seek(SeekFrom::Start(complete))?;
+    file.read_to_end(&mut tail)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(partial_path(path))?
+        .write_all(&tail)?;
+    file.set_len(complete)?;
+    Ok(Some(tail.len() as u64))
+}
+
+/// 确保索引状态与数据文件当前长度一致，必要时（重新）打开；失败时本批不维护索引
+fn sync_index(path: &Path, file: &File, interval: u64, index: &mut Option<IndexWriter>) {
+    let data_len = file.metadata().map_or(0, |m| m.len());
+    if index
+        .as_ref()
+        .is_some_and(|writer| writer.in_sync(data_len))
+    {
+        return;
+    }
+    *index = IndexWriter::open(path, interval).ok();
+}
+
+/// `setup_tracing_with_broadcast` 安装的全局管线，可用于运行时暂停 / 恢复
+pub fn global_pipeline() -> Option<&'static Pipeline> {
+    GLOBAL_PIPELINE.get()
+}
+
+/// 全局管线的运行状态，`setup_tracing_with_broadcast` 之前返回 None
+pub fn tracing_status() -> Option<TracingStatus> {
+    GLOBAL_PIPELINE.get().map(Pipeline::status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pipeline_emit_entry() {
+        let path =
+            std::env::temp_dir().join(format!("listen_tracing_emit_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.clone()));
+        let entry = |level: &str, message: &str| LogEntry {
+            timestamp: "2024-06-01T00:00:00.000+00:00".into(),
+            level: level.to_string(),
+            target: "sse::test".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        };
+
+        pipeline.emit_entry(entry("ERROR", "synthetic")).await;
+        // 返回时已写入缓存和文件，无需轮询
+        assert_eq!(pipeline.cache().read().await.len(), 1);
+        let persisted = std::fs::read_to_string(&path).unwrap();
+        let line: LogEntry = serde_json::from_str(persisted.trim_end()).unwrap();
+        assert_eq!(line, entry("ERROR", "synthetic"));
+        assert_eq!(rx.recv().await.unwrap().message, "synthetic");
+
+        pipeline.pause_persistence("test");
+        pipeline.set_cache_level(Some(Level::WARN));
+        pipeline.emit_entry(entry("INFO", "skipped")).await;
+        // 暂停时只有管理日志本身落盘
+        let persisted: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap().message)
+            .collect();
+        assert_eq!(persisted, ["synthetic", "persistence paused"]);
+        let cached: Vec<String> = pipeline
+            .cache()
+            .read()
+            .await
+            .to_vec()
+            .into_iter()
+            .map(|log| log.message)
+            .collect();
+        assert_eq!(cached, ["synthetic", "persistence paused"]);
+
+        let stats = pipeline.stats().snapshot();
+        assert_eq!((stats.events.error, stats.events.info), (1, 1));
+        assert_eq!(stats.skipped_persistence, 1);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_full_writer_channel_drops() {
+        let (tx, rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(None);
+        pipeline.set_writer_capacity(2);
+
+        // 单线程运行时下写入任务在让出之前无法消费，第 3 条起通道已满
+        for i in 0..5 {
+            pipeline.ingest(LogEntry {
+                level: "INFO".to_string(),
+                message: format!("burst {}", i),
+                ..Default::default()
+            });
+        }
+        let status = pipeline.status();
+        assert_eq!(status.stats.dropped, 3);
+        assert_eq!(status.writer_backlog, 2);
+
+        let logs = crate::broadcast::tests::wait_for_cache(pipeline.cache(), 2).await;
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[1].message, "burst 1");
+        // 广播不受写入通道影响
+        assert_eq!(rx.len(), 5);
+        assert_eq!(pipeline.status().writer_backlog, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let path =
+            std::env::temp_dir().join(format!("listen_tracing_pause_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.clone()));
+        let entry = |message: &str| LogEntry {
+            level: "INFO".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        };
+
+        pipeline.ingest(entry("before"));
+        let handle = pipeline.clone();
+        std::thread::spawn(move || {
+            handle.pause_persistence("ops");
+            handle.pause_broadcast("ops");
+        })
+        .join()
+        .unwrap();
+        assert!(pipeline.is_paused());
+        pipeline.ingest(entry("during"));
+        let status = pipeline.status();
+        assert!(status.persistence_paused && status.broadcast_paused);
+        assert_eq!(status.stats.skipped_persistence, 1);
+        pipeline.resume_persistence("ops");
+        pipeline.resume_broadcast("ops");
+        pipeline.resume_broadcast("ops");
+        assert!(!pipeline.is_paused());
+        assert!(!pipeline.status().persistence_paused);
+        pipeline.ingest(entry("after"));
+
+        crate::broadcast::tests::wait_for_cache(pipeline.cache(), 7).await;
+        let persisted: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap().message)
+            .collect();
+        assert_eq!(
+            persisted,
+            [
+                "before",
+                "persistence paused",
+                "broadcast paused",
+                "persistence resumed",
+                "broadcast resumed",
+                "after"
+            ]
+        );
+
+        let mut broadcasted = Vec::new();
+        while let Ok(log) = rx.try_recv() {
+            broadcasted.push(log);
+        }
+        let messages: Vec<&str> = broadcasted.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "before",
+                "persistence paused",
+                "broadcast paused",
+                "persistence resumed",
+                "broadcast resumed",
+                "after"
+            ]
+        );
+        assert_eq!(broadcasted[4].fields["by"], "ops");
+        assert_eq!(broadcasted[4].fields["skipped_total"], 1);
+
+        let stats = pipeline.stats().snapshot();
+        assert_eq!(stats.skipped_persistence, 1);
+        assert_eq!(stats.skipped_broadcast, 1);
+        // 缓存不受暂停影响
+        assert_eq!(pipeline.cache().read().await.len(), 7);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_route_rules() {
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_routes_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, mut rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.clone()));
+        pipeline.set_routes(
+            RouteRules::parse(
+                r#"route "payments::*" { persist = false }
+                   route "debug::*" { broadcast = false }"#,
+            )
+            .unwrap(),
+        );
+        for (target, message) in [
+            ("payments::card", "charge"),
+            ("orders", "placed"),
+            ("debug::dump", "state"),
+            ("payments", "refund"),
+        ] {
+            pipeline.ingest(LogEntry {
+                target: target.to_string(),
+                message: message.to_string(),
+                ..Default::default()
+            });
+        }
+
+        crate::broadcast::tests::wait_for_cache(pipeline.cache(), 4).await;
+        let persisted: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap().message)
+            .collect();
+        assert_eq!(persisted, ["placed", "state"]);
+
+        let mut broadcasted = Vec::new();
+        while let Ok(log) = rx.try_recv() {
+            broadcasted.push(log.message);
+        }
+        assert_eq!(broadcasted, ["charge", "placed", "refund"]);
+
+        let matched: Vec<(String, u64)> = pipeline
+            .route_stats()
+            .into_iter()
+            .map(|s| (s.rule.pattern, s.matched))
+            .collect();
+        assert_eq!(
+            matched,
+            [("payments::*".to_string(), 2), ("debug::*".to_string(), 1)]
+        );
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_repair_partial_line() {
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_repair_{}.jsonl",
+            std::process::id()
+        ));
+        let partial = partial_path(&path);
+        let entry = |message: &str| LogEntry {
+            timestamp: "2024-06-01T00:00:00+00:00".into(),
+            level: "INFO".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        };
+        let head = format!(
+            "{}\n{}\n",
+            serde_json::to_string(&entry("first")).unwrap(),
+            serde_json::to_string(&entry("second")).unwrap()
+        );
+        let last = serde_json::to_string(&entry("订单已拒绝")).unwrap();
+        let utf8_start = last.find('订').unwrap();
+
+        // 各个截断位置，含多字节字符中间
+        for cut in [1, 10, utf8_start + 1, utf8_start + 2, last.len() - 1] {
+            let _ = std::fs::remove_file(&partial);
+            let mut data = head.clone().into_bytes();
+            data.extend_from_slice(&last.as_bytes()[..cut]);
+            std::fs::write(&path, &data).unwrap();
+
+            assert_eq!(repair_partial_line(&path).unwrap(), Some(cut as u64));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), head);
+            let mut moved = last.as_bytes()[..cut].to_vec();
+            moved.push(b'\n');
+            assert_eq!(std::fs::read(&partial).unwrap(), moved, "cut at {}", cut);
+        }
+
+        // 完整但缺少换行的行只补换行；已以换行结尾或不存在时不做任何事
+        std::fs::write(&path, format!("{}{}", head, last)).unwrap();
+        assert_eq!(repair_partial_line(&path).unwrap(), None);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}{}\n", head, last)
+        );
+        assert_eq!(repair_partial_line(&path).unwrap(), None);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(repair_partial_line(&path).unwrap(), None);
+
+        // 写入任务启动时自动修复，之后追加的行不会与半行粘连
+        let _ = std::fs::remove_file(&partial);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let mut data = head.clone().into_bytes();
+        data.extend_from_slice(&last.as_bytes()[..utf8_start + 1]);
+        std::fs::write(&path, &data).unwrap();
+        let (tx, _rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.clone()));
+        pipeline.ingest(entry("after restart"));
+        pipeline.writer_guard().flush_and_close().await;
+        let messages: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap().message)
+            .collect();
+        assert_eq!(messages, ["first", "second", "after restart"]);
+        assert_eq!(
+            crate::index::query_log_files(&[&path], None, None)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&partial);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_length_prefixed_persist_and_repair() {
+        use crate::serializer::{decode_records, JsonlSerializer, LogSerializer};
+
+        /// JSON 记录加长度前缀，不依赖二进制格式的 feature
+        struct FramedJson;
+        impl LogSerializer for FramedJson {
+            fn name(&self) -> &'static str {
+                "framed-json"
+            }
+            fn serialize(&self, record: &serde_json::Value, out: &mut Vec<u8>) {
+                JsonlSerializer.serialize(record, out);
+            }
+            fn deserialize(
+                &self,
+                bytes: &[u8],
+            ) -> Result<serde_json::Value, crate::serializer::DecodeError> {
+                JsonlSerializer.deserialize(bytes)
+            }
+            fn framing(&self) -> Framing {
+                Framing::LengthPrefixed
+            }
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("listen_tracing_framed_{}.bin", std::process::id()));
+        let partial = partial_path(&path);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&partial);
+        let messages = |path: &Path| -> Vec<String> {
+            decode_records(&FramedJson, &std::fs::read(path).unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|value| LogEntry::migrate(value).unwrap().message)
+                .collect()
+        };
+        let run = |message: &'static str| {
+            let path = path.clone();
+            async move {
+                let (tx, _rx) = broadcast::channel(16);
+                let mut pipeline = Pipeline::new(tx, LogCache::default());
+                pipeline.set_persist_path(Some(path));
+                pipeline.set_serializer(Arc::new(FramedJson));
+                pipeline.set_index_interval(1);
+                pipeline.ingest(LogEntry {
+                    level: "INFO".to_string(),
+                    message: format!("{}\nwith newline", message),
+                    ..Default::default()
+                });
+                pipeline.writer_guard().flush_and_close().await;
+            }
+        };
+        run("first").await;
+        assert_eq!(messages(&path), ["first\nwith newline"]);
+
+        // 被截断的记录在下次启动时移入旁路文件
+        let mut data = std::fs::read(&path).unwrap();
+        let complete = data.len();
+        data.extend_from_slice(&[0, 0, 0, 40, b'{']);
+        std::fs::write(&path, &data).unwrap();
+        run("second").await;
+        assert_eq!(
+            messages(&path),
+            ["first\nwith newline", "second\nwith newline"]
+        );
+        assert_eq!(std::fs::read(&partial).unwrap(), [0, 0, 0, 40, b'{']);
+        assert!(std::fs::read(&path).unwrap().len() > complete);
+        // 长度前缀文件不维护按行的索引
+        assert!(!crate::index::index_path(&path).exists());
+        assert_eq!(repair_partial_frame(&path).unwrap(), None);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&partial);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_then_closes() {
+        use std::sync::atomic::AtomicUsize;
+
+        fn entry(message: String) -> LogEntry {
+            LogEntry {
+                level: "INFO".to_string(),
+                message,
+                ..Default::default()
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_shutdown_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, _rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.clone()));
+
+        // 另一个组件持续写日志，直到关闭完成之后
+        let sent = Arc::new(AtomicUsize::new(0));
+        let producer = tokio::spawn({
+            let (pipeline, sent) = (pipeline.clone(), sent.clone());
+            async move {
+                for i in 0..5000 {
+                    pipeline.ingest(entry(format!("event {}", i)));
+                    sent.store(i + 1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        });
+
+        let (cancel, cancelled) = oneshot::channel::<()>();
+        let shutdown = tokio::spawn(
+            pipeline
+                .writer_guard()
+                .shutdown_on(cancelled, Duration::from_millis(50)),
+        );
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let before_cancel = sent.load(Ordering::SeqCst);
+        cancel.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(pipeline.status().draining);
+        shutdown.await.unwrap();
+        assert!(!pipeline.status().draining);
+        producer.abort();
+
+        let entries: Vec<LogEntry> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let (last, events) = entries.split_last().unwrap();
+        assert_eq!(last.message, "pipeline shutting down");
+        assert_eq!(last.target, ADMIN_TARGET);
+        assert_eq!(last.fields["grace_ms"], 50);
+        // 取消前的日志全部落盘，宽限期内的日志也照常写入
+        assert!(events.len() > before_cancel, "{}", events.len());
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(event.message, format!("event {}", i));
+        }
+
+        // 关闭后的日志不再写入，重复关闭直接返回
+        let dropped = pipeline.stats().snapshot().dropped;
+        pipeline.ingest(entry("late".to_string()));
+        assert_eq!(pipeline.stats().snapshot().dropped, dropped + 1);
+        pipeline
+            .writer_guard()
+            .shutdown(Duration::from_secs(60))
+            .await;
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+
+    #[tokio::test]
+    async fn test_flush_timeout_aborts_stuck_writer() {
+        let entry = |message: &str| LogEntry {
+            level: "INFO".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        };
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let mut pipeline = Pipeline::new(tx, cache.clone());
+        pipeline.set_persist_path(None);
+
+        // 持有缓存读锁模拟卡住的写入：写入任务取走第一条后等不到写锁
+        let stuck = cache.read().await;
+        pipeline.ingest(entry("first"));
+        tokio::task::yield_now().await;
+        pipeline.ingest(entry("second"));
+        pipeline.ingest(entry("third"));
+
+        let started = std::time::Instant::now();
+        let err = pipeline
+            .writer_guard()
+            .flush_and_close_timeout(Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let FlushError::Timeout { abandoned, .. } = err;
+        assert_eq!(abandoned, 2);
+        assert_eq!(err.code(), "LT-IO-003");
+        assert_eq!(pipeline.stats().snapshot().dropped, 2);
+
+        // 写入任务已中止：释放锁后也不会再写入缓存
+        drop(stuck);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(cache.read().await.is_empty());
+        pipeline.ingest(entry("after close"));
+        assert_eq!(pipeline.stats().snapshot().dropped, 3);
+
+        // 写入正常时与 flush_and_close 相同
+        let cache = LogCache::default();
+        let (tx, _rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, cache.clone());
+        pipeline.set_persist_path(None);
+        pipeline.ingest(entry("fine"));
+        pipeline
+            .writer_guard()
+            .flush_and_close_timeout(Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(cache.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_destination_levels() {
+        skip_if_events_compiled_out!();
+        use crate::BroadcastLogLayer;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_dest_levels_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, mut rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .with_persist_path(&path)
+            .with_persist_level(Some(Level::DEBUG))
+            .with_broadcast_level(Some(Level::INFO))
+            .with_cache_level(Some(Level::WARN));
+        let pipeline = layer.pipeline().clone();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::trace!("trace");
+        tracing::debug!("debug");
+        tracing::info!("info");
+        tracing::warn!("warn");
+        tracing::error!("error");
+        // 运行时放开广播
+        pipeline.set_broadcast_level(None);
+        assert_eq!(pipeline.broadcast_level(), None);
+        tracing::trace!("trace again");
+
+        let mut broadcasted = Vec::new();
+        while let Ok(log) = rx.try_recv() {
+            broadcasted.push(log.message);
+        }
+        assert_eq!(broadcasted, ["info", "warn", "error", "trace again"]);
+
+        pipeline.writer_guard().flush_and_close().await;
+        let cached: Vec<String> = cache
+            .read()
+            .await
+            .iter()
+            .map(|log| log.message.clone())
+            .collect();
+        assert_eq!(cached, ["warn", "error"]);
+        let persisted: Vec<String> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap().message)
+            .collect();
+        assert_eq!(persisted, ["debug", "info", "warn", "error"]);
+
+        let stats = pipeline.stats().snapshot();
+        assert_eq!(stats.events.total(), 6);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(
+            stats.destinations,
+            crate::DestinationCounts {
+                broadcast: 4,
+                cache: 2,
+                persist: 4,
+            }
+        );
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+}
@@ -0,0 +1,490 @@
+//! PostgreSQL 输出（需开启 `postgres` feature）
+//!
+//! `PostgresSink` 负责缓冲、按间隔批量生成多行 INSERT、自动建表和断线重试，
+//! 实际执行 SQL 交给调用方实现的 `PgExecutor`，因此不绑定具体驱动。
+//! 生成的 SQL 默认为 PostgreSQL 方言，`PgExecutor::dialect` 返回 `SqlDialect::Sqlite` 时
+//! 改用 SQLite 的建表语句与 `?` 占位符，便于本地开发或嵌入式部署。
+//! 以 sqlx 为例：
+//!
+//! ```ignore
+//! impl PgExecutor for sqlx::PgPool {
+//!     async fn execute(&self, sql: &str, params: &[Option<String>]) -> Result<u64, PgError> {
+//!         let mut query = sqlx::query(sql);
+//!         for param in params {
+//!             query = query.bind(param.clone());
+//!         }
+//!         Ok(query.execute(self).await?.rows_affected())
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::sink::{spawn_sink, LogSink, SinkError, SinkStats};
+use crate::{ConfigError, LogEntry};
+
+pub type PgError = Box<dyn std::error::Error + Send + Sync>;
+
+/// 每行绑定的参数个数：timestamp, level, target, message, fields, trace_id
+const COLUMNS_PER_ROW: usize = 6;
+
+/// 生成 SQL 使用的方言
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SqlDialect {
+    /// `$n` 占位符，`TIMESTAMPTZ` / `JSONB` 列，文本参数由 `::timestamptz` / `::jsonb` 转换
+    #[default]
+    Postgres,
+    /// `?` 占位符，timestamp 为 RFC 3339 文本，fields 为 JSON 文本（可用 `json_extract` 查询）
+    Sqlite,
+}
+
+impl SqlDialect {
+    /// 单条语句最多可插入的行数：Postgres 最多 65535 个绑定参数，SQLite 默认 32766 个
+    fn max_batch_rows(self) -> usize {
+        let max_params = match self {
+            SqlDialect::Postgres => u16::MAX as usize,
+            SqlDialect::Sqlite => 32_766,
+        };
+        max_params / COLUMNS_PER_ROW
+    }
+
+    fn create_table_sql(self, table: &str) -> String {
+        match self {
+            SqlDialect::Postgres => format!(
+                "CREATE TABLE IF NOT EXISTS {} (\
+                 id BIGSERIAL PRIMARY KEY, \
+                 timestamp TIMESTAMPTZ NOT NULL, \
+                 level TEXT NOT NULL, \
+                 target TEXT NOT NULL, \
+                 message TEXT NOT NULL, \
+                 fields JSONB NOT NULL DEFAULT '{{}}'::jsonb, \
+                 trace_id TEXT)",
+                table
+            ),
+            SqlDialect::Sqlite => format!(
+                "CREATE TABLE IF NOT EXISTS {} (\
+                 id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                 timestamp TEXT NOT NULL, \
+                 level TEXT NOT NULL, \
+                 target TEXT NOT NULL, \
+                 message TEXT NOT NULL, \
+                 fields TEXT NOT NULL DEFAULT '{{}}', \
+                 trace_id TEXT)",
+                table
+            ),
+        }
+    }
+
+    fn insert_sql(self, table: &str, rows: usize) -> String {
+        let values: Vec<String> = (0..rows)
+            .map(|row| match self {
+                SqlDialect::Postgres => {
+                    let p = row * COLUMNS_PER_ROW;
+                    format!(
+                        "(${}::timestamptz, ${}, ${}, ${}, ${}::jsonb, ${})",
+                        p + 1,
+                        p + 2,
+                        p + 3,
+                        p + 4,
+                        p + 5,
+                        p + 6
+                    )
+                }
+                SqlDialect::Sqlite => "(?, ?, ?, ?, ?, ?)".to_string(),
+            })
+            .collect();
+        format!(
+            "INSERT INTO {} (timestamp, level, target, message, fields, trace_id) VALUES {}",
+            table,
+            values.join(", ")
+        )
+    }
+}
+
+/// 执行参数化 SQL 的最小接口，参数均以文本绑定，由 SQL 里的显式类型转换还原
+pub trait PgExecutor: Send + Sync + 'static {
+    fn execute(
+        &self,
+        sql: &str,
+        params: &[Option<String>],
+    ) -> impl Future<Output = Result<u64, PgError>> + Send;
+
+    /// 连接的数据库方言，决定建表语句与占位符写法
+    fn dialect(&self) -> SqlDialect {
+        SqlDialect::Postgres
+    }
+}
+
+/// 批量写入 Postgres 的 sink
+pub struct PostgresSink<E: PgExecutor> {
+    executor: E,
+    table: String,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_buffer: usize,
+    auto_migrate: bool,
+    migrated: bool,
+    /// 上次写入失败：之后只在 flush 周期重试，不再由每条新日志触发，避免数据库恢复期间被逐条冲击
+    failing: bool,
+    buffer: VecDeque<LogEntry>,
+    stats: Arc<SinkStats>,
+}
+
+impl<E: PgExecutor> PostgresSink<E> {
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            table: "logs".to_string(),
+            batch_size: 200,
+            flush_interval: Duration::from_secs(1),
+            max_buffer: 10_000,
+            auto_migrate: false,
+            migrated: false,
+            failing: false,
+            buffer: VecDeque::new(),
+            stats: Arc::new(SinkStats::default()),
+        }
+    }
+
+    /// 目标表名，只允许字母、数字、下划线和 `.`（schema 限定），其余取值返回
+    /// `ConfigError`（`option` 为 `postgres_table`）
+    pub fn with_table(mut self, table: &str) -> Result<Self, ConfigError> {
+        if table.is_empty()
+            || !table
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(ConfigError::new(
+                "postgres_table",
+                table,
+                "only ASCII letters, digits, '_' and '.' are allowed",
+            ));
+        }
+        self.table = table.to_string();
+        Ok(self)
+    }
+
+    /// 每批的行数，超过方言的绑定参数上限时按上限拆分
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// 断线期间最多缓冲的条数，超出后丢弃最旧的条目并计入 dropped
+    pub fn with_max_buffer(mut self, max_buffer: usize) -> Self {
+        self.max_buffer = max_buffer.max(1);
+        self
+    }
+
+    /// 首次写入前执行 CREATE TABLE IF NOT EXISTS
+    pub fn with_auto_migrate(mut self, auto_migrate: bool) -> Self {
+        self.auto_migrate = auto_migrate;
+        self
+    }
+
+    /// sent 为成功插入的行数，failed 为失败的批次数，dropped 为因缓冲溢出丢弃的条数
+    pub fn stats(&self) -> Arc<SinkStats> {
+        self.stats.clone()
+    }
+
+    /// 按执行器的方言生成的建表语句
+    pub fn create_table_sql(&self) -> String {
+        self.executor.dialect().create_table_sql(&self.table)
+    }
+
+    fn row_params(entry: &LogEntry, params: &mut Vec<Option<String>>) {
+        params.push(Some(entry.timestamp.to_string()));
+        params.push(Some(entry.level.clone()));
+        params.push(Some(entry.target.clone()));
+        params.push(Some(entry.message.clone()));
+        params.push(Some(
+            serde_json::to_string(&entry.fields).unwrap_or_else(|_| "{}".to_string()),
+        ));
+        params.push(entry.correlation_id.clone());
+    }
+
+    async fn write_batches(&mut self) {
+        if self.auto_migrate && !self.migrated {
+            match self.executor.execute(&self.create_table_sql(), &[]).await {
+                Ok(_) => self.migrated = true,
+                Err(e) => {
+                    SinkError::send("postgres", "migration", e).report();
+                    self.stats.record_failed(1);
+                    self.failing = true;
+                    return;
+                }
+            }
+        }
+
+        let dialect = self.executor.dialect();
+        while !self.buffer.is_empty() {
+            let rows = self
+                .buffer
+                .len()
+                .min(self.batch_size)
+                .min(dialect.max_batch_rows());
+            let mut params = Vec::with_capacity(rows * COLUMNS_PER_ROW);
+            for entry in self.buffer.iter().take(rows) {
+                Self::row_params(entry, &mut params);
+            }
+            let sql = dialect.insert_sql(&self.table, rows);
+            match self.executor.execute(&sql, &params).await {
+                Ok(_) => {
+                    self.buffer.drain(..rows);
+                    self.stats.record_sent(rows as u64);
+                }
+                Err(e) => {
+                    // 保留缓冲区，下个 flush 周期重试
+                    SinkError::send("postgres", "insert", e).report();
+                    self.stats.record_failed(1);
+                    self.failing = true;
+                    return;
+                }
+            }
+        }
+        self.failing = false;
+    }
+}
+
+impl<E: PgExecutor> LogSink for PostgresSink<E> {
+    async fn send(&mut self, entry: &LogEntry) {
+        self.buffer.push_back(entry.clone());
+        if self.buffer.len() > self.max_buffer {
+            let overflow = self.buffer.len() - self.max_buffer;
+            self.buffer.drain(..overflow);
+            self.stats.record_dropped(overflow as u64);
+        }
+        if self.buffer.len() >= self.batch_size && !self.failing {
+            self.write_batches().await;
+        }
+    }
+
+    async fn flush(&mut self) {
+        self.write_batches().await;
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(self.flush_interval)
+    }
+}
+
+/// 在 `logs` 表不存在时按执行器的方言建表，供部署时显式迁移；表结构见 `PostgresSink::create_table_sql`
+pub async fn create_logs_table<E: PgExecutor>(executor: &E) -> Result<(), PgError> {
+    let sql = executor.dialect().create_table_sql("logs");
+    executor.execute(&sql, &[]).await.map(|_| ())
+}
+
+/// 订阅广播通道，按 `batch_size` 条或 `flush_interval` 攒批插入 `logs` 表；
+/// 插入失败时保留整批，之后只在 flush 周期重试，直到写入成功
+pub fn spawn_db_sink<E: PgExecutor>(
+    tx: &broadcast::Sender<LogEntry>,
+    executor: E,
+    batch_size: usize,
+    flush_interval: Duration,
+) -> JoinHandle<()> {
+    spawn_sink(
+        tx,
+        PostgresSink::new(executor)
+            .with_batch_size(batch_size)
+            .with_flush_interval(flush_interval),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockExecutor {
+        statements: Mutex<Vec<(String, Vec<Option<String>>)>>,
+        failures_left: AtomicUsize,
+    }
+
+    impl PgExecutor for Arc<MockExecutor> {
+        async fn execute(&self, sql: &str, params: &[Option<String>]) -> Result<u64, PgError> {
+            if self.failures_left.load(Ordering::SeqCst) > 0 {
+                self.failures_left.fetch_sub(1, Ordering::SeqCst);
+                return Err("connection reset".into());
+            }
+            self.statements
+                .lock()
+                .unwrap()
+                .push((sql.to_string(), params.to_vec()));
+            Ok((params.len() / COLUMNS_PER_ROW) as u64)
+        }
+    }
+
+    /// 内存 SQLite 上的执行器，参数按 `?` 顺序绑定
+    struct SqliteExecutor(Mutex<rusqlite::Connection>);
+
+    impl PgExecutor for Arc<SqliteExecutor> {
+        async fn execute(&self, sql: &str, params: &[Option<String>]) -> Result<u64, PgError> {
+            let connection = self.0.lock().unwrap();
+            Ok(connection.execute(sql, rusqlite::params_from_iter(params))? as u64)
+        }
+
+        fn dialect(&self) -> SqlDialect {
+            SqlDialect::Sqlite
+        }
+    }
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: "2024-06-01T00:00:00+00:00".into(),
+            level: "INFO".to_string(),
+            target: "pg_test".to_string(),
+            message: message.to_string(),
+            correlation_id: Some("trace-1".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_and_retries() {
+        let executor = Arc::new(MockExecutor::default());
+        let mut sink = PostgresSink::new(executor.clone())
+            .with_batch_size(2)
+            .with_max_buffer(3)
+            .with_auto_migrate(true);
+        let stats = sink.stats();
+
+        executor.failures_left.store(2, Ordering::SeqCst);
+        for message in ["a", "b", "c", "d"] {
+            sink.send(&entry(message)).await;
+        }
+        // 建表失败后，之后的日志不再逐条重试；缓冲区上限 3 导致最旧的 "a" 被丢弃
+        assert_eq!(stats.snapshot().failed, 1);
+        assert_eq!(executor.failures_left.load(Ordering::SeqCst), 1);
+        assert_eq!(stats.snapshot().dropped, 1);
+
+        // 只在 flush 周期重试，第二次建表仍失败，第三次一次性补写
+        sink.flush().await;
+        assert_eq!(stats.snapshot().failed, 2);
+        sink.flush().await;
+        let statements = executor.statements.lock().unwrap();
+        assert!(statements[0]
+            .0
+            .starts_with("CREATE TABLE IF NOT EXISTS logs"));
+        assert_eq!(statements.len(), 3);
+        assert!(statements[1].0.contains("($7::timestamptz"));
+        assert_eq!(statements[1].1[3].as_deref(), Some("b"));
+        assert_eq!(statements[1].1[5].as_deref(), Some("trace-1"));
+        assert_eq!(statements[2].1[3].as_deref(), Some("d"));
+        assert_eq!(stats.snapshot().sent, 3);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_db_sink() {
+        let executor = Arc::new(MockExecutor::default());
+        create_logs_table(&executor).await.unwrap();
+
+        let (tx, _rx) = broadcast::channel(16);
+        let handle = spawn_db_sink(&tx, executor.clone(), 2, Duration::from_secs(60));
+        executor.failures_left.store(1, Ordering::SeqCst);
+        for message in ["a", "b", "c"] {
+            tx.send(entry(message)).unwrap();
+        }
+        drop(tx);
+        handle.await.unwrap();
+
+        // 第一批插入失败后整批保留，关闭时与剩余条目一起写出
+        let statements = executor.statements.lock().unwrap();
+        assert!(statements[0]
+            .0
+            .starts_with("CREATE TABLE IF NOT EXISTS logs"));
+        let messages: Vec<&str> = statements[1..]
+            .iter()
+            .flat_map(|(_, params)| params.chunks(COLUMNS_PER_ROW))
+            .map(|row| row[3].as_deref().unwrap())
+            .collect();
+        assert_eq!(messages, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_dialect() {
+        let executor = Arc::new(SqliteExecutor(Mutex::new(
+            rusqlite::Connection::open_in_memory().unwrap(),
+        )));
+        create_logs_table(&executor).await.unwrap();
+        // 建表语句可重复执行
+        create_logs_table(&executor).await.unwrap();
+
+        let (tx, _rx) = broadcast::channel(16);
+        let handle = spawn_db_sink(&tx, executor.clone(), 2, Duration::from_secs(60));
+        let mut with_fields = entry("b");
+        with_fields
+            .fields
+            .insert("order_id".to_string(), serde_json::json!(42));
+        with_fields.correlation_id = None;
+        for entry in [entry("a"), with_fields, entry("c")] {
+            tx.send(entry).unwrap();
+        }
+        drop(tx);
+        handle.await.unwrap();
+
+        let connection = executor.0.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT message, json_extract(fields, '$.order_id'), trace_id FROM logs ORDER BY id",
+            )
+            .unwrap();
+        let rows: Vec<(String, Option<i64>, Option<String>)> = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let trace = Some("trace-1".to_string());
+        assert_eq!(
+            rows,
+            [
+                ("a".to_string(), None, trace.clone()),
+                ("b".to_string(), Some(42), None),
+                ("c".to_string(), None, trace),
+            ]
+        );
+        let (timestamp, level): (String, String) = connection
+            .query_row("SELECT DISTINCT timestamp, level FROM logs", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(
+            (timestamp.as_str(), level.as_str()),
+            ("2024-06-01T00:00:00+00:00", "INFO")
+        );
+    }
+
+    #[test]
+    fn test_with_table_validation() {
+        let sink = PostgresSink::new(Arc::new(MockExecutor::default()))
+            .with_table("audit.app_logs")
+            .unwrap();
+        assert!(sink
+            .create_table_sql()
+            .starts_with("CREATE TABLE IF NOT EXISTS audit.app_logs ("));
+
+        for table in ["", "logs; DROP TABLE users", "app-logs"] {
+            let err = PostgresSink::new(Arc::new(MockExecutor::default()))
+                .with_table(table)
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), "LT-CFG-001");
+            assert_eq!(
+                (err.option.as_str(), err.value.as_str()),
+                ("postgres_table", table)
+            );
+        }
+    }
+}
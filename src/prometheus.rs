@@ -0,0 +1,365 @@
+//! Prometheus 文本格式导出
+//!
+//! `LogStats::render_prometheus` 生成 text exposition format 0.0.4，可直接由已有的
+//! `/metrics` 处理函数返回（Content-Type 为 `text/plain; version=0.0.4`），不依赖 metrics 类 crate。
+//! 计数器取自只增不减的原子计数，跨多次抓取单调；缓存条目数与分位数为 gauge。
+//! 各指标按固定顺序输出，分位数按（字段，target 前缀）排序，前后两次输出可直接 diff。
+
+use std::fmt::{Display, Write as _};
+
+use crate::LogStats;
+
+impl LogStats {
+    /// 以 `prefix` 为指标名前缀（如 `listen_log`，不合法的字符替换为 `_`，空串表示不加前缀）
+    /// 渲染全部计数器与 gauge
+    pub fn render_prometheus(&self, prefix: &str) -> String {
+        let snapshot = self.snapshot();
+        let (cache_entries, cache_bytes) = self.cache_size();
+        let mut out = Exposition::new(prefix);
+
+        out.family(
+            "events_total",
+            "counter",
+            "Events seen by the layer, by level",
+        );
+        for (level, count) in [
+            ("error", snapshot.events.error),
+            ("warn", snapshot.events.warn),
+            ("info", snapshot.events.info),
+            ("debug", snapshot.events.debug),
+            ("trace", snapshot.events.trace),
+        ] {
+            out.sample("events_total", &[("level", level)], count);
+        }
+        for (name, help, value) in [
+            (
+                "dropped_total",
+                "Entries dropped because the writer channel was full or closed",
+                snapshot.dropped,
+            ),
+            (
+                "write_errors_total",
+                "Failed writes to the persistence or audit file",
+                snapshot.write_errors,
+            ),
+            (
+                "bytes_written_total",
+                "Bytes appended to the persistence file",
+                snapshot.bytes_written,
+            ),
+            (
+                "skipped_persistence_total",
+                "Entries not persisted while persistence was paused",
+                snapshot.skipped_persistence,
+            ),
+            (
+                "skipped_broadcast_total",
+                "Entries not broadcast while broadcast was paused",
+                snapshot.skipped_broadcast,
+            ),
+            (
+                "truncated_fields_total",
+                "Fields dropped by field limits",
+                snapshot.truncated_fields,
+            ),
+            (
+                "sampled_out_total",
+                "Events dropped by sampling",
+                snapshot.sampled_out,
+            ),
+            (
+                "non_numeric_fields_total",
+                "Non-numeric values seen in percentile fields",
+                snapshot.non_numeric_fields,
+            ),
+            (
+                "verbose_windows_total",
+                "Verbose capture windows opened after errors",
+                snapshot.verbose_windows,
+            ),
+            (
+                "verbose_entries_total",
+                "Extra entries captured inside verbose windows",
+                snapshot.verbose_entries,
+            ),
+        ] {
+            out.family(name, "counter", help);
+            out.sample(name, &[], value);
+        }
+
+        out.family(
+            "destination_entries_total",
+            "counter",
+            "Entries delivered to each destination",
+        );
+        for (destination, count) in [
+            ("broadcast", snapshot.destinations.broadcast),
+            ("cache", snapshot.destinations.cache),
+            ("persist", snapshot.destinations.persist),
+        ] {
+            out.sample(
+                "destination_entries_total",
+                &[("destination", destination)],
+                count,
+            );
+        }
+
+        out.family("cache_entries", "gauge", "Entries currently in the cache");
+        out.sample("cache_entries", &[], cache_entries);
+        out.family(
+            "cache_bytes",
+            "gauge",
+            "Estimated bytes currently in the cache",
+        );
+        out.sample("cache_bytes", &[], cache_bytes);
+
+        let percentiles = self.all_field_percentiles();
+        if !percentiles.is_empty() {
+            out.family(
+                "field_value",
+                "gauge",
+                "Approximate percentiles of tracked numeric fields since start",
+            );
+            for entry in &percentiles {
+                for (quantile, value) in [
+                    ("0.5", entry.percentiles.p50),
+                    ("0.9", entry.percentiles.p90),
+                    ("0.99", entry.percentiles.p99),
+                ] {
+                    out.sample(
+                        "field_value",
+                        &[
+                            ("field", &entry.field),
+                            ("target", &entry.target),
+                            ("quantile", quantile),
+                        ],
+                        Float(value),
+                    );
+                }
+            }
+            out.family(
+                "field_observations_total",
+                "counter",
+                "Values counted into field percentiles",
+            );
+            for entry in &percentiles {
+                out.sample(
+                    "field_observations_total",
+                    &[("field", &entry.field), ("target", &entry.target)],
+                    entry.percentiles.count,
+                );
+            }
+        }
+        out.out
+    }
+}
+
+struct Exposition {
+    prefix: String,
+    out: String,
+}
+
+impl Exposition {
+    fn new(prefix: &str) -> Self {
+        let mut sanitized: String = prefix
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+            sanitized.insert(0, '_');
+        }
+        if !sanitized.is_empty() && !sanitized.ends_with('_') {
+            sanitized.push('_');
+        }
+        Self {
+            prefix: sanitized,
+            out: String::new(),
+        }
+    }
+
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {}{} {}", self.prefix, name, help);
+        let _ = writeln!(self.out, "# TYPE {}{} {}", self.prefix, name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out.push_str(&self.prefix);
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            self.out.push('{');
+            for (i, (label, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.out.push(',');
+                }
+                let _ = write!(self.out, "{}=\"{}\"", label, escape_label_value(value));
+            }
+            self.out.push('}');
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+}
+
+/// 标签值中的反斜杠、双引号和换行需转义
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 按 exposition format 的写法输出浮点数（`NaN`、`+Inf`、`-Inf`）
+struct Float(f64);
+
+impl Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            v if v.is_nan() => f.write_str("NaN"),
+            v if v == f64::INFINITY => f.write_str("+Inf"),
+            v if v == f64::NEG_INFINITY => f.write_str("-Inf"),
+            v => write!(f, "{}", v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::{BTreeMap, HashMap};
+
+    /// 按行检查格式，返回 样本（名称 + 标签原文）-> 值
+    fn parse(text: &str) -> BTreeMap<String, f64> {
+        let mut types = HashMap::new();
+        let mut samples = BTreeMap::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                let (name, help) = rest.split_once(' ').expect("HELP without text");
+                assert!(is_metric_name(name) && !help.is_empty(), "{}", line);
+            } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(matches!(kind, "counter" | "gauge"), "{}", line);
+                assert!(
+                    types.insert(name, kind).is_none(),
+                    "duplicate TYPE: {}",
+                    line
+                );
+            } else {
+                let (series, value) = line.rsplit_once(' ').expect("sample without value");
+                let name = series.split('{').next().unwrap();
+                assert!(is_metric_name(name), "{}", line);
+                let kind = types
+                    .get(name)
+                    .unwrap_or_else(|| panic!("no TYPE: {}", line));
+                assert_eq!(*kind == "counter", name.ends_with("_total"), "{}", line);
+                if let Some(labels) = series.strip_prefix(name) {
+                    check_labels(labels);
+                }
+                let value: f64 = match value {
+                    "NaN" => f64::NAN,
+                    "+Inf" => f64::INFINITY,
+                    "-Inf" => f64::NEG_INFINITY,
+                    v => v.parse().unwrap_or_else(|_| panic!("bad value: {}", line)),
+                };
+                assert!(samples.insert(series.to_string(), value).is_none());
+            }
+        }
+        samples
+    }
+
+    fn is_metric_name(name: &str) -> bool {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    /// `{a="x",b="y\"z"}`：值内只允许 `\\`、`\"`、`\n` 三种转义
+    fn check_labels(labels: &str) {
+        if labels.is_empty() {
+            return;
+        }
+        let mut chars = labels
+            .strip_prefix('{')
+            .and_then(|l| l.strip_suffix('}'))
+            .expect("unbalanced braces")
+            .chars()
+            .peekable();
+        while chars.peek().is_some() {
+            let name: String = chars.by_ref().take_while(|c| *c != '=').collect();
+            assert!(is_metric_name(&name) && !name.contains(':'), "{}", labels);
+            assert_eq!(chars.next(), Some('"'), "{}", labels);
+            loop {
+                match chars.next().expect("unterminated label value") {
+                    '"' => break,
+                    '\\' => assert!(matches!(chars.next(), Some('\\' | '"' | 'n'))),
+                    '\n' => panic!("raw newline in {}", labels),
+                    _ => {}
+                }
+            }
+            if chars.peek().is_some() {
+                assert_eq!(chars.next(), Some(','), "{}", labels);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let stats = LogStats::default();
+        stats.track_field_percentiles(["latency_ms"], ["we\"ird\\x"]);
+        for (level, n) in [("ERROR", 42), ("INFO", 3)] {
+            for _ in 0..n {
+                stats.record_event(level);
+            }
+        }
+        stats.record_write_error();
+        stats.record_cache_size(10234, 1 << 20);
+        let value = json!(12.5);
+        stats.record_field_values("we\"ird\\x::db", |_| Some(&value));
+
+        let text = stats.render_prometheus("listen-log");
+        let before = parse(&text);
+        assert_eq!(before[r#"listen_log_events_total{level="error"}"#], 42.0);
+        assert_eq!(before["listen_log_write_errors_total"], 1.0);
+        assert_eq!(before["listen_log_cache_entries"], 10234.0);
+        assert_eq!(
+            before[r#"listen_log_field_observations_total{field="latency_ms",target="we\"ird\\x"}"#],
+            1.0
+        );
+        // 输出稳定，没有新数据时完全相同
+        assert_eq!(stats.render_prometheus("listen-log"), text);
+
+        stats.record_event("ERROR");
+        stats.record_dropped(2);
+        stats.record_cache_size(0, 0);
+        let after = parse(&stats.render_prometheus("listen-log"));
+        assert_eq!(
+            before.keys().collect::<Vec<_>>(),
+            after.keys().collect::<Vec<_>>()
+        );
+        for (series, value) in &before {
+            let name = series.split('{').next().unwrap();
+            if name.ends_with("_total") {
+                assert!(after[series] >= *value, "{} went backwards", series);
+            }
+        }
+        assert_eq!(after[r#"listen_log_events_total{level="error"}"#], 43.0);
+        assert_eq!(after["listen_log_cache_entries"], 0.0);
+
+        assert!(stats
+            .render_prometheus("")
+            .starts_with("# HELP events_total "));
+    }
+}
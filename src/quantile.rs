@@ -0,0 +1,383 @@
+//! 数值字段的近似分位数
+//!
+//! `QuantileSketch` 为 CKMS 流式分位数（Cormode 等，"Effective Computation of Biased Quantiles
+//! over Data Streams"）的目标分位数版本：只对 p50 / p90 / p99 保证秩误差，分别不超过
+//! 0.5% / 0.25% / 0.05%，内存随误差参数与数据分布增长而不随条目数线性增长。
+//!
+//! `FieldPercentiles` 按（字段名，target 前缀）分组维护这些草图，由 `LogStats` 持有：
+//! `BroadcastLogLayer::with_field_percentiles` 指定要跟踪的字段和前缀，之后每条日志中
+//! 这些字段的数值都会计入，非数值计入 `LogStatsSnapshot::non_numeric_fields` 后忽略。
+//! 前缀 `""` 总是存在，汇总所有 target。
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::routing::target_has_prefix;
+
+/// 目标分位数及其允许的秩误差
+const TARGETS: [(f64, f64); 3] = [(0.5, 0.005), (0.9, 0.0025), (0.99, 0.0005)];
+
+/// 攒够这么多个值再一次性合并进草图
+const BUFFER: usize = 512;
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    value: f64,
+    /// 与前一个样本之间的最小秩差
+    width: f64,
+    /// 秩的不确定范围
+    delta: f64,
+}
+
+/// CKMS 流式分位数草图，见模块文档
+#[derive(Clone, Debug, Default)]
+pub struct QuantileSketch {
+    samples: Vec<Sample>,
+    buffer: Vec<f64>,
+    /// 已合并进 `samples` 的条目数
+    merged: f64,
+}
+
+impl QuantileSketch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 计入一个值，NaN 被忽略
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.buffer.push(value);
+        if self.buffer.len() >= BUFFER {
+            self.flush();
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.merged as u64 + self.buffer.len() as u64
+    }
+
+    /// 第 `q` 分位数（0..=1），空草图返回 None；只有 `TARGETS` 中的分位数保证误差
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        self.flush();
+        let first = self.samples.first()?;
+        let rank = (q * self.merged).ceil();
+        let bound = rank + self.invariant(rank) / 2.0;
+        let mut previous = first.value;
+        let mut r = 0.0;
+        for pair in self.samples.windows(2) {
+            r += pair[0].width;
+            if r + pair[1].width + pair[1].delta > bound {
+                return Some(previous);
+            }
+            previous = pair[1].value;
+        }
+        Some(previous)
+    }
+
+    /// p50 / p90 / p99 及条目数，空草图返回 None
+    pub fn percentiles(&mut self) -> Option<Percentiles> {
+        Some(Percentiles {
+            p50: self.quantile(0.5)?,
+            p90: self.quantile(0.9)?,
+            p99: self.quantile(0.99)?,
+            count: self.count(),
+        })
+    }
+
+    /// 秩 `r` 处允许的样本宽度
+    fn invariant(&self, r: f64) -> f64 {
+        TARGETS
+            .iter()
+            .map(|&(q, epsilon)| {
+                if q * self.merged <= r {
+                    2.0 * epsilon * r / q
+                } else {
+                    2.0 * epsilon * (self.merged - r) / (1.0 - q)
+                }
+            })
+            .fold(f64::MAX, f64::min)
+    }
+
+    /// 把缓冲的值按序合并进样本，然后压缩
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.sort_by(f64::total_cmp);
+        let mut r = 0.0;
+        let mut i = 0;
+        for &value in &buffer {
+            while i < self.samples.len() && self.samples[i].value <= value {
+                r += self.samples[i].width;
+                i += 1;
+            }
+            // 插在两端时秩是确定的
+            let delta = if i == 0 || i == self.samples.len() {
+                0.0
+            } else {
+                (self.invariant(r).floor() - 1.0).max(0.0)
+            };
+            self.samples.insert(
+                i,
+                Sample {
+                    value,
+                    width: 1.0,
+                    delta,
+                },
+            );
+            i += 1;
+            r += 1.0;
+            self.merged += 1.0;
+        }
+        buffer.clear();
+        self.buffer = buffer;
+        self.compress();
+    }
+
+    /// 从后往前合并相邻样本，保持宽度不超过不变式
+    fn compress(&mut self) {
+        if self.samples.len() < 2 {
+            return;
+        }
+        let mut next = self.samples.len() - 1;
+        let mut r = self.merged - 1.0 - self.samples[next].width;
+        for i in (0..next).rev() {
+            let current = self.samples[i];
+            let upper = self.samples[next];
+            if current.width + upper.width + upper.delta <= self.invariant(r) {
+                self.samples[next].width += current.width;
+                self.samples.remove(i);
+                next -= 1;
+            } else {
+                next = i;
+            }
+            r -= current.width;
+        }
+    }
+}
+
+/// 一组值的近似分位数
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    /// 计入的值的个数
+    pub count: u64,
+}
+
+/// `FieldPercentiles::all` 中的一项
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FieldPercentilesEntry {
+    pub field: String,
+    /// target 前缀，`""` 表示所有 target
+    pub target: String,
+    #[serde(flatten)]
+    pub percentiles: Percentiles,
+}
+
+#[derive(Debug, Default)]
+struct Tracked {
+    fields: Vec<String>,
+    /// 不含总是存在的 `""`
+    target_prefixes: Vec<String>,
+    sketches: BTreeMap<(String, String), QuantileSketch>,
+}
+
+/// 按（字段名，target 前缀）分组的分位数草图，见模块文档
+#[derive(Debug, Default)]
+pub struct FieldPercentiles {
+    /// 未配置字段时跳过加锁
+    enabled: AtomicBool,
+    tracked: Mutex<Tracked>,
+}
+
+impl FieldPercentiles {
+    /// 设置要跟踪的字段与 target 前缀，清空已有数据
+    pub fn track<F, P>(&self, fields: F, target_prefixes: P)
+    where
+        F: IntoIterator,
+        F::Item: Into<String>,
+        P: IntoIterator,
+        P::Item: Into<String>,
+    {
+        let mut tracked = self.lock();
+        *tracked = Tracked {
+            fields: fields.into_iter().map(Into::into).collect(),
+            target_prefixes: target_prefixes
+                .into_iter()
+                .map(Into::into)
+                .filter(|prefix: &String| !prefix.is_empty())
+                .collect(),
+            sketches: BTreeMap::new(),
+        };
+        self.enabled
+            .store(!tracked.fields.is_empty(), Ordering::Relaxed);
+    }
+
+    /// 跟踪中的字段名
+    pub fn fields(&self) -> Vec<String> {
+        self.lock().fields.clone()
+    }
+
+    /// 计入一条日志中被跟踪字段的值，返回其中非数值的个数
+    pub fn observe<'a>(&self, target: &str, field: impl Fn(&str) -> Option<&'a Value>) -> u64 {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let mut tracked = self.lock();
+        let Tracked {
+            fields,
+            target_prefixes,
+            sketches,
+        } = &mut *tracked;
+        let mut non_numeric = 0;
+        for name in fields.iter() {
+            let Some(value) = field(name) else {
+                continue;
+            };
+            let Some(value) = value.as_f64() else {
+                non_numeric += 1;
+                continue;
+            };
+            let prefixes = std::iter::once("").chain(
+                target_prefixes
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|prefix| target_has_prefix(target, prefix)),
+            );
+            for prefix in prefixes {
+                sketches
+                    .entry((name.clone(), prefix.to_string()))
+                    .or_default()
+                    .insert(value);
+            }
+        }
+        non_numeric
+    }
+
+    /// `field` 在 target 前缀 `target` 下的分位数（`""` 为所有 target），没有数据时返回 None
+    pub fn get(&self, field: &str, target: &str) -> Option<Percentiles> {
+        self.lock()
+            .sketches
+            .get_mut(&(field.to_string(), target.to_string()))?
+            .percentiles()
+    }
+
+    /// 所有有数据的分组，按字段名、前缀排序
+    pub fn all(&self) -> Vec<FieldPercentilesEntry> {
+        self.lock()
+            .sketches
+            .iter_mut()
+            .filter_map(|((field, target), sketch)| {
+                Some(FieldPercentilesEntry {
+                    field: field.clone(),
+                    target: target.clone(),
+                    percentiles: sketch.percentiles()?,
+                })
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tracked> {
+        self.tracked.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 确定性的伪随机数，不依赖外部 crate
+    fn lcg(seed: &mut u64) -> f64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (*seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    #[test]
+    fn test_sketch_accuracy() {
+        let mut seed = 7;
+        // 均匀分布、长尾分布（近似延迟）与有序输入
+        let datasets: [Vec<f64>; 3] = [
+            (0..20_000).map(|_| lcg(&mut seed) * 1000.0).collect(),
+            (0..20_000)
+                .map(|_| (-(1.0 - lcg(&mut seed)).ln() * 50.0).powf(1.5))
+                .collect(),
+            (0..20_000).map(f64::from).collect(),
+        ];
+        for data in datasets {
+            let mut sketch = QuantileSketch::new();
+            for &value in &data {
+                sketch.insert(value);
+            }
+            let mut sorted = data.clone();
+            sorted.sort_by(f64::total_cmp);
+            let n = sorted.len() as f64;
+            for (q, epsilon) in TARGETS {
+                let estimate = sketch.quantile(q).unwrap();
+                // 估计值在排序后的秩与目标秩之差不超过 epsilon * n
+                let low = sorted.partition_point(|&v| v < estimate) as f64;
+                let high = sorted.partition_point(|&v| v <= estimate) as f64;
+                let target = q * n;
+                let error = if target < low {
+                    low - target
+                } else if target > high {
+                    target - high
+                } else {
+                    0.0
+                };
+                assert!(
+                    error <= epsilon * n + 1.0,
+                    "q={} estimate={} rank error {}",
+                    q,
+                    estimate,
+                    error
+                );
+            }
+            assert_eq!(sketch.count(), 20_000);
+            assert!(sketch.samples.len() < 2_000, "{}", sketch.samples.len());
+        }
+        assert!(QuantileSketch::new().percentiles().is_none());
+    }
+
+    #[test]
+    fn test_field_groups_and_non_numeric() {
+        let percentiles = FieldPercentiles::default();
+        let observe = |target: &str, value: Value| {
+            percentiles.observe(target, |name| (name == "latency_ms").then_some(&value))
+        };
+        // 未配置时不计入
+        assert_eq!(observe("app", json!(1)), 0);
+
+        percentiles.track(["latency_ms"], ["app::orders", ""]);
+        for i in 1..=100 {
+            observe("app::orders::fill", json!(i));
+            observe("app::ordersx", json!(i * 10));
+        }
+        assert_eq!(observe("app", json!("slow")), 1);
+
+        let orders = percentiles.get("latency_ms", "app::orders").unwrap();
+        assert_eq!(orders.count, 100);
+        assert_eq!(orders.p50, 50.0);
+        assert_eq!(orders.p99, 99.0);
+        let all = percentiles.get("latency_ms", "").unwrap();
+        assert_eq!(all.count, 200);
+        assert!(percentiles.get("fill_ms", "").is_none());
+        let groups: Vec<_> = percentiles
+            .all()
+            .into_iter()
+            .map(|entry| entry.target)
+            .collect();
+        assert_eq!(groups, ["", "app::orders"]);
+    }
+}
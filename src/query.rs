@@ -0,0 +1,704 @@
+//! `LogQuery::q` 使用的查询表达式，例如
+//! `level>=warn AND target:executor AND (msg~"time*out" OR msg:refused) AND ts>2024-06-01T00:00:00Z`
+//!
+//! - 字段：`level`、`target`、`msg` / `message`、`ts` / `timestamp`、`cid` / `correlation_id`、
+//!   `fields.<name>`
+//! - 文本比较：`:` 包含、`=` 相等、`!=` 不等、`~` 通配（`*` 任意串，`?` 单个字符，可出现在任意位置），
+//!   均忽略大小写；缺失的字段按空字符串处理
+//! - `level` 与 `ts` 支持 `= != < <= > >=`（`:` 等同 `=`），`level` 按严重程度比较，
+//!   `ts` 接受 RFC 3339 或 `YYYY-MM-DD`（UTC 零点）
+//! - `fields.<name>` 另支持 `< <= > >=` 数值比较，字段值须为数字（或可解析为数字的字符串），否则不匹配
+//! - 组合：`NOT` > `AND` > `OR`，可用括号分组，关键字不区分大小写
+//! - 值可以是不含空白和 `)` 的裸词，或带 `\"`、`\\` 转义的双引号字符串
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde_json::Value;
+use tracing::Level;
+
+use crate::LogEntry;
+
+/// 查询表达式语法树
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryExpr {
+    And(Vec<QueryExpr>),
+    Or(Vec<QueryExpr>),
+    Not(Box<QueryExpr>),
+    Level(CmpOp, Level),
+    Timestamp(CmpOp, DateTime<FixedOffset>),
+    Text {
+        field: TextField,
+        op: TextOp,
+        value: String,
+    },
+    /// `fields.<field>` 的数值比较
+    Number {
+        field: String,
+        op: CmpOp,
+        value: f64,
+    },
+}
+
+/// 有序比较
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// 文本比较
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextOp {
+    Contains,
+    Equals,
+    NotEquals,
+    Wildcard,
+}
+
+/// 可做文本比较的字段
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextField {
+    Target,
+    Message,
+    CorrelationId,
+    Field(String),
+}
+
+/// 解析失败：`position` 为输入中的字节偏移，`expected` 为该位置可接受的内容
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryParseError {
+    pub position: usize,
+    pub expected: Vec<String>,
+    pub found: String,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid query at position {}: expected {}, found {}",
+            self.position,
+            self.expected.join(" or "),
+            self.found
+        )
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+impl QueryParseError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        "LT-QRY-001"
+    }
+}
+
+const FIELDS: [&str; 6] = ["level", "target", "msg", "ts", "cid", "fields.<name>"];
+const OPERATORS: [&str; 8] = [">=", "<=", "!=", ">", "<", "=", ":", "~"];
+const ORDERED_OPERATORS: [&str; 7] = [":", "=", "!=", "<", "<=", ">", ">="];
+const TEXT_OPERATORS: [&str; 4] = [":", "=", "!=", "~"];
+
+impl QueryExpr {
+    /// 解析查询表达式
+    pub fn parse(input: &str) -> Result<QueryExpr, QueryParseError> {
+        let mut parser = Parser { input, pos: 0 };
+        let expr = parser.parse_or()?;
+        parser.skip_ws();
+        if parser.pos < input.len() {
+            return Err(parser.error(&["AND", "OR", "end of input"]));
+        }
+        Ok(expr)
+    }
+
+    /// 判断日志是否满足表达式
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        match self {
+            QueryExpr::And(terms) => terms.iter().all(|term| term.matches(entry)),
+            QueryExpr::Or(terms) => terms.iter().any(|term| term.matches(entry)),
+            QueryExpr::Not(inner) => !inner.matches(entry),
+            QueryExpr::Level(op, level) => entry
+                .level_value()
+                .is_some_and(|actual| op.holds(severity(actual).cmp(&severity(*level)))),
+            QueryExpr::Timestamp(op, ts) => entry
+                .timestamp
+                .to_datetime()
+                .is_some_and(|actual| op.holds(actual.cmp(&ts.to_utc()))),
+            QueryExpr::Text { field, op, value } => {
+                let actual = field.value(entry).to_lowercase();
+                let value = value.to_lowercase();
+                match op {
+                    TextOp::Contains => actual.contains(&value),
+                    TextOp::Equals => actual == value,
+                    TextOp::NotEquals => actual != value,
+                    TextOp::Wildcard => wildcard_contains(&actual, &value),
+                }
+            }
+            QueryExpr::Number { field, op, value } => {
+                let actual = match entry.fields.get(field) {
+                    Some(Value::Number(n)) => n.as_f64(),
+                    Some(Value::String(s)) => s.trim().parse().ok(),
+                    _ => None,
+                };
+                actual
+                    .and_then(|actual| actual.partial_cmp(value))
+                    .is_some_and(|ordering| op.holds(ordering))
+            }
+        }
+    }
+}
+
+impl CmpOp {
+    fn holds(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            CmpOp::Eq => ordering == Equal,
+            CmpOp::Ne => ordering != Equal,
+            CmpOp::Lt => ordering == Less,
+            CmpOp::Le => ordering != Greater,
+            CmpOp::Gt => ordering == Greater,
+            CmpOp::Ge => ordering != Less,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            CmpOp::Eq => "=",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        }
+    }
+}
+
+impl TextOp {
+    fn as_str(self) -> &'static str {
+        match self {
+            TextOp::Contains => ":",
+            TextOp::Equals => "=",
+            TextOp::NotEquals => "!=",
+            TextOp::Wildcard => "~",
+        }
+    }
+}
+
+impl TextField {
+    fn value(&self, entry: &LogEntry) -> String {
+        match self {
+            TextField::Target => entry.target.clone(),
+            TextField::Message => entry.message.clone(),
+            TextField::CorrelationId => entry.correlation_id.clone().unwrap_or_default(),
+            TextField::Field(name) => match entry.fields.get(name) {
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+                None => String::new(),
+            },
+        }
+    }
+}
+
+/// 严重程度，ERROR 最高
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 5,
+        Level::WARN => 4,
+        Level::INFO => 3,
+        Level::DEBUG => 2,
+        Level::TRACE => 1,
+    }
+}
+
+/// pattern 是否匹配 text 的某个子串，`*` 匹配任意串，`?` 匹配单个字符
+fn wildcard_contains(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    (0..=text.len()).any(|start| wildcard_prefix(&text[start..], &pattern))
+}
+
+/// pattern 是否匹配 text 的某个前缀
+fn wildcard_prefix(text: &[char], pattern: &[char]) -> bool {
+    // 回溯到最近一个 `*`，经典的线性通配匹配
+    let (mut t, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    loop {
+        if p == pattern.len() {
+            return true;
+        }
+        if pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if t < text.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            t += 1;
+            p += 1;
+        } else if let Some((star_p, star_t)) = star.filter(|&(_, star_t)| star_t < text.len()) {
+            star = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, expected: &[&str]) -> QueryParseError {
+        self.error_at(self.pos, expected)
+    }
+
+    fn error_at(&self, position: usize, expected: &[&str]) -> QueryParseError {
+        let found = match self.input[position..].split_whitespace().next() {
+            Some(token) => format!("`{}`", token),
+            None => "end of input".to_string(),
+        };
+        QueryParseError {
+            position,
+            expected: expected.iter().map(|s| s.to_string()).collect(),
+            found,
+        }
+    }
+
+    /// 消耗关键字（不区分大小写），其后必须是边界，避免把 `order` 当成 `OR`
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.skip_ws();
+        let rest = self.rest();
+        let Some(head) = rest.get(..keyword.len()) else {
+            return false;
+        };
+        let boundary = rest[keyword.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || c == '(' || c == ')' || c == '"');
+        if head.eq_ignore_ascii_case(keyword) && boundary {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<QueryExpr, QueryParseError> {
+        let mut terms = vec![self.parse_and()?];
+        while self.eat_keyword("OR") {
+            terms.push(self.parse_and()?);
+        }
+        Ok(flatten(terms, QueryExpr::Or))
+    }
+
+    fn parse_and(&mut self) -> Result<QueryExpr, QueryParseError> {
+        let mut terms = vec![self.parse_unary()?];
+        while self.eat_keyword("AND") {
+            terms.push(self.parse_unary()?);
+        }
+        Ok(flatten(terms, QueryExpr::And))
+    }
+
+    fn parse_unary(&mut self) -> Result<QueryExpr, QueryParseError> {
+        if self.eat_keyword("NOT") {
+            return Ok(QueryExpr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.skip_ws();
+        if self.rest().starts_with('(') {
+            self.pos += 1;
+            let expr = self.parse_or()?;
+            self.skip_ws();
+            if !self.rest().starts_with(')') {
+                return Err(self.error(&[")", "AND", "OR"]));
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<QueryExpr, QueryParseError> {
+        let field_pos = self.pos;
+        let name_len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(self.rest().len());
+        if name_len == 0 {
+            let mut expected = FIELDS.to_vec();
+            expected.extend(["(", "NOT"]);
+            return Err(self.error(&expected));
+        }
+        let name = &self.input[field_pos..field_pos + name_len];
+        self.pos += name_len;
+
+        self.skip_ws();
+        let op_pos = self.pos;
+        let Some(op) = OPERATORS.into_iter().find(|op| self.rest().starts_with(op)) else {
+            return Err(self.error(&OPERATORS));
+        };
+        self.pos += op.len();
+
+        self.skip_ws();
+        let value_pos = self.pos;
+        let value = self.parse_value()?;
+
+        match name.to_ascii_lowercase().as_str() {
+            "level" => {
+                let op = ordered_op(op).ok_or_else(|| self.error_at(op_pos, &ORDERED_OPERATORS))?;
+                let level = value.parse::<Level>().map_err(|_| {
+                    self.error_at(value_pos, &["error", "warn", "info", "debug", "trace"])
+                })?;
+                Ok(QueryExpr::Level(op, level))
+            }
+            "ts" | "timestamp" => {
+                let op = ordered_op(op).ok_or_else(|| self.error_at(op_pos, &ORDERED_OPERATORS))?;
+                let ts = parse_timestamp(&value).ok_or_else(|| {
+                    self.error_at(value_pos, &["RFC 3339 timestamp", "YYYY-MM-DD"])
+                })?;
+                Ok(QueryExpr::Timestamp(op, ts))
+            }
+            lowered => {
+                let field = match lowered {
+                    "target" => TextField::Target,
+                    "msg" | "message" => TextField::Message,
+                    "cid" | "correlation_id" => TextField::CorrelationId,
+                    _ => match name.strip_prefix("fields.") {
+                        Some(key) if !key.is_empty() => {
+                            // 文本运算符之外的 `< <= > >=` 按数值比较
+                            if let (None, Some(op)) = (text_op(op), ordered_op(op)) {
+                                let value = value
+                                    .parse::<f64>()
+                                    .ok()
+                                    .filter(|value| value.is_finite())
+                                    .ok_or_else(|| self.error_at(value_pos, &["number"]))?;
+                                return Ok(QueryExpr::Number {
+                                    field: key.to_string(),
+                                    op,
+                                    value,
+                                });
+                            }
+                            TextField::Field(key.to_string())
+                        }
+                        _ => return Err(self.error_at(field_pos, &FIELDS)),
+                    },
+                };
+                let op = text_op(op).ok_or_else(|| self.error_at(op_pos, &TEXT_OPERATORS))?;
+                Ok(QueryExpr::Text { field, op, value })
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<String, QueryParseError> {
+        if !self.rest().starts_with('"') {
+            let len = self
+                .rest()
+                .find(|c: char| c.is_whitespace() || c == ')' || c == '(' || c == '"')
+                .unwrap_or(self.rest().len());
+            if len == 0 {
+                return Err(self.error(&["value"]));
+            }
+            let value = self.rest()[..len].to_string();
+            self.pos += len;
+            return Ok(value);
+        }
+
+        let start = self.pos;
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                    Some(_) => {
+                        return Err(self.error_at(start + offset, &["\\\"", "\\\\"]));
+                    }
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err(self.error_at(self.input.len(), &["closing `\"`"]))
+    }
+}
+
+fn flatten(mut terms: Vec<QueryExpr>, combine: fn(Vec<QueryExpr>) -> QueryExpr) -> QueryExpr {
+    if terms.len() == 1 {
+        terms.remove(0)
+    } else {
+        combine(terms)
+    }
+}
+
+fn ordered_op(op: &str) -> Option<CmpOp> {
+    Some(match op {
+        ":" | "=" => CmpOp::Eq,
+        "!=" => CmpOp::Ne,
+        "<" => CmpOp::Lt,
+        "<=" => CmpOp::Le,
+        ">" => CmpOp::Gt,
+        ">=" => CmpOp::Ge,
+        _ => return None,
+    })
+}
+
+fn text_op(op: &str) -> Option<TextOp> {
+    Some(match op {
+        ":" => TextOp::Contains,
+        "=" => TextOp::Equals,
+        "!=" => TextOp::NotEquals,
+        "~" => TextOp::Wildcard,
+        _ => return None,
+    })
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok().or_else(|| {
+        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+        Some(date.and_hms_opt(0, 0, 0)?.and_utc().fixed_offset())
+    })
+}
+
+fn write_quoted(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            f.write_str("\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    f.write_str("\"")
+}
+
+/// 输出完全加括号的规范形式，重新解析后得到相同的语法树
+impl fmt::Display for QueryExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryExpr::And(terms) | QueryExpr::Or(terms) => {
+                let joiner = if matches!(self, QueryExpr::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                f.write_str("(")?;
+                for (i, term) in terms.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(joiner)?;
+                    }
+                    write!(f, "{}", term)?;
+                }
+                f.write_str(")")
+            }
+            QueryExpr::Not(inner) => write!(f, "NOT {}", inner),
+            QueryExpr::Level(op, level) => write!(f, "level{}{}", op.as_str(), level),
+            QueryExpr::Timestamp(op, ts) => write!(f, "ts{}{}", op.as_str(), ts.to_rfc3339()),
+            QueryExpr::Number { field, op, value } => {
+                write!(f, "fields.{}{}{}", field, op.as_str(), value)
+            }
+            QueryExpr::Text { field, op, value } => {
+                match field {
+                    TextField::Target => f.write_str("target")?,
+                    TextField::Message => f.write_str("msg")?,
+                    TextField::CorrelationId => f.write_str("cid")?,
+                    TextField::Field(name) => write!(f, "fields.{}", name)?,
+                }
+                f.write_str(op.as_str())?;
+                write_quoted(f, value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::tests::xorshift;
+    use serde_json::json;
+
+    fn entry(level: &str, target: &str, message: &str, timestamp: &str) -> LogEntry {
+        LogEntry {
+            level: level.to_string(),
+            target: target.to_string(),
+            message: message.to_string(),
+            timestamp: timestamp.to_string().into(),
+            fields: [
+                ("symbol".to_string(), json!("BTC")),
+                ("qty".to_string(), json!(3)),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_example_query() {
+        let expr = QueryExpr::parse(
+            r#"level>=warn AND target:executor AND (msg~"timeout" OR msg~"refused") AND ts>2024-06-01T00:00:00Z"#,
+        )
+        .unwrap();
+        let ts = "2024-06-02T08:00:00+00:00";
+        assert!(expr.matches(&entry("ERROR", "bot::executor", "read TIMEOUT", ts)));
+        assert!(expr.matches(&entry("WARN", "bot::executor", "connection refused", ts)));
+        assert!(!expr.matches(&entry("INFO", "bot::executor", "read timeout", ts)));
+        assert!(!expr.matches(&entry("ERROR", "api", "read timeout", ts)));
+        assert!(!expr.matches(&entry("ERROR", "bot::executor", "filled", ts)));
+        let old = "2024-05-31T23:59:59+00:00";
+        assert!(!expr.matches(&entry("ERROR", "bot::executor", "read timeout", old)));
+    }
+
+    #[test]
+    fn test_valid_expressions() {
+        let e = entry(
+            "WARN",
+            "bot::executor",
+            r#"order "A-1" timed out"#,
+            "2024-06-01T12:00:00+08:00",
+        );
+        let cases = [
+            ("level=warn", true),
+            ("level:WARN", true),
+            ("level!=warn", false),
+            ("level>info", true),
+            ("level<=info", false),
+            ("level<error", true),
+            ("target=BOT::EXECUTOR", true),
+            ("target!=api", true),
+            ("msg:timed", true),
+            ("message~\"order*out\"", true),
+            ("msg~\"time?\"", true),
+            ("msg~\"x*y\"", false),
+            (r#"msg:"\"A-1\"""#, true),
+            ("fields.symbol=btc", true),
+            ("fields.qty=3", true),
+            ("fields.qty>2.5", true),
+            ("fields.qty<=3", true),
+            ("fields.qty<3", false),
+            ("fields.symbol>1", false),
+            ("fields.missing>=0", false),
+            ("fields.missing!=x", true),
+            ("cid=\"\"", true),
+            ("ts=2024-06-01T04:00:00Z", true),
+            ("ts>=2024-06-01", true),
+            ("ts<2024-06-01", false),
+            ("NOT level=error", true),
+            ("not not level=warn", true),
+            ("level=error OR level=warn", true),
+            ("level=error or msg:order and target:api", false),
+            ("(level=error OR msg:order) AND target:bot", true),
+            ("  ( ( level=warn ) )  ", true),
+            ("target:order OR(level=warn)", true),
+        ];
+        for (query, expected) in cases {
+            let expr = QueryExpr::parse(query).unwrap_or_else(|e| panic!("{}: {}", query, e));
+            assert_eq!(expr.matches(&e), expected, "{}", query);
+            let reparsed = QueryExpr::parse(&expr.to_string()).unwrap();
+            assert_eq!(reparsed, expr, "{} -> {}", query, expr);
+        }
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        let cases: [(&str, usize, &str); 17] = [
+            ("", 0, "level"),
+            ("   ", 3, "level"),
+            ("level", 5, ">="),
+            ("level>=", 7, "value"),
+            ("level>=loud", 7, "warn"),
+            ("level~warn", 5, "="),
+            ("ts>yesterday", 3, "YYYY-MM-DD"),
+            ("host=a", 0, "target"),
+            ("fields.=a", 0, "fields.<name>"),
+            ("msg>a", 3, "~"),
+            ("fields.qty>many", 11, "number"),
+            ("(level=warn", 11, ")"),
+            ("level=warn)", 10, "end of input"),
+            ("level=warn AND", 14, "level"),
+            ("level=warn level=info", 11, "AND"),
+            (r#"msg:"open"#, 9, "closing `\"`"),
+            (r#"msg:"a\x""#, 6, "\\\""),
+        ];
+        for (query, position, expected) in cases {
+            let error = QueryExpr::parse(query).unwrap_err();
+            assert_eq!(error.position, position, "{}: {}", query, error);
+            assert!(
+                error.expected.iter().any(|e| e == expected),
+                "{}: {}",
+                query,
+                error
+            );
+        }
+        let error = QueryExpr::parse("level=warn OR )").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid query at position 14: expected level or target or msg or ts or cid or fields.<name> or ( or NOT, found `)`"
+        );
+    }
+
+    #[test]
+    fn test_random_inputs_never_panic() {
+        let mut rng = xorshift(0x2545_f491_4f6c_dd1d);
+        let mut next = move |n: usize| rng(n as u64) as usize;
+        let tokens = [
+            "level",
+            "ts",
+            "msg",
+            "target",
+            "fields.k",
+            "cid",
+            "=",
+            ">=",
+            "<",
+            "!=",
+            ":",
+            "~",
+            "warn",
+            "2024-06-01",
+            "-2.5",
+            "\"q\\\"x\"",
+            "\"",
+            "(",
+            ")",
+            " AND ",
+            " OR ",
+            "NOT ",
+            " ",
+            "*",
+            "é",
+            "order",
+        ];
+        let e = entry("WARN", "app", "order placed", "2024-06-01T00:00:00Z");
+        for _ in 0..5000 {
+            let query: String = (0..1 + next(12))
+                .map(|_| tokens[next(tokens.len())])
+                .collect();
+            match QueryExpr::parse(&query) {
+                Ok(expr) => {
+                    expr.matches(&e);
+                    assert_eq!(QueryExpr::parse(&expr.to_string()), Ok(expr), "{}", query);
+                }
+                Err(error) => {
+                    assert!(error.position <= query.len(), "{}", query);
+                    assert!(query.is_char_boundary(error.position), "{}", query);
+                    assert!(!error.expected.is_empty(), "{}", query);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::cache::{estimate_map_json_len, ByteCounter};
+use crate::clock::monotonic_ns;
+use crate::collapse::REPEATED_FIELD;
+use crate::limits::mark_truncated;
+use crate::sha256::sha256;
+use crate::tracing_utils::rfc3339_to_epoch_millis;
+use crate::{
+    correlation, LogEntry, LogStats, LogTimestamp, TracingVisitor, VisitorConfig,
+    ENTRY_OVERHEAD_BYTES, LOG_ENTRY_SCHEMA,
+};
+
+/// `BroadcastLogLayer` 广播、缓存和持久化的日志记录类型
+///
+/// 默认使用 `LogEntry`；自定义类型实现本 trait 后即可复用同一套管线。
+/// 管线自己合成的条目（暂停记录、周期摘要、span 事件）以 `LogEntry` 生成，
+/// 再经 `From<LogEntry>` 转换。
+pub trait LogRecord: Serialize + Clone + From<LogEntry> + Send + Sync + 'static {
+    /// 由 tracing 事件构造记录
+    fn from_event<S>(event: &Event<'_>, ctx: &RecordContext<'_, S>) -> Self
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>;
+
+    /// 级别字符串，用于统计和缓存的级别索引
+    fn level(&self) -> &str;
+
+    /// target，用于识别管线内部条目（管理记录、摘要）
+    fn target(&self) -> &str;
+
+    /// RFC3339 时间戳，默认供 `timestamp_millis` 解析
+    fn timestamp(&self) -> Option<&str> {
+        None
+    }
+
+    /// epoch 毫秒，用于持久化文件的时间索引；返回 None 的记录不会成为索引点
+    fn timestamp_millis(&self) -> Option<i64> {
+        self.timestamp().and_then(rfc3339_to_epoch_millis)
+    }
+
+    /// 按名称取结构化字段，用于字段分位数（见 `quantile` 模块）；默认没有字段
+    fn field(&self, _name: &str) -> Option<&Value> {
+        None
+    }
+
+    /// 写入内存缓存时由 `LogBuffer` 分配的序号，默认忽略
+    fn set_seq(&mut self, _seq: u64) {}
+
+    /// 与 `other` 内容相同（时间戳、序号等除外），可折叠为一条（见 `collapse` 模块）；默认不折叠
+    fn same_content(&self, _other: &Self) -> bool {
+        false
+    }
+
+    /// 记录折叠后的重复次数，默认忽略
+    fn set_repeated(&mut self, _count: u64) {}
+
+    /// 在缓存字节预算中的估算占用，默认取 JSON 序列化长度
+    fn estimate_bytes(&self) -> usize {
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, self)
+            .map(|_| counter.0)
+            .unwrap_or_default()
+    }
+}
+
+/// 构造记录时可用的上下文：Layer 的 span 上下文及其字段捕获配置
+pub struct RecordContext<'a, S> {
+    ctx: Context<'a, S>,
+    config: &'a Arc<VisitorConfig>,
+    stats: &'a LogStats,
+    now: DateTime<Utc>,
+}
+
+impl<'a, S> RecordContext<'a, S>
+where
+    S: Subscriber + for<'l> LookupSpan<'l>,
+{
+    pub(crate) fn new(
+        ctx: Context<'a, S>,
+        config: &'a Arc<VisitorConfig>,
+        stats: &'a LogStats,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            ctx,
+            config,
+            stats,
+            now,
+        }
+    }
+
+    /// 事件的墙上时间；每条事件只读取一次时钟，时钟回拨检测与记录的 `timestamp` 共用
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    /// tracing 的 Layer 上下文，可用于查找当前 span
+    pub fn layer_context(&self) -> &Context<'a, S> {
+        &self.ctx
+    }
+
+    /// 按 Layer 的配置（sanitize、长度上限、字段上限等）捕获事件的 message 和字段；
+    /// 有字段因 `FieldLimits` 被丢弃时写入 `_truncated_fields` 并计入管线统计
+    pub fn visit(&self, event: &Event<'_>) -> TracingVisitor {
+        let mut visitor = TracingVisitor::new(self.config.clone());
+        event.record(&mut visitor);
+        if visitor.dropped_fields > 0 {
+            mark_truncated(&mut visitor.fields, visitor.dropped_fields);
+            self.stats.record_truncated_fields(visitor.dropped_fields);
+        }
+        visitor
+    }
+
+    /// 按内置方式构造 `LogEntry`，便于自定义记录在其基础上扩展
+    pub fn entry(&self, event: &Event<'_>) -> LogEntry {
+        LogEntry::from_event(event, self)
+    }
+}
+
+/// 调用点指纹：target、文件、行号、事件名与字段名的 SHA-256，取前 8 字节的十六进制
+///
+/// tracing 不保留 message 的格式模板，同一调用点即视为同一模板，
+/// 因此 `"user {} failed"` 无论插入什么值指纹都相同。
+fn fingerprint(metadata: &Metadata<'_>) -> String {
+    let mut key = String::new();
+    for part in [
+        metadata.target(),
+        metadata.file().unwrap_or_default(),
+        &metadata.line().unwrap_or_default().to_string(),
+        metadata.name(),
+    ] {
+        key.push_str(part);
+        key.push('\0');
+    }
+    for field in metadata.fields() {
+        key.push_str(field.name());
+        key.push('\0');
+    }
+    sha256(key.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// 显式指定的事件名；宏自动生成的 `event <file>:<line>` 返回 None
+fn event_name(metadata: &Metadata<'_>) -> Option<String> {
+    let name = metadata.name();
+    let generated = name
+        .strip_prefix("event ")
+        .and_then(|rest| rest.rsplit_once(':'))
+        .is_some_and(|(file, line)| {
+            Some(file) == metadata.file() && line.parse().ok() == metadata.line()
+        });
+    (!generated).then(|| name.to_owned())
+}
+
+impl LogRecord for LogEntry {
+    fn from_event<S>(event: &Event<'_>, ctx: &RecordContext<'_, S>) -> Self
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let visitor = ctx.visit(event);
+        let now = ctx.now();
+        let mut entry = LogEntry {
+            schema: LOG_ENTRY_SCHEMA,
+            timestamp: LogTimestamp::new(now, ctx.config.time_repr),
+            level: event.metadata().level().as_str().to_owned(),
+            target: event.metadata().target().to_owned(),
+            message: visitor
+                .message
+                .unwrap_or_else(|| "<no message>".to_string()),
+            correlation_id: correlation::current_correlation_id(),
+            fields: visitor.fields,
+            seq: None,
+            mono_ns: Some(monotonic_ns()),
+            fingerprint: ctx
+                .config
+                .fingerprint
+                .then(|| fingerprint(event.metadata())),
+            date_bucket: ctx.config.bucket.map(|bucket| bucket.bucket(now)),
+            name: event_name(event.metadata()),
+        };
+        ctx.config.escalations.apply(&mut entry);
+        entry
+    }
+
+    fn level(&self) -> &str {
+        &self.level
+    }
+
+    fn target(&self) -> &str {
+        &self.target
+    }
+
+    fn timestamp(&self) -> Option<&str> {
+        self.timestamp.as_str()
+    }
+
+    fn timestamp_millis(&self) -> Option<i64> {
+        self.timestamp.epoch_millis()
+    }
+
+    fn field(&self, name: &str) -> Option<&Value> {
+        self.fields.get(name)
+    }
+
+    fn set_seq(&mut self, seq: u64) {
+        self.seq = Some(seq);
+    }
+
+    /// 级别、target、message 与字段均相同
+    fn same_content(&self, other: &Self) -> bool {
+        self.level == other.level
+            && self.target == other.target
+            && self.message == other.message
+            && self.fields == other.fields
+    }
+
+    fn set_repeated(&mut self, count: u64) {
+        self.fields
+            .insert(REPEATED_FIELD.to_string(), Value::from(count));
+    }
+
+    /// message 长度 + 字段的估算 JSON 长度（见 `cache::estimate_json_len`）+ 固定开销
+    fn estimate_bytes(&self) -> usize {
+        let fields = if self.fields.is_empty() {
+            0
+        } else {
+            estimate_map_json_len(self.fields.iter())
+        };
+        self.message.len() + fields + ENTRY_OVERHEAD_BYTES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogBuffer, DEFAULT_CACHE_CAPACITY};
+    use std::time::Duration;
+    use tokio::sync::broadcast;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Serialize, Clone, Debug)]
+    struct HostRecord {
+        #[serde(flatten)]
+        entry: LogEntry,
+        host: String,
+    }
+
+    impl From<LogEntry> for HostRecord {
+        fn from(entry: LogEntry) -> Self {
+            Self {
+                entry,
+                host: "pipeline".to_string(),
+            }
+        }
+    }
+
+    impl LogRecord for HostRecord {
+        fn from_event<S>(event: &Event<'_>, ctx: &RecordContext<'_, S>) -> Self
+        where
+            S: Subscriber + for<'a> LookupSpan<'a>,
+        {
+            Self {
+                entry: ctx.entry(event),
+                host: "node-1".to_string(),
+            }
+        }
+
+        fn level(&self) -> &str {
+            &self.entry.level
+        }
+
+        fn target(&self) -> &str {
+            &self.entry.target
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_record_type() {
+        skip_if_events_compiled_out!();
+        let path = std::env::temp_dir().join(format!(
+            "listen-tracing-record-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+
+        let (tx, mut rx) = broadcast::channel::<HostRecord>(16);
+        let cache = LogBuffer::<HostRecord>::new(DEFAULT_CACHE_CAPACITY).shared();
+        let layer = BroadcastLogLayer::for_record(tx, cache.clone())
+            .with_persist_path(&path)
+            .with_max_message_bytes(8);
+        let pipeline = layer.pipeline().clone();
+        let stats = layer.stats();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::Registry::default().with(layer));
+
+        tracing::warn!(order = 7, "order rejected by exchange");
+        pipeline.pause_persistence("test");
+
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received.host, "node-1");
+        assert_eq!(received.entry.message, "order re…(truncated)");
+        assert_eq!(received.entry.fields["order"], 7);
+        // 管理记录经 From<LogEntry> 进入同一管线
+        let admin = rx.recv().await.unwrap();
+        assert_eq!(admin.host, "pipeline");
+
+        for _ in 0..100 {
+            if cache.read().await.len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let cached = cache.read().await;
+        assert_eq!(cached.iter_level("warn").count(), 2);
+        assert_eq!(stats.snapshot().events.warn, 2);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let line: serde_json::Value =
+            serde_json::from_str(written.lines().next().unwrap()).unwrap();
+        assert_eq!(line["host"], "node-1");
+        assert_eq!(line["level"], "WARN");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+}
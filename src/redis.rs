@@ -0,0 +1,618 @@
+//! Redis 输出（需开启 `redis` feature）
+//!
+//! 内置一个极简的 RESP 客户端：`RedisSink` 把每条日志 PUBLISH 到频道，
+//! `RedisLogStore` 用 MULTI 包裹的 LPUSH + LTRIM 维护一个多副本共享的最近日志列表。
+//! 连接失败时退化为仅本地运行，每次断线只经 `SinkError::report` 提示一次，绝不 panic。
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::sink::{LogSink, SinkError};
+use crate::store::LogStore;
+use crate::{LogCache, LogEntry, TimestampFormat, Timestamps, DEFAULT_CACHE_CAPACITY};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// RESP 响应
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisReply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<RedisReply>>),
+}
+
+/// 带指数退避重连的 Redis 连接
+///
+/// 断线后在退避窗口内直接返回错误，不阻塞调用方；窗口过后的下一次命令才会尝试重连。
+pub struct RedisConnection {
+    addr: String,
+    stream: Option<BufStream<TcpStream>>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl RedisConnection {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            stream: None,
+            backoff: INITIAL_BACKOFF,
+            retry_at: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// 发送一条命令并读取响应，服务端错误响应转换为 io::Error
+    pub async fn command(&mut self, args: &[&[u8]]) -> io::Result<RedisReply> {
+        let mut replies = self.round_trip(&encode_command(args), 1).await?;
+        match replies.pop() {
+            Some(RedisReply::Error(msg)) => Err(io::Error::other(msg)),
+            Some(reply) => Ok(reply),
+            None => unreachable!("round_trip returns one reply per command"),
+        }
+    }
+
+    /// 以 MULTI / EXEC 包裹多条命令，一次往返发出并原子执行，返回各命令的结果；
+    /// 任何一条入队或执行失败时返回错误
+    pub async fn transaction(&mut self, commands: &[&[&[u8]]]) -> io::Result<Vec<RedisReply>> {
+        let mut buf = encode_command(&[b"MULTI"]);
+        for args in commands {
+            buf.extend_from_slice(&encode_command(args));
+        }
+        buf.extend_from_slice(&encode_command(&[b"EXEC"]));
+        let mut replies = self.round_trip(&buf, commands.len() + 2).await?;
+        // 读完全部响应后再判断，保证连接上的请求与响应仍然对齐
+        let exec = replies.pop();
+        if let Some(RedisReply::Error(msg)) = replies
+            .into_iter()
+            .find(|reply| matches!(reply, RedisReply::Error(_)))
+        {
+            return Err(io::Error::other(msg));
+        }
+        match exec {
+            Some(RedisReply::Array(Some(results))) => {
+                match results.iter().find_map(|reply| match reply {
+                    RedisReply::Error(msg) => Some(msg.clone()),
+                    _ => None,
+                }) {
+                    Some(msg) => Err(io::Error::other(msg)),
+                    None => Ok(results),
+                }
+            }
+            Some(RedisReply::Error(msg)) => Err(io::Error::other(msg)),
+            other => Err(io::Error::other(format!(
+                "transaction aborted: {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// 写出 `buf` 并读取 `replies` 条响应；连接出错时标记断线
+    async fn round_trip(&mut self, buf: &[u8], replies: usize) -> io::Result<Vec<RedisReply>> {
+        if self.stream.is_none() {
+            self.connect().await?;
+        }
+        let stream = self.stream.as_mut().expect("connected above");
+        let result = async {
+            stream.write_all(buf).await?;
+            stream.flush().await?;
+            let mut out = Vec::with_capacity(replies);
+            for _ in 0..replies {
+                out.push(read_reply(stream).await?);
+            }
+            Ok(out)
+        }
+        .await;
+        if result.is_err() {
+            self.mark_down();
+        }
+        result
+    }
+
+    async fn connect(&mut self) -> io::Result<()> {
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    format!("redis {} unavailable, backing off", self.addr),
+                ));
+            }
+        }
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&self.addr)).await {
+            Ok(Ok(stream)) => {
+                self.stream = Some(BufStream::new(stream));
+                self.backoff = INITIAL_BACKOFF;
+                self.retry_at = None;
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                self.mark_down();
+                Err(e)
+            }
+            Err(_) => {
+                self.mark_down();
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "redis connect timed out",
+                ))
+            }
+        }
+    }
+
+    fn mark_down(&mut self) {
+        self.stream = None;
+        self.retry_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+fn encode_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buf.extend_from_slice(arg);
+        buf.extend_from_slice(b"\r\n");
+    }
+    buf
+}
+
+async fn read_line(stream: &mut BufStream<TcpStream>) -> io::Result<String> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "redis closed connection",
+        ));
+    }
+    Ok(line.trim_end_matches("\r\n").to_string())
+}
+
+fn parse_len(s: &str) -> io::Result<i64> {
+    s.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad RESP length: {}", s),
+        )
+    })
+}
+
+async fn read_reply(stream: &mut BufStream<TcpStream>) -> io::Result<RedisReply> {
+    let line = read_line(stream).await?;
+    let (kind, rest) = line.split_at(line.len().min(1));
+    match kind {
+        "+" => Ok(RedisReply::Status(rest.to_string())),
+        "-" => Ok(RedisReply::Error(rest.to_string())),
+        ":" => Ok(RedisReply::Integer(parse_len(rest)?)),
+        "$" => {
+            let len = parse_len(rest)?;
+            if len < 0 {
+                return Ok(RedisReply::Bulk(None));
+            }
+            let mut buf = vec![0; len as usize + 2];
+            stream.read_exact(&mut buf).await?;
+            buf.truncate(len as usize);
+            Ok(RedisReply::Bulk(Some(buf)))
+        }
+        "*" => {
+            let len = parse_len(rest)?;
+            if len < 0 {
+                return Ok(RedisReply::Array(None));
+            }
+            let mut items = Vec::with_capacity(len as usize);
+            for _ in 0..len {
+                items.push(Box::pin(read_reply(stream)).await?);
+            }
+            Ok(RedisReply::Array(Some(items)))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected RESP line: {}", line),
+        )),
+    }
+}
+
+/// 把每条日志以 JSON 形式 PUBLISH 到指定频道，供非 Rust 服务订阅实时日志流
+pub struct RedisSink {
+    conn: RedisConnection,
+    channel: String,
+    timestamp_format: TimestampFormat,
+    degraded: bool,
+}
+
+impl RedisSink {
+    pub fn new(addr: impl Into<String>, channel: impl Into<String>) -> Self {
+        Self {
+            conn: RedisConnection::new(addr),
+            channel: channel.into(),
+            timestamp_format: TimestampFormat::default(),
+            degraded: false,
+        }
+    }
+
+    /// 发布的 JSON 中 `timestamp` 的格式，默认 `TimestampFormat::Native`
+    pub fn with_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+}
+
+impl LogSink for RedisSink {
+    async fn send(&mut self, entry: &LogEntry) {
+        let payload = match serde_json::to_vec(&Timestamps(entry, self.timestamp_format)) {
+            Ok(payload) => payload,
+            Err(_) => return,
+        };
+        match self
+            .conn
+            .command(&[b"PUBLISH", self.channel.as_bytes(), &payload])
+            .await
+        {
+            Ok(_) => {
+                if self.degraded {
+                    eprintln!("listen-tracing: redis sink reconnected");
+                    self.degraded = false;
+                }
+            }
+            Err(e) => {
+                // 同一次断线只提示一次，避免日志风暴时刷屏
+                if !self.degraded {
+                    SinkError::send("redis", "publish", e).report();
+                    self.degraded = true;
+                }
+            }
+        }
+    }
+}
+
+/// 基于 Redis 列表的共享缓存（LPUSH + LTRIM），多副本可查询同一份最近日志
+///
+/// 通过 `store::spawn_store_writer` 从广播通道写入。
+/// Redis 不可用时写入和查询都退化到内置的本地 LogCache；恢复后先把断线期间的条目
+/// 按原顺序补写到 Redis（最多 `capacity` 条），再清空本地缓存，查询不会丢掉断线的这段时间。
+pub struct RedisLogStore {
+    conn: Mutex<RedisConnection>,
+    key: String,
+    capacity: usize,
+    local: LogCache,
+    /// 断线期间未写入 Redis 的条目（已序列化），最旧的在前
+    backlog: Mutex<VecDeque<Vec<u8>>>,
+    degraded: AtomicBool,
+}
+
+impl RedisLogStore {
+    pub fn new(addr: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            conn: Mutex::new(RedisConnection::new(addr)),
+            key: key.into(),
+            capacity: DEFAULT_CACHE_CAPACITY,
+            local: LogCache::default(),
+            backlog: Mutex::new(VecDeque::new()),
+            degraded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// 降级时使用的本地缓存
+    pub fn local(&self) -> &LogCache {
+        &self.local
+    }
+
+    /// 是否处于断线降级状态
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// 在一个事务中按顺序 LPUSH 全部条目并 LTRIM，其他副本看不到未裁剪的列表
+    async fn try_append(&self, payloads: &[Vec<u8>]) -> io::Result<()> {
+        let mut conn = self.conn.lock().await;
+        let stop = (self.capacity - 1).to_string();
+        let lpush: Vec<&[u8]> = [b"LPUSH".as_slice(), self.key.as_bytes()]
+            .into_iter()
+            .chain(payloads.iter().map(Vec::as_slice))
+            .collect();
+        conn.transaction(&[
+            &lpush,
+            &[b"LTRIM", self.key.as_bytes(), b"0", stop.as_bytes()],
+        ])
+        .await?;
+        Ok(())
+    }
+
+    /// 补写断线期间积压的条目，成功后清空本地缓存
+    async fn replay(&self, backlog: &mut VecDeque<Vec<u8>>) -> io::Result<()> {
+        if backlog.is_empty() {
+            return Ok(());
+        }
+        self.try_append(backlog.make_contiguous()).await?;
+        let replayed = backlog.len();
+        backlog.clear();
+        // 本地缓存只在断线期间写入
+        if self.degraded.swap(false, Ordering::Relaxed) {
+            self.local.write().await.clear();
+            eprintln!(
+                "listen-tracing: redis store reconnected, replayed {} entries",
+                replayed
+            );
+        }
+        Ok(())
+    }
+
+    /// 同一次断线只报告一次
+    fn degrade(&self, operation: &'static str, e: io::Error) {
+        if !self.degraded.swap(true, Ordering::Relaxed) {
+            SinkError::send("redis", operation, e).report();
+        }
+    }
+
+    async fn try_snapshot(&self) -> io::Result<Vec<LogEntry>> {
+        let mut conn = self.conn.lock().await;
+        let stop = (self.capacity - 1).to_string();
+        let reply = conn
+            .command(&[b"LRANGE", self.key.as_bytes(), b"0", stop.as_bytes()])
+            .await?;
+        let items = match reply {
+            RedisReply::Array(Some(items)) => items,
+            RedisReply::Array(None) => Vec::new(),
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected LRANGE reply: {:?}", other),
+                ))
+            }
+        };
+        // LPUSH 让最新的条目排在列表头部，这里翻转回 旧 -> 新；
+        // 旧版本副本写入的条目经 migrate 升级
+        Ok(items
+            .into_iter()
+            .rev()
+            .filter_map(|item| match item {
+                RedisReply::Bulk(Some(bytes)) => serde_json::from_slice(&bytes)
+                    .ok()
+                    .and_then(|value| LogEntry::migrate(value).ok()),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+impl LogStore for RedisLogStore {
+    async fn append(&self, entry: LogEntry) {
+        let payload = match serde_json::to_vec(&entry) {
+            Ok(payload) => payload,
+            Err(_) => return,
+        };
+        let mut backlog = self.backlog.lock().await;
+        backlog.push_back(payload);
+        if let Err(e) = self.replay(&mut backlog).await {
+            // 超出容量的部分补写后也会被 LTRIM 裁掉
+            if backlog.len() > self.capacity {
+                backlog.pop_front();
+            }
+            self.degrade("lpush", e);
+            self.local.append(entry).await;
+        }
+    }
+
+    async fn snapshot(&self) -> Vec<LogEntry> {
+        let result = match self.replay(&mut *self.backlog.lock().await).await {
+            Ok(()) => self.try_snapshot().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.degrade("lrange", e);
+                self.local.snapshot().await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::query_logs;
+    use crate::LogQuery;
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    /// 只支持 PUBLISH / LPUSH / LTRIM / LRANGE 与 MULTI / EXEC 的内存版 Redis
+    async fn fake_redis() -> (String, Arc<Mutex<Vec<Vec<String>>>>) {
+        fake_redis_on(TcpListener::bind("127.0.0.1:0").await.unwrap())
+    }
+
+    fn fake_redis_on(listener: TcpListener) -> (String, Arc<Mutex<Vec<Vec<String>>>>) {
+        let addr = listener.local_addr().unwrap().to_string();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let recorded = commands.clone();
+        tokio::spawn(async move {
+            let list = Arc::new(Mutex::new(VecDeque::<Vec<u8>>::new()));
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let list = list.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut stream = BufStream::new(socket);
+                    let mut queued: Option<Vec<Vec<Vec<u8>>>> = None;
+                    while let Ok(RedisReply::Array(Some(args))) = read_reply(&mut stream).await {
+                        let args: Vec<Vec<u8>> = args
+                            .into_iter()
+                            .map(|a| match a {
+                                RedisReply::Bulk(Some(b)) => b,
+                                _ => Vec::new(),
+                            })
+                            .collect();
+                        let text: Vec<String> = args
+                            .iter()
+                            .map(|a| String::from_utf8_lossy(a).to_string())
+                            .collect();
+                        recorded.lock().await.push(text);
+                        let reply = match (args[0].as_slice(), &mut queued) {
+                            (b"MULTI", queued) => {
+                                *queued = Some(Vec::new());
+                                b"+OK\r\n".to_vec()
+                            }
+                            (b"EXEC", queued) => {
+                                let commands = queued.take().unwrap_or_default();
+                                let mut reply = format!("*{}\r\n", commands.len()).into_bytes();
+                                for args in commands {
+                                    reply.extend(execute(&list, &args).await);
+                                }
+                                reply
+                            }
+                            (_, Some(queued)) => {
+                                queued.push(args);
+                                b"+QUEUED\r\n".to_vec()
+                            }
+                            (_, None) => execute(&list, &args).await,
+                        };
+                        stream.write_all(&reply).await.unwrap();
+                        stream.flush().await.unwrap();
+                    }
+                });
+            }
+        });
+        (addr, commands)
+    }
+
+    async fn execute(list: &Mutex<VecDeque<Vec<u8>>>, args: &[Vec<u8>]) -> Vec<u8> {
+        let mut list = list.lock().await;
+        match args[0].as_slice() {
+            b"PUBLISH" => b":1\r\n".to_vec(),
+            b"LPUSH" => {
+                for value in &args[2..] {
+                    list.push_front(value.clone());
+                }
+                format!(":{}\r\n", list.len()).into_bytes()
+            }
+            b"LTRIM" => {
+                let stop: usize = String::from_utf8_lossy(&args[3]).parse().unwrap();
+                list.truncate(stop + 1);
+                b"+OK\r\n".to_vec()
+            }
+            b"LRANGE" => {
+                let refs: Vec<&[u8]> = list.iter().map(Vec::as_slice).collect();
+                encode_command(&refs)
+            }
+            _ => b"-ERR unknown command\r\n".to_vec(),
+        }
+    }
+
+    fn entry(level: &str, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: "2024-06-01T00:00:00+00:00".into(),
+            level: level.to_string(),
+            target: "redis_test".to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redis_sink_and_store() {
+        let (addr, commands) = fake_redis().await;
+
+        let mut sink = RedisSink::new(addr.clone(), "logs");
+        sink.send(&entry("INFO", "published")).await;
+        let published = commands.lock().await[0].clone();
+        assert_eq!(published[0], "PUBLISH");
+        assert_eq!(published[1], "logs");
+        let decoded: LogEntry = serde_json::from_str(&published[2]).unwrap();
+        assert_eq!(decoded.message, "published");
+
+        let mut sink = RedisSink::new(addr.clone(), "logs")
+            .with_timestamp_format(TimestampFormat::EpochMillis);
+        sink.send(&entry("INFO", "epoch")).await;
+        let published = commands.lock().await[1].clone();
+        assert!(published[2].contains(r#""timestamp":1717200000000"#));
+        let decoded = LogEntry::migrate(serde_json::from_str(&published[2]).unwrap()).unwrap();
+        assert_eq!(decoded, entry("INFO", "epoch"));
+
+        let store = RedisLogStore::new(addr, "recent").with_capacity(2);
+        for (level, message) in [("INFO", "a"), ("ERROR", "b"), ("ERROR", "c")] {
+            store.append(entry(level, message)).await;
+        }
+        let snapshot = store.snapshot().await;
+        let messages: Vec<_> = snapshot.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["b", "c"]);
+
+        let query = LogQuery {
+            level: Some("error".to_string()),
+            ..Default::default()
+        };
+        let page = query_logs(&store, &query).await;
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].entry.message, "c");
+        assert!(store.local().read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_redis_unavailable_degrades_to_local() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let mut sink = RedisSink::new(addr.clone(), "logs");
+        sink.send(&entry("INFO", "dropped")).await;
+
+        let store = RedisLogStore::new(addr, "recent");
+        store.append(entry("WARN", "kept locally")).await;
+        let snapshot = store.snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].message, "kept locally");
+        assert!(store.is_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_redis_store_replays_outage() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let store = RedisLogStore::new(addr.to_string(), "recent").with_capacity(3);
+        store.append(entry("WARN", "during 1")).await;
+        store.append(entry("WARN", "during 2")).await;
+        assert!(store.is_degraded());
+
+        // Redis 恢复，退避到期后第一次写入先补写积压的条目
+        let (_, commands) = fake_redis_on(TcpListener::bind(addr).await.unwrap());
+        tokio::time::sleep(INITIAL_BACKOFF * 2).await;
+        store.append(entry("INFO", "after")).await;
+        assert!(!store.is_degraded());
+        assert!(store.local().read().await.is_empty());
+
+        let messages: Vec<_> = store
+            .snapshot()
+            .await
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        assert_eq!(messages, ["during 1", "during 2", "after"]);
+        // LPUSH 与 LTRIM 在同一个事务中
+        let names: Vec<_> = commands
+            .lock()
+            .await
+            .iter()
+            .map(|command| command[0].clone())
+            .collect();
+        assert_eq!(names, ["MULTI", "LPUSH", "LTRIM", "EXEC", "LRANGE"]);
+    }
+}
@@ -0,0 +1,311 @@
+//! HTTP 请求日志，供 axum / tower 等框架的中间件调用
+//!
+//! 与 `viewer` 一样不绑定 HTTP 框架：中间件在请求开始时调用 `RequestLogConfig::start`，
+//! 在请求的 span 与关联 ID 作用域内运行处理函数，完成后以状态码调用 `RequestLog::finish`。
+//! `finish` 产生一条 target 为 `REQUEST_TARGET` 的事件，带 method / path / status / latency_ms /
+//! request_id 字段，照常进入广播、缓存和持久化文件。以 axum 为例：
+//!
+//! ```ignore
+//! async fn log_requests(req: Request, next: Next) -> Response {
+//!     let request_id = req.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok());
+//!     let Some(log) = CONFIG.start(req.method().as_str(), req.uri().path(), request_id) else {
+//!         return next.run(req).await;
+//!     };
+//!     let mut response = log.run(next.run(req)).await;
+//!     response.headers_mut().insert(REQUEST_ID_HEADER, log.request_id().parse().unwrap());
+//!     log.finish(response.status().as_u16());
+//!     response
+//! }
+//! ```
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use tracing::{Instrument, Level, Span};
+
+use crate::correlation::{with_correlation_id, CORRELATION_ID};
+
+/// 请求完成事件与请求 span 使用的 target
+pub const REQUEST_TARGET: &str = "listen_tracing::request";
+
+/// 传入的请求 ID 请求头
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 传入的请求 ID 超过该长度时视为无效，改为生成新的
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 生成请求 ID 的进程内计数
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// 请求日志的配置
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestLogConfig {
+    /// 不记录的路径，完整匹配，默认 `/healthz`
+    pub skip_paths: Vec<String>,
+    /// 5xx 的级别，默认 ERROR
+    pub server_error_level: Level,
+    /// 4xx 的级别，默认 WARN
+    pub client_error_level: Level,
+    /// 其余状态码的级别，默认 INFO
+    pub success_level: Level,
+}
+
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        Self {
+            skip_paths: vec!["/healthz".to_string()],
+            server_error_level: Level::ERROR,
+            client_error_level: Level::WARN,
+            success_level: Level::INFO,
+        }
+    }
+}
+
+impl RequestLogConfig {
+    /// 追加一个不记录的路径
+    pub fn skip(mut self, path: impl Into<String>) -> Self {
+        self.skip_paths.push(path.into());
+        self
+    }
+
+    /// 状态码对应的级别
+    pub fn level_for(&self, status: u16) -> Level {
+        match status {
+            500..=599 => self.server_error_level,
+            400..=499 => self.client_error_level,
+            _ => self.success_level,
+        }
+    }
+
+    /// 开始记录一个请求，`path` 被跳过时返回 None
+    ///
+    /// `request_id` 为传入的 `x-request-id`，为空或过长时生成新的 ID。
+    pub fn start(&self, method: &str, path: &str, request_id: Option<&str>) -> Option<RequestLog> {
+        if self.skip_paths.iter().any(|skip| skip == path) {
+            return None;
+        }
+        let request_id = match request_id.map(str::trim) {
+            Some(id) if !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN => id.to_string(),
+            _ => generate_request_id(),
+        };
+        let span = tracing::info_span!(
+            target: REQUEST_TARGET,
+            "request",
+            method,
+            path,
+            request_id = %request_id,
+        );
+        Some(RequestLog {
+            span,
+            request_id,
+            method: method.to_string(),
+            path: path.to_string(),
+            level_for: self.clone(),
+            started: Instant::now(),
+        })
+    }
+}
+
+/// 毫秒时间戳加进程内计数，同一进程内不重复
+fn generate_request_id() -> String {
+    format!(
+        "{:x}-{:04x}",
+        chrono::Utc::now().timestamp_millis(),
+        REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// 一个进行中的请求，由 `RequestLogConfig::start` 创建
+#[derive(Debug)]
+pub struct RequestLog {
+    span: Span,
+    request_id: String,
+    method: String,
+    path: String,
+    level_for: RequestLogConfig,
+    started: Instant,
+}
+
+impl RequestLog {
+    /// 请求的 span，带 method / path / request_id 字段
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// 传入或生成的请求 ID，可写回响应的 `x-request-id`
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// 在请求的 span 内、以请求 ID 为关联 ID 运行 `fut`，期间的日志都带上同一个 ID
+    pub async fn run<F: Future>(&self, fut: F) -> F::Output {
+        with_correlation_id(self.request_id.clone(), fut.instrument(self.span.clone())).await
+    }
+
+    /// 请求完成，按状态码对应的级别记录一条事件，同样以请求 ID 为关联 ID
+    pub fn finish(self, status: u16) {
+        let latency_ms = self.started.elapsed().as_millis() as u64;
+        let _entered = self.span.enter();
+        macro_rules! finished {
+            ($level:expr) => {
+                tracing::event!(
+                    target: REQUEST_TARGET,
+                    $level,
+                    method = %self.method,
+                    path = %self.path,
+                    status,
+                    latency_ms,
+                    request_id = %self.request_id,
+                    "{} {} {}",
+                    self.method,
+                    self.path,
+                    status
+                )
+            };
+        }
+        CORRELATION_ID.sync_scope(self.request_id.clone(), || {
+            match self.level_for.level_for(status) {
+                Level::ERROR => finished!(Level::ERROR),
+                Level::WARN => finished!(Level::WARN),
+                Level::INFO => finished!(Level::INFO),
+                Level::DEBUG => finished!(Level::DEBUG),
+                _ => finished!(Level::TRACE),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogCache};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn test_request_entries() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+        let config = RequestLogConfig {
+            client_error_level: Level::INFO,
+            ..Default::default()
+        }
+        .skip("/metrics");
+
+        assert!(config.start("GET", "/healthz", None).is_none());
+        assert!(config.start("GET", "/metrics", None).is_none());
+
+        let log = config.start("GET", "/orders/7", None).unwrap();
+        let generated = log.request_id().to_string();
+        log.run(async { tracing::info!("loading order") }).await;
+        log.finish(200);
+        let log = config.start("POST", "/orders", Some("req-abc")).unwrap();
+        log.finish(404);
+        config
+            .start("DELETE", "/orders/7", Some(&"x".repeat(200)))
+            .unwrap()
+            .finish(503);
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 4).await;
+        assert_eq!(logs.len(), 4);
+        assert_eq!(logs[0].message, "loading order");
+        assert_eq!(logs[0].correlation_id.as_deref(), Some(generated.as_str()));
+
+        let summary: Vec<(&str, &str, &str)> = logs[1..]
+            .iter()
+            .map(|e| (e.level.as_str(), e.message.as_str(), e.target.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("INFO", "GET /orders/7 200", REQUEST_TARGET),
+                ("INFO", "POST /orders 404", REQUEST_TARGET),
+                ("ERROR", "DELETE /orders/7 503", REQUEST_TARGET),
+            ]
+        );
+        let fields = &logs[2].fields;
+        assert_eq!(fields["method"], "POST");
+        assert_eq!(fields["path"], "/orders");
+        assert_eq!(fields["status"], 404);
+        assert_eq!(fields["request_id"], "req-abc");
+        assert!(fields["latency_ms"].is_u64());
+        assert_eq!(logs[1].fields["request_id"], generated.as_str());
+        // 过长的传入 ID 被替换
+        assert_ne!(logs[3].fields["request_id"], "x".repeat(200).as_str());
+        assert_eq!(RequestLogConfig::default().level_for(404), Level::WARN);
+    }
+
+    /// 模块文档中的中间件流程：请求头 -> `start` -> `run`（处理函数）-> `finish`，并写回响应头
+    async fn middleware(
+        config: &RequestLogConfig,
+        path: &str,
+        headers: &[(&str, &str)],
+        status: u16,
+    ) -> Option<String> {
+        let request_id = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(REQUEST_ID_HEADER))
+            .map(|(_, value)| *value);
+        let handler = async {
+            tracing::info!(target: "app::handler", "handling {}", path);
+            status
+        };
+        let Some(log) = config.start("GET", path, request_id) else {
+            handler.await;
+            return None;
+        };
+        let status = log.run(handler).await;
+        let response_id = log.request_id().to_string();
+        log.finish(status);
+        Some(response_id)
+    }
+
+    #[tokio::test]
+    async fn test_middleware_flow() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+        let config = RequestLogConfig::default();
+
+        let echoed = middleware(&config, "/orders", &[("X-Request-Id", "req-42")], 404).await;
+        assert_eq!(echoed.as_deref(), Some("req-42"));
+        let generated = middleware(&config, "/payments", &[], 500).await.unwrap();
+        assert_eq!(middleware(&config, "/healthz", &[], 200).await, None);
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 5).await;
+        let summary: Vec<_> = logs
+            .iter()
+            .map(|e| {
+                (
+                    e.level.as_str(),
+                    e.target.as_str(),
+                    e.correlation_id.as_deref(),
+                    e.fields.get("request_id").and_then(|id| id.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("INFO", "app::handler", Some("req-42"), None),
+                ("WARN", REQUEST_TARGET, Some("req-42"), Some("req-42")),
+                ("INFO", "app::handler", Some(generated.as_str()), None),
+                (
+                    "ERROR",
+                    REQUEST_TARGET,
+                    Some(generated.as_str()),
+                    Some(generated.as_str())
+                ),
+                // 跳过的路径照常运行处理函数，不产生请求事件
+                ("INFO", "app::handler", None, None),
+            ]
+        );
+        assert_eq!(logs[1].fields["status"], 404);
+        assert_eq!(logs[3].message, "GET /payments 500");
+    }
+}
@@ -0,0 +1,246 @@
+use crate::store::{query_logs, LogPage, LogStore};
+use crate::{CacheStats, LogBuffer, LogCache, LogEntry, LogQuery, DEFAULT_CACHE_CAPACITY};
+
+/// 未匹配任何前缀的日志所在分桶的名称
+pub const DEFAULT_BUCKET: &str = "default";
+
+/// 按 target 前缀把日志分到各自有界缓存的 LogStore，避免一个组件刷屏挤掉其他组件的历史
+///
+/// 前缀按模块边界匹配：`"executor"` 匹配 `executor` 与 `executor::orders`，
+/// 但不匹配 `executors`；多个前缀都匹配时取最长的。
+/// 通过 `store::spawn_store_writer` 从统一的广播通道写入。
+pub struct RoutedLogCache {
+    buckets: Vec<(String, LogCache)>,
+    default: LogCache,
+}
+
+impl RoutedLogCache {
+    /// `routes` 为 (target 前缀, 容量)，默认分桶容量为 `DEFAULT_CACHE_CAPACITY`
+    pub fn new<I, P>(routes: I) -> Self
+    where
+        I: IntoIterator<Item = (P, usize)>,
+        P: Into<String>,
+    {
+        let mut buckets: Vec<(String, LogCache)> = routes
+            .into_iter()
+            .map(|(prefix, capacity)| (prefix.into(), LogBuffer::new(capacity).shared()))
+            .collect();
+        // 长前缀优先匹配
+        buckets.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Self {
+            buckets,
+            default: LogCache::default(),
+        }
+    }
+
+    pub fn with_default_capacity(mut self, capacity: usize) -> Self {
+        self.default = LogBuffer::new(capacity).shared();
+        self
+    }
+
+    /// target 所属分桶的名称
+    pub fn route(&self, target: &str) -> &str {
+        self.buckets
+            .iter()
+            .find(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(DEFAULT_BUCKET, |(prefix, _)| prefix.as_str())
+    }
+
+    /// 按名称取分桶，`DEFAULT_BUCKET` 为默认分桶
+    pub fn bucket(&self, component: &str) -> Option<&LogCache> {
+        if component == DEFAULT_BUCKET {
+            return Some(&self.default);
+        }
+        self.buckets
+            .iter()
+            .find(|(prefix, _)| prefix == component)
+            .map(|(_, cache)| cache)
+    }
+
+    fn buckets(&self) -> impl Iterator<Item = (&str, &LogCache)> {
+        self.buckets
+            .iter()
+            .map(|(prefix, cache)| (prefix.as_str(), cache))
+            .chain(std::iter::once((DEFAULT_BUCKET, &self.default)))
+    }
+
+    /// 各分桶的缓存状态
+    pub async fn stats(&self) -> Vec<(String, CacheStats)> {
+        let mut stats = Vec::new();
+        for (name, cache) in self.buckets() {
+            stats.push((name.to_string(), cache.read().await.stats()));
+        }
+        stats
+    }
+
+    /// 按 `query.component` 选择分桶查询；未指定时查询全部分桶，未知组件返回空结果
+    pub async fn query(&self, query: &LogQuery) -> LogPage {
+        match query.component.as_deref() {
+            Some(component) => match self.bucket(component) {
+                Some(cache) => query_logs(cache, query).await,
+                None => LogPage {
+                    page: query.page.unwrap_or(1).max(1),
+                    page_size: query
+                        .page_size
+                        .unwrap_or(crate::store::DEFAULT_PAGE_SIZE)
+                        .max(1),
+                    ..Default::default()
+                },
+            },
+            None => query_logs(self, query).await,
+        }
+    }
+}
+
+/// 按产生顺序合并：全部条目都带 `mono_ns` 时按它排序（不受时钟回拨影响），
+/// 否则按时间戳排序，解析失败的排在最前；稳定排序保证同一分桶内的顺序不变
+fn sort_by_time(entries: &mut [LogEntry]) {
+    if entries.iter().all(|entry| entry.mono_ns.is_some()) {
+        entries.sort_by_key(|entry| entry.mono_ns);
+        return;
+    }
+    entries.sort_by_cached_key(|entry| {
+        entry
+            .timestamp
+            .to_datetime()
+            .map(|t| t.timestamp_nanos_opt().unwrap_or_default())
+    });
+}
+
+impl LogStore for RoutedLogCache {
+    async fn append(&self, entry: LogEntry) {
+        let cache = self
+            .bucket(self.route(&entry.target))
+            .unwrap_or(&self.default);
+        cache.write().await.push(entry);
+    }
+
+    /// 合并全部分桶并按时间排序
+    async fn snapshot(&self) -> Vec<LogEntry> {
+        let mut entries = Vec::new();
+        for (_, cache) in self.buckets() {
+            entries.extend(cache.read().await.iter().cloned());
+        }
+        sort_by_time(&mut entries);
+        entries
+    }
+
+    async fn snapshot_level(&self, level: &str) -> Vec<LogEntry> {
+        let mut entries = Vec::new();
+        for (_, cache) in self.buckets() {
+            entries.extend(cache.read().await.iter_level(level).cloned());
+        }
+        sort_by_time(&mut entries);
+        entries
+    }
+}
+
+impl Default for RoutedLogCache {
+    fn default() -> Self {
+        Self::new(std::iter::empty::<(String, usize)>())
+            .with_default_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(target: &str, second: u32) -> LogEntry {
+        LogEntry {
+            timestamp: format!("2024-06-01T00:00:{:02}+00:00", second).into(),
+            level: "INFO".to_string(),
+            target: target.to_string(),
+            message: format!("{} {}", target, second),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buckets_are_isolated() {
+        let routed =
+            RoutedLogCache::new(vec![("ingestor", 3), ("executor", 2)]).with_default_capacity(5);
+        assert_eq!(routed.route("executor::orders"), "executor");
+        assert_eq!(routed.route("executors"), DEFAULT_BUCKET);
+
+        routed.append(entry("executor::orders", 0)).await;
+        routed.append(entry("strategy", 1)).await;
+        // 刷屏的 ingestor 只淘汰自己的历史
+        for second in 2..20 {
+            routed.append(entry("ingestor::ws", second)).await;
+        }
+
+        let stats = routed.stats().await;
+        let entries: Vec<(&str, usize)> = stats
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats.entries))
+            .collect();
+        assert_eq!(
+            entries,
+            [("ingestor", 3), ("executor", 1), (DEFAULT_BUCKET, 1)]
+        );
+        assert_eq!(stats[0].1.evicted, 15);
+
+        let mut query = LogQuery {
+            component: Some("executor".to_string()),
+            ..Default::default()
+        };
+        let page = routed.query(&query).await;
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].entry.message, "executor::orders 0");
+
+        query.component = None;
+        let page = routed.query(&query).await;
+        let messages: Vec<&str> = page
+            .items
+            .iter()
+            .map(|hit| hit.entry.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "ingestor::ws 19",
+                "ingestor::ws 18",
+                "ingestor::ws 17",
+                "strategy 1",
+                "executor::orders 0"
+            ]
+        );
+
+        query.component = Some("unknown".to_string());
+        assert_eq!(routed.query(&query).await.total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_merge_orders_by_mono_ns_after_clock_step() {
+        let routed = RoutedLogCache::new(vec![("executor", 4)]);
+        // 第二条之后时钟回拨了 40 秒
+        for (target, second, mono_ns) in [
+            ("executor", 50, 1),
+            ("strategy", 55, 2),
+            ("executor", 15, 3),
+            ("strategy", 16, 4),
+        ] {
+            routed
+                .append(LogEntry {
+                    mono_ns: Some(mono_ns),
+                    ..entry(target, second)
+                })
+                .await;
+        }
+        let messages: Vec<String> = routed
+            .snapshot()
+            .await
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+        assert_eq!(
+            messages,
+            ["executor 50", "strategy 55", "executor 15", "strategy 16"]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use tracing::Metadata;
+use tracing_subscriber::filter::{filter_fn, FilterFn};
+
+/// 按 target 决定日志去向的规则：是否持久化、广播、输出到控制台
+///
+/// 配置语法（可写在文件中，`#` 开头为注释，未写的开关默认为 true）：
+///
+/// ```text
+/// route "payments::*" { persist = false, broadcast = true, console = true }
+/// route "exchange::ws" { console = false }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "broadcast", derive(serde::Serialize))]
+pub struct RouteRule {
+    /// target 通配模式，`*` 匹配任意字符（含 `::`）；以 `::*` 结尾时也匹配前缀本身
+    pub pattern: String,
+    pub persist: bool,
+    pub broadcast: bool,
+    pub console: bool,
+}
+
+impl RouteRule {
+    /// 三个去向都开启的规则
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            persist: true,
+            broadcast: true,
+            console: true,
+        }
+    }
+
+    pub fn matches(&self, target: &str) -> bool {
+        target_matches(&self.pattern, target)
+    }
+}
+
+/// 单条规则及其命中次数，供审计确认规则确实生效
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "broadcast", derive(serde::Serialize))]
+pub struct RouteRuleStats {
+    #[cfg_attr(feature = "broadcast", serde(flatten))]
+    pub rule: RouteRule,
+    pub matched: u64,
+}
+
+/// 有序规则表，按书写顺序取第一条匹配的规则；没有规则匹配时各去向均开启
+#[derive(Debug, Default)]
+pub struct RouteRules {
+    rules: Vec<(RouteRule, AtomicU64)>,
+}
+
+/// 规则解析失败，`line` 从 1 开始
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RouteParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid route rule at line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+impl std::error::Error for RouteParseError {}
+
+impl RouteParseError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        "LT-CFG-003"
+    }
+}
+
+impl RouteRules {
+    pub fn new(rules: impl IntoIterator<Item = RouteRule>) -> Self {
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| (rule, AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// 解析配置文本，语法见 `RouteRule`
+    pub fn parse(input: &str) -> Result<Self, RouteParseError> {
+        let mut tokens = Tokens::new(input);
+        let mut rules = Vec::new();
+        while let Some(token) = tokens.next()? {
+            match token {
+                Token::Word("route") => {}
+                Token::Punct(';') => continue,
+                other => return Err(tokens.error(format!("expected `route`, found {}", other))),
+            }
+            let pattern = match tokens.next()? {
+                Some(Token::Str(pattern)) => pattern,
+                other => return Err(tokens.unexpected("quoted target pattern", other)),
+            };
+            tokens.expect('{')?;
+            let mut rule = RouteRule::new(pattern);
+            loop {
+                let key = match tokens.next()? {
+                    Some(Token::Punct('}')) => break,
+                    Some(Token::Word(key)) => key,
+                    other => {
+                        return Err(
+                            tokens.unexpected("`persist`, `broadcast`, `console` or `}`", other)
+                        )
+                    }
+                };
+                tokens.expect('=')?;
+                let value = match tokens.next()? {
+                    Some(Token::Word("true")) => true,
+                    Some(Token::Word("false")) => false,
+                    other => return Err(tokens.unexpected("`true` or `false`", other)),
+                };
+                match key {
+                    "persist" => rule.persist = value,
+                    "broadcast" => rule.broadcast = value,
+                    "console" => rule.console = value,
+                    _ => return Err(tokens.error(format!("unknown option `{}`", key))),
+                }
+                match tokens.next()? {
+                    Some(Token::Punct(',')) => {}
+                    Some(Token::Punct('}')) => break,
+                    other => return Err(tokens.unexpected("`,` or `}`", other)),
+                }
+            }
+            rules.push(rule);
+        }
+        Ok(Self::new(rules))
+    }
+
+    /// 从环境变量读取：`LOG_ROUTES` 为规则文本，`LOG_ROUTES_FILE` 为规则文件路径，两者都设置时合并（文件在前）
+    pub fn from_env() -> Result<Self, RouteParseError> {
+        let mut input = String::new();
+        if let Ok(path) = std::env::var("LOG_ROUTES_FILE") {
+            input = std::fs::read_to_string(&path).map_err(|e| RouteParseError {
+                line: 0,
+                message: format!("failed to read {}: {}", path, e),
+            })?;
+            input.push('\n');
+        }
+        if let Ok(routes) = std::env::var("LOG_ROUTES") {
+            input.push_str(&routes);
+        }
+        Self::parse(&input)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &RouteRule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// 第一条匹配 target 的规则，不计数
+    pub fn find(&self, target: &str) -> Option<&RouteRule> {
+        self.rules().find(|rule| rule.matches(target))
+    }
+
+    /// 同 `find`，并计入该规则的命中次数；每条日志进入管线时调用一次
+    pub fn route(&self, target: &str) -> Option<&RouteRule> {
+        let (rule, matched) = self.rules.iter().find(|(rule, _)| rule.matches(target))?;
+        matched.fetch_add(1, Ordering::Relaxed);
+        Some(rule)
+    }
+
+    /// 各规则及命中次数，按书写顺序排列
+    pub fn stats(&self) -> Vec<RouteRuleStats> {
+        self.rules
+            .iter()
+            .map(|(rule, matched)| RouteRuleStats {
+                rule: rule.clone(),
+                matched: matched.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// 控制台 fmt Layer 的 per-layer 过滤器，丢弃 `console = false` 的 target
+    pub fn console_filter(self: Arc<Self>) -> FilterFn<impl Fn(&Metadata<'_>) -> bool> {
+        filter_fn(move |metadata| self.find(metadata.target()).is_none_or(|rule| rule.console))
+    }
+}
+
+/// 进程级的规则表，首次调用时按 `RouteRules::from_env` 读取；配置有误时打印错误并不启用任何规则
+pub fn env_routes() -> Arc<RouteRules> {
+    static ROUTES: OnceLock<Arc<RouteRules>> = OnceLock::new();
+    ROUTES
+        .get_or_init(|| {
+            Arc::new(RouteRules::from_env().unwrap_or_else(|e| {
+                eprintln!("listen-tracing: [{}] ignoring route rules: {}", e.code(), e);
+                RouteRules::default()
+            }))
+        })
+        .clone()
+}
+
+/// target 通配匹配，语法见 `RouteRule::pattern`
+pub(crate) fn target_matches(pattern: &str, target: &str) -> bool {
+    if let Some(module) = pattern.strip_suffix("::*") {
+        if target == module {
+            return true;
+        }
+    }
+    glob_matches(target, pattern)
+}
+
+/// `target` 是模块 `prefix` 本身或其子模块，`hyper` 匹配 `hyper::client` 而不匹配 `hyperx`
+#[cfg(feature = "broadcast")]
+pub(crate) fn target_has_prefix(target: &str, prefix: &str) -> bool {
+    target
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// 整体通配匹配，`*` 匹配任意长度的字符
+fn glob_matches(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] != '*' && pattern[p] == text[t] {
+            t += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            star = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+enum Token<'a> {
+    Word(&'a str),
+    Str(String),
+    Punct(char),
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Punct(c) => write!(f, "`{}`", c),
+        }
+    }
+}
+
+struct Tokens<'a> {
+    input: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: String) -> RouteParseError {
+        RouteParseError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn unexpected(&self, expected: &str, found: Option<Token<'_>>) -> RouteParseError {
+        let found = found.map_or_else(|| "end of input".to_string(), |t| t.to_string());
+        self.error(format!("expected {}, found {}", expected, found))
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), RouteParseError> {
+        match self.next()? {
+            Some(Token::Punct(c)) if c == punct => Ok(()),
+            other => Err(self.unexpected(&format!("`{}`", punct), other)),
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Token<'a>>, RouteParseError> {
+        let mut chars = self.input[self.pos..].char_indices().peekable();
+        // 跳过空白与注释
+        let mut in_comment = false;
+        let start = loop {
+            let Some(&(i, c)) = chars.peek() else {
+                self.pos = self.input.len();
+                return Ok(None);
+            };
+            if c == '\n' {
+                self.line += 1;
+                in_comment = false;
+            } else if c == '#' {
+                in_comment = true;
+            } else if !in_comment && !c.is_whitespace() {
+                break self.pos + i;
+            }
+            chars.next();
+        };
+        let rest = &self.input[start..];
+        let c = rest.chars().next().unwrap_or_default();
+        if c == '"' {
+            let Some(end) = rest[1..].find('"') else {
+                return Err(self.error("unterminated string".to_string()));
+            };
+            self.pos = start + end + 2;
+            return Ok(Some(Token::Str(rest[1..end + 1].to_string())));
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            self.pos = start + len;
+            return Ok(Some(Token::Word(&rest[..len])));
+        }
+        self.pos = start + c.len_utf8();
+        Ok(Some(Token::Punct(c)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match_rules() {
+        let rules = RouteRules::parse(
+            r#"
+            # 合规：支付日志不落盘
+            route "payments::*" { persist = false, broadcast = true, console = true }
+            route "exchange::ws" { console = false, }
+            route "*::heartbeat" {}
+            "#,
+        )
+        .unwrap();
+        let parsed: Vec<&RouteRule> = rules.rules().collect();
+        assert_eq!(
+            parsed,
+            [
+                &RouteRule {
+                    persist: false,
+                    ..RouteRule::new("payments::*")
+                },
+                &RouteRule {
+                    console: false,
+                    ..RouteRule::new("exchange::ws")
+                },
+                &RouteRule::new("*::heartbeat"),
+            ]
+        );
+
+        assert!(!rules.route("payments").unwrap().persist);
+        assert!(!rules.route("payments::card::refund").unwrap().persist);
+        assert!(rules.route("paymentsx").is_none());
+        assert!(!rules.route("exchange::ws").unwrap().console);
+        assert!(rules.route("exchange::ws::book").is_none());
+        assert!(rules.route("feed::binance::heartbeat").is_some());
+        assert!(rules.find("payments::card").is_some());
+
+        let matched: Vec<u64> = rules.stats().iter().map(|s| s.matched).collect();
+        assert_eq!(matched, [2, 1, 1]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (input, line, message) in [
+            (
+                "route payments {}",
+                1,
+                "expected quoted target pattern, found `payments`",
+            ),
+            (
+                "route \"a\" { persist = maybe }",
+                1,
+                "expected `true` or `false`, found `maybe`",
+            ),
+            (
+                "route \"a\" {\n  replicate = true }",
+                2,
+                "unknown option `replicate`",
+            ),
+            (
+                "route \"a\" { persist = true",
+                1,
+                "expected `,` or `}`, found end of input",
+            ),
+            ("rule \"a\" {}", 1, "expected `route`, found `rule`"),
+            ("route \"a {}", 1, "unterminated string"),
+        ] {
+            let err = RouteRules::parse(input).err().unwrap();
+            assert_eq!(
+                (err.line, err.message.as_str()),
+                (line, message),
+                "{}",
+                input
+            );
+        }
+        assert!(RouteRules::parse("").unwrap().is_empty());
+    }
+}
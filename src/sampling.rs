@@ -0,0 +1,292 @@
+//! 按 trace 一致的日志采样
+//!
+//! 带 trace 键（事件或所在 span 的 `trace_id` / `request_id` 字段，或任务的关联 ID）的日志
+//! 按键的哈希决定去留，同一请求的日志要么全部保留要么全部丢弃；没有键的日志逐条采样。
+//! WARN / ERROR 永远保留，并让同一 trace 之后 `force_keep` 时长内的日志也全部保留，
+//! 以便看到出错请求的后续经过。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::broadcast::unquote_debug;
+use crate::correlation;
+
+/// 默认识别的 trace 键字段
+pub const DEFAULT_TRACE_KEYS: [&str; 2] = ["trace_id", "request_id"];
+
+/// 强制保留表超过该长度时清理过期项
+const FORCED_CLEANUP_LEN: usize = 1024;
+
+/// 采样配置
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SamplingConfig {
+    /// 保留比例，0.0 ~ 1.0
+    pub rate: f64,
+    /// 作为 trace 键的字段名，按顺序取第一个存在的
+    pub keys: Vec<String>,
+    /// WARN / ERROR 之后同一 trace 强制保留的时长
+    pub force_keep: Duration,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            keys: DEFAULT_TRACE_KEYS
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+            force_keep: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SamplingConfig {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate: rate.clamp(0.0, 1.0),
+            ..Default::default()
+        }
+    }
+}
+
+/// 挂在 span extensions 中的 trace 键，子 span 与事件沿 span 链向上查找
+struct TraceKey(String);
+
+/// 按 `SamplingConfig` 做保留决定，`BroadcastLogLayer` 对每个事件调用一次
+#[derive(Debug)]
+pub struct Sampler {
+    config: SamplingConfig,
+    /// 保留阈值：哈希值小于它的保留
+    threshold: u64,
+    /// 无 trace 键时逐条采样的计数
+    untraced: AtomicU64,
+    /// trace 键 -> 强制保留截止时刻
+    forced: Mutex<HashMap<String, Instant>>,
+}
+
+impl Sampler {
+    pub fn new(config: SamplingConfig) -> Self {
+        let threshold = if config.rate >= 1.0 {
+            u64::MAX
+        } else {
+            (config.rate.max(0.0) * u64::MAX as f64) as u64
+        };
+        Self {
+            config,
+            threshold,
+            untraced: AtomicU64::new(0),
+            forced: Mutex::default(),
+        }
+    }
+
+    pub fn config(&self) -> &SamplingConfig {
+        &self.config
+    }
+
+    /// 是否保留一条 `level` 级别、trace 键为 `key` 的日志
+    pub fn keep(&self, level: Level, key: Option<&str>, now: Instant) -> bool {
+        let Some(key) = key else {
+            if level <= Level::WARN {
+                return true;
+            }
+            let n = self.untraced.fetch_add(1, Ordering::Relaxed);
+            return mix(n) < self.threshold;
+        };
+        let mut forced = self.forced.lock().unwrap();
+        if level <= Level::WARN {
+            if forced.len() >= FORCED_CLEANUP_LEN {
+                forced.retain(|_, until| *until > now);
+            }
+            forced.insert(key.to_string(), now + self.config.force_keep);
+            return true;
+        }
+        if forced.get(key).is_some_and(|until| *until > now) {
+            return true;
+        }
+        drop(forced);
+        mix(fnv1a(key.as_bytes())) < self.threshold
+    }
+
+    /// 事件的 trace 键：事件字段优先，其次由内向外的 span，最后是任务的关联 ID
+    pub(crate) fn event_key<S>(&self, event: &Event<'_>, ctx: &Context<'_, S>) -> Option<String>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = KeyVisitor::new(&self.config.keys);
+        event.record(&mut visitor);
+        visitor
+            .key()
+            .or_else(|| {
+                ctx.event_scope(event)?.find_map(|span| {
+                    let extensions = span.extensions();
+                    extensions.get::<TraceKey>().map(|key| key.0.clone())
+                })
+            })
+            .or_else(correlation::current_correlation_id)
+    }
+
+    /// span 创建时记下其 trace 键
+    pub(crate) fn on_new_span<S>(&self, attrs: &Attributes<'_>, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = KeyVisitor::new(&self.config.keys);
+        attrs.record(&mut visitor);
+        self.store_key(visitor, id, ctx);
+    }
+
+    /// span 之后补记 trace 键字段（`Span::record`）时更新
+    pub(crate) fn on_record<S>(&self, values: &Record<'_>, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = KeyVisitor::new(&self.config.keys);
+        values.record(&mut visitor);
+        self.store_key(visitor, id, ctx);
+    }
+
+    fn store_key<S>(&self, visitor: KeyVisitor<'_>, id: &Id, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        if let (Some(key), Some(span)) = (visitor.key(), ctx.span(id)) {
+            span.extensions_mut().replace(TraceKey(key));
+        }
+    }
+}
+
+/// 只收集配置中 trace 键字段的 visitor，下标即字段的优先级
+struct KeyVisitor<'a> {
+    keys: &'a [String],
+    found: Vec<Option<String>>,
+}
+
+impl<'a> KeyVisitor<'a> {
+    fn new(keys: &'a [String]) -> Self {
+        Self {
+            keys,
+            found: vec![None; keys.len()],
+        }
+    }
+
+    fn key(self) -> Option<String> {
+        self.found.into_iter().flatten().next()
+    }
+
+    fn put(&mut self, field: &Field, value: impl FnOnce() -> String) {
+        if let Some(i) = self.keys.iter().position(|key| key == field.name()) {
+            self.found[i] = Some(value());
+        }
+    }
+}
+
+impl Visit for KeyVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.put(field, || value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.put(field, || unquote_debug(format!("{:?}", value)));
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// splitmix64 的混合步骤，把顺序计数或相近的哈希打散到整个 u64 范围
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::{BroadcastLogLayer, LogCache, LogEntry};
+    use std::collections::BTreeMap;
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn test_trace_coherent_sampling() {
+        skip_if_events_compiled_out!();
+        let (tx, mut rx) = tokio::sync::broadcast::channel(8192);
+        let clock = MockClock::new(chrono::Utc::now());
+        let layer = BroadcastLogLayer::new(tx, LogCache::default())
+            .without_persistence()
+            .with_clock(clock.clone())
+            .with_sampling(SamplingConfig::new(0.5));
+        let stats = layer.stats();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        for trace in 0..200 {
+            async {
+                for step in 0..5 {
+                    tracing::info!(step, "span trace {}", trace);
+                }
+            }
+            .instrument(tracing::info_span!("request", trace_id = %format!("t-{}", trace)))
+            .await;
+            for step in 0..5 {
+                tracing::debug!(request_id = trace, step, "field trace {}", trace);
+            }
+        }
+        for i in 0..1000 {
+            tracing::info!("untraced {}", i);
+        }
+
+        let mut per_trace: BTreeMap<String, usize> = BTreeMap::new();
+        let mut untraced = 0;
+        while let Ok(entry) = rx.try_recv() {
+            match entry.message.strip_prefix("untraced") {
+                Some(_) => untraced += 1,
+                None => *per_trace.entry(entry.message).or_default() += 1,
+            }
+        }
+        // 同一 trace 的 5 条日志要么全部保留要么全部丢弃
+        assert!(per_trace.values().all(|&n| n == 5), "{:?}", per_trace);
+        let kept_spans = per_trace.keys().filter(|m| m.starts_with("span")).count();
+        let kept_fields = per_trace.len() - kept_spans;
+        assert!((60..=140).contains(&kept_spans), "{}", kept_spans);
+        assert!((60..=140).contains(&kept_fields), "{}", kept_fields);
+        assert!((400..=600).contains(&untraced), "{}", untraced);
+        assert_eq!(
+            stats.snapshot().sampled_out,
+            (2000 - per_trace.len() * 5 + 1000 - untraced) as u64
+        );
+
+        // 找一个被丢弃的 trace：出错后其后续日志在 force_keep 内保留
+        let dropped = (0..200)
+            .find(|trace| !per_trace.contains_key(&format!("field trace {}", trace)))
+            .unwrap();
+        let emit = |level: Level| match level {
+            Level::ERROR => tracing::error!(request_id = dropped, "failed"),
+            _ => tracing::info!(request_id = dropped, "after"),
+        };
+        emit(Level::INFO);
+        emit(Level::ERROR);
+        emit(Level::INFO);
+        clock.advance(Duration::from_secs(31));
+        emit(Level::INFO);
+        let messages: Vec<String> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|entry: LogEntry| entry.message)
+            .collect();
+        assert_eq!(messages, ["failed", "after"]);
+    }
+}
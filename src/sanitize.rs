@@ -0,0 +1,177 @@
+use std::borrow::Cow;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde::Serialize;
+
+/// 连续空白（控制字符之外的空格等）保留的最大长度，超出部分删除
+pub const MAX_WHITESPACE_RUN: usize = 8;
+
+/// 捕获字符串中控制字符的处理方式
+///
+/// `Strip` 与 `Escape` 还会删除 ANSI CSI 序列（如 `\x1b[31m`），并把连续空白截到
+/// `MAX_WHITESPACE_RUN` 个，避免按原文渲染 message 的查看器被终端控制序列污染。
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitize {
+    /// 原样保留（serde_json 仍会在 JSON 中正确转义）
+    Off,
+    /// 删除控制字符
+    #[default]
+    Strip,
+    /// 替换为可见的转义文本，如 `\n`、`\u{0}`
+    Escape,
+}
+
+/// 按模式处理控制字符，无需处理时不分配
+pub fn sanitize(s: &str, mode: Sanitize) -> Cow<'_, str> {
+    if mode == Sanitize::Off || is_clean(s) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut whitespace = 0;
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            skip_csi(&mut chars);
+            continue;
+        }
+        if !c.is_control() {
+            whitespace = if c.is_whitespace() { whitespace + 1 } else { 0 };
+            if whitespace <= MAX_WHITESPACE_RUN {
+                out.push(c);
+            }
+            continue;
+        }
+        if mode == Sanitize::Escape {
+            whitespace = 0;
+            match c {
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                _ => out.extend(c.escape_unicode()),
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// 不含控制字符且没有超长的连续空白
+fn is_clean(s: &str) -> bool {
+    let mut whitespace = 0;
+    s.chars().all(|c| {
+        whitespace = if c.is_whitespace() { whitespace + 1 } else { 0 };
+        !c.is_control() && whitespace <= MAX_WHITESPACE_RUN
+    })
+}
+
+/// 跳过 `ESC [` 之后的参数字节、中间字节与结束字节
+fn skip_csi(chars: &mut Peekable<Chars<'_>>) {
+    while chars.next_if(|c| matches!(c, '\x20'..='\x3f')).is_some() {}
+    chars.next_if(|c| matches!(c, '\x40'..='\x7e'));
+}
+
+/// 同 `sanitize`，但接收已拥有的字符串，无需处理时原样返回，避免再次拷贝
+pub(crate) fn sanitize_owned(s: String, mode: Sanitize) -> String {
+    match sanitize(&s, mode) {
+        Cow::Borrowed(_) => s,
+        Cow::Owned(out) => out,
+    }
+}
+
+/// 截断时追加的标记
+pub const TRUNCATED_MARKER: &str = "…(truncated)";
+
+/// 超过 `max` 字节时在不大于 `max` 的字符边界处截断并追加 `TRUNCATED_MARKER`
+pub fn truncate_bytes(mut s: String, max: usize) -> String {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    s.push_str(TRUNCATED_MARKER);
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogCache, LogEntry};
+    use tokio::sync::broadcast;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn test_sanitize_modes() {
+        assert!(matches!(
+            sanitize("plain", Sanitize::Strip),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(sanitize("a\x00b\nc", Sanitize::Off), "a\x00b\nc");
+        assert_eq!(sanitize("a\x00b\nc", Sanitize::Strip), "abc");
+        assert_eq!(sanitize("a\x00b\nc", Sanitize::Escape), "a\\u{0}b\\nc");
+        assert_eq!(Sanitize::default(), Sanitize::Strip);
+    }
+
+    #[test]
+    fn test_sanitize_ansi_and_whitespace() {
+        let raw = "\x1b[1;31mERROR\x1b[0m disk\x00 full\r\n\x1b[2K日本語 ключ 🚀\x1b[";
+        assert_eq!(
+            sanitize(raw, Sanitize::Strip),
+            "ERROR disk full日本語 ключ 🚀"
+        );
+        assert_eq!(
+            sanitize(raw, Sanitize::Escape),
+            "ERROR disk\\u{0} full\\r\\n日本語 ключ 🚀"
+        );
+        assert_eq!(sanitize(raw, Sanitize::Off), raw);
+        // 非 CSI 的 ESC 按普通控制字符处理
+        assert_eq!(sanitize("a\x1bb", Sanitize::Escape), "a\\u{1b}b");
+
+        let padded = format!("a{}b\u{3000}\u{3000}c", " ".repeat(20));
+        assert_eq!(
+            sanitize(&padded, Sanitize::Strip),
+            format!("a{}b\u{3000}\u{3000}c", " ".repeat(MAX_WHITESPACE_RUN))
+        );
+        let indented = format!("a{}b", " ".repeat(MAX_WHITESPACE_RUN));
+        assert!(matches!(
+            sanitize(&indented, Sanitize::Strip),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_persisted_line_has_no_control_chars() {
+        skip_if_events_compiled_out!();
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_sanitize_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .with_persist_path(&path)
+            .with_sanitize(Sanitize::Escape);
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!(detail = "x\x00y", "bad\x00 input\nsecond line");
+        crate::broadcast::tests::wait_for_cache(&cache, 1).await;
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].chars().any(char::is_control));
+        let entry: LogEntry = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(entry.message, "bad\\u{0} input\\nsecond line");
+        assert_eq!(entry.fields["detail"], "x\\u{0}y");
+    }
+}
@@ -0,0 +1,317 @@
+//! 命名保存的查询（看板的"收藏的过滤条件"）
+//!
+//! `SavedQueries` 在内存中以读写锁保存 名称 -> `LogQuery`，每次修改后整体写回一个 JSON 文件：
+//! 先写临时文件再 rename，进程中途崩溃时旧文件保持完整。文件默认放在持久化文件旁边
+//! （`<persist_path>.queries.json`，见 `queries_path`）。
+//!
+//! 读取时忽略未知的顶层键，单条查询中本版本不认识的字段（更新版本写入的）原样保留，
+//! 下次写回时不会丢失。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::store::{query_logs, LogPage, LogStore};
+use crate::LogQuery;
+
+/// 保存文件的默认大小上限
+pub const DEFAULT_MAX_QUERIES_BYTES: usize = 64 * 1024;
+
+/// 持久化文件 `path` 对应的保存文件路径：`<path>.queries.json`
+pub fn queries_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".queries.json");
+    PathBuf::from(name)
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SavedQueryError {
+    Io(io::Error),
+    /// 保存文件不是合法的 JSON
+    Json(serde_json::Error),
+    /// 写回后文件将超过大小上限，本次修改未生效
+    TooLarge {
+        bytes: usize,
+        max: usize,
+    },
+}
+
+impl SavedQueryError {
+    /// 稳定的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            SavedQueryError::Io(_) => "LT-IO-002",
+            SavedQueryError::Json(_) => "LT-PRS-005",
+            SavedQueryError::TooLarge { .. } => "LT-CFG-004",
+        }
+    }
+}
+
+impl fmt::Display for SavedQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SavedQueryError::Io(e) => write!(f, "failed to access saved queries: {}", e),
+            SavedQueryError::Json(e) => write!(f, "invalid saved queries file: {}", e),
+            SavedQueryError::TooLarge { bytes, max } => write!(
+                f,
+                "saved queries would take {} bytes, exceeding the limit of {}",
+                bytes, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SavedQueryError {}
+
+impl From<io::Error> for SavedQueryError {
+    fn from(e: io::Error) -> Self {
+        SavedQueryError::Io(e)
+    }
+}
+
+/// 文件中的一条查询：已知字段进入 `query`，其余保留在 `extra`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StoredQuery {
+    #[serde(flatten)]
+    query: LogQuery,
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct QueriesFile {
+    #[serde(default)]
+    queries: BTreeMap<String, StoredQuery>,
+}
+
+/// 命名保存的查询，见模块文档
+#[derive(Debug)]
+pub struct SavedQueries {
+    path: PathBuf,
+    max_bytes: usize,
+    queries: RwLock<BTreeMap<String, StoredQuery>>,
+}
+
+impl SavedQueries {
+    /// 打开保存文件，不存在时从空集合开始（首次保存时创建）
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, SavedQueryError> {
+        let path = path.into();
+        let queries = match fs::read(&path) {
+            Ok(bytes) => {
+                serde_json::from_slice::<QueriesFile>(&bytes)
+                    .map_err(SavedQueryError::Json)?
+                    .queries
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            max_bytes: DEFAULT_MAX_QUERIES_BYTES,
+            queries: RwLock::new(queries),
+        })
+    }
+
+    /// 保存文件的大小上限（默认 `DEFAULT_MAX_QUERIES_BYTES`）
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 保存（或覆盖）一条查询，覆盖时返回旧的定义
+    pub fn save_query(
+        &self,
+        name: impl Into<String>,
+        query: LogQuery,
+    ) -> Result<Option<LogQuery>, SavedQueryError> {
+        let mut queries = self.queries.write().unwrap_or_else(PoisonError::into_inner);
+        let mut updated = queries.clone();
+        let previous = updated.insert(
+            name.into(),
+            StoredQuery {
+                query,
+                extra: BTreeMap::new(),
+            },
+        );
+        self.write(&updated)?;
+        *queries = updated;
+        Ok(previous.map(|stored| stored.query))
+    }
+
+    /// 删除一条查询，返回被删除的定义
+    pub fn remove_query(&self, name: &str) -> Result<Option<LogQuery>, SavedQueryError> {
+        let mut queries = self.queries.write().unwrap_or_else(PoisonError::into_inner);
+        if !queries.contains_key(name) {
+            return Ok(None);
+        }
+        let mut updated = queries.clone();
+        let removed = updated.remove(name);
+        self.write(&updated)?;
+        *queries = updated;
+        Ok(removed.map(|stored| stored.query))
+    }
+
+    pub fn get_query(&self, name: &str) -> Option<LogQuery> {
+        self.queries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .map(|stored| stored.query.clone())
+    }
+
+    /// 全部查询，按名称排序
+    pub fn list_queries(&self) -> Vec<(String, LogQuery)> {
+        self.queries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, stored)| (name.clone(), stored.query.clone()))
+            .collect()
+    }
+
+    /// 以保存的查询调用 `query_logs`，名称不存在时返回 None
+    pub async fn run_saved<S: LogStore + ?Sized>(&self, name: &str, store: &S) -> Option<LogPage> {
+        let query = self.get_query(name)?;
+        Some(query_logs(store, &query).await)
+    }
+
+    /// 写临时文件后 rename，超过大小上限时不写
+    fn write(&self, queries: &BTreeMap<String, StoredQuery>) -> Result<(), SavedQueryError> {
+        #[derive(Serialize)]
+        struct Borrowed<'a> {
+            queries: &'a BTreeMap<String, StoredQuery>,
+        }
+        let bytes =
+            serde_json::to_vec_pretty(&Borrowed { queries }).map_err(SavedQueryError::Json)?;
+        if bytes.len() > self.max_bytes {
+            return Err(SavedQueryError::TooLarge {
+                bytes: bytes.len(),
+                max: self.max_bytes,
+            });
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::LogStore;
+    use crate::{LogCache, LogEntry};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "listen-tracing-saved-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(queries_path(&path));
+        queries_path(&path)
+    }
+
+    #[tokio::test]
+    async fn test_save_list_run() {
+        let path = temp_path("crud");
+        let saved = SavedQueries::open(&path).unwrap();
+        let warnings = LogQuery {
+            level: Some("WARN".to_string()),
+            ..Default::default()
+        };
+        assert!(saved.save_query("warnings", warnings).unwrap().is_none());
+        let fills = LogQuery {
+            keyword: Some("fill".to_string()),
+            ..Default::default()
+        };
+        saved.save_query("fills", fills).unwrap();
+        // 同名覆盖返回旧定义
+        let errors = LogQuery {
+            level: Some("ERROR".to_string()),
+            ..Default::default()
+        };
+        let previous = saved.save_query("warnings", errors).unwrap().unwrap();
+        assert_eq!(previous.level.as_deref(), Some("WARN"));
+
+        let names: Vec<String> = saved.list_queries().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["fills", "warnings"]);
+
+        let cache = LogCache::default();
+        for (level, message) in [
+            ("ERROR", "rejected"),
+            ("INFO", "order fill"),
+            ("WARN", "slow"),
+        ] {
+            cache
+                .append(LogEntry {
+                    level: level.to_string(),
+                    message: message.to_string(),
+                    ..Default::default()
+                })
+                .await;
+        }
+        let page = saved.run_saved("warnings", &cache).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].entry.message, "rejected");
+        assert!(saved.run_saved("missing", &cache).await.is_none());
+
+        // 重新打开后内容一致
+        let reopened = SavedQueries::open(&path).unwrap();
+        assert_eq!(reopened.list_queries().len(), 2);
+        assert_eq!(
+            reopened.get_query("fills").unwrap().keyword.as_deref(),
+            Some("fill")
+        );
+        assert!(reopened.remove_query("fills").unwrap().is_some());
+        assert!(reopened.remove_query("fills").unwrap().is_none());
+        assert_eq!(SavedQueries::open(&path).unwrap().list_queries().len(), 1);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_unknown_fields_and_size_cap() {
+        let path = temp_path("compat");
+        fs::write(
+            &path,
+            r#"{"version": 9, "queries": {"slow": {"keyword": "slow", "sort": "desc"}}}"#,
+        )
+        .unwrap();
+        let saved = SavedQueries::open(&path).unwrap().with_max_bytes(1024);
+        assert_eq!(
+            saved.get_query("slow").unwrap().keyword.as_deref(),
+            Some("slow")
+        );
+
+        // 写回时保留不认识的字段
+        saved.save_query("other", LogQuery::default()).unwrap();
+        let value: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(value["queries"]["slow"]["sort"], "desc");
+
+        let huge = LogQuery {
+            keyword: Some("x".repeat(2000)),
+            ..Default::default()
+        };
+        let err = saved.save_query("huge", huge).unwrap_err();
+        assert_eq!(err.code(), "LT-CFG-004");
+        assert!(saved.get_query("huge").is_none());
+        assert_eq!(SavedQueries::open(&path).unwrap().list_queries().len(), 2);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(SavedQueries::open(&path).unwrap_err().code(), "LT-PRS-005");
+        let _ = fs::remove_file(&path);
+    }
+}
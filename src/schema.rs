@@ -0,0 +1,265 @@
+//! `LogEntry` 结构与 `LogQuery` 参数的自描述，供前端或客户端生成类型
+//!
+//! 与 `viewer` 一样不绑定 HTTP 框架：由调用方把 `schema()` 的 JSON 挂到 `GET /logs/schema`。
+//! 描述是手工维护的常量，测试会与实际的序列化结果和 `LogQuery` 字段逐一核对，结构变化时必须同步修改。
+
+use serde::Serialize;
+
+use crate::LOG_ENTRY_SCHEMA;
+
+/// `LogEntry` 的一个 JSON 字段
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    /// JSON 类型：`string`、`integer`、`object`
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    /// 是否可能缺省（缺省时不写出该键）
+    pub optional: bool,
+    /// 引入该字段的 schema 版本
+    pub since: u8,
+    pub description: &'static str,
+}
+
+/// `LogQuery` 的一个查询参数
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamSchema {
+    pub name: &'static str,
+    /// 参数值类型：`string`、`integer`、`boolean`、`array`
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    /// 取值限定在这些值之内，空表示不限
+    pub allowed: &'static [&'static str],
+    pub description: &'static str,
+}
+
+/// `schema()` 的结果
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogSchema {
+    /// 当前的 `LOG_ENTRY_SCHEMA`
+    pub version: u8,
+    pub entry: &'static [FieldSchema],
+    pub query: &'static [ParamSchema],
+}
+
+const LEVELS: &[&str] = &["ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+
+const fn field(
+    name: &'static str,
+    ty: &'static str,
+    optional: bool,
+    since: u8,
+    description: &'static str,
+) -> FieldSchema {
+    FieldSchema {
+        name,
+        ty,
+        optional,
+        since,
+        description,
+    }
+}
+
+const fn param(
+    name: &'static str,
+    ty: &'static str,
+    allowed: &'static [&'static str],
+    description: &'static str,
+) -> ParamSchema {
+    ParamSchema {
+        name,
+        ty,
+        allowed,
+        description,
+    }
+}
+
+/// `LogEntry` 的字段，顺序与序列化顺序一致
+pub const LOG_ENTRY_FIELDS: &[FieldSchema] = &[
+    field("schema", "integer", false, 2, "entry schema version"),
+    field("timestamp", "string", false, 1, "RFC 3339 timestamp"),
+    field(
+        "level",
+        "string",
+        false,
+        1,
+        "ERROR, WARN, INFO, DEBUG or TRACE",
+    ),
+    field(
+        "target",
+        "string",
+        false,
+        1,
+        "tracing target, usually the module path",
+    ),
+    field("message", "string", false, 1, "formatted message"),
+    field(
+        "correlation_id",
+        "string",
+        true,
+        2,
+        "correlation id of the task",
+    ),
+    field("fields", "object", true, 2, "structured event fields"),
+    field(
+        "seq",
+        "integer",
+        true,
+        3,
+        "cache sequence number, only on cached entries",
+    ),
+    field("fingerprint", "string", true, 4, "call-site fingerprint"),
+    field(
+        "mono_ns",
+        "integer",
+        true,
+        5,
+        "process-local monotonic nanoseconds",
+    ),
+    field(
+        "date_bucket",
+        "string",
+        true,
+        6,
+        "partition key derived from the timestamp",
+    ),
+    field("name", "string", true, 7, "explicit event name"),
+];
+
+/// `LogQuery` 的参数
+pub const LOG_QUERY_PARAMS: &[ParamSchema] = &[
+    param("level", "string", LEVELS, "only entries of this level"),
+    param(
+        "keyword",
+        "string",
+        &[],
+        "case-insensitive substring of message or target",
+    ),
+    param("page", "integer", &[], "1-based page number"),
+    param("page_size", "integer", &[], "entries per page, default 50"),
+    param(
+        "include_highlights",
+        "boolean",
+        &["true", "false"],
+        "return keyword match ranges",
+    ),
+    param(
+        "component",
+        "string",
+        &[],
+        "component bucket, RoutedLogCache only",
+    ),
+    param(
+        "q",
+        "string",
+        &[],
+        "query expression, replaces level and keyword",
+    ),
+    param(
+        "field",
+        "array",
+        &[],
+        "[name, value] pair, exact match on a structured field",
+    ),
+    param(
+        "tz",
+        "string",
+        &[],
+        "display timezone for returned timestamps, IANA name or UTC offset",
+    ),
+];
+
+/// 当前 `LogEntry` 与 `LogQuery` 的描述
+pub fn schema() -> LogSchema {
+    LogSchema {
+        version: LOG_ENTRY_SCHEMA,
+        entry: LOG_ENTRY_FIELDS,
+        query: LOG_QUERY_PARAMS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogEntry, LogQuery};
+    use serde_json::{json, Value};
+
+    fn json_type(value: &Value) -> &'static str {
+        match value {
+            Value::String(_) => "string",
+            Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+            Value::Object(_) => "object",
+            _ => "other",
+        }
+    }
+
+    #[test]
+    fn test_entry_fields_match_serde_output() {
+        let full = LogEntry {
+            correlation_id: Some("req-1".to_string()),
+            fields: [("qty".to_string(), json!(5))].into_iter().collect(),
+            seq: Some(1),
+            fingerprint: Some("ab12".to_string()),
+            mono_ns: Some(42),
+            date_bucket: Some("2024-06-01".to_string()),
+            name: Some("order_filled".to_string()),
+            ..Default::default()
+        };
+        let Value::Object(full) = serde_json::to_value(&full).unwrap() else {
+            panic!("LogEntry is not an object");
+        };
+        let names: Vec<&str> = full.keys().map(String::as_str).collect();
+        let mut described: Vec<&str> = LOG_ENTRY_FIELDS.iter().map(|f| f.name).collect();
+        described.sort();
+        assert_eq!(names, described);
+        for field in LOG_ENTRY_FIELDS {
+            assert_eq!(json_type(&full[field.name]), field.ty, "{}", field.name);
+            assert!(field.since <= LOG_ENTRY_SCHEMA);
+        }
+
+        let Value::Object(minimal) = serde_json::to_value(LogEntry::default()).unwrap() else {
+            panic!("LogEntry is not an object");
+        };
+        for field in LOG_ENTRY_FIELDS {
+            assert_eq!(
+                !minimal.contains_key(field.name),
+                field.optional,
+                "{}",
+                field.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_params_match_log_query() {
+        // 从 Debug 输出取 LogQuery 的字段名：`LogQuery { level: None, keyword: None, .. }`
+        let debug = format!("{:?}", LogQuery::default());
+        let inner = debug
+            .strip_prefix("LogQuery { ")
+            .and_then(|s| s.strip_suffix(" }"))
+            .unwrap();
+        let names: Vec<&str> = inner
+            .split(", ")
+            .map(|pair| pair.split(':').next().unwrap())
+            .collect();
+        let described: Vec<&str> = LOG_QUERY_PARAMS.iter().map(|p| p.name).collect();
+        assert_eq!(names, described);
+
+        // 每个参数都能按描述的类型被反序列化
+        for param in LOG_QUERY_PARAMS {
+            let value = match param.ty {
+                "integer" => json!(3),
+                "boolean" => json!(true),
+                "array" => json!(["user_id", "42"]),
+                _ => json!(param.allowed.first().copied().unwrap_or("x")),
+            };
+            let query: LogQuery = serde_json::from_value(json!({ param.name: value })).unwrap();
+            assert_ne!(format!("{:?}", query), debug, "{}", param.name);
+        }
+
+        let described = serde_json::to_value(schema()).unwrap();
+        assert_eq!(described["version"], LOG_ENTRY_SCHEMA);
+        assert_eq!(described["entry"][0]["type"], "integer");
+        assert_eq!(described["query"][0]["allowed"][0], "ERROR");
+    }
+}
@@ -0,0 +1,349 @@
+//! 持久化文件的记录编码
+//!
+//! 写入任务先把记录（按 `JsonSchema` 命名、`TimestampFormat` 格式化之后）转成 JSON 值，
+//! 再交给 `BroadcastLogLayer::with_serializer` 配置的 `LogSerializer` 编码，按其 `Framing` 分隔：
+//!
+//! - `JsonlSerializer`：每条一行的 JSONL；未配置时写入任务不经过 JSON 值、直接输出 JSON 文本，
+//!   内容相同，只是键保持结构体的字段顺序（经过 JSON 值时按字母序）
+//! - `MessagePackSerializer`（feature `msgpack`）、`CborSerializer`（feature `cbor`）：
+//!   每条前加 4 字节大端长度，记录内容可以包含任意字节
+//!
+//! 读回时用 `decode_records` 拆分并解码，再交给 `LogEntry::migrate`。`follow`、`export`、
+//! 索引查询等按行读取文件的功能只支持 JSONL，长度前缀的文件不维护索引。
+
+use std::fmt;
+
+use serde_json::Value;
+
+/// 记录之间的分隔方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// 每条记录后跟一个 `\n`，记录本身不能含换行
+    Newline,
+    /// 每条记录前加 4 字节大端长度
+    LengthPrefixed,
+}
+
+/// 把一条记录（JSON 值）编码为持久化格式
+pub trait LogSerializer: Send + Sync {
+    /// 格式名称，记入 `EffectiveConfig::serializer`
+    fn name(&self) -> &'static str;
+
+    /// 把 `record` 编码后追加到 `out`，不含分隔
+    fn serialize(&self, record: &Value, out: &mut Vec<u8>);
+
+    /// 解码一条记录（不含分隔）
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, DecodeError>;
+
+    fn framing(&self) -> Framing;
+}
+
+/// JSON Lines
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonlSerializer;
+
+impl LogSerializer for JsonlSerializer {
+    fn name(&self) -> &'static str {
+        "jsonl"
+    }
+
+    fn serialize(&self, record: &Value, out: &mut Vec<u8>) {
+        // 写入 Vec 不会失败，Value 也总能序列化
+        let _ = serde_json::to_writer(out, record);
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, DecodeError> {
+        serde_json::from_slice(bytes).map_err(|e| DecodeError {
+            offset: e.column().saturating_sub(1),
+            reason: "invalid JSON",
+        })
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Newline
+    }
+}
+
+/// MessagePack，见 `msgpack` feature
+#[cfg(feature = "msgpack")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePackSerializer;
+
+#[cfg(feature = "msgpack")]
+impl LogSerializer for MessagePackSerializer {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn serialize(&self, record: &Value, out: &mut Vec<u8>) {
+        crate::msgpack::encode(record, out);
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, DecodeError> {
+        crate::msgpack::decode(bytes)
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::LengthPrefixed
+    }
+}
+
+/// CBOR，见 `cbor` feature
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborSerializer;
+
+#[cfg(feature = "cbor")]
+impl LogSerializer for CborSerializer {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn serialize(&self, record: &Value, out: &mut Vec<u8>) {
+        crate::cbor::encode(record, out);
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<Value, DecodeError> {
+        crate::cbor::decode(bytes)
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::LengthPrefixed
+    }
+}
+
+/// 编码一条记录并按 `framing` 加上分隔，追加到 `out`
+pub fn encode_record(serializer: &dyn LogSerializer, record: &Value, out: &mut Vec<u8>) {
+    match serializer.framing() {
+        Framing::Newline => {
+            serializer.serialize(record, out);
+            out.push(b'\n');
+        }
+        Framing::LengthPrefixed => {
+            // 先占位，编码后回填长度
+            let start = out.len();
+            out.extend_from_slice(&[0; 4]);
+            serializer.serialize(record, out);
+            let len = (out.len() - start - 4) as u32;
+            out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+        }
+    }
+}
+
+/// 按 `framing` 拆分出各条记录的字节；末尾不完整的长度前缀记录返回错误
+pub fn split_records(bytes: &[u8], framing: Framing) -> Result<Vec<&[u8]>, DecodeError> {
+    match framing {
+        Framing::Newline => Ok(bytes
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .collect()),
+        Framing::LengthPrefixed => {
+            let mut records = Vec::new();
+            let mut offset = 0;
+            while offset < bytes.len() {
+                let record = bytes
+                    .get(offset..offset + 4)
+                    .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+                    .and_then(|len| bytes.get(offset + 4..offset + 4 + len))
+                    .ok_or(DecodeError {
+                        offset,
+                        reason: "truncated record",
+                    })?;
+                records.push(record);
+                offset += 4 + record.len();
+            }
+            Ok(records)
+        }
+    }
+}
+
+/// 拆分并逐条解码持久化文件的内容
+pub fn decode_records(
+    serializer: &dyn LogSerializer,
+    bytes: &[u8],
+) -> Result<Vec<Value>, DecodeError> {
+    split_records(bytes, serializer.framing())?
+        .into_iter()
+        .map(|record| serializer.deserialize(record))
+        .collect()
+}
+
+/// 记录解码失败：`offset` 为出错位置（拆分时相对整个文件，解码时相对这条记录）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+impl DecodeError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        "LT-PRS-006"
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid log record at byte {}: {}",
+            self.offset, self.reason
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// 嵌套层数上限，防止恶意输入耗尽栈
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+pub(crate) const MAX_DEPTH: usize = 128;
+
+/// 二进制解码的游标
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn error(&self, reason: &'static str) -> DecodeError {
+        DecodeError {
+            offset: self.pos,
+            reason,
+        }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| self.error("unexpected end of record"))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn string(&mut self, len: usize) -> Result<Value, DecodeError> {
+        let start = self.pos;
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes)
+            .map(|s| Value::String(s.to_owned()))
+            .map_err(|_| DecodeError {
+                offset: start,
+                reason: "string is not valid UTF-8",
+            })
+    }
+
+    /// 记录应恰好解码完
+    pub(crate) fn finish(&self) -> Result<(), DecodeError> {
+        if self.remaining() == 0 {
+            Ok(())
+        } else {
+            Err(self.error("trailing bytes after record"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogEntry, LogTimestamp};
+    use serde_json::json;
+
+    fn entries() -> Vec<LogEntry> {
+        vec![
+            LogEntry {
+                timestamp: LogTimestamp::from("2024-06-01T12:00:00.123456+00:00"),
+                level: "INFO".to_string(),
+                target: "app::orders".to_string(),
+                message: "order filled\nsecond line".to_string(),
+                correlation_id: Some("req-1".to_string()),
+                fields: [
+                    ("qty".to_string(), json!(3)),
+                    ("price".to_string(), json!(-12.5)),
+                    ("tags".to_string(), json!(["a", "b"])),
+                    ("meta".to_string(), json!({"ok": true, "none": null})),
+                ]
+                .into_iter()
+                .collect(),
+                seq: Some(u64::MAX),
+                name: Some("order_filled".to_string()),
+                ..Default::default()
+            },
+            LogEntry {
+                timestamp: LogTimestamp::from("2024-06-01T12:00:01+00:00"),
+                level: "WARN".to_string(),
+                target: "app".to_string(),
+                message: "日志".repeat(100),
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn round_trip(serializer: &dyn LogSerializer) -> Vec<u8> {
+        let entries = entries();
+        let mut out = Vec::new();
+        for entry in &entries {
+            encode_record(serializer, &serde_json::to_value(entry).unwrap(), &mut out);
+        }
+        let decoded: Vec<LogEntry> = decode_records(serializer, &out)
+            .unwrap()
+            .into_iter()
+            .map(|value| LogEntry::migrate(value).unwrap())
+            .collect();
+        assert_eq!(decoded, entries, "{}", serializer.name());
+        out
+    }
+
+    #[test]
+    fn test_serializers_round_trip() {
+        let jsonl = round_trip(&JsonlSerializer);
+        // 与直接序列化的 JSONL 逐行等价
+        let lines: Vec<Value> = std::str::from_utf8(&jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let direct: Vec<Value> = entries()
+            .iter()
+            .map(|entry| serde_json::from_str(&serde_json::to_string(entry).unwrap()).unwrap())
+            .collect();
+        assert_eq!(lines, direct);
+
+        #[cfg(feature = "msgpack")]
+        {
+            let msgpack = round_trip(&MessagePackSerializer);
+            assert!(msgpack.len() < jsonl.len());
+            let err =
+                decode_records(&MessagePackSerializer, &msgpack[..msgpack.len() - 1]).unwrap_err();
+            assert_eq!(err.reason, "truncated record");
+            assert_eq!(err.code(), "LT-PRS-006");
+        }
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = round_trip(&CborSerializer);
+            assert!(cbor.len() < jsonl.len());
+            assert!(decode_records(&CborSerializer, &cbor[..cbor.len() - 1]).is_err());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::io;
+use std::sync::OnceLock;
+
+use tracing_subscriber::{reload, Layer, Registry};
+
+use crate::file_lock::LockError;
+
+/// 可在安装后追加到全局 subscriber 的 Layer
+pub type ExtraLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+type ExtraLayers = Vec<ExtraLayer>;
+
+/// 本 crate 安装的全局 subscriber 中预留的 reload 槽位
+static EXTRA_LAYERS: OnceLock<reload::Handle<ExtraLayers, Registry>> = OnceLock::new();
+
+/// 安装或挂载 Layer 失败的原因
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SetupError {
+    /// 全局 subscriber 由其他代码安装，没有可挂载的槽位
+    ForeignSubscriber,
+    /// 本 crate 的 subscriber 已被丢弃，槽位失效
+    Reload(reload::Error),
+    /// 安装全局 subscriber 失败（例如与其他线程竞争）
+    Init(String),
+    /// 持久化文件被其他进程锁定，见 `file_lock`
+    Lock(LockError),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::ForeignSubscriber => write!(
+                f,
+                "a global tracing subscriber was installed outside listen-tracing; \
+                 it has no slot to attach layers to"
+            ),
+            SetupError::Reload(e) => write!(f, "failed to attach layer: {}", e),
+            SetupError::Init(e) => write!(f, "failed to install global subscriber: {}", e),
+            SetupError::Lock(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+impl SetupError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`；`Lock` 返回内层 `LockError` 的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            SetupError::ForeignSubscriber => "LT-SET-001",
+            SetupError::Reload(_) => "LT-SET-002",
+            SetupError::Init(_) => "LT-SET-003",
+            SetupError::Lock(e) => e.code(),
+        }
+    }
+}
+
+/// 新建空的 reload 槽位，需放在紧贴 `Registry` 的位置；安装成功后调用 `register_extra_layers`
+pub(crate) fn extra_layers_slot() -> (
+    reload::Layer<ExtraLayers, Registry>,
+    reload::Handle<ExtraLayers, Registry>,
+) {
+    reload::Layer::new(Vec::new())
+}
+
+pub(crate) fn register_extra_layers(handle: reload::Handle<ExtraLayers, Registry>) {
+    let _ = EXTRA_LAYERS.set(handle);
+}
+
+/// `setup_tracing` 的终端输出去向
+pub(crate) enum ConsoleOutput {
+    Journald(tracing_journald::Layer),
+    /// 标准输出；以 systemd 服务运行但 journald 不可用时带上失败原因
+    Stdout(Option<io::Error>),
+}
+
+/// 以 systemd 服务运行时尝试创建 journald layer，失败时回退到标准输出，
+/// 避免容器里设置了 `IS_SYSTEMD_SERVICE` 却没有 journald socket 时进程在启动阶段 panic
+pub(crate) fn console_output(
+    systemd: bool,
+    journald: impl FnOnce() -> io::Result<tracing_journald::Layer>,
+) -> ConsoleOutput {
+    if !systemd {
+        return ConsoleOutput::Stdout(None);
+    }
+    match journald() {
+        Ok(layer) => ConsoleOutput::Journald(layer),
+        Err(e) => ConsoleOutput::Stdout(Some(e)),
+    }
+}
+
+/// 把 Layer 挂到本 crate 已安装的全局 subscriber 上，无需重新初始化
+///
+/// 挂载的 Layer 位于全局 `EnvFilter` 之下，只会收到通过过滤的事件；
+/// 不支持带 per-layer filter（`Layer::with_filter`）的 Layer。
+pub fn attach_layer(layer: ExtraLayer) -> Result<(), SetupError> {
+    let handle = EXTRA_LAYERS.get().ok_or(SetupError::ForeignSubscriber)?;
+    handle
+        .modify(|layers| layers.push(layer))
+        .map_err(SetupError::Reload)
+}
+
+#[cfg(all(test, feature = "broadcast"))]
+mod tests {
+    use super::{console_output, ConsoleOutput};
+    use crate::{
+        setup_tracing, setup_tracing_with_layer, BroadcastLogLayer, LogCache, CONFIG_TARGET,
+    };
+    use std::io;
+
+    #[test]
+    fn test_journald_unavailable_falls_back_to_stdout() {
+        let unavailable = || {
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no journald socket",
+            ))
+        };
+        match console_output(true, unavailable) {
+            ConsoleOutput::Stdout(Some(e)) => assert_eq!(e.to_string(), "no journald socket"),
+            _ => panic!("expected stdout fallback"),
+        }
+        assert!(matches!(
+            console_output(false, || panic!("journald must not be tried")),
+            ConsoleOutput::Stdout(None)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_attaches_after_setup_tracing() {
+        skip_if_events_compiled_out!();
+        setup_tracing();
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_max_message_bytes(4096)
+            .with_display_fields(["venue", "order_id"]);
+        let config = setup_tracing_with_layer(layer).unwrap();
+        assert!(config.attached);
+        assert_eq!(config.directives, None);
+        assert_eq!(config.persist_path, None);
+        assert_eq!(config.max_message_bytes, Some(4096));
+        assert_eq!(config.display_fields, ["order_id", "venue"]);
+
+        tracing::info!("attached via reload slot");
+
+        for _ in 0..100 {
+            let cache = cache.read().await;
+            if cache
+                .iter()
+                .any(|log| log.message == "attached via reload slot")
+            {
+                // 启动横幅先于任何事件进入管线
+                let banner = cache.iter().next().unwrap();
+                assert_eq!(banner.target, CONFIG_TARGET);
+                assert_eq!(banner.level, "INFO");
+                assert_eq!(banner.fields["attached"], true);
+                assert_eq!(banner.fields["max_message_bytes"], 4096);
+                assert_eq!(banner.fields["sanitize"], "strip");
+                assert_eq!(
+                    serde_json::to_value(&config).unwrap(),
+                    serde_json::Value::Object(banner.fields.clone().into_iter().collect())
+                );
+                return;
+            }
+            drop(cache);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        panic!("broadcast layer did not receive the event");
+    }
+}
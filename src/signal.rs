@@ -0,0 +1,83 @@
+//! 收到 SIGTERM / SIGINT 时把缓冲中的日志写完（需开启 `signal` feature，仅 unix）
+
+use std::future::Future;
+
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::pipeline::LogWriterGuard;
+use crate::LogRecord;
+
+/// 等待 SIGTERM 或 SIGINT，随后调用 `guard.flush_and_close()`
+///
+/// 会消耗 guard，应在启动时 spawn 一次：
+/// `tokio::spawn(flush_on_signal(pipeline.writer_guard()))`。
+/// 注册后这两个信号不再默认终止进程，本函数在刷新完成后返回，
+/// 由调用方（或自己的关闭流程）负责退出。
+pub async fn flush_on_signal<R: LogRecord>(guard: LogWriterGuard<R>) {
+    let (mut term, mut int) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(term), Ok(int)) => (term, int),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("listen-tracing: failed to register signal handlers: {}", e);
+            return;
+        }
+    };
+    flush_on(guard, async {
+        tokio::select! {
+            _ = term.recv() => {}
+            _ = int.recv() => {}
+        }
+    })
+    .await;
+}
+
+/// 等待任意触发条件后刷新并关闭写入任务，`flush_on_signal` 即以信号为触发条件
+pub async fn flush_on<R: LogRecord, F: Future>(guard: LogWriterGuard<R>, trigger: F) {
+    trigger.await;
+    guard.flush_and_close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Pipeline;
+    use crate::{LogCache, LogEntry};
+    use tokio::sync::{broadcast, oneshot};
+
+    #[tokio::test]
+    async fn test_flush_writes_everything_before_close() {
+        let path = std::env::temp_dir().join(format!(
+            "listen_tracing_signal_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+        let (tx, _rx) = broadcast::channel(16);
+        let mut pipeline = Pipeline::new(tx, LogCache::default());
+        pipeline.set_persist_path(Some(path.clone()));
+
+        let (trigger, triggered) = oneshot::channel::<()>();
+        let flush = tokio::spawn(flush_on(pipeline.writer_guard(), triggered));
+        for i in 0..500 {
+            pipeline.ingest(LogEntry {
+                level: "INFO".to_string(),
+                message: format!("shutdown {}", i),
+                ..Default::default()
+            });
+        }
+        trigger.send(()).unwrap();
+        flush.await.unwrap();
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 500);
+        assert!(lines.ends_with("\n"));
+
+        // 关闭后的日志不再写入
+        pipeline.ingest(LogEntry::default());
+        assert_eq!(pipeline.stats().snapshot().dropped, 1);
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(crate::index::index_path(&path));
+    }
+}
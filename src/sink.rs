@@ -0,0 +1,313 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::LogEntry;
+
+/// 可插拔的日志输出端，订阅广播通道后逐条处理
+///
+/// 实现方自行处理连接失败等错误（降级并输出诊断），不得 panic，
+/// 以免影响日志管线的其余部分。
+pub trait LogSink: Send + 'static {
+    fn send(&mut self, entry: &LogEntry) -> impl Future<Output = ()> + Send;
+
+    /// 批量型 sink 在这里写出缓冲区，`spawn_sink` 会按 `flush_interval` 定期调用
+    fn flush(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// 定期 flush 的间隔，None 表示不需要定时 flush
+    fn flush_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// sink 输出失败的原因
+///
+/// sink 不向调用方返回错误，而是通过 `report` 输出带错误码的诊断，
+/// 便于按 `[LT-SNK-001]` 之类的前缀做告警分类。
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SinkError {
+    /// `sink` 执行 `operation`（send、insert 等）失败
+    Send {
+        sink: &'static str,
+        operation: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// 单条日志超过输出端允许的大小，已丢弃
+    TooLarge { sink: &'static str, bytes: usize },
+    /// 落后于广播通道，跳过了 `skipped` 条
+    Lagged { sink: &'static str, skipped: u64 },
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Send {
+                sink,
+                operation,
+                source,
+            } => write!(f, "{} {} failed: {}", sink, operation, source),
+            SinkError::TooLarge { sink, bytes } => {
+                write!(f, "{} message too large ({} bytes), dropped", sink, bytes)
+            }
+            SinkError::Lagged { sink, skipped } => {
+                write!(f, "{} lagged, {} entries skipped", sink, skipped)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SinkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SinkError::Send { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl SinkError {
+    /// 构造 `Send`，自定义 sink 可与 `report` 配合使用
+    pub fn send(
+        sink: &'static str,
+        operation: &'static str,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        SinkError::Send {
+            sink,
+            operation,
+            source: source.into(),
+        }
+    }
+
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        match self {
+            SinkError::Send { .. } => "LT-SNK-001",
+            SinkError::TooLarge { .. } => "LT-SNK-002",
+            SinkError::Lagged { .. } => "LT-SNK-003",
+        }
+    }
+
+    /// 输出到 stderr：`listen-tracing: [LT-SNK-001] gelf send failed: ...`
+    pub fn report(&self) {
+        eprintln!("listen-tracing: [{}] {}", self.code(), self);
+    }
+}
+
+/// 为 sink 订阅广播通道并在后台任务中驱动它，通道关闭后 flush 一次再退出
+pub fn spawn_sink<S: LogSink>(tx: &broadcast::Sender<LogEntry>, mut sink: S) -> JoinHandle<()> {
+    let mut rx = tx.subscribe();
+    tokio::spawn(async move {
+        let period = sink.flush_interval().unwrap_or(Duration::from_secs(3600));
+        let mut ticker = tokio::time::interval(period);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                received = rx.recv() => match received {
+                    Ok(entry) => sink.send(&entry).await,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        SinkError::Lagged {
+                            sink: "sink",
+                            skipped: n,
+                        }
+                        .report();
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = ticker.tick() => sink.flush().await,
+            }
+        }
+        sink.flush().await;
+    })
+}
+
+/// 订阅广播通道并按条数或时间攒批，供批量写入的 sink（数据库、Loki 等）复用
+pub fn batched_stream(
+    tx: &broadcast::Sender<LogEntry>,
+    max_batch: usize,
+    max_delay: Duration,
+) -> BatchReceiver {
+    BatchReceiver {
+        rx: tx.subscribe(),
+        max_batch: max_batch.max(1),
+        max_delay,
+        batch: Vec::new(),
+        deadline: None,
+        lagged: 0,
+        closed: false,
+    }
+}
+
+/// `batched_stream` 返回的批次接收端，通过 `next().await` 逐批取出
+pub struct BatchReceiver {
+    rx: broadcast::Receiver<LogEntry>,
+    max_batch: usize,
+    max_delay: Duration,
+    /// 未交出的批次保存在这里，`next()` 被取消（如在 select! 中）时不会丢失
+    batch: Vec<LogEntry>,
+    deadline: Option<tokio::time::Instant>,
+    lagged: u64,
+    closed: bool,
+}
+
+impl BatchReceiver {
+    /// 等待下一批：攒满 `max_batch` 条，或自本批第一条起经过 `max_delay` 时返回；
+    /// 落后于广播（lag）时先交出已攒的部分批次；通道关闭且无剩余时返回 None
+    pub async fn next(&mut self) -> Option<Vec<LogEntry>> {
+        if self.closed {
+            return None;
+        }
+        loop {
+            let received = match self.deadline {
+                None => self.rx.recv().await,
+                Some(deadline) => match tokio::time::timeout_at(deadline, self.rx.recv()).await {
+                    Ok(received) => received,
+                    Err(_) => return Some(self.take()),
+                },
+            };
+            match received {
+                Ok(entry) => {
+                    self.batch.push(entry);
+                    if self.batch.len() >= self.max_batch {
+                        return Some(self.take());
+                    }
+                    let max_delay = self.max_delay;
+                    self.deadline
+                        .get_or_insert_with(|| tokio::time::Instant::now() + max_delay);
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    self.lagged += n;
+                    if !self.batch.is_empty() {
+                        return Some(self.take());
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    self.closed = true;
+                    let batch = self.take();
+                    return (!batch.is_empty()).then_some(batch);
+                }
+            }
+        }
+    }
+
+    fn take(&mut self) -> Vec<LogEntry> {
+        self.deadline = None;
+        std::mem::take(&mut self.batch)
+    }
+
+    /// 因落后而被跳过的条目总数
+    pub fn lagged(&self) -> u64 {
+        self.lagged
+    }
+}
+
+/// sink 的投递计数，可在多个任务间共享
+#[derive(Debug, Default)]
+pub struct SinkStats {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// SinkStats 的某一时刻快照
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SinkStatsSnapshot {
+    pub sent: u64,
+    pub failed: u64,
+    pub dropped: u64,
+}
+
+impl SinkStats {
+    pub fn record_sent(&self, n: u64) {
+        self.sent.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn record_failed(&self, n: u64) {
+        self.failed.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self, n: u64) {
+        self.dropped.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> SinkStatsSnapshot {
+        SinkStatsSnapshot {
+            sent: self.sent.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 把 tracing 的级别映射为 syslog 数值级别（RFC 5424 severity）
+pub fn syslog_severity(level: &str) -> u8 {
+    match level.to_ascii_uppercase().as_str() {
+        "ERROR" => 3,
+        "WARN" | "WARNING" => 4,
+        "INFO" => 6,
+        _ => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message: &str) -> LogEntry {
+        LogEntry {
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_by_count_and_time() {
+        let (tx, _rx) = broadcast::channel(64);
+        let mut batches = batched_stream(&tx, 3, Duration::from_millis(50));
+
+        for i in 0..4 {
+            tx.send(entry(&i.to_string())).unwrap();
+        }
+        // 条数触发
+        let batch = batches.next().await.unwrap();
+        assert_eq!(batch.len(), 3);
+
+        // 时间触发：剩余 1 条在 max_delay 后交出
+        let started = std::time::Instant::now();
+        let batch = batches.next().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].message, "3");
+        assert!(started.elapsed() >= Duration::from_millis(40));
+
+        tx.send(entry("last")).unwrap();
+        drop(tx);
+        assert_eq!(batches.next().await.unwrap()[0].message, "last");
+        assert!(batches.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lag_flushes_partial_batch() {
+        let (tx, _rx) = broadcast::channel(2);
+        let mut batches = batched_stream(&tx, 10, Duration::from_secs(5));
+        tx.send(entry("a")).unwrap();
+        tx.send(entry("b")).unwrap();
+        // 被取消的 next() 已攒下 a、b，不会丢失
+        let first = tokio::time::timeout(Duration::from_millis(20), batches.next()).await;
+        assert!(first.is_err());
+        for i in 0..5 {
+            tx.send(entry(&i.to_string())).unwrap();
+        }
+        let batch = batches.next().await.unwrap();
+        let messages: Vec<&str> = batch.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["a", "b"]);
+        assert_eq!(batches.lagged(), 3);
+    }
+}
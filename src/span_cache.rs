@@ -0,0 +1,204 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use tracing::{Event, Instrument, Span, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+
+use crate::{LogBuffer, LogCache, LogEntry, LogRecord};
+
+/// 是否有 span 挂载过子缓存；从未挂载时 `on_event` 不必遍历 span 链
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+
+/// `capture_scope` 默认保留的条目数
+pub const DEFAULT_CAPTURE_LIMIT: usize = 200;
+
+static NEXT_CAPTURE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 挂在 span extensions 中的子缓存
+struct SpanCaches<R>(Vec<LogCache<R>>);
+
+/// 让 `span` 内（含其子 span）的事件在写入全局缓存之外，同时写入 `cache`
+///
+/// 适合按任务收集日志，例如把单个 job 的日志附在其结果上。同一 span 可挂多个缓存，
+/// 嵌套的 span 各自的缓存都会收到。span 未启用或全局 subscriber 不基于 `Registry` 时返回 false。
+pub fn attach_cache_to_span<R: LogRecord>(span: &Span, cache: LogCache<R>) -> bool {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let span = registry.span(id)?;
+        let mut extensions = span.extensions_mut();
+        match extensions.get_mut::<SpanCaches<R>>() {
+            Some(caches) => caches.0.push(cache),
+            None => extensions.insert(SpanCaches(vec![cache])),
+        }
+        ATTACHED.store(true, Ordering::Relaxed);
+        Some(())
+    })
+    .flatten()
+    .is_some()
+}
+
+/// 收集 `fut` 执行期间产生的日志，随结果一起返回，例如附在 API 错误响应中
+///
+/// 条目照常进入管线，同时写入本作用域的缓冲区，最多保留最新的 `DEFAULT_CAPTURE_LIMIT` 条，
+/// `seq` 为作用域内的序号。作用域由带 `capture_id` 字段的 span 标识，缓冲区挂在该 span 上，
+/// `fut` 完成或被丢弃时随 span 关闭一起释放。`fut` 内 `tokio::spawn` 的任务需
+/// `.in_current_span()` 才会被收集；span 被过滤关闭时返回空列表。
+pub async fn capture_scope<F: Future>(fut: F) -> (F::Output, Vec<LogEntry>) {
+    capture_scope_with_limit(DEFAULT_CAPTURE_LIMIT, fut).await
+}
+
+/// 同 `capture_scope`，指定保留的条目数
+pub async fn capture_scope_with_limit<F: Future>(
+    max_entries: usize,
+    fut: F,
+) -> (F::Output, Vec<LogEntry>) {
+    let id = NEXT_CAPTURE_ID.fetch_add(1, Ordering::Relaxed);
+    let span = tracing::info_span!("capture_scope", capture_id = id);
+    let cache = LogBuffer::new(max_entries).shared();
+    attach_cache_to_span(&span, cache.clone());
+    let output = fut.instrument(span).await;
+    let entries = cache.read().await.to_vec();
+    (output, entries)
+}
+
+/// 是否有 span 挂载过子缓存
+pub(crate) fn any_attached() -> bool {
+    ATTACHED.load(Ordering::Relaxed)
+}
+
+/// 把记录写入事件所在 span 链上挂载的全部子缓存
+///
+/// 在 `on_event` 中同步调用：缓存未被占用时直接写入；被占用（例如正在被读取）时
+/// 交给 tokio 任务写入，此时与同一缓存中其他条目的顺序不保证。
+pub(crate) fn write_span_caches<S, R>(event: &Event<'_>, ctx: &Context<'_, S>, log: &R)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    R: LogRecord,
+{
+    if !ATTACHED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(scope) = ctx.event_scope(event) else {
+        return;
+    };
+    for span in scope {
+        let extensions = span.extensions();
+        let Some(caches) = extensions.get::<SpanCaches<R>>() else {
+            continue;
+        };
+        for cache in &caches.0 {
+            match cache.try_write() {
+                Ok(mut cache) => cache.push(log.clone()),
+                Err(_) => {
+                    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                        let (cache, log) = (cache.clone(), log.clone());
+                        handle.spawn(async move { cache.write().await.push(log) });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogEntry};
+    use std::time::Duration;
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    async fn job(id: u32, cache: LogCache) {
+        assert!(attach_cache_to_span(&Span::current(), cache));
+        for step in 0..3 {
+            tracing::info!(step, "job {} step {}", id, step);
+            async {
+                tracing::debug!("job {} nested", id);
+            }
+            .instrument(tracing::debug_span!("nested"))
+            .await;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_span_scoped_caches() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = tokio::sync::broadcast::channel(64);
+        let global = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, global.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        let (job1, job2) = (LogCache::default(), LogCache::default());
+        tracing::info!("outside any job");
+        tokio::join!(
+            job(1, job1.clone()).instrument(tracing::info_span!("job", id = 1)),
+            job(2, job2.clone()).instrument(tracing::info_span!("job", id = 2)),
+        );
+
+        for (id, cache) in [(1, &job1), (2, &job2)] {
+            let messages: Vec<String> = cache
+                .read()
+                .await
+                .iter()
+                .map(|e| e.message.clone())
+                .collect();
+            let expected: Vec<String> = (0..3)
+                .flat_map(|step| {
+                    [
+                        format!("job {} step {}", id, step),
+                        format!("job {} nested", id),
+                    ]
+                })
+                .collect();
+            assert_eq!(messages, expected);
+        }
+
+        let all: Vec<LogEntry> = crate::broadcast::tests::wait_for_cache(&global, 13).await;
+        assert_eq!(all[0].message, "outside any job");
+        assert_eq!(all.len(), 13);
+    }
+
+    #[tokio::test]
+    async fn test_capture_scope() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = tokio::sync::broadcast::channel(64);
+        let global = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, global.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        let request = |id: u32| async move {
+            tracing::info!("request {} start", id);
+            tokio::task::yield_now().await;
+            tracing::warn!("request {} failed", id);
+            id
+        };
+        let ((a, logs_a), (b, logs_b)) =
+            tokio::join!(capture_scope(request(1)), capture_scope(request(2)));
+        tracing::info!("outside");
+        let (_, capped) = capture_scope_with_limit(2, async {
+            for i in 0..5 {
+                tracing::info!("line {}", i);
+            }
+        })
+        .await;
+
+        let messages =
+            |logs: &[LogEntry]| -> Vec<String> { logs.iter().map(|e| e.message.clone()).collect() };
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(messages(&logs_a), ["request 1 start", "request 1 failed"]);
+        assert_eq!(messages(&logs_b), ["request 2 start", "request 2 failed"]);
+        assert_eq!(messages(&capped), ["line 3", "line 4"]);
+
+        // 条目照常进入全局缓存
+        let all = crate::broadcast::tests::wait_for_cache(&global, 10).await;
+        assert_eq!(all.len(), 10);
+    }
+
+    #[test]
+    fn test_attach_without_subscriber() {
+        assert!(!attach_cache_to_span(&Span::none(), LogCache::default()));
+    }
+}
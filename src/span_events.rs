@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::clock::{Clock, SharedClock};
+use crate::{correlation, LogEntry, TracingVisitor, VisitorConfig, LOG_ENTRY_SCHEMA};
+
+/// 是否把 span 生命周期合成为 LogEntry
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpanEvents {
+    /// 不输出（默认）
+    #[default]
+    None,
+    /// 仅在 span 关闭时输出一条，带存活时长与 busy/idle 时间
+    Close,
+    /// 额外输出 new / enter / exit
+    Full,
+}
+
+/// 存放在 span extensions 中的计时与字段
+struct SpanTiming {
+    created: Instant,
+    entered_at: Option<Instant>,
+    busy: Duration,
+    fields: BTreeMap<String, Value>,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn span_entry<S>(
+    span: &tracing_subscriber::registry::SpanRef<'_, S>,
+    clock: &SharedClock,
+    message: &str,
+    extra: &[(&str, Value)],
+) -> LogEntry
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let metadata = span.metadata();
+    let mut fields = span
+        .extensions()
+        .get::<SpanTiming>()
+        .map(|timing| timing.fields.clone())
+        .unwrap_or_default();
+    fields.insert("kind".to_string(), json!("span"));
+    fields.insert("span".to_string(), json!(metadata.name()));
+    for (key, value) in extra {
+        fields.insert(key.to_string(), value.clone());
+    }
+    LogEntry {
+        schema: LOG_ENTRY_SCHEMA,
+        timestamp: clock.now().to_rfc3339().into(),
+        level: metadata.level().to_string(),
+        target: metadata.target().to_string(),
+        message: message.to_string(),
+        correlation_id: correlation::current_correlation_id(),
+        fields,
+        seq: None,
+        fingerprint: None,
+        mono_ns: Some(crate::clock::monotonic_ns()),
+        date_bucket: None,
+        name: None,
+    }
+}
+
+pub(crate) fn on_new_span<S>(
+    mode: SpanEvents,
+    config: &Arc<VisitorConfig>,
+    attrs: &Attributes<'_>,
+    id: &Id,
+    ctx: &Context<'_, S>,
+) -> Option<LogEntry>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if mode == SpanEvents::None {
+        return None;
+    }
+    let span = ctx.span(id)?;
+    let mut visitor = TracingVisitor::new(config.clone());
+    attrs.record(&mut visitor);
+    span.extensions_mut().insert(SpanTiming {
+        created: config.clock.instant(),
+        entered_at: None,
+        busy: Duration::ZERO,
+        fields: visitor.fields,
+    });
+    (mode == SpanEvents::Full).then(|| span_entry(&span, &config.clock, "span new", &[]))
+}
+
+pub(crate) fn on_record<S>(
+    config: &Arc<VisitorConfig>,
+    id: &Id,
+    values: &Record<'_>,
+    ctx: &Context<'_, S>,
+) where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some(span) = ctx.span(id) else { return };
+    let mut extensions = span.extensions_mut();
+    if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+        let mut visitor = TracingVisitor::new(config.clone());
+        values.record(&mut visitor);
+        timing.fields.extend(visitor.fields);
+    }
+}
+
+pub(crate) fn on_enter<S>(
+    mode: SpanEvents,
+    config: &VisitorConfig,
+    id: &Id,
+    ctx: &Context<'_, S>,
+) -> Option<LogEntry>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let span = ctx.span(id)?;
+    span.extensions_mut().get_mut::<SpanTiming>()?.entered_at = Some(config.clock.instant());
+    (mode == SpanEvents::Full).then(|| span_entry(&span, &config.clock, "span enter", &[]))
+}
+
+pub(crate) fn on_exit<S>(
+    mode: SpanEvents,
+    config: &VisitorConfig,
+    id: &Id,
+    ctx: &Context<'_, S>,
+) -> Option<LogEntry>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let span = ctx.span(id)?;
+    {
+        let mut extensions = span.extensions_mut();
+        let timing = extensions.get_mut::<SpanTiming>()?;
+        if let Some(entered_at) = timing.entered_at.take() {
+            timing.busy += config.clock.instant().saturating_duration_since(entered_at);
+        }
+    }
+    (mode == SpanEvents::Full).then(|| span_entry(&span, &config.clock, "span exit", &[]))
+}
+
+pub(crate) fn on_close<S>(config: &VisitorConfig, id: &Id, ctx: &Context<'_, S>) -> Option<LogEntry>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let span = ctx.span(id)?;
+    let (total, busy) = {
+        let extensions = span.extensions();
+        let timing = extensions.get::<SpanTiming>()?;
+        (
+            config
+                .clock
+                .instant()
+                .saturating_duration_since(timing.created),
+            timing.busy,
+        )
+    };
+    let idle = total.saturating_sub(busy);
+    Some(span_entry(
+        &span,
+        &config.clock,
+        "span closed",
+        &[
+            ("duration_ms", json!(millis(total))),
+            ("busy_ms", json!(millis(busy))),
+            ("idle_ms", json!(millis(idle))),
+        ],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BroadcastLogLayer, LogCache};
+    use tokio::sync::broadcast;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[tokio::test]
+    async fn test_span_close_and_full_events() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = broadcast::channel(64);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_span_events(SpanEvents::Close);
+        let subscriber = Registry::default().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("process_order", order_id = 7);
+            span.in_scope(|| {
+                std::thread::sleep(Duration::from_millis(5));
+                tracing::info!("inside");
+            });
+        });
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].message, "inside");
+        let closed = &logs[1];
+        assert_eq!(closed.message, "span closed");
+        assert_eq!(closed.fields["kind"], "span");
+        assert_eq!(closed.fields["span"], "process_order");
+        assert_eq!(closed.fields["order_id"], 7);
+        let busy = closed.fields["busy_ms"].as_f64().unwrap();
+        let duration = closed.fields["duration_ms"].as_f64().unwrap();
+        assert!(busy >= 5.0 && duration >= busy);
+        assert!(closed.fields["idle_ms"].as_f64().unwrap() >= 0.0);
+
+        let (tx, _rx) = broadcast::channel(64);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_span_events(SpanEvents::Full);
+        tracing::subscriber::with_default(Registry::default().with(layer), || {
+            tracing::info_span!("job").in_scope(|| {});
+        });
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 4).await;
+        let messages: Vec<_> = logs.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["span new", "span enter", "span exit", "span closed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_span_timing() {
+        skip_if_events_compiled_out!();
+        let (tx, mut rx) = broadcast::channel(64);
+        let layer = BroadcastLogLayer::new(tx, LogCache::default())
+            .without_persistence()
+            .with_span_timing(true);
+        tracing::subscriber::with_default(Registry::default().with(layer), || {
+            let span = tracing::info_span!("checkout");
+            span.in_scope(|| {});
+            drop(span);
+        });
+        let closed = rx.try_recv().unwrap();
+        assert_eq!(closed.message, "span closed");
+        assert_eq!(closed.fields["span"], "checkout");
+        assert!(closed.fields["busy_ms"].as_f64().unwrap() >= 0.0);
+        assert!(closed.fields["idle_ms"].as_f64().unwrap() >= 0.0);
+        assert!(rx.try_recv().is_err());
+
+        // 已是 Full 时保持 Full；关闭后不再输出 span 条目
+        let (tx, mut rx) = broadcast::channel(64);
+        let layer = BroadcastLogLayer::new(tx, LogCache::default())
+            .without_persistence()
+            .with_span_events(SpanEvents::Full)
+            .with_span_timing(true);
+        tracing::subscriber::with_default(Registry::default().with(layer), || {
+            tracing::info_span!("job").in_scope(|| {});
+        });
+        assert_eq!(std::iter::from_fn(|| rx.try_recv().ok()).count(), 4);
+
+        let (tx, mut rx) = broadcast::channel(64);
+        let layer = BroadcastLogLayer::new(tx, LogCache::default())
+            .without_persistence()
+            .with_span_timing(true)
+            .with_span_timing(false);
+        tracing::subscriber::with_default(Registry::default().with(layer), || {
+            tracing::info_span!("job").in_scope(|| {});
+        });
+        assert!(rx.try_recv().is_err());
+    }
+}
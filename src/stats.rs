@@ -0,0 +1,282 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::quantile::{FieldPercentiles, FieldPercentilesEntry, Percentiles};
+
+/// 整条日志管线的计数器，Layer 与后台任务共享同一个实例
+#[derive(Debug, Default)]
+pub struct LogStats {
+    error: AtomicU64,
+    warn: AtomicU64,
+    info: AtomicU64,
+    debug: AtomicU64,
+    trace: AtomicU64,
+    dropped: AtomicU64,
+    write_errors: AtomicU64,
+    bytes_written: AtomicU64,
+    skipped_persistence: AtomicU64,
+    skipped_broadcast: AtomicU64,
+    truncated_fields: AtomicU64,
+    sampled_out: AtomicU64,
+    broadcast_entries: AtomicU64,
+    cached_entries: AtomicU64,
+    persisted_entries: AtomicU64,
+    non_numeric_fields: AtomicU64,
+    verbose_windows: AtomicU64,
+    verbose_entries: AtomicU64,
+    /// 写入任务最近一次写入后缓存的条目数与估算字节数（当前值，不是累计）
+    cache_entries: AtomicU64,
+    cache_bytes: AtomicU64,
+    percentiles: FieldPercentiles,
+}
+
+/// 按级别统计的事件数
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LevelCounts {
+    pub error: u64,
+    pub warn: u64,
+    pub info: u64,
+    pub debug: u64,
+    pub trace: u64,
+}
+
+impl LevelCounts {
+    pub fn total(&self) -> u64 {
+        self.error + self.warn + self.info + self.debug + self.trace
+    }
+}
+
+/// 各去向实际收到的条目数
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DestinationCounts {
+    /// 发往广播（主通道及具名通道）的条目数
+    pub broadcast: u64,
+    /// 写入缓存的条目数
+    pub cache: u64,
+    /// 写入持久化文件的条目数
+    pub persist: u64,
+}
+
+/// LogStats 的某一时刻快照
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogStatsSnapshot {
+    pub events: LevelCounts,
+    pub dropped: u64,
+    pub write_errors: u64,
+    pub bytes_written: u64,
+    /// 持久化暂停期间未写入文件的条目数
+    pub skipped_persistence: u64,
+    /// 广播暂停期间未广播的条目数
+    pub skipped_broadcast: u64,
+    /// 因 `FieldLimits` 被丢弃的字段数
+    pub truncated_fields: u64,
+    /// 被采样丢弃的事件数
+    pub sampled_out: u64,
+    /// 按去向统计，受各去向最低级别、暂停与路由规则影响
+    pub destinations: DestinationCounts,
+    /// 跟踪分位数的字段取到非数值的次数，见 `quantile` 模块
+    pub non_numeric_fields: u64,
+    /// 错误后打开的详细捕获窗口数，见 `verbosity` 模块
+    pub verbose_windows: u64,
+    /// 详细捕获窗口内额外收录的低级别事件数
+    pub verbose_entries: u64,
+}
+
+impl LogStatsSnapshot {
+    /// 与更早的快照相减，得到这段时间内的增量
+    pub fn delta(&self, earlier: &LogStatsSnapshot) -> LogStatsSnapshot {
+        LogStatsSnapshot {
+            events: LevelCounts {
+                error: self.events.error.saturating_sub(earlier.events.error),
+                warn: self.events.warn.saturating_sub(earlier.events.warn),
+                info: self.events.info.saturating_sub(earlier.events.info),
+                debug: self.events.debug.saturating_sub(earlier.events.debug),
+                trace: self.events.trace.saturating_sub(earlier.events.trace),
+            },
+            dropped: self.dropped.saturating_sub(earlier.dropped),
+            write_errors: self.write_errors.saturating_sub(earlier.write_errors),
+            bytes_written: self.bytes_written.saturating_sub(earlier.bytes_written),
+            skipped_persistence: self
+                .skipped_persistence
+                .saturating_sub(earlier.skipped_persistence),
+            skipped_broadcast: self
+                .skipped_broadcast
+                .saturating_sub(earlier.skipped_broadcast),
+            truncated_fields: self
+                .truncated_fields
+                .saturating_sub(earlier.truncated_fields),
+            sampled_out: self.sampled_out.saturating_sub(earlier.sampled_out),
+            destinations: DestinationCounts {
+                broadcast: self
+                    .destinations
+                    .broadcast
+                    .saturating_sub(earlier.destinations.broadcast),
+                cache: self
+                    .destinations
+                    .cache
+                    .saturating_sub(earlier.destinations.cache),
+                persist: self
+                    .destinations
+                    .persist
+                    .saturating_sub(earlier.destinations.persist),
+            },
+            non_numeric_fields: self
+                .non_numeric_fields
+                .saturating_sub(earlier.non_numeric_fields),
+            verbose_windows: self.verbose_windows.saturating_sub(earlier.verbose_windows),
+            verbose_entries: self.verbose_entries.saturating_sub(earlier.verbose_entries),
+        }
+    }
+
+    /// 是否没有任何活动
+    pub fn is_idle(&self) -> bool {
+        self.events.total() == 0
+            && self.dropped == 0
+            && self.write_errors == 0
+            && self.bytes_written == 0
+            && self.skipped_persistence == 0
+            && self.skipped_broadcast == 0
+    }
+}
+
+impl LogStats {
+    pub fn record_event(&self, level: &str) {
+        let counter = if level.eq_ignore_ascii_case("ERROR") {
+            &self.error
+        } else if level.eq_ignore_ascii_case("WARN") {
+            &self.warn
+        } else if level.eq_ignore_ascii_case("INFO") {
+            &self.info
+        } else if level.eq_ignore_ascii_case("DEBUG") {
+            &self.debug
+        } else {
+            &self.trace
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self, n: u64) {
+        self.dropped.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn record_write(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_write_error(&self) {
+        self.write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_skipped_persistence(&self) {
+        self.skipped_persistence.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_skipped_broadcast(&self) {
+        self.skipped_broadcast.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_truncated_fields(&self, n: u64) {
+        self.truncated_fields.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn record_sampled_out(&self) {
+        self.sampled_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_broadcast(&self) {
+        self.broadcast_entries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cached(&self, n: u64) {
+        self.cached_entries.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn record_persisted(&self) {
+        self.persisted_entries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_verbose_window(&self) {
+        self.verbose_windows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_verbose_entry(&self) {
+        self.verbose_entries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cache_size(&self, entries: u64, bytes: u64) {
+        self.cache_entries.store(entries, Ordering::Relaxed);
+        self.cache_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// 最近一次写入后缓存的（条目数, 估算字节数）
+    pub fn cache_size(&self) -> (u64, u64) {
+        (
+            self.cache_entries.load(Ordering::Relaxed),
+            self.cache_bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    /// 设置跟踪分位数的数值字段和 target 前缀（`""` 总是包含），清空已有数据
+    pub fn track_field_percentiles<F, P>(&self, fields: F, target_prefixes: P)
+    where
+        F: IntoIterator,
+        F::Item: Into<String>,
+        P: IntoIterator,
+        P::Item: Into<String>,
+    {
+        self.percentiles.track(fields, target_prefixes);
+    }
+
+    /// 计入一条日志中被跟踪字段的值，`field` 按名称取字段
+    pub fn record_field_values<'a>(&self, target: &str, field: impl Fn(&str) -> Option<&'a Value>) {
+        let non_numeric = self.percentiles.observe(target, field);
+        if non_numeric > 0 {
+            self.non_numeric_fields
+                .fetch_add(non_numeric, Ordering::Relaxed);
+        }
+    }
+
+    /// 字段 `field` 在 target 前缀 `target` 下（`""` 为所有 target）自启动以来的近似分位数
+    pub fn field_percentiles(&self, field: &str, target: &str) -> Option<Percentiles> {
+        self.percentiles.get(field, target)
+    }
+
+    /// 跟踪分位数的字段名
+    pub fn percentile_fields(&self) -> Vec<String> {
+        self.percentiles.fields()
+    }
+
+    /// 所有有数据的（字段，target 前缀）分组
+    pub fn all_field_percentiles(&self) -> Vec<FieldPercentilesEntry> {
+        self.percentiles.all()
+    }
+
+    pub fn snapshot(&self) -> LogStatsSnapshot {
+        LogStatsSnapshot {
+            events: LevelCounts {
+                error: self.error.load(Ordering::Relaxed),
+                warn: self.warn.load(Ordering::Relaxed),
+                info: self.info.load(Ordering::Relaxed),
+                debug: self.debug.load(Ordering::Relaxed),
+                trace: self.trace.load(Ordering::Relaxed),
+            },
+            dropped: self.dropped.load(Ordering::Relaxed),
+            write_errors: self.write_errors.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            skipped_persistence: self.skipped_persistence.load(Ordering::Relaxed),
+            skipped_broadcast: self.skipped_broadcast.load(Ordering::Relaxed),
+            truncated_fields: self.truncated_fields.load(Ordering::Relaxed),
+            sampled_out: self.sampled_out.load(Ordering::Relaxed),
+            destinations: DestinationCounts {
+                broadcast: self.broadcast_entries.load(Ordering::Relaxed),
+                cache: self.cached_entries.load(Ordering::Relaxed),
+                persist: self.persisted_entries.load(Ordering::Relaxed),
+            },
+            non_numeric_fields: self.non_numeric_fields.load(Ordering::Relaxed),
+            verbose_windows: self.verbose_windows.load(Ordering::Relaxed),
+            verbose_entries: self.verbose_entries.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,882 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::Level;
+
+use crate::query::{QueryExpr, QueryParseError};
+use crate::{LogCache, LogEntry, LogQuery};
+
+/// 未指定 page_size 时的默认分页大小
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// 最近日志存储的抽象，查询函数通过它同时支持本地缓存和远端后端
+pub trait LogStore: Send + Sync {
+    /// 追加一条日志，超出容量时由实现负责淘汰最旧的条目
+    fn append(&self, entry: LogEntry) -> impl Future<Output = ()> + Send;
+
+    /// 按时间顺序（旧 -> 新）返回当前保留的全部日志
+    fn snapshot(&self) -> impl Future<Output = Vec<LogEntry>> + Send;
+
+    /// 按时间顺序返回指定级别（忽略大小写）的日志，有索引的实现应覆盖默认的全量过滤
+    fn snapshot_level(&self, level: &str) -> impl Future<Output = Vec<LogEntry>> + Send {
+        async move {
+            let mut entries = self.snapshot().await;
+            entries.retain(|entry| entry.level.eq_ignore_ascii_case(level));
+            entries
+        }
+    }
+
+    /// 钉住的日志（见 `LogBuffer::pin`），按时间顺序；不支持钉住的实现返回空
+    fn pinned(&self) -> impl Future<Output = Vec<LogEntry>> + Send {
+        async { Vec::new() }
+    }
+}
+
+impl LogStore for LogCache {
+    async fn append(&self, entry: LogEntry) {
+        self.write().await.push(entry);
+    }
+
+    async fn snapshot(&self) -> Vec<LogEntry> {
+        self.read().await.to_vec()
+    }
+
+    async fn snapshot_level(&self, level: &str) -> Vec<LogEntry> {
+        self.read().await.iter_level(level).cloned().collect()
+    }
+
+    async fn pinned(&self) -> Vec<LogEntry> {
+        self.read().await.pinned().cloned().collect()
+    }
+}
+
+/// 分页查询结果，items 按时间倒序（最新在前）
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LogPage {
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+    pub items: Vec<LogHit>,
+}
+
+/// 单条查询结果，序列化时与 LogEntry 字段平铺，仅在请求时附带 `highlights`
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LogHit {
+    #[serde(flatten)]
+    pub entry: LogEntry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<Highlights>,
+    /// 条目已被钉住，见 `LogBuffer::pin`；为 false 时不序列化
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+}
+
+/// keyword 命中位置：`(start, end)` 字节区间，均落在 UTF-8 字符边界上
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Highlights {
+    pub message: Vec<(usize, usize)>,
+    pub target: Vec<(usize, usize)>,
+}
+
+/// 查找 keyword 在 text 中所有不重叠的出现位置（忽略大小写，与 `matches_query` 一致）
+///
+/// 在小写化后的文本上匹配，再映射回原文本的字符边界，
+/// 因此即使大小写转换改变了字节长度，返回的区间也可以直接切片原文本。
+pub fn keyword_ranges(text: &str, keyword: &str) -> Vec<(usize, usize)> {
+    let keyword = keyword.to_lowercase();
+    if keyword.is_empty() {
+        return Vec::new();
+    }
+    // lowered 中每个字节对应的原字符区间
+    let mut lowered = String::with_capacity(text.len());
+    let mut origin = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        let end = start + c.len_utf8();
+        for lower in c.to_lowercase() {
+            lowered.push(lower);
+            origin.extend(std::iter::repeat_n((start, end), lower.len_utf8()));
+        }
+    }
+    lowered
+        .match_indices(&keyword)
+        .map(|(at, matched)| (origin[at].0, origin[at + matched.len() - 1].1))
+        .collect()
+}
+
+/// 判断单条日志是否满足查询条件（level 精确匹配，keyword 匹配 message/target，均忽略大小写）
+pub fn matches_query(entry: &LogEntry, query: &LogQuery) -> bool {
+    if let Some(level) = &query.level {
+        if !entry.level.eq_ignore_ascii_case(level) {
+            return false;
+        }
+    }
+    if let Some(keyword) = &query.keyword {
+        let keyword = keyword.to_lowercase();
+        if !entry.message.to_lowercase().contains(&keyword)
+            && !entry.target.to_lowercase().contains(&keyword)
+        {
+            return false;
+        }
+    }
+    matches_field(entry, query)
+}
+
+/// `LogQuery::field` 的精确匹配，未设置时总是匹配
+fn matches_field(entry: &LogEntry, query: &LogQuery) -> bool {
+    let Some((name, expected)) = &query.field else {
+        return true;
+    };
+    match entry.fields.get(name) {
+        Some(Value::String(actual)) => actual == expected,
+        Some(actual) => serde_json::from_str::<Value>(expected).is_ok_and(|v| v == *actual),
+        None => false,
+    }
+}
+
+/// 解析后的查询：过滤条件与分页窗口
+struct PreparedQuery<'a> {
+    query: &'a LogQuery,
+    expr: Option<QueryExpr>,
+    page: usize,
+    page_size: usize,
+}
+
+impl<'a> PreparedQuery<'a> {
+    fn parse(query: &'a LogQuery) -> Result<Self, QueryParseError> {
+        let expr = match query.q.as_deref().map(str::trim) {
+            Some(q) if !q.is_empty() => Some(QueryExpr::parse(q)?),
+            _ => None,
+        };
+        Ok(Self {
+            query,
+            expr,
+            page: page_of(query),
+            page_size: page_size_of(query),
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        match &self.expr {
+            Some(expr) => expr.matches(entry) && matches_field(entry, self.query),
+            None => matches_query(entry, self.query),
+        }
+    }
+
+    /// 第 `index` 个（从 0 开始）匹配项是否落在请求的页内
+    fn in_page(&self, index: usize) -> bool {
+        let skip = (self.page - 1) * self.page_size;
+        (skip..skip + self.page_size).contains(&index)
+    }
+
+    fn hit(&self, entry: LogEntry, pinned: bool) -> LogHit {
+        let keyword = self
+            .query
+            .keyword
+            .as_deref()
+            .filter(|_| self.query.include_highlights && self.expr.is_none());
+        LogHit {
+            highlights: keyword.map(|keyword| Highlights {
+                message: keyword_ranges(&entry.message, keyword),
+                target: keyword_ranges(&entry.target, keyword),
+            }),
+            entry,
+            pinned,
+        }
+    }
+
+    fn empty_page(&self) -> LogPage {
+        LogPage {
+            page: self.page,
+            page_size: self.page_size,
+            ..Default::default()
+        }
+    }
+}
+
+fn page_of(query: &LogQuery) -> usize {
+    query.page.unwrap_or(1).max(1)
+}
+
+fn page_size_of(query: &LogQuery) -> usize {
+    query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1)
+}
+
+/// `q` 无法解析时的结果
+fn invalid_page(query: &LogQuery) -> LogPage {
+    LogPage {
+        page: page_of(query),
+        page_size: page_size_of(query),
+        ..Default::default()
+    }
+}
+
+/// 对任意 LogStore 执行过滤 + 分页查询，page 从 1 开始
+///
+/// 钉住的条目带 `pinned: true`，已被淘汰（或清空）的也照常参与过滤，排在其他条目之后（更早）。
+/// `q` 无法解析时返回空页；需要把错误位置反馈给调用方时使用 `try_query_logs`。
+pub async fn query_logs<S: LogStore + ?Sized>(store: &S, query: &LogQuery) -> LogPage {
+    match try_query_logs(store, query).await {
+        Ok(page) => page,
+        Err(_) => invalid_page(query),
+    }
+}
+
+/// 同 `query_logs`，`q` 解析失败时返回带位置和期望内容的错误
+pub async fn try_query_logs<S: LogStore + ?Sized>(
+    store: &S,
+    query: &LogQuery,
+) -> Result<LogPage, QueryParseError> {
+    let prepared = PreparedQuery::parse(query)?;
+
+    // 有 level 条件时走级别索引，只取出该级别的条目
+    let candidates = match (&prepared.expr, &query.level) {
+        (None, Some(level)) => store.snapshot_level(level).await,
+        _ => store.snapshot().await,
+    };
+    let (pinned_seqs, evicted) = pinned_outside(store.pinned().await, &candidates);
+    let matched: Vec<LogEntry> = evicted
+        .into_iter()
+        .chain(candidates)
+        .rev()
+        .filter(|entry| prepared.matches(entry))
+        .collect();
+
+    let total = matched.len();
+    let items = matched
+        .into_iter()
+        .skip((prepared.page - 1) * prepared.page_size)
+        .take(prepared.page_size)
+        .map(|entry| {
+            let pinned = entry.seq.is_some_and(|seq| pinned_seqs.contains(&seq));
+            prepared.hit(entry, pinned)
+        })
+        .collect();
+
+    Ok(LogPage {
+        total,
+        items,
+        ..prepared.empty_page()
+    })
+}
+
+/// 钉住条目的序号，以及其中不在 `candidates`（按序号递增）里的条目
+fn pinned_outside(
+    pinned: Vec<LogEntry>,
+    candidates: &[LogEntry],
+) -> (BTreeSet<u64>, Vec<LogEntry>) {
+    let seqs = pinned.iter().filter_map(|entry| entry.seq).collect();
+    let outside = pinned
+        .into_iter()
+        .filter(|entry| {
+            candidates
+                .binary_search_by_key(&entry.seq, |candidate| candidate.seq)
+                .is_err()
+        })
+        .collect();
+    (seqs, outside)
+}
+
+/// 在一次读锁、一次遍历内执行多个查询，结果与逐个调用 `query_logs` 相同，顺序与 `queries` 一致
+///
+/// 适合仪表盘每次刷新同时发出的多个查询；`q` 无法解析的查询得到空页。
+pub async fn query_logs_multi(cache: &LogCache, queries: &[LogQuery]) -> Vec<LogPage> {
+    let prepared: Vec<Option<PreparedQuery>> = queries
+        .iter()
+        .map(|query| PreparedQuery::parse(query).ok())
+        .collect();
+    let mut pages: Vec<LogPage> = prepared
+        .iter()
+        .zip(queries)
+        .map(|(prepared, query)| match prepared {
+            Some(prepared) => prepared.empty_page(),
+            None => invalid_page(query),
+        })
+        .collect();
+
+    let buffer = cache.read().await;
+    for entry in buffer.iter().rev().chain(buffer.pinned_evicted().rev()) {
+        let pinned = entry.seq.is_some_and(|seq| buffer.is_pinned(seq));
+        for (prepared, page) in prepared.iter().zip(&mut pages) {
+            let Some(prepared) = prepared.as_ref().filter(|p| p.matches(entry)) else {
+                continue;
+            };
+            if prepared.in_page(page.total) {
+                page.items.push(prepared.hit(entry.clone(), pinned));
+            }
+            page.total += 1;
+        }
+    }
+    pages
+}
+
+/// 清空内存缓存，供看板的"清屏"操作使用；持久化文件与广播不受影响
+///
+/// 之后写入的条目序号接着清空前的继续，`logs_since` 的客户端无需重置。
+pub async fn clear_cache(cache: &LogCache) {
+    cache.write().await.clear();
+}
+
+/// 增量轮询：按顺序返回 `seq > after_seq` 的条目，最多 `limit` 条
+///
+/// 客户端把收到的最大 `seq` 作为下一次的 `after_seq`，首次轮询传 0。
+/// `after_seq` 早于缓存中最旧的条目时（中间的已被淘汰）从最旧的条目开始返回。
+pub async fn logs_since(cache: &LogCache, after_seq: u64, limit: usize) -> Vec<LogEntry> {
+    cache
+        .read()
+        .await
+        .iter_since(after_seq)
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+/// `context_window` 的结果，entries 按时间顺序排列
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ContextWindow {
+    /// 请求的条目已被淘汰，entries 改为围绕缓存中最旧的条目
+    pub anchor_evicted: bool,
+    pub entries: Vec<LogEntry>,
+}
+
+/// 取序号为 `seq` 的条目前后各 `before` / `after` 条（不限级别），包含该条目本身
+///
+/// `same_target_only` 时只计入与锚点 target 相同的条目。
+/// `seq` 已被淘汰时以最旧的保留条目为锚点并设置 `anchor_evicted`；尚未分配的序号返回空窗口。
+pub async fn context_window(
+    cache: &LogCache,
+    seq: u64,
+    before: usize,
+    after: usize,
+    same_target_only: bool,
+) -> ContextWindow {
+    let buffer = cache.read().await;
+    let (anchor, anchor_evicted) = match buffer.position(seq) {
+        Ok(index) => (index, false),
+        Err(0) if !buffer.is_empty() => (0, true),
+        Err(_) => return ContextWindow::default(),
+    };
+    let target = buffer.iter().nth(anchor).map(|entry| entry.target.clone());
+    let keep = |entry: &&LogEntry| !same_target_only || Some(&entry.target) == target.as_ref();
+
+    let mut entries: Vec<LogEntry> = buffer
+        .iter()
+        .rev()
+        .skip(buffer.len() - anchor)
+        .filter(keep)
+        .take(before)
+        .cloned()
+        .collect();
+    entries.reverse();
+    entries.extend(
+        buffer
+            .iter()
+            .skip(anchor)
+            .filter(keep)
+            .take(after + 1)
+            .cloned(),
+    );
+    ContextWindow {
+        anchor_evicted,
+        entries,
+    }
+}
+
+/// 当前存储中出现过的 target，已排序去重，用于前端筛选下拉框
+pub async fn distinct_targets<S: LogStore + ?Sized>(store: &S) -> Vec<String> {
+    let targets: BTreeSet<String> = store
+        .snapshot()
+        .await
+        .into_iter()
+        .map(|entry| entry.target)
+        .collect();
+    targets.into_iter().collect()
+}
+
+/// 当前存储中出现过的 level，去重后按严重程度排序（ERROR 在前），无法识别的排在最后
+pub async fn distinct_levels<S: LogStore + ?Sized>(store: &S) -> Vec<String> {
+    let levels: BTreeSet<String> = store
+        .snapshot()
+        .await
+        .into_iter()
+        .map(|entry| entry.level)
+        .collect();
+    let mut levels: Vec<String> = levels.into_iter().collect();
+    levels.sort_by_key(|level| {
+        let parsed = level.parse::<Level>().ok();
+        (parsed.is_none(), parsed)
+    });
+    levels
+}
+
+/// 订阅广播通道，把每条日志写入给定的 LogStore（例如多副本共享的远端缓存）
+pub fn spawn_store_writer<S: LogStore + 'static>(
+    tx: &broadcast::Sender<LogEntry>,
+    store: Arc<S>,
+) -> JoinHandle<()> {
+    let mut rx = tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(entry) => store.append(entry).await,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    eprintln!("listen-tracing: store writer lagged, {} entries skipped", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::tests::xorshift;
+    use crate::{LogBuffer, PinError};
+
+    #[tokio::test]
+    async fn test_level_index_matches_brute_force() {
+        let mut next = xorshift(0x9e37_79b9_7f4a_7c15);
+        let levels = ["ERROR", "WARN", "INFO", "DEBUG", "info", "Error"];
+        let cache = LogBuffer::new(200).with_max_bytes(40_000).shared();
+        let mut all = Vec::new();
+        for i in 0..2000 {
+            let entry = LogEntry {
+                level: levels[next(levels.len() as u64) as usize].to_string(),
+                message: format!("event {} {}", i, "x".repeat(next(300) as usize)),
+                target: format!("module_{}", next(4)),
+                ..Default::default()
+            };
+            all.push(entry.clone());
+            cache.append(entry).await;
+
+            if i % 97 == 0 {
+                let retained = cache.snapshot().await;
+                for level in ["error", "INFO", "warn", "DEBUG", "TRACE"] {
+                    let query = LogQuery {
+                        level: Some(level.to_string()),
+                        keyword: (next(2) == 0).then(|| "module_1".to_string()),
+                        page: Some(1 + next(3) as usize),
+                        page_size: Some(1 + next(40) as usize),
+                        ..Default::default()
+                    };
+                    let expected: Vec<&LogEntry> = retained
+                        .iter()
+                        .rev()
+                        .filter(|entry| matches_query(entry, &query))
+                        .collect();
+                    let page = query_logs(&cache, &query).await;
+                    assert_eq!(page.total, expected.len());
+                    let skip = (page.page - 1) * page.page_size;
+                    let items: Vec<&LogEntry> = page.items.iter().map(|hit| &hit.entry).collect();
+                    let expected: Vec<&LogEntry> = expected
+                        .into_iter()
+                        .skip(skip)
+                        .take(page.page_size)
+                        .collect();
+                    assert_eq!(items, expected);
+                }
+            }
+        }
+        // 保留的是最新的条目
+        let retained = cache.snapshot().await;
+        let last = retained.last().unwrap();
+        assert_eq!(last.message, all.last().unwrap().message);
+        assert_eq!(last.seq, Some(all.len() as u64));
+    }
+
+    #[tokio::test]
+    async fn test_query_highlights() {
+        let cache = LogCache::default();
+        cache
+            .append(LogEntry {
+                target: "order::book".to_string(),
+                message: "ORDER filled, İorder pending".to_string(),
+                ..Default::default()
+            })
+            .await;
+
+        let mut query = LogQuery {
+            keyword: Some("order".to_string()),
+            ..Default::default()
+        };
+        let page = query_logs(&cache, &query).await;
+        assert_eq!(page.items[0].highlights, None);
+        assert!(!serde_json::to_string(&page).unwrap().contains("highlights"));
+
+        query.include_highlights = true;
+        let page = query_logs(&cache, &query).await;
+        let hit = &page.items[0];
+        let highlights = hit.highlights.as_ref().unwrap();
+        let matched: Vec<&str> = highlights
+            .message
+            .iter()
+            .map(|&(start, end)| &hit.entry.message[start..end])
+            .collect();
+        assert_eq!(matched, ["ORDER", "order"]);
+        assert_eq!(highlights.target, [(0, 5)]);
+
+        // 小写化后变长的字符（İ -> i̇）不影响原文偏移
+        assert_eq!(keyword_ranges("İx", "i̇x"), [(0, 3)]);
+    }
+
+    #[tokio::test]
+    async fn test_q_takes_precedence() {
+        let cache = LogCache::default();
+        for (level, message) in [
+            ("ERROR", "order timeout"),
+            ("WARN", "connection refused"),
+            ("INFO", "order filled"),
+        ] {
+            cache
+                .append(LogEntry {
+                    level: level.to_string(),
+                    message: message.to_string(),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        let mut query = LogQuery {
+            level: Some("INFO".to_string()),
+            keyword: Some("order".to_string()),
+            q: Some(r#"level>=warn AND (msg~"time*" OR msg:refused)"#.to_string()),
+            ..Default::default()
+        };
+        let page = query_logs(&cache, &query).await;
+        let messages: Vec<&str> = page
+            .items
+            .iter()
+            .map(|hit| hit.entry.message.as_str())
+            .collect();
+        assert_eq!(messages, ["connection refused", "order timeout"]);
+
+        query.q = Some("level>=".to_string());
+        let error = try_query_logs(&cache, &query).await.unwrap_err();
+        assert_eq!(error.position, 7);
+        assert_eq!(query_logs(&cache, &query).await.total, 0);
+
+        // 空白的 q 视为未设置
+        query.q = Some("  ".to_string());
+        assert_eq!(query_logs(&cache, &query).await.total, 1);
+    }
+
+    #[tokio::test]
+    async fn test_logs_since() {
+        let cache = LogBuffer::new(8).shared();
+        for i in 0..6 {
+            cache
+                .append(LogEntry {
+                    message: format!("event {}", i),
+                    ..Default::default()
+                })
+                .await;
+        }
+        let first = logs_since(&cache, 0, 100).await;
+        assert_eq!(first.len(), 6);
+        let midpoint = first[2].seq.unwrap();
+
+        let newer = logs_since(&cache, midpoint, 100).await;
+        let messages: Vec<&str> = newer.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["event 3", "event 4", "event 5"]);
+        assert!(newer.iter().all(|e| e.seq > Some(midpoint)));
+        assert_eq!(logs_since(&cache, midpoint, 2).await.len(), 2);
+
+        let latest = newer.last().unwrap().seq.unwrap();
+        assert!(logs_since(&cache, latest, 100).await.is_empty());
+
+        // 落后太多时从最旧的保留条目开始
+        for i in 6..12 {
+            cache
+                .append(LogEntry {
+                    message: format!("event {}", i),
+                    ..Default::default()
+                })
+                .await;
+        }
+        let caught_up = logs_since(&cache, midpoint, 100).await;
+        assert_eq!(caught_up.len(), 8);
+        assert_eq!(caught_up[0].message, "event 4");
+    }
+
+    #[tokio::test]
+    async fn test_clear_cache_keeps_file() {
+        skip_if_events_compiled_out!();
+        use crate::BroadcastLogLayer;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let path =
+            std::env::temp_dir().join(format!("listen-tracing-clear-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (tx, mut rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .with_persist_path(&path)
+            .with_index_interval(0);
+        let pipeline = layer.pipeline().clone();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::info!("first");
+        tracing::warn!("second");
+        let before = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        assert_eq!(before.len(), 2);
+
+        clear_cache(&cache).await;
+        assert!(cache.read().await.is_empty());
+        assert_eq!(cache.read().await.bytes(), 0);
+        assert!(cache.read().await.iter_level("WARN").next().is_none());
+
+        tracing::info!("third");
+        let after = crate::broadcast::tests::wait_for_cache(&cache, 1).await;
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].seq, Some(3));
+        assert_eq!(logs_since(&cache, before[1].seq.unwrap(), 10).await, after);
+
+        pipeline.writer_guard().flush_and_close().await;
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+        let received: Vec<String> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|entry| entry.message)
+            .collect();
+        assert_eq!(received, ["first", "second", "third"]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_context_window() {
+        let cache = LogBuffer::new(10).shared();
+        for i in 0..12 {
+            cache
+                .append(LogEntry {
+                    level: if i == 7 { "ERROR" } else { "DEBUG" }.to_string(),
+                    target: if i % 2 == 0 { "app::db" } else { "app::http" }.to_string(),
+                    message: format!("event {}", i),
+                    ..Default::default()
+                })
+                .await;
+        }
+        let messages = |window: &ContextWindow| -> Vec<String> {
+            window.entries.iter().map(|e| e.message.clone()).collect()
+        };
+
+        // event 7 的序号为 8
+        let window = context_window(&cache, 8, 2, 3, false).await;
+        assert!(!window.anchor_evicted);
+        assert_eq!(
+            messages(&window),
+            ["event 5", "event 6", "event 7", "event 8", "event 9", "event 10"]
+        );
+
+        let window = context_window(&cache, 8, 2, 3, true).await;
+        assert_eq!(
+            messages(&window),
+            ["event 3", "event 5", "event 7", "event 9", "event 11"]
+        );
+
+        // 序号 1、2 已被淘汰，以最旧的保留条目为锚点
+        let window = context_window(&cache, 1, 5, 2, false).await;
+        assert!(window.anchor_evicted);
+        assert_eq!(messages(&window), ["event 2", "event 3", "event 4"]);
+
+        let window = context_window(&cache, 99, 5, 5, false).await;
+        assert_eq!(window, ContextWindow::default());
+    }
+
+    #[tokio::test]
+    async fn test_distinct_targets_and_levels() {
+        let cache = LogCache::default();
+        for (level, target) in [
+            ("INFO", "bot::executor"),
+            ("DEBUG", "api"),
+            ("ERROR", "bot::executor"),
+            ("INFO", "api"),
+            ("WARN", "db"),
+        ] {
+            cache
+                .append(LogEntry {
+                    level: level.to_string(),
+                    target: target.to_string(),
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        assert_eq!(
+            distinct_targets(&cache).await,
+            ["api", "bot::executor", "db"]
+        );
+        assert_eq!(
+            distinct_levels(&cache).await,
+            ["ERROR", "WARN", "INFO", "DEBUG"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_logs_multi_matches_single_queries() {
+        let mut next = xorshift(0x2545_f491_4f6c_dd1d);
+        let levels = ["ERROR", "WARN", "INFO", "DEBUG"];
+        let words = ["order", "payment", "timeout", "Order", "retry"];
+        let q = [
+            "",
+            "level:error",
+            "msg:order AND NOT msg:retry",
+            "target:svc_1",
+            "(oops",
+        ];
+        let cache = LogBuffer::new(300).shared();
+        for round in 0..20 {
+            for i in 0..50 {
+                let entry = LogEntry {
+                    level: levels[next(4) as usize].to_string(),
+                    target: format!("svc_{}", next(3)),
+                    message: format!("{} {} #{}", words[next(5) as usize], round, i),
+                    ..Default::default()
+                };
+                cache.append(entry).await;
+            }
+            let queries: Vec<LogQuery> = (0..1 + next(6))
+                .map(|_| LogQuery {
+                    level: (next(2) == 0).then(|| levels[next(4) as usize].to_lowercase()),
+                    keyword: (next(2) == 0).then(|| words[next(5) as usize].to_string()),
+                    page: (next(3) > 0).then(|| 1 + next(4) as usize),
+                    page_size: (next(3) > 0).then(|| next(30) as usize),
+                    include_highlights: next(2) == 0,
+                    q: (next(3) == 0).then(|| q[next(5) as usize].to_string()),
+                    ..Default::default()
+                })
+                .collect();
+            let batch = query_logs_multi(&cache, &queries).await;
+            assert_eq!(batch.len(), queries.len());
+            for (query, page) in queries.iter().zip(batch) {
+                assert_eq!(page, query_logs(&cache, query).await, "{:?}", query);
+            }
+        }
+        assert!(query_logs_multi(&cache, &[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_entries_survive_eviction_and_clear() {
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogBuffer::new(3).with_max_pinned(2).shared();
+        let mut pipeline = crate::pipeline::Pipeline::new(tx, cache.clone());
+        pipeline.set_persist_path(None);
+        let entry = |message: String| LogEntry {
+            level: "INFO".to_string(),
+            message,
+            ..Default::default()
+        };
+        for i in 1..=3 {
+            cache.append(entry(format!("m{}", i))).await;
+        }
+        assert_eq!(pipeline.pin_entry(1).await, Ok(true));
+        assert_eq!(pipeline.pin_entry(1).await, Ok(false));
+        assert_eq!(pipeline.pin_entry(3).await, Ok(true));
+        let err = pipeline.pin_entry(2).await.unwrap_err();
+        assert_eq!(err.code(), "LT-CFG-005");
+
+        // m1 被淘汰后仍出现在查询结果末尾（最旧），m3 仍在主队列中
+        for i in 4..=5 {
+            cache.append(entry(format!("m{}", i))).await;
+        }
+        assert_eq!(
+            pipeline.pin_entry(1).await,
+            Ok(false),
+            "already pinned copies are kept"
+        );
+        assert_eq!(
+            pipeline.pin_entry(2).await.unwrap_err(),
+            PinError::NotFound { seq: 2 }
+        );
+        let page = query_logs(&cache, &LogQuery::default()).await;
+        let items: Vec<_> = page
+            .items
+            .iter()
+            .map(|hit| (hit.entry.message.as_str(), hit.pinned))
+            .collect();
+        assert_eq!(
+            items,
+            [("m5", false), ("m4", false), ("m3", true), ("m1", true)]
+        );
+        let json = serde_json::to_value(&page.items[0]).unwrap();
+        assert!(json.get("pinned").is_none());
+        assert_eq!(
+            serde_json::to_value(&page.items[3]).unwrap()["pinned"],
+            true
+        );
+
+        // 清空后只剩钉住的条目；过滤条件照常生效
+        clear_cache(&cache).await;
+        let page = query_logs(&cache, &LogQuery::default()).await;
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].entry.message, "m3");
+        let warn = LogQuery {
+            level: Some("WARN".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query_logs(&cache, &warn).await.total, 0);
+        let multi = query_logs_multi(&cache, &[LogQuery::default()]).await;
+        assert_eq!(multi[0], page);
+
+        assert_eq!(pipeline.unpin_entry(3).await.unwrap().message, "m3");
+        assert!(pipeline.unpin_entry(3).await.is_none());
+        let pinned = pipeline.list_pinned().await;
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(pinned[0].message, "m1");
+        assert_eq!(cache.read().await.stats().pinned, 1);
+    }
+
+    #[tokio::test]
+    async fn test_query_by_field() {
+        skip_if_events_compiled_out!();
+        use crate::BroadcastLogLayer;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (tx, _rx) = broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        tracing::info!(user_id = "42", "login");
+        tracing::info!(user_id = "7", "login");
+        tracing::warn!(user_id = 42, "order rejected");
+        tracing::info!(user = "42", "no user_id");
+        tracing::info!(user_id = "42", "logout");
+        crate::broadcast::tests::wait_for_cache(&cache, 5).await;
+
+        let mut query = LogQuery {
+            field: Some(("user_id".to_string(), "42".to_string())),
+            ..Default::default()
+        };
+        let messages = |page: LogPage| -> Vec<String> {
+            page.items
+                .into_iter()
+                .map(|hit| hit.entry.message)
+                .collect()
+        };
+        assert_eq!(
+            messages(query_logs(&cache, &query).await),
+            ["logout", "order rejected", "login"]
+        );
+
+        // 与 level、keyword 以及 q 同时生效
+        query.level = Some("INFO".to_string());
+        assert_eq!(
+            messages(query_logs(&cache, &query).await),
+            ["logout", "login"]
+        );
+        query.keyword = Some("out".to_string());
+        assert_eq!(messages(query_logs(&cache, &query).await), ["logout"]);
+        query.q = Some("level>=warn".to_string());
+        assert_eq!(
+            messages(query_logs(&cache, &query).await),
+            ["order rejected"]
+        );
+
+        query.field = Some(("user_id".to_string(), "4".to_string()));
+        assert_eq!(query_logs(&cache, &query).await.total, 0);
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{DefaultFields, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// `setup_tracing_styled` 的终端输出样式
+#[derive(Clone, Debug)]
+pub struct LogStyle {
+    /// 行首时间戳（compact 时只显示时分秒）
+    pub timestamps: bool,
+    /// 显示事件的 target
+    pub target: bool,
+    /// 在级别前加单字符图标：✗ ⚠ ℹ • ·
+    pub icons: bool,
+    /// 紧凑布局：省略 span 上下文
+    pub compact: bool,
+    /// 按级别着色：ERROR 红色加粗、WARN 黄色、TRACE 暗灰
+    pub ansi: bool,
+    /// 时间戳按此时区显示，None 时终端输出用本地时区、`render_entry` 保留记录时的写法
+    #[cfg(feature = "tz")]
+    pub display_timezone: Option<crate::timezone::Tz>,
+}
+
+impl Default for LogStyle {
+    fn default() -> Self {
+        Self {
+            timestamps: true,
+            target: true,
+            icons: true,
+            compact: false,
+            ansi: true,
+            #[cfg(feature = "tz")]
+            display_timezone: None,
+        }
+    }
+}
+
+impl LogStyle {
+    fn icon(level: &Level) -> char {
+        match *level {
+            Level::ERROR => '✗',
+            Level::WARN => '⚠',
+            Level::INFO => 'ℹ',
+            Level::DEBUG => '•',
+            Level::TRACE => '·',
+        }
+    }
+
+    pub(crate) fn color(level: &Level) -> &'static str {
+        match *level {
+            Level::ERROR => "\x1b[1;31m",
+            Level::WARN => "\x1b[33m",
+            Level::INFO => "\x1b[32m",
+            Level::DEBUG => "\x1b[34m",
+            Level::TRACE => "\x1b[2;37m",
+        }
+    }
+}
+
+pub(crate) const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_DIM: &str = "\x1b[2m";
+
+/// 按 `LogStyle` 输出单行日志的 `FormatEvent`
+#[derive(Clone, Debug, Default)]
+pub struct StyledFormat {
+    style: LogStyle,
+}
+
+impl StyledFormat {
+    pub fn new(style: LogStyle) -> Self {
+        Self { style }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for StyledFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let ansi = writer.has_ansi_escapes();
+        let (dim, reset) = if ansi {
+            (ANSI_DIM, ANSI_RESET)
+        } else {
+            ("", "")
+        };
+        let metadata = event.metadata();
+        let level = metadata.level();
+
+        if self.style.timestamps {
+            let now = chrono::Local::now().fixed_offset();
+            #[cfg(feature = "tz")]
+            let now = match self.style.display_timezone {
+                Some(tz) => now.with_timezone(&tz).fixed_offset(),
+                None => now,
+            };
+            let timestamp = if self.style.compact {
+                now.format("%H:%M:%S%.3f")
+            } else {
+                now.format("%Y-%m-%dT%H:%M:%S%.6f%:z")
+            };
+            write!(writer, "{}{}{} ", dim, timestamp, reset)?;
+        }
+
+        if ansi {
+            write!(writer, "{}", LogStyle::color(level))?;
+        }
+        if self.style.icons {
+            write!(writer, "{} ", LogStyle::icon(level))?;
+        }
+        write!(writer, "{:>5}{} ", level.as_str(), reset)?;
+
+        if !self.style.compact {
+            if let Some(scope) = ctx.event_scope() {
+                for span in scope.from_root() {
+                    write!(writer, "{}", span.name())?;
+                    let extensions = span.extensions();
+                    if let Some(fields) = extensions.get::<FormattedFields<N>>() {
+                        if !fields.is_empty() {
+                            write!(writer, "{{{}}}", fields)?;
+                        }
+                    }
+                    write!(writer, ":")?;
+                }
+                write!(writer, " ")?;
+            }
+        }
+
+        if self.style.target {
+            write!(writer, "{}{}:{} ", dim, metadata.target(), reset)?;
+        }
+
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+#[cfg(feature = "broadcast")]
+impl LogStyle {
+    /// 按本样式把一条已记录的 `LogEntry`（缓存、广播或 JSONL 中读回的）渲染为单行，不含换行符
+    ///
+    /// 布局与终端输出一致；结构化字段按 logfmt 追加在 message 之后，
+    /// 条目中没有 span 上下文，`compact` 只影响时间戳格式。
+    pub fn render_entry(&self, entry: &crate::LogEntry) -> String {
+        use std::fmt::Write as _;
+
+        let (dim, reset) = if self.ansi {
+            (ANSI_DIM, ANSI_RESET)
+        } else {
+            ("", "")
+        };
+        let level = entry.level_value();
+        let mut out = String::new();
+
+        if self.timestamps {
+            let _ = write!(out, "{}{}{} ", dim, self.entry_timestamp(entry), reset);
+        }
+
+        if let (true, Some(level)) = (self.ansi, &level) {
+            out.push_str(LogStyle::color(level));
+        }
+        if self.icons {
+            let icon = level.as_ref().map_or(' ', LogStyle::icon);
+            let _ = write!(out, "{} ", icon);
+        }
+        let _ = write!(out, "{:>5}{} ", entry.level, reset);
+
+        if self.target {
+            let _ = write!(out, "{}{}:{} ", dim, entry.target, reset);
+        }
+        out.push_str(&entry.message);
+
+        if !entry.fields.is_empty() {
+            let fields = entry
+                .fields
+                .iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    (key.clone(), value)
+                })
+                .collect();
+            out.push(' ');
+            out.push_str(&crate::tracing_utils::fmt_logfmt(&fields));
+        }
+        out
+    }
+
+    fn entry_timestamp(&self, entry: &crate::LogEntry) -> String {
+        #[cfg(feature = "tz")]
+        if let Some(tz) = self.display_timezone {
+            return match entry.timestamp.to_datetime() {
+                Some(at) if self.compact => {
+                    at.with_timezone(&tz).format("%H:%M:%S%.3f").to_string()
+                }
+                _ => entry.timestamp_in(tz),
+            };
+        }
+        match entry.timestamp.to_datetime() {
+            Some(at) if self.compact => at
+                .with_timezone(&chrono::Local)
+                .format("%H:%M:%S%.3f")
+                .to_string(),
+            _ => entry.timestamp.to_string(),
+        }
+    }
+}
+
+/// 按 `style` 构造的 fmt Layer，可自行组合进 subscriber（例如指定 writer）
+pub fn styled_layer<S>(
+    style: LogStyle,
+) -> tracing_subscriber::fmt::Layer<S, DefaultFields, StyledFormat>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_ansi(style.ansi)
+        .event_format(StyledFormat::new(style))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture(style: LogStyle, emit: impl FnOnce()) -> String {
+        let output = Captured::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(styled_layer(style).with_writer(move || writer.clone()));
+        tracing::subscriber::with_default(subscriber, emit);
+        let bytes = output.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_styled_icons_and_colors() {
+        skip_if_events_compiled_out!();
+        let plain = LogStyle {
+            timestamps: false,
+            ansi: false,
+            ..Default::default()
+        };
+        let output = capture(plain.clone(), || {
+            let _span = tracing::info_span!("order", id = 7).entered();
+            tracing::error!(venue = "binance", "order rejected");
+            tracing::warn!("slow fill");
+        });
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "✗ ERROR order{id=7}: listen_tracing::style::tests: order rejected venue=\"binance\"",
+                "⚠  WARN order{id=7}: listen_tracing::style::tests: slow fill",
+            ]
+        );
+
+        let compact = LogStyle {
+            compact: true,
+            target: false,
+            icons: false,
+            ..plain
+        };
+        let output = capture(compact, || {
+            let _span = tracing::info_span!("order").entered();
+            tracing::error!("order rejected");
+        });
+        assert_eq!(output, "ERROR order rejected\n");
+
+        let colored = LogStyle {
+            timestamps: false,
+            ..Default::default()
+        };
+        let output = capture(colored, || tracing::error!("order rejected"));
+        assert!(
+            output.starts_with("\x1b[1;31m✗ ERROR\x1b[0m "),
+            "{:?}",
+            output
+        );
+    }
+
+    #[cfg(feature = "broadcast")]
+    #[test]
+    fn test_render_entry() {
+        let entry = crate::LogEntry {
+            timestamp: "2024-06-01T00:00:00+00:00".into(),
+            level: "WARN".to_string(),
+            target: "svc::order".to_string(),
+            message: "slow fill".to_string(),
+            fields: [
+                ("venue".to_string(), serde_json::json!("binance us")),
+                ("qty".to_string(), serde_json::json!(5)),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let plain = LogStyle {
+            ansi: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            plain.render_entry(&entry),
+            "2024-06-01T00:00:00+00:00 ⚠  WARN svc::order: slow fill qty=5 venue=\"binance us\""
+        );
+        let colored = LogStyle {
+            timestamps: false,
+            target: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            colored.render_entry(&entry),
+            "\x1b[33m⚠  WARN\x1b[0m slow fill qty=5 venue=\"binance us\""
+        );
+
+        #[cfg(feature = "tz")]
+        {
+            let zoned = LogStyle {
+                display_timezone: Some(crate::timezone::Tz::Asia__Shanghai),
+                ..plain
+            };
+            assert!(zoned
+                .render_entry(&entry)
+                .starts_with("2024-06-01T08:00:00+08:00 ⚠"));
+            let compact = LogStyle {
+                compact: true,
+                ..zoned
+            };
+            assert!(compact.render_entry(&entry).starts_with("08:00:00.000 ⚠"));
+        }
+    }
+}
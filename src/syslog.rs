@@ -0,0 +1,350 @@
+//! syslog 输出（需开启 `syslog` feature），按 RFC 5424 格式发送到 rsyslog 等服务端
+//!
+//! UDP 每条日志一个数据报；TCP 使用 RFC 6587 的 octet-counting 分帧，
+//! 断线后按指数退避重连，期间的日志计入 failed 并丢弃。
+
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::SecondsFormat;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::sink::{spawn_sink, syslog_severity, LogSink, SinkError, SinkStats};
+use crate::LogEntry;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 结构化字段所在 SD-ELEMENT 的默认 SD-ID（32473 为 RFC 5612 保留的示例企业号）
+pub const DEFAULT_SD_ID: &str = "fields@32473";
+
+/// syslog facility（RFC 5424 表 1）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facility {
+    Kern = 0,
+    User = 1,
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyslogTransport {
+    #[default]
+    Udp,
+    Tcp,
+}
+
+/// 只保留 PRINTUSASCII（33..=126）中允许的字符并截断到 `max` 个，空串返回 NILVALUE `-`
+fn header_field(value: &str, max: usize, forbidden: &[char]) -> String {
+    let out: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && !forbidden.contains(&c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(max)
+        .collect();
+    if out.is_empty() {
+        "-".to_string()
+    } else {
+        out
+    }
+}
+
+/// SD-PARAM 的值需转义 `"`、`\` 和 `]`
+fn escape_param_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// 把 LogEntry 格式化为一条 RFC 5424 消息（不含传输层分帧）
+pub fn format_rfc5424(entry: &LogEntry, facility: Facility, hostname: &str, sd_id: &str) -> String {
+    let pri = facility as u8 * 8 + syslog_severity(&entry.level);
+    // RFC 5424 最多允许 6 位小数
+    let timestamp = entry
+        .timestamp
+        .to_datetime()
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Micros, true))
+        .unwrap_or_else(|| "-".to_string());
+    let sd_name_forbidden = ['=', ']', '"'];
+
+    let mut params = Vec::new();
+    if let Some(id) = &entry.correlation_id {
+        params.push(format!("correlation_id=\"{}\"", escape_param_value(id)));
+    }
+    for (key, value) in &entry.fields {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        params.push(format!(
+            "{}=\"{}\"",
+            header_field(key, 32, &sd_name_forbidden),
+            escape_param_value(&value)
+        ));
+    }
+    let structured_data = if params.is_empty() {
+        "-".to_string()
+    } else {
+        format!("[{} {}]", sd_id, params.join(" "))
+    };
+
+    format!(
+        "<{}>1 {} {} {} {} - {} {}",
+        pri,
+        timestamp,
+        header_field(hostname, 255, &[]),
+        header_field(&entry.target, 48, &[]),
+        std::process::id(),
+        structured_data,
+        entry.message
+    )
+}
+
+/// 以 RFC 5424 格式把日志发送到 syslog 服务端的 sink
+pub struct SyslogSink {
+    addr: String,
+    transport: SyslogTransport,
+    facility: Facility,
+    hostname: String,
+    sd_id: String,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    stats: Arc<SinkStats>,
+}
+
+impl SyslogSink {
+    /// `addr` 可带 `udp://` 或 `tcp://` 前缀，缺省为 UDP
+    pub fn new(addr: impl Into<String>, facility: Facility) -> Self {
+        let addr = addr.into();
+        let (transport, addr) = if let Some(rest) = addr.strip_prefix("tcp://") {
+            (SyslogTransport::Tcp, rest.to_string())
+        } else if let Some(rest) = addr.strip_prefix("udp://") {
+            (SyslogTransport::Udp, rest.to_string())
+        } else {
+            (SyslogTransport::Udp, addr)
+        };
+        Self {
+            addr,
+            transport,
+            facility,
+            hostname: std::env::var("HOSTNAME").unwrap_or_default(),
+            sd_id: DEFAULT_SD_ID.to_string(),
+            udp: None,
+            tcp: None,
+            backoff: INITIAL_BACKOFF,
+            retry_at: None,
+            stats: Arc::new(SinkStats::default()),
+        }
+    }
+
+    pub fn with_transport(mut self, transport: SyslogTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// HOSTNAME 字段，默认读取环境变量 `HOSTNAME`，为空时输出 `-`
+    pub fn with_hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    /// 结构化字段使用的 SD-ID，形如 `name@<企业号>`
+    pub fn with_sd_id(mut self, sd_id: impl Into<String>) -> Self {
+        self.sd_id = sd_id.into();
+        self
+    }
+
+    pub fn stats(&self) -> Arc<SinkStats> {
+        self.stats.clone()
+    }
+
+    async fn send_udp(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.udp.is_none() {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(&self.addr).await?;
+            self.udp = Some(socket);
+        }
+        let socket = self.udp.as_ref().expect("bound above");
+        socket.send(message).await.map(|_| ())
+    }
+
+    async fn send_tcp(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.tcp.is_none() {
+            if let Some(retry_at) = self.retry_at {
+                if Instant::now() < retry_at {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        format!("syslog {} unavailable, backing off", self.addr),
+                    ));
+                }
+            }
+            let stream =
+                match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&self.addr)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        self.mark_down();
+                        return Err(e);
+                    }
+                    Err(_) => {
+                        self.mark_down();
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "syslog connect timed out",
+                        ));
+                    }
+                };
+            self.tcp = Some(stream);
+            self.backoff = INITIAL_BACKOFF;
+            self.retry_at = None;
+        }
+
+        let stream = self.tcp.as_mut().expect("connected above");
+        let mut frame = format!("{} ", message.len()).into_bytes();
+        frame.extend_from_slice(message);
+        let result = stream.write_all(&frame).await;
+        if result.is_err() {
+            self.mark_down();
+        }
+        result
+    }
+
+    fn mark_down(&mut self) {
+        self.tcp = None;
+        self.retry_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+impl LogSink for SyslogSink {
+    async fn send(&mut self, entry: &LogEntry) {
+        let message = format_rfc5424(entry, self.facility, &self.hostname, &self.sd_id);
+        let result = match self.transport {
+            SyslogTransport::Udp => self.send_udp(message.as_bytes()).await,
+            SyslogTransport::Tcp => self.send_tcp(message.as_bytes()).await,
+        };
+        match result {
+            Ok(()) => self.stats.record_sent(1),
+            Err(e) => {
+                // 退避期间不重复提示
+                if e.kind() != io::ErrorKind::NotConnected {
+                    SinkError::send("syslog", "send", e).report();
+                }
+                self.udp = None;
+                self.stats.record_failed(1);
+            }
+        }
+    }
+}
+
+/// 订阅广播通道，把日志发送到 syslog 服务端；`addr` 规则同 `SyslogSink::new`
+pub fn spawn_syslog_sink(
+    tx: &broadcast::Sender<LogEntry>,
+    addr: impl Into<String>,
+    facility: Facility,
+) -> JoinHandle<()> {
+    spawn_sink(tx, SyslogSink::new(addr, facility))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn entry() -> LogEntry {
+        LogEntry {
+            timestamp: "2024-06-01T12:00:00.123456789+00:00".into(),
+            level: "WARN".to_string(),
+            target: "bot::executor".to_string(),
+            message: "order rejected".to_string(),
+            correlation_id: Some("req-1".to_string()),
+            fields: [
+                ("qty".to_string(), json!(3)),
+                ("reason".to_string(), json!("limit \"hit\"")),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format_rfc5424() {
+        let message = format_rfc5424(&entry(), Facility::Local0, "host-1", DEFAULT_SD_ID);
+        assert_eq!(
+            message,
+            format!(
+                "<132>1 2024-06-01T12:00:00.123456Z host-1 bot::executor {} - \
+                 [fields@32473 correlation_id=\"req-1\" qty=\"3\" reason=\"limit \\\"hit\\\"\"] \
+                 order rejected",
+                std::process::id()
+            )
+        );
+
+        let plain = LogEntry {
+            level: "INFO".to_string(),
+            ..Default::default()
+        };
+        assert!(
+            format_rfc5424(&plain, Facility::User, "", DEFAULT_SD_ID).starts_with("<14>1 - - - ")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_syslog_over_udp_and_tcp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut sink =
+            SyslogSink::new(format!("udp://{}", addr), Facility::Daemon).with_hostname("host-1");
+        sink.send(&entry()).await;
+        let mut buf = vec![0; 4096];
+        let n = server.recv(&mut buf).await.unwrap();
+        let received = String::from_utf8_lossy(&buf[..n]).to_string();
+        assert!(received.starts_with("<28>1 2024-06-01T12:00:00.123456Z host-1 bot::executor"));
+        assert!(received.ends_with("order rejected"));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut sink =
+            SyslogSink::new(format!("tcp://{}", addr), Facility::Daemon).with_hostname("host-1");
+        sink.send(&entry()).await;
+        sink.send(&entry()).await;
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let expected = format_rfc5424(&entry(), Facility::Daemon, "host-1", DEFAULT_SD_ID);
+        let frame = format!("{} {}", expected.len(), expected);
+        let mut buf = vec![0; frame.len() * 2];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, format!("{}{}", frame, frame).into_bytes());
+        assert_eq!(sink.stats().snapshot().sent, 2);
+    }
+}
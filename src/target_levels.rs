@@ -0,0 +1,138 @@
+//! 按 target 覆盖 `BroadcastLogLayer` 的最低级别
+//!
+//! 在全局过滤指令之后生效，只影响本 Layer：例如 `hyper` 低于 WARN 的事件不广播、不缓存、
+//! 不持久化，控制台等其他 Layer 照常输出。target 按模块前缀匹配，取最长的一条
+//! （`hyper::client` 的覆盖优先于 `hyper`）。
+//!
+//! `TargetLevelOverrides` 可克隆，各副本共享同一张表，安装后仍可调整。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use tracing::Level;
+
+use crate::routing::target_has_prefix;
+
+#[derive(Debug, Default)]
+struct Shared {
+    /// 表为空时跳过加锁
+    active: AtomicBool,
+    levels: RwLock<HashMap<String, Level>>,
+}
+
+/// target 前缀 -> 最低级别，见模块文档
+#[derive(Clone, Debug, Default)]
+pub struct TargetLevelOverrides {
+    shared: Arc<Shared>,
+}
+
+impl TargetLevelOverrides {
+    /// 设置（或替换）`target` 的最低级别，返回旧值
+    pub fn set(&self, target: impl Into<String>, level: Level) -> Option<Level> {
+        self.update(|levels| levels.insert(target.into(), level))
+    }
+
+    /// 删除 `target` 的覆盖，返回旧值
+    pub fn remove(&self, target: &str) -> Option<Level> {
+        self.update(|levels| levels.remove(target))
+    }
+
+    pub fn clear(&self) {
+        self.update(HashMap::clear);
+    }
+
+    /// 当前的全部覆盖，按 target 排序
+    pub fn to_vec(&self) -> Vec<(String, Level)> {
+        let mut overrides: Vec<_> = self
+            .read()
+            .iter()
+            .map(|(target, level)| (target.clone(), *level))
+            .collect();
+        overrides.sort();
+        overrides
+    }
+
+    /// 对 `target` 生效的覆盖：最长的匹配前缀，没有时返回 None
+    pub fn level_for(&self, target: &str) -> Option<Level> {
+        if !self.shared.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.read()
+            .iter()
+            .filter(|(prefix, _)| target_has_prefix(target, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+    }
+
+    /// `target` 的 `level` 事件是否被覆盖滤掉
+    pub fn suppresses(&self, target: &str, level: &Level) -> bool {
+        self.level_for(target).is_some_and(|min| *level > min)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Level>> {
+        self.shared
+            .levels
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut HashMap<String, Level>) -> T) -> T {
+        let mut levels = self
+            .shared
+            .levels
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let result = f(&mut levels);
+        self.shared
+            .active
+            .store(!levels.is_empty(), Ordering::Relaxed);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::tests::wait_for_cache;
+    use crate::{BroadcastLogLayer, LogCache};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[tokio::test]
+    async fn test_overrides_filter_cache() {
+        skip_if_events_compiled_out!();
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_target_level_override("hyper", Level::WARN)
+            .with_target_level_override("hyper::client::pool", Level::DEBUG);
+        let overrides = layer.target_level_overrides();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::info!(target: "hyper::proto", "suppressed");
+        tracing::warn!(target: "hyper::proto", "kept: at the override level");
+        // 最长前缀优先
+        tracing::debug!(target: "hyper::client::pool", "kept: longer prefix");
+        // 按模块边界匹配
+        tracing::info!(target: "hyperx", "kept: different module");
+        tracing::info!(target: "app", "kept: no override");
+
+        // 安装后调整
+        overrides.remove("hyper");
+        tracing::info!(target: "hyper::proto", "kept: override removed");
+
+        let messages: Vec<String> = wait_for_cache(&cache, 5)
+            .await
+            .into_iter()
+            .map(|log| log.message)
+            .collect();
+        assert!(messages.iter().all(|message| message.starts_with("kept")));
+        assert_eq!(messages.len(), 5);
+        assert_eq!(
+            overrides.to_vec(),
+            [("hyper::client::pool".to_string(), Level::DEBUG)]
+        );
+    }
+}
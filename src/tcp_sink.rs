@@ -0,0 +1,274 @@
+//! NDJSON over TCP 输出（需开启 `tcp_sink` feature），对接 Vector、Fluent Bit 等采集端的 TCP 源
+//!
+//! 每条 `LogEntry` 序列化为一行 JSON，经一条长连接发送。断线后按指数退避重连，
+//! 期间的日志暂存在有界缓冲中（满时丢弃最旧的，计入 dropped），重连后按原顺序先行补发。
+//! TCP 写入成功只表示进入了内核发送缓冲，对端恰好断开时最后几条仍可能丢失。
+//! 采集端停止读取导致一行在写入超时（默认 5 秒）内写不完时同样按断线处理，
+//! 该行重连后整行重发，对端可能先收到它被截断的前半部分。
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::sink::{spawn_sink, LogSink, SinkError, SinkStats};
+use crate::LogEntry;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// 默认的单行写入超时
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// 断线期间检查能否重连、补发缓冲的间隔
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 默认最多缓冲的条数
+pub const DEFAULT_TCP_BUFFER: usize = 10_000;
+
+/// 以 NDJSON 格式把日志发送到 TCP 采集端的 sink
+pub struct TcpSink {
+    addr: String,
+    stream: Option<TcpStream>,
+    /// 尚未写出的行（含结尾换行），最旧的在前
+    pending: VecDeque<Vec<u8>>,
+    capacity: usize,
+    write_timeout: Duration,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    stats: Arc<SinkStats>,
+}
+
+impl TcpSink {
+    /// `addr` 为 `host:port`；断线期间最多缓冲 `buffer` 条（至少 1 条）
+    pub fn new(addr: impl Into<String>, buffer: usize) -> Self {
+        Self {
+            addr: addr.into(),
+            stream: None,
+            pending: VecDeque::new(),
+            capacity: buffer.max(1),
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            backoff: INITIAL_BACKOFF,
+            retry_at: None,
+            stats: Arc::new(SinkStats::default()),
+        }
+    }
+
+    /// 单行写入的超时，超时后断开连接并按退避重连
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn stats(&self) -> Arc<SinkStats> {
+        self.stats.clone()
+    }
+
+    /// 缓冲中等待发送的条数
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// 按顺序写出缓冲；连接不可用或写入失败时保留剩余部分
+    async fn drain(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if let Err(e) = self.connect().await {
+            if e.kind() != io::ErrorKind::NotConnected {
+                SinkError::send("tcp", "connect", e).report();
+            }
+            return;
+        }
+        let stream = self.stream.as_mut().expect("connected above");
+        while let Some(line) = self.pending.front() {
+            let result =
+                match tokio::time::timeout(self.write_timeout, stream.write_all(line)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "tcp write timed out",
+                    )),
+                };
+            if let Err(e) = result {
+                SinkError::send("tcp", "send", e).report();
+                self.mark_down();
+                return;
+            }
+            self.pending.pop_front();
+            self.stats.record_sent(1);
+        }
+    }
+
+    async fn connect(&mut self) -> io::Result<()> {
+        if self.stream.is_some() {
+            return Ok(());
+        }
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    format!("tcp {} unavailable, backing off", self.addr),
+                ));
+            }
+        }
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&self.addr)).await {
+            Ok(Ok(stream)) => {
+                // 逐行写出，不等待攒包
+                let _ = stream.set_nodelay(true);
+                self.stream = Some(stream);
+                self.backoff = INITIAL_BACKOFF;
+                self.retry_at = None;
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                self.mark_down();
+                Err(e)
+            }
+            Err(_) => {
+                self.mark_down();
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "tcp connect timed out",
+                ))
+            }
+        }
+    }
+
+    fn mark_down(&mut self) {
+        self.stream = None;
+        self.retry_at = Some(Instant::now() + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+impl LogSink for TcpSink {
+    async fn send(&mut self, entry: &LogEntry) {
+        let Ok(mut line) = serde_json::to_vec(entry) else {
+            self.stats.record_failed(1);
+            return;
+        };
+        line.push(b'\n');
+        if self.pending.len() >= self.capacity {
+            self.pending.pop_front();
+            self.stats.record_dropped(1);
+        }
+        self.pending.push_back(line);
+        self.drain().await;
+    }
+
+    async fn flush(&mut self) {
+        self.drain().await;
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(RETRY_INTERVAL)
+    }
+}
+
+/// 订阅广播通道，把日志以 NDJSON 发送到 `addr`；断线期间最多缓冲 `buffer` 条
+pub fn spawn_tcp_sink(
+    tx: &broadcast::Sender<LogEntry>,
+    addr: impl Into<String>,
+    buffer: usize,
+) -> JoinHandle<()> {
+    spawn_sink(tx, TcpSink::new(addr, buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn entry(i: u64) -> LogEntry {
+        LogEntry {
+            timestamp: "2024-06-01T12:00:00.123456+00:00".into(),
+            level: "INFO".to_string(),
+            target: "bot::executor".to_string(),
+            message: format!("order {}\nfilled", i),
+            fields: [("qty".to_string(), json!(i))].into_iter().collect(),
+            seq: Some(i),
+            ..Default::default()
+        }
+    }
+
+    async fn read_entries(listener: &TcpListener, n: usize) -> Vec<LogEntry> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut entries = Vec::new();
+        while entries.len() < n {
+            let line = lines.next_line().await.unwrap().unwrap();
+            entries.push(serde_json::from_str(&line).unwrap());
+        }
+        entries
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_with_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut sink = TcpSink::new(addr.to_string(), 2);
+        sink.send(&entry(1)).await;
+        sink.send(&entry(2)).await;
+        assert_eq!(read_entries(&listener, 2).await, [entry(1), entry(2)]);
+
+        // 采集端下线：模拟检测到断线，之后连接被拒绝，条目进入缓冲
+        drop(listener);
+        sink.stream = None;
+        for i in 3..=5 {
+            sink.send(&entry(i)).await;
+        }
+        assert_eq!(sink.pending(), 2);
+
+        // 采集端恢复，退避到期后按顺序补发，超出缓冲的最旧一条已丢弃
+        let listener = TcpListener::bind(addr).await.unwrap();
+        tokio::time::sleep(INITIAL_BACKOFF * 2).await;
+        sink.flush().await;
+        assert_eq!(sink.pending(), 0);
+        assert_eq!(read_entries(&listener, 2).await, [entry(4), entry(5)]);
+        let stats = sink.stats().snapshot();
+        assert_eq!((stats.sent, stats.dropped), (4, 1));
+    }
+
+    #[tokio::test]
+    async fn test_write_timeout_marks_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut sink = TcpSink::new(listener.local_addr().unwrap().to_string(), 4)
+            .with_write_timeout(Duration::from_millis(200));
+        // 采集端接受连接但从不读取，足够大的一行会填满双方的内核缓冲
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+        let mut stalled = entry(1);
+        stalled.message = "x".repeat(32 << 20);
+
+        let start = Instant::now();
+        sink.send(&stalled).await;
+        assert!(
+            start.elapsed() < DEFAULT_WRITE_TIMEOUT,
+            "{:?}",
+            start.elapsed()
+        );
+        assert!(sink.stream.is_none());
+        assert!(sink.retry_at.is_some());
+        assert_eq!(sink.pending(), 1);
+        assert_eq!(sink.stats().snapshot().sent, 0);
+        drop(accept.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_tcp_sink() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (tx, _rx) = broadcast::channel(16);
+        let handle = spawn_tcp_sink(&tx, listener.local_addr().unwrap().to_string(), 16);
+        tx.send(entry(1)).unwrap();
+        tx.send(entry(2)).unwrap();
+        assert_eq!(read_entries(&listener, 2).await, [entry(1), entry(2)]);
+        drop(tx);
+        handle.await.unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+//! `LogEntry::timestamp` 的存储形式
+//!
+//! 默认在产生事件时即格式化为 RFC 3339 字符串。`TimeRepr::EpochNanos` 只记下 epoch 纳秒（`i64`，不分配），
+//! 到序列化、显示或需要文本时才格式化；两种形式序列化出的 JSON 完全相同，
+//! 持久化文件、广播订阅者与查询结果看不出区别。
+
+use std::borrow::Cow;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::format_rfc3339;
+
+/// 新条目 `timestamp` 的存储形式，见 `BroadcastLogLayer::with_time_repr`
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeRepr {
+    /// 产生事件时格式化为 RFC 3339 字符串
+    #[default]
+    Rfc3339String,
+    /// 记录 epoch 纳秒，序列化或显示时才格式化
+    EpochNanos,
+}
+
+/// 条目的时间戳：RFC 3339 文本，或尚未格式化的 epoch 纳秒
+///
+/// 序列化时总是输出 RFC 3339 字符串（与 `Utc::now().to_rfc3339()` 格式一致），反序列化得到 `Rfc3339`。
+/// 比较时两种形式按格式化后的文本相等。
+#[derive(Clone, Debug)]
+pub enum LogTimestamp {
+    Rfc3339(String),
+    EpochNanos(i64),
+}
+
+impl LogTimestamp {
+    /// 按 `repr` 记录 `now`；超出 i64 纳秒可表示范围（1677–2262 年）时退回 RFC 3339 文本
+    pub fn new(now: DateTime<Utc>, repr: TimeRepr) -> Self {
+        match (repr, now.timestamp_nanos_opt()) {
+            (TimeRepr::EpochNanos, Some(nanos)) => LogTimestamp::EpochNanos(nanos),
+            _ => LogTimestamp::Rfc3339(format_rfc3339(now)),
+        }
+    }
+
+    /// 已格式化的文本，`EpochNanos` 返回 None
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LogTimestamp::Rfc3339(text) => Some(text),
+            LogTimestamp::EpochNanos(_) => None,
+        }
+    }
+
+    /// RFC 3339 文本，`EpochNanos` 此时才格式化
+    pub fn to_rfc3339(&self) -> Cow<'_, str> {
+        match self {
+            LogTimestamp::Rfc3339(text) => Cow::Borrowed(text),
+            LogTimestamp::EpochNanos(nanos) => {
+                Cow::Owned(format_rfc3339(DateTime::from_timestamp_nanos(*nanos)))
+            }
+        }
+    }
+
+    /// 解析为 UTC 时间，文本无法解析时返回 None
+    pub fn to_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            LogTimestamp::Rfc3339(text) => DateTime::parse_from_rfc3339(text)
+                .ok()
+                .map(|t| t.with_timezone(&Utc)),
+            LogTimestamp::EpochNanos(nanos) => Some(DateTime::from_timestamp_nanos(*nanos)),
+        }
+    }
+
+    /// epoch 毫秒，`EpochNanos` 无需解析
+    pub fn epoch_millis(&self) -> Option<i64> {
+        match self {
+            LogTimestamp::EpochNanos(nanos) => Some(nanos.div_euclid(1_000_000)),
+            LogTimestamp::Rfc3339(_) => self.to_datetime().map(|t| t.timestamp_millis()),
+        }
+    }
+}
+
+impl Default for LogTimestamp {
+    fn default() -> Self {
+        LogTimestamp::Rfc3339(String::new())
+    }
+}
+
+impl From<String> for LogTimestamp {
+    fn from(text: String) -> Self {
+        LogTimestamp::Rfc3339(text)
+    }
+}
+
+impl From<&str> for LogTimestamp {
+    fn from(text: &str) -> Self {
+        LogTimestamp::Rfc3339(text.to_string())
+    }
+}
+
+impl fmt::Display for LogTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_rfc3339())
+    }
+}
+
+impl PartialEq for LogTimestamp {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LogTimestamp::Rfc3339(a), LogTimestamp::Rfc3339(b)) => a == b,
+            (LogTimestamp::EpochNanos(a), LogTimestamp::EpochNanos(b)) => a == b,
+            _ => self.to_rfc3339() == other.to_rfc3339(),
+        }
+    }
+}
+
+impl PartialEq<str> for LogTimestamp {
+    fn eq(&self, other: &str) -> bool {
+        self.to_rfc3339() == other
+    }
+}
+
+impl PartialEq<&str> for LogTimestamp {
+    fn eq(&self, other: &&str) -> bool {
+        self.to_rfc3339() == *other
+    }
+}
+
+impl PartialEq<String> for LogTimestamp {
+    fn eq(&self, other: &String) -> bool {
+        self.to_rfc3339() == other.as_str()
+    }
+}
+
+impl Serialize for LogTimestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_rfc3339())
+    }
+}
+
+impl<'de> Deserialize<'de> for LogTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(LogTimestamp::Rfc3339)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch_nanos_matches_rfc3339() {
+        for now in [
+            DateTime::from_timestamp_nanos(1_717_243_200_000_000_000),
+            DateTime::from_timestamp_nanos(1_717_243_200_123_000_000),
+            DateTime::from_timestamp_nanos(1_717_243_200_123_456_789),
+        ] {
+            let text = LogTimestamp::new(now, TimeRepr::Rfc3339String);
+            let nanos = LogTimestamp::new(now, TimeRepr::EpochNanos);
+            assert!(matches!(nanos, LogTimestamp::EpochNanos(_)));
+            assert_eq!(text.as_str(), Some(now.to_rfc3339().as_str()));
+            assert_eq!(nanos.as_str(), None);
+            assert_eq!(text, nanos);
+            assert_eq!(nanos, now.to_rfc3339());
+            assert_eq!(
+                serde_json::to_string(&nanos).unwrap(),
+                serde_json::to_string(&text).unwrap()
+            );
+            assert_eq!(nanos.epoch_millis(), Some(now.timestamp_millis()));
+            assert_eq!(text.epoch_millis(), Some(now.timestamp_millis()));
+        }
+        let parsed: LogTimestamp = serde_json::from_str("\"2024-06-01T12:00:00+00:00\"").unwrap();
+        assert_eq!(parsed, LogTimestamp::EpochNanos(1_717_243_200_000_000_000));
+        assert_eq!(LogTimestamp::from("garbage").epoch_millis(), None);
+    }
+
+    #[tokio::test]
+    async fn test_layer_time_repr() {
+        skip_if_events_compiled_out!();
+        use crate::clock::MockClock;
+        use crate::{BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let at = DateTime::from_timestamp_nanos(1_717_243_200_123_456_000);
+        let mut lines = Vec::new();
+        for repr in [TimeRepr::Rfc3339String, TimeRepr::EpochNanos] {
+            let (tx, _rx) = tokio::sync::broadcast::channel(16);
+            let cache = LogCache::default();
+            let layer = BroadcastLogLayer::new(tx, cache.clone())
+                .without_persistence()
+                .with_clock(MockClock::new(at))
+                .with_time_repr(repr);
+            let _guard =
+                tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+            tracing::info!("tick");
+            let logs = crate::broadcast::tests::wait_for_cache(&cache, 1).await;
+            assert_eq!(
+                matches!(logs[0].timestamp, LogTimestamp::EpochNanos(_)),
+                repr == TimeRepr::EpochNanos
+            );
+            let mut entry = logs[0].clone();
+            entry.seq = None;
+            entry.mono_ns = None;
+            lines.push(serde_json::to_string(&entry).unwrap());
+        }
+        assert_eq!(lines[0], lines[1]);
+        assert!(lines[0].contains(r#""timestamp":"2024-06-01T12:00:00.123456+00:00""#));
+    }
+}
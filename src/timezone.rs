@@ -0,0 +1,238 @@
+//! 按指定时区展示时间戳
+//!
+//! 条目的时间戳始终以 UTC（或 epoch 纳秒）记录，这里只负责展示。时区为 chrono-tz 的 `Tz`，
+//! 规则取自 IANA tz 数据库，夏令时（包括南半球）与历史规则变更都按数据库计算；
+//! 供 `LogEntry::timestamp_in`、`tracing_utils::render_table_in`、`LogStyle::display_timezone`
+//! 与查询参数 `LogQuery::tz` 使用。
+//!
+//! 把当地时间换算为时刻时使用 chrono 的 `TimeZone::from_local_datetime`：夏令时切换的空档
+//! 返回 `LocalResult::None`，重叠返回 `Ambiguous`，不会 panic。
+
+use std::fmt;
+
+use chrono::SecondsFormat;
+pub use chrono_tz::Tz;
+
+use crate::store::LogPage;
+use crate::{LogEntry, LogQuery, LogTimestamp};
+
+/// 解析 IANA 时区名称，不区分大小写，如 `Asia/Shanghai`、`america/new_york`、`UTC`
+pub fn parse_timezone(name: &str) -> Result<Tz, TimezoneError> {
+    let name = name.trim();
+    name.parse::<Tz>()
+        .or_else(|_| Tz::from_str_insensitive(name))
+        .map_err(|_| TimezoneError {
+            name: name.to_string(),
+        })
+}
+
+/// 无法识别的时区名称，错误信息列出几个有效的写法
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimezoneError {
+    pub name: String,
+}
+
+impl TimezoneError {
+    /// 稳定的错误码，见 `error_code::ERROR_CODES`
+    pub fn code(&self) -> &'static str {
+        "LT-CFG-006"
+    }
+}
+
+impl fmt::Display for TimezoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown timezone `{}`: expected an IANA name such as Asia/Shanghai, \
+             America/New_York, Europe/London or UTC",
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for TimezoneError {}
+
+impl LogEntry {
+    /// 时间戳在 `tz` 中的 RFC3339 表示；时间戳无法解析时原样返回
+    pub fn timestamp_in(&self, tz: Tz) -> String {
+        match self.timestamp.to_datetime() {
+            Some(at) => at
+                .with_timezone(&tz)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+            None => self.timestamp.to_string(),
+        }
+    }
+}
+
+impl LogQuery {
+    /// 解析 `tz` 参数，未指定时返回 None
+    pub fn display_timezone(&self) -> Result<Option<Tz>, TimezoneError> {
+        self.tz.as_deref().map(parse_timezone).transpose()
+    }
+}
+
+impl LogPage {
+    /// 把各条结果的时间戳改写为 `tz` 中的 RFC3339 表示，指向同一时刻
+    pub fn localize(&mut self, tz: Tz) {
+        for hit in &mut self.items {
+            hit.entry.timestamp = LogTimestamp::from(hit.entry.timestamp_in(tz));
+        }
+    }
+}
+
+/// 按 `query.tz` 改写查询结果的时间戳，供 HTTP 查询接口的 `?tz=` 参数使用
+pub fn localize_page(page: &mut LogPage, query: &LogQuery) -> Result<(), TimezoneError> {
+    if let Some(tz) = query.display_timezone()? {
+        page.localize(tz);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn local(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn offset_at(tz: Tz, text: &str) -> String {
+        tz.offset_from_utc_datetime(&utc(text).naive_utc())
+            .fix()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(parse_timezone("asia/shanghai"), Ok(Tz::Asia__Shanghai));
+        assert_eq!(parse_timezone(" UTC "), Ok(Tz::UTC));
+        for name in [
+            "Europe/Madrid",
+            "Australia/Sydney",
+            "America/Sao_Paulo",
+            "Asia/Jakarta",
+        ] {
+            assert_eq!(parse_timezone(name).unwrap().name(), name);
+        }
+        for text in ["Mars/Olympus", "+08:00", ""] {
+            let err = parse_timezone(text).unwrap_err();
+            assert_eq!(err.code(), "LT-CFG-006");
+            assert!(err.to_string().contains("Asia/Shanghai"), "{}", err);
+        }
+
+        let entry = LogEntry {
+            timestamp: "2024-06-01T12:00:00.250+00:00".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            entry.timestamp_in(Tz::Asia__Shanghai),
+            "2024-06-01T20:00:00.250+08:00"
+        );
+        let unparsed = LogEntry {
+            timestamp: "yesterday".into(),
+            ..Default::default()
+        };
+        assert_eq!(unparsed.timestamp_in(Tz::Asia__Shanghai), "yesterday");
+    }
+
+    #[test]
+    fn test_dst_transitions() {
+        let new_york = Tz::America__New_York;
+        // 2024-03-10 02:00 EST 起跳到 03:00 EDT
+        assert_eq!(offset_at(new_york, "2024-03-10T06:59:59+00:00"), "-05:00");
+        assert_eq!(offset_at(new_york, "2024-03-10T07:00:00+00:00"), "-04:00");
+        // 2024-11-03 02:00 EDT 回拨到 01:00 EST
+        assert_eq!(offset_at(new_york, "2024-11-03T05:59:59+00:00"), "-04:00");
+        assert_eq!(offset_at(new_york, "2024-11-03T06:00:00+00:00"), "-05:00");
+        // 2007 年之前美国夏令时从 4 月第一个周日开始
+        assert_eq!(offset_at(new_york, "2006-03-20T12:00:00+00:00"), "-05:00");
+        assert_eq!(offset_at(new_york, "2006-04-02T07:00:00+00:00"), "-04:00");
+
+        // 空档与重叠中的当地时间
+        assert_eq!(
+            new_york.from_local_datetime(&local("2024-03-10 02:30:00")),
+            LocalResult::None
+        );
+        let LocalResult::Ambiguous(early, late) =
+            new_york.from_local_datetime(&local("2024-11-03 01:30:00"))
+        else {
+            panic!("01:30 should be ambiguous");
+        };
+        assert_eq!(early.to_rfc3339(), "2024-11-03T01:30:00-04:00");
+        assert_eq!(late.to_rfc3339(), "2024-11-03T01:30:00-05:00");
+
+        // 欧盟各地在 01:00 UTC 同时切换
+        for (tz, before, after) in [
+            (Tz::Europe__London, "+00:00", "+01:00"),
+            (Tz::Europe__Madrid, "+01:00", "+02:00"),
+        ] {
+            assert_eq!(offset_at(tz, "2024-03-31T00:59:59+00:00"), before);
+            assert_eq!(offset_at(tz, "2024-03-31T01:00:00+00:00"), after);
+        }
+        assert!(matches!(
+            Tz::Europe__Madrid.from_local_datetime(&local("2024-10-27 02:30:00")),
+            LocalResult::Ambiguous(..)
+        ));
+
+        // 南半球：悉尼的夏令时在 10 月开始、次年 4 月结束
+        let sydney = Tz::Australia__Sydney;
+        assert_eq!(offset_at(sydney, "2024-01-15T00:00:00+00:00"), "+11:00");
+        assert_eq!(offset_at(sydney, "2024-07-15T00:00:00+00:00"), "+10:00");
+        assert!(matches!(
+            sydney.from_local_datetime(&local("2024-04-07 02:30:00")),
+            LocalResult::Ambiguous(..)
+        ));
+        assert_eq!(
+            sydney.from_local_datetime(&local("2024-10-06 02:30:00")),
+            LocalResult::None
+        );
+        // 圣保罗 2019 年起取消夏令时，之前南半球夏季为 -02:00
+        assert_eq!(
+            offset_at(Tz::America__Sao_Paulo, "2018-01-15T12:00:00+00:00"),
+            "-02:00"
+        );
+        assert_eq!(
+            offset_at(Tz::America__Sao_Paulo, "2024-01-15T12:00:00+00:00"),
+            "-03:00"
+        );
+        assert_eq!(
+            offset_at(Tz::Asia__Jakarta, "2024-01-15T12:00:00+00:00"),
+            "+07:00"
+        );
+    }
+
+    #[test]
+    fn test_localize_page() {
+        let mut page = LogPage {
+            total: 1,
+            page: 1,
+            page_size: 10,
+            items: vec![crate::store::LogHit {
+                entry: LogEntry {
+                    timestamp: "2024-01-15T08:00:00+00:00".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+        };
+        let mut query = LogQuery {
+            tz: Some("Asia/Tokyo".to_string()),
+            ..Default::default()
+        };
+        localize_page(&mut page, &query).unwrap();
+        assert_eq!(
+            page.items[0].entry.timestamp.to_string(),
+            "2024-01-15T17:00:00+09:00"
+        );
+
+        query.tz = Some("Tokio".to_string());
+        assert_eq!(localize_page(&mut page, &query).unwrap_err().name, "Tokio");
+    }
+}
@@ -1,5 +1,8 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
 use bigdecimal::BigDecimal;
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
 use serde_json::Value;
 
 /// Option<T> 格式化为 String（ToString 类型）
@@ -16,6 +19,51 @@ pub fn fmt_opt_or<T: ToString>(v: &Option<T>, default: &str) -> String {
         .unwrap_or_else(|| default.to_string())
 }
 
+/// Option<T> 格式化为 String，仅在 None 时才调用 `default` 计算默认值
+///
+/// ```
+/// use std::cell::Cell;
+/// use listen_tracing::tracing_utils::fmt_opt_or_else;
+///
+/// let calls = Cell::new(0);
+/// let fallback = || {
+///     calls.set(calls.get() + 1);
+///     format!("missing since {}", "2024-06-01")
+/// };
+/// assert_eq!(fmt_opt_or_else(&Some(42), fallback), "42");
+/// assert_eq!(calls.get(), 0);
+/// assert_eq!(fmt_opt_or_else(&None::<i32>, fallback), "missing since 2024-06-01");
+/// assert_eq!(calls.get(), 1);
+/// ```
+pub fn fmt_opt_or_else<T: ToString, F: FnOnce() -> String>(v: &Option<T>, default: F) -> String {
+    v.as_ref().map(ToString::to_string).unwrap_or_else(default)
+}
+
+/// 依次取第一个为 Some 的值格式化为 String，全部为 None 时返回 `"null"`，
+/// 代替多层嵌套的 `fmt_opt_or`：`fmt_first(&[&nickname, &username, &email])`
+pub fn fmt_first<T: ToString>(opts: &[&Option<T>]) -> String {
+    opts.iter()
+        .find_map(|v| v.as_ref())
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "null".to_string())
+}
+
+/// Display 结果为空（如空集合）时改用 `default`，避免日志中出现看似缺失的空白字段
+pub fn fmt_display_or<T: std::fmt::Display>(v: &T, default: &str) -> String {
+    let s = v.to_string();
+    if s.is_empty() {
+        default.to_string()
+    } else {
+        s
+    }
+}
+
+/// 同 `fmt_display_or`，None 也使用 `default`
+pub fn fmt_opt_display_or<T: std::fmt::Display>(v: &Option<T>, default: &str) -> String {
+    v.as_ref()
+        .map_or_else(|| default.to_string(), |v| fmt_display_or(v, default))
+}
+
 /// Option<NaiveDate> 格式化为 YYYY-MM-DD
 pub fn fmt_naive_date(v: &Option<NaiveDate>) -> String {
     v.map(|d| d.format("%Y-%m-%d").to_string())
@@ -28,6 +76,32 @@ pub fn fmt_naive_datetime(v: &Option<NaiveDateTime>) -> String {
         .unwrap_or_else(|| "null".to_string())
 }
 
+/// 同 `fmt_naive_datetime`，把 `v` 视为 UTC 时间转换到 `tz` 显示，并附上偏移，如 `2024-06-01 20:00:00 +08:00`
+#[cfg(feature = "tz")]
+pub fn fmt_naive_datetime_tz(v: &Option<NaiveDateTime>, tz: crate::timezone::Tz) -> String {
+    v.map(|d| {
+        d.and_utc()
+            .with_timezone(&tz)
+            .format("%Y-%m-%d %H:%M:%S %:z")
+            .to_string()
+    })
+    .unwrap_or_else(|| "null".to_string())
+}
+
+/// RFC3339 时间字符串（如 `LogEntry::timestamp`）转换为 epoch 毫秒，解析失败返回 None
+pub fn rfc3339_to_epoch_millis(ts: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(ts)
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+/// epoch 毫秒转换为毫秒精度的 UTC RFC3339 字符串，超出可表示范围时返回空字符串
+pub fn epoch_millis_to_rfc3339(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, false))
+        .unwrap_or_default()
+}
+
 /// Option<BigDecimal> 转换为字符串
 pub fn fmt_bigdecimal(v: &Option<BigDecimal>) -> String {
     v.as_ref()
@@ -42,23 +116,631 @@ pub fn fmt_json_value(v: &Option<Value>) -> String {
         .unwrap_or_else(|| "null".to_string())
 }
 
+/// Option<serde_json::Value> 转换为带缩进的多行 JSON，None 输出 `"null"`
+///
+/// 供调试时人工查看嵌套结构；输出有意跨多行，会让单条日志占据多行，不适合按行处理的场景，此时用 `fmt_json_value`。
+pub fn fmt_json_value_pretty(v: &Option<Value>) -> String {
+    v.as_ref()
+        .and_then(|v| serde_json::to_string_pretty(v).ok())
+        .unwrap_or_else(|| "null".to_string())
+}
+
+/// 字节序列格式化为小写十六进制，如签名、哈希
+pub fn fmt_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 十六进制过长时省略中间部分，首尾各保留 `keep` 个字符：`fmt_hex_short(&hash, 4)` => `"a1b2…e5f6"`
+pub fn fmt_hex_short(bytes: &[u8], keep: usize) -> String {
+    let hex = fmt_hex(bytes);
+    if hex.len() <= keep * 2 {
+        return hex;
+    }
+    format!("{}…{}", &hex[..keep], &hex[hex.len() - keep..])
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// 字节序列格式化为标准 base64（带 `=` 填充）
+pub fn fmt_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Option 字节序列格式化为十六进制
+pub fn fmt_opt_hex<T: AsRef<[u8]>>(v: &Option<T>) -> String {
+    v.as_ref()
+        .map(|b| fmt_hex(b.as_ref()))
+        .unwrap_or_else(|| "null".to_string())
+}
+
+/// Option 字节序列格式化为 base64
+pub fn fmt_opt_base64<T: AsRef<[u8]>>(v: &Option<T>) -> String {
+    v.as_ref()
+        .map(|b| fmt_base64(b.as_ref()))
+        .unwrap_or_else(|| "null".to_string())
+}
+
+/// 比率格式化为百分比：`fmt_percent(0.025, 2)` => `"2.50%"`，负数带 `-`
+///
+/// NaN 输出 `"NaN"`，无穷大输出 `"inf%"` / `"-inf%"`。
+pub fn fmt_percent(ratio: f64, decimals: usize) -> String {
+    if ratio.is_nan() {
+        return "NaN".to_string();
+    }
+    if ratio.is_infinite() {
+        return format!("{}inf%", if ratio < 0.0 { "-" } else { "" });
+    }
+    format!("{:.*}%", decimals, ratio * 100.0)
+}
+
+/// 同 `fmt_percent`，总是带符号，适合涨跌幅：`+2.50%` / `-1.20%`；
+/// 为 0（含舍入后为 0）时输出 `+0.00%`，需要其他写法时用 `fmt_signed_percent_with`
+pub fn fmt_signed_percent(ratio: f64, decimals: usize) -> String {
+    fmt_signed_percent_with(ratio, decimals, "+")
+}
+
+/// 同 `fmt_signed_percent`，舍入后为 0 时以 `zero_sign` 作前缀，如 `""`（`0.00%`）或 `"±"`
+pub fn fmt_signed_percent_with(ratio: f64, decimals: usize, zero_sign: &str) -> String {
+    if ratio.is_nan() {
+        return "NaN".to_string();
+    }
+    if ratio.is_infinite() {
+        return format!("{}inf%", if ratio < 0.0 { "-" } else { "+" });
+    }
+    let digits = format!("{:.*}", decimals, (ratio * 100.0).abs());
+    let sign = if digits.bytes().all(|b| b == b'0' || b == b'.') {
+        zero_sign
+    } else if ratio < 0.0 {
+        "-"
+    } else {
+        "+"
+    };
+    format!("{}{}%", sign, digits)
+}
+
+/// Option<f64> 格式化为带符号的百分比，None 输出 `"null"`
+pub fn fmt_opt_signed_percent(v: &Option<f64>, decimals: usize) -> String {
+    v.map(|ratio| fmt_signed_percent(ratio, decimals))
+        .unwrap_or_else(|| "null".to_string())
+}
+
+/// 耗时格式化为带单位的短文本：`850µs`、`12.3ms`、`1.234s`、`2m03s`、`1h02m03s`
+///
+/// 先按小单位的精度四舍五入再选单位，999.96ms 写作 `1.000s`，59.9996s 写作 `1m00s`，
+/// 分钟及以上按秒四舍五入。
+pub fn fmt_duration(d: Duration) -> String {
+    let nanos = d.as_nanos();
+    let micros = (nanos + 500) / 1000;
+    let tenth_millis = (nanos + 50_000) / 100_000;
+    let millis = (nanos + 500_000) / 1_000_000;
+    if millis >= 60_000 {
+        let secs = (millis + 500) / 1000;
+        if secs >= 3600 {
+            format!("{}h{:02}m{:02}s", secs / 3600, secs % 3600 / 60, secs % 60)
+        } else {
+            format!("{}m{:02}s", secs / 60, secs % 60)
+        }
+    } else if tenth_millis >= 10_000 {
+        format!("{}.{:03}s", millis / 1000, millis % 1000)
+    } else if micros >= 1000 {
+        format!("{}.{}ms", tenth_millis / 10, tenth_millis % 10)
+    } else if nanos >= 1000 {
+        format!("{}µs", micros)
+    } else {
+        format!("{}ns", nanos)
+    }
+}
+
+/// 按 logfmt 拼接键值对：`key=value key2="value with space"`，键按字典序
+///
+/// 值为空或含空白、`=`、`"`、控制字符时加双引号，引号内的 `"`、`\` 转义为 `\"`、`\\`，
+/// 换行、制表、回车转义为 `\n`、`\t`、`\r`，其余控制字符转义为 `\uXXXX`；其他字符原样输出。
+/// 键中的空白、`=`、`"` 与控制字符替换为 `_`，空键写作 `_`。
+pub fn fmt_logfmt(map: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    for (key, value) in map {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        if key.is_empty() {
+            out.push('_');
+        }
+        out.extend(key.chars().map(|c| {
+            if c.is_whitespace() || c.is_control() || c == '=' || c == '"' {
+                '_'
+            } else {
+                c
+            }
+        }));
+        out.push('=');
+        let quote = value.is_empty()
+            || value
+                .contains(|c: char| c.is_whitespace() || c.is_control() || c == '=' || c == '"');
+        if quote {
+            push_logfmt_quoted(&mut out, value);
+        } else {
+            out.push_str(value);
+        }
+    }
+    out
+}
+
+fn push_logfmt_quoted(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// 比较两个 JSON 值，只返回发生变化的路径及新旧值：`{"qty": {"old": 10, "new": 15}}`
+///
+/// 路径以 `.` 连接对象键、`[i]` 表示数组下标，根本身变化时路径为 `$`；
+/// 新增的键只有 `new`，删除的键只有 `old`。数组按位置比较，长度变化另记为 `<路径>.len()`。
+/// 类型不同（如对象变为数字）时整体记为一处变化。
+pub fn diff_json(before: &Value, after: &Value) -> Value {
+    let mut changes = serde_json::Map::new();
+    diff_into(&mut changes, "", before, after);
+    Value::Object(changes)
+}
+
+fn diff_into(
+    changes: &mut serde_json::Map<String, Value>,
+    path: &str,
+    before: &Value,
+    after: &Value,
+) {
+    let child = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+    match (before, after) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                match new.get(key) {
+                    Some(new_value) => diff_into(changes, &child(key), old_value, new_value),
+                    None => {
+                        changes.insert(child(key), serde_json::json!({ "old": old_value }));
+                    }
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    changes.insert(child(key), serde_json::json!({ "new": new_value }));
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            let path = if path.is_empty() { "$" } else { path };
+            if old.len() != new.len() {
+                changes.insert(
+                    format!("{}.len()", path),
+                    serde_json::json!({ "old": old.len(), "new": new.len() }),
+                );
+            }
+            for i in 0..old.len().max(new.len()) {
+                let item = format!("{}[{}]", path, i);
+                match (old.get(i), new.get(i)) {
+                    (Some(old_item), Some(new_item)) => {
+                        diff_into(changes, &item, old_item, new_item)
+                    }
+                    (Some(old_item), None) => {
+                        changes.insert(item, serde_json::json!({ "old": old_item }));
+                    }
+                    (None, Some(new_item)) => {
+                        changes.insert(item, serde_json::json!({ "new": new_item }));
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if before != after => {
+            let path = if path.is_empty() { "$" } else { path };
+            changes.insert(
+                path.to_string(),
+                serde_json::json!({ "old": before, "new": after }),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// 序列化后比较，返回 `diff_json` 结果的 JSON 字符串，用于只记录状态变化的部分；序列化失败时返回 `"null"`
+#[cfg(feature = "broadcast")]
+pub fn fmt_diff<T: serde::Serialize>(before: &T, after: &T) -> String {
+    match (serde_json::to_value(before), serde_json::to_value(after)) {
+        (Ok(before), Ok(after)) => diff_json(&before, &after).to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+/// 字符在终端中占的列数：CJK 与 emoji 占两列，控制字符、组合字符与零宽字符占零列
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x00..=0x1F | 0x7F..=0x9F => 0,
+        0x0300..=0x036F | 0x200B..=0x200F | 0x20D0..=0x20FF | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// 字符串在终端中占的列数，见 `char_width`
+pub fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// `render_table` 的列
+#[cfg(feature = "broadcast")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Timestamp,
+    Level,
+    Target,
+    Message,
+    /// 结构化字段，字符串按原文显示，其他值按 JSON 显示，缺失时留空
+    Field(&'static str),
+}
+
+#[cfg(feature = "broadcast")]
+impl Column {
+    fn header(self) -> String {
+        match self {
+            Column::Timestamp => "TIMESTAMP".to_string(),
+            Column::Level => "LEVEL".to_string(),
+            Column::Target => "TARGET".to_string(),
+            Column::Message => "MESSAGE".to_string(),
+            Column::Field(name) => name.to_uppercase(),
+        }
+    }
+
+    /// 单元格文本，换行与制表符替换为空格
+    fn cell(
+        self,
+        entry: &crate::LogEntry,
+        timestamp: &dyn Fn(&crate::LogEntry) -> String,
+    ) -> String {
+        let text = match self {
+            Column::Timestamp => timestamp(entry),
+            Column::Level => entry.level.clone(),
+            Column::Target => entry.target.clone(),
+            Column::Message => entry.message.clone(),
+            Column::Field(name) => match entry.fields.get(name) {
+                Some(Value::String(s)) => s.clone(),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            },
+        };
+        text.chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect()
+    }
+}
+
+/// 列间距
+#[cfg(feature = "broadcast")]
+const TABLE_GAP: &str = "  ";
+
+/// 把查询结果渲染为按列对齐的文本表格，首行为表头，第二行为分隔线
+///
+/// 列宽按显示宽度（见 `display_width`）取表头与单元格的最大值；总宽超过 `max_width` 时
+/// 每次收窄当前最宽的一列，直到放得下或各列只剩一个字符，`max_width` 为 0 表示不限制。
+/// 放不下的单元格截断并以 `…` 结尾。
+#[cfg(feature = "broadcast")]
+pub fn render_table(entries: &[crate::LogEntry], columns: &[Column], max_width: usize) -> String {
+    render_table_styled(entries, columns, max_width, false)
+}
+
+/// 同 `render_table`，`ansi` 为 true 时按 `LogStyle` 的配色为级别列着色
+#[cfg(feature = "broadcast")]
+pub fn render_table_styled(
+    entries: &[crate::LogEntry],
+    columns: &[Column],
+    max_width: usize,
+    ansi: bool,
+) -> String {
+    render_rows(entries, columns, max_width, ansi, &|entry| {
+        entry.timestamp.to_string()
+    })
+}
+
+/// 同 `render_table_styled`，时间戳列按 `tz` 显示（见 `LogEntry::timestamp_in`）
+#[cfg(feature = "tz")]
+pub fn render_table_in(
+    entries: &[crate::LogEntry],
+    columns: &[Column],
+    max_width: usize,
+    ansi: bool,
+    tz: crate::timezone::Tz,
+) -> String {
+    render_rows(entries, columns, max_width, ansi, &|entry| {
+        entry.timestamp_in(tz)
+    })
+}
+
+#[cfg(feature = "broadcast")]
+fn render_rows(
+    entries: &[crate::LogEntry],
+    columns: &[Column],
+    max_width: usize,
+    ansi: bool,
+    timestamp: &dyn Fn(&crate::LogEntry) -> String,
+) -> String {
+    if columns.is_empty() {
+        return String::new();
+    }
+    let headers: Vec<String> = columns.iter().map(|c| c.header()).collect();
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|entry| columns.iter().map(|c| c.cell(entry, timestamp)).collect())
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| display_width(h)).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(display_width(cell));
+        }
+    }
+    let gaps = TABLE_GAP.len() * (columns.len() - 1);
+    if max_width > 0 {
+        while widths.iter().sum::<usize>() + gaps > max_width {
+            let Some(widest) = widths
+                .iter_mut()
+                .filter(|w| **w > 1)
+                .reduce(|a, b| if *b > *a { b } else { a })
+            else {
+                break;
+            };
+            *widest -= 1;
+        }
+    }
+
+    let mut out = String::new();
+    let separator: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+    for (i, row) in std::iter::once(&headers)
+        .chain(std::iter::once(&separator))
+        .chain(&rows)
+        .enumerate()
+    {
+        let mut line = String::new();
+        for (col, (cell, &width)) in row.iter().zip(&widths).enumerate() {
+            if col > 0 {
+                line.push_str(TABLE_GAP);
+            }
+            let (text, used) = fit_width(cell, width);
+            let color = (ansi && i >= 2 && columns[col] == Column::Level)
+                .then(|| cell.parse::<tracing::Level>().ok())
+                .flatten()
+                .map(|level| crate::LogStyle::color(&level));
+            match color {
+                Some(color) => {
+                    line.push_str(color);
+                    line.push_str(&text);
+                    line.push_str(crate::style::ANSI_RESET);
+                }
+                None => line.push_str(&text),
+            }
+            line.extend(std::iter::repeat_n(' ', width - used));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// 截断到不超过 `width` 列，被截断时以 `…` 结尾；返回文本及其实际宽度
+#[cfg(feature = "broadcast")]
+fn fit_width(text: &str, width: usize) -> (String, usize) {
+    let total = display_width(text);
+    if total <= width {
+        return (text.to_string(), total);
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = char_width(c);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    if width > 0 {
+        out.push('…');
+        used += 1;
+    }
+    (out, used)
+}
+
+/// 以 key => value 形式输出结构化日志，可选在字段前给出消息：
+/// `trace_kv!(info, "operation completed"; "id" => id, "qty" => qty)`
 #[macro_export]
 macro_rules! trace_kv {
+    ($level:ident, $msg:literal; $( $key:expr => $val:expr ),+ $(,)?) => {
+        tracing::$level!( $( $key = ?$val ),+ , $msg );
+    };
     ($level:ident, $( $key:expr => $val:expr ),+ $(,)?) => {
         tracing::$level!( $( $key = ?$val ),+ );
     };
 }
 
+/// 同 `trace_kv!`，级别在运行时给出（`tracing::Level`）：
+/// `trace_kv_at!(level, "retrying"; "attempt" => n)`
+///
+/// 按级别分派到五个静态级别的分支；被 `max_level_*` feature 静态关闭的分支编译为空，
+/// 运行时传入这些级别时不做任何事，字段表达式也不会求值。
+#[macro_export]
+macro_rules! trace_kv_at {
+    ($level:expr, $($rest:tt)+) => {{
+        let level: tracing::Level = $level;
+        if level == tracing::Level::ERROR {
+            $crate::trace_kv!(error, $($rest)+);
+        } else if level == tracing::Level::WARN {
+            $crate::trace_kv!(warn, $($rest)+);
+        } else if level == tracing::Level::INFO {
+            $crate::trace_kv!(info, $($rest)+);
+        } else if level == tracing::Level::DEBUG {
+            $crate::trace_kv!(debug, $($rest)+);
+        } else {
+            $crate::trace_kv!(trace, $($rest)+);
+        }
+    }};
+}
+
+/// 只记录前后两个状态的差异，字段名为 `diff`（见 `fmt_diff`）：
+/// `trace_diff!(info, "position updated", &before, &after)`
+#[cfg(feature = "broadcast")]
+#[macro_export]
+macro_rules! trace_diff {
+    ($level:ident, $msg:literal, $before:expr, $after:expr $(,)?) => {
+        tracing::$level!(diff = %$crate::tracing_utils::fmt_diff($before, $after), $msg);
+    };
+}
+
+/// `log_scope!` 返回的 guard：创建时记录 `enter`，drop 时记录 `leave` 及耗时
+///
+/// 两条事件都在创建时的当前 span 下，guard 被移出该 span 后 drop 也一样。
+#[must_use = "the leave event is logged when the guard is dropped"]
+pub struct LogScope {
+    level: tracing::Level,
+    name: &'static str,
+    start: Instant,
+    span: tracing::Span,
+    emit: fn(tracing::Level, &'static str, Option<Duration>),
+}
+
+impl LogScope {
+    /// 由 `log_scope!` 调用，`emit` 在调用处展开，事件的 target 与位置为调用处
+    #[doc(hidden)]
+    pub fn enter(
+        level: tracing::Level,
+        name: &'static str,
+        emit: fn(tracing::Level, &'static str, Option<Duration>),
+    ) -> Self {
+        emit(level, name, None);
+        Self {
+            level,
+            name,
+            start: Instant::now(),
+            span: tracing::Span::current(),
+            emit,
+        }
+    }
+}
+
+impl Drop for LogScope {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        self.span
+            .in_scope(|| (self.emit)(self.level, self.name, Some(elapsed)));
+    }
+}
+
+/// 在函数开头写一行即可记录进入与离开：
+/// `let _scope = log_scope!(tracing::Level::DEBUG, "place_order");`
+///
+/// 进入时输出 `enter`，guard drop 时输出 `leave`，均带 `scope` 字段；`leave` 另带
+/// `elapsed`（见 `fmt_duration`），因 panic 展开而离开时带 `panicked = true`。
+/// 级别在运行时给出，分派方式同 `trace_kv_at!`。
+#[macro_export]
+macro_rules! log_scope {
+    ($level:expr, $name:expr $(,)?) => {
+        $crate::tracing_utils::LogScope::enter(
+            $level,
+            $name,
+            |level: tracing::Level,
+             scope: &'static str,
+             elapsed: ::std::option::Option<::std::time::Duration>| {
+                if level == tracing::Level::ERROR {
+                    $crate::__log_scope_event!(tracing::Level::ERROR, scope, elapsed);
+                } else if level == tracing::Level::WARN {
+                    $crate::__log_scope_event!(tracing::Level::WARN, scope, elapsed);
+                } else if level == tracing::Level::INFO {
+                    $crate::__log_scope_event!(tracing::Level::INFO, scope, elapsed);
+                } else if level == tracing::Level::DEBUG {
+                    $crate::__log_scope_event!(tracing::Level::DEBUG, scope, elapsed);
+                } else {
+                    $crate::__log_scope_event!(tracing::Level::TRACE, scope, elapsed);
+                }
+            },
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_scope_event {
+    ($level:expr, $scope:expr, $elapsed:expr) => {
+        match $elapsed {
+            None => tracing::event!($level, scope = $scope, "enter"),
+            Some(elapsed) if ::std::thread::panicking() => tracing::event!(
+                $level,
+                scope = $scope,
+                elapsed = %$crate::tracing_utils::fmt_duration(elapsed),
+                panicked = true,
+                "leave"
+            ),
+            Some(elapsed) => tracing::event!(
+                $level,
+                scope = $scope,
+                elapsed = %$crate::tracing_utils::fmt_duration(elapsed),
+                "leave"
+            ),
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::NaiveDate;
     use serde_json::json;
-    use crate::setup_tracing;
-    use crate::tracing_utils::{fmt_json_value, fmt_naive_date};
+    use crate::tracing_utils::{
+        diff_json, epoch_millis_to_rfc3339, fmt_base64, fmt_display_or, fmt_duration, fmt_first,
+        fmt_hex, fmt_hex_short, fmt_json_value, fmt_json_value_pretty, fmt_logfmt, fmt_naive_date,
+        fmt_opt_base64, fmt_opt_display_or, fmt_opt_hex, fmt_opt_signed_percent, fmt_percent,
+        fmt_signed_percent, fmt_signed_percent_with, rfc3339_to_epoch_millis,
+    };
+    use std::time::Duration;
 
-    #[tokio::test]
-    async fn test_get_coin_data() {
-        setup_tracing();
+    #[test]
+    fn test_get_coin_data() {
+        #[cfg(feature = "setup")]
+        crate::setup_tracing();
 
         // 模拟 genesis_date
         let genesis_date = Some(NaiveDate::from_ymd_opt(2020, 5, 1).unwrap());
@@ -75,5 +757,428 @@ mod tests {
        );
     }
 
-}
+    #[test]
+    fn test_epoch_millis_conversion() {
+        let ms = 1_717_200_000_123;
+        assert_eq!(rfc3339_to_epoch_millis("2024-06-01T00:00:00.123+00:00"), Some(ms));
+        assert_eq!(rfc3339_to_epoch_millis("2024-06-01T08:00:00.123+08:00"), Some(ms));
+        assert_eq!(epoch_millis_to_rfc3339(ms), "2024-06-01T00:00:00.123+00:00");
+        assert_eq!(rfc3339_to_epoch_millis(&epoch_millis_to_rfc3339(-1)), Some(-1));
+
+        assert_eq!(rfc3339_to_epoch_millis("2024-06-01 00:00:00"), None);
+        assert_eq!(rfc3339_to_epoch_millis(""), None);
+        assert_eq!(epoch_millis_to_rfc3339(i64::MAX), "");
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let before = json!({
+            "symbol": "BTC",
+            "qty": 10,
+            "risk": {"limit": 100, "tags": ["a", "b"]},
+            "legacy": true,
+            "fills": [1, 2],
+        });
+        let after = json!({
+            "symbol": "BTC",
+            "qty": 15,
+            "risk": {"limit": 100, "tags": ["a", "c"]},
+            "note": "rebalanced",
+            "fills": [1, 2, 3],
+        });
+        assert_eq!(
+            diff_json(&before, &after),
+            json!({
+                "qty": {"old": 10, "new": 15},
+                "risk.tags[1]": {"old": "b", "new": "c"},
+                "legacy": {"old": true},
+                "note": {"new": "rebalanced"},
+                "fills.len()": {"old": 2, "new": 3},
+                "fills[2]": {"new": 3},
+            })
+        );
+
+        // 类型变化整体记为一处
+        assert_eq!(
+            diff_json(&json!({"price": {"bid": 1}}), &json!({"price": 1.5})),
+            json!({"price": {"old": {"bid": 1}, "new": 1.5}})
+        );
+        assert_eq!(
+            diff_json(&json!(1), &json!("1")),
+            json!({"$": {"old": 1, "new": "1"}})
+        );
+        assert_eq!(diff_json(&before, &before), json!({}));
+    }
+
+    #[cfg(feature = "broadcast")]
+    #[tokio::test]
+    async fn test_trace_diff() {
+        skip_if_events_compiled_out!();
+        use crate::{BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
 
+        #[derive(serde::Serialize)]
+        struct Position {
+            symbol: &'static str,
+            qty: u32,
+        }
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::Registry::default().with(layer));
+
+        let before = Position {
+            symbol: "BTC",
+            qty: 10,
+        };
+        let after = Position {
+            symbol: "BTC",
+            qty: 15,
+        };
+        trace_diff!(info, "position updated", &before, &after);
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 1).await;
+        assert_eq!(logs[0].message, "position updated");
+        assert_eq!(logs[0].fields["diff"], r#"{"qty":{"new":15,"old":10}}"#);
+    }
+
+    #[test]
+    fn test_fmt_first() {
+        let (none, alice, bob): (Option<&str>, _, _) = (None, Some("alice"), Some("bob"));
+        assert_eq!(fmt_first(&[&none, &none]), "null");
+        assert_eq!(fmt_first::<u32>(&[]), "null");
+        assert_eq!(fmt_first(&[&alice, &none, &bob]), "alice");
+        assert_eq!(fmt_first(&[&none, &none, &bob]), "bob");
+    }
+
+    #[test]
+    fn test_fmt_display_or() {
+        struct Tags(Vec<&'static str>);
+        impl std::fmt::Display for Tags {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0.join(","))
+            }
+        }
+
+        assert_eq!(fmt_display_or(&Tags(vec![]), "<none>"), "<none>");
+        assert_eq!(fmt_display_or(&Tags(vec!["a", "b"]), "<none>"), "a,b");
+        assert_eq!(fmt_display_or(&"", "-"), "-");
+        assert_eq!(fmt_opt_display_or(&Some(Tags(vec![])), "<none>"), "<none>");
+        assert_eq!(fmt_opt_display_or(&Some(Tags(vec!["a"])), "<none>"), "a");
+        assert_eq!(fmt_opt_display_or(&None::<Tags>, "<none>"), "<none>");
+    }
+
+    #[test]
+    fn test_fmt_json_value_pretty() {
+        let nested = Some(json!({"order": {"id": 7, "tags": ["a"]}}));
+        let pretty = fmt_json_value_pretty(&nested);
+        assert!(pretty.contains('\n'));
+        assert!(pretty.contains("\n    \"id\": 7"));
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&pretty).ok(), nested);
+        assert_eq!(fmt_json_value(&nested), r#"{"order":{"id":7,"tags":["a"]}}"#);
+        assert_eq!(fmt_json_value_pretty(&None), "null");
+    }
+
+    #[test]
+    fn test_fmt_logfmt() {
+        let map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(fmt_logfmt(&map(&[])), "");
+        assert_eq!(
+            fmt_logfmt(&map(&[("qty", "5"), ("id", "42"), ("side", "buy")])),
+            "id=42 qty=5 side=buy"
+        );
+        assert_eq!(
+            fmt_logfmt(&map(&[
+                ("msg", "order filled"),
+                ("expr", "a=b"),
+                ("note", "say \"hi\""),
+                ("empty", ""),
+                ("path", "C:\\tmp"),
+            ])),
+            r#"empty="" expr="a=b" msg="order filled" note="say \"hi\"" path=C:\tmp"#
+        );
+        for (value, expected) in [
+            ("say \"hi\"", r#"v="say \"hi\"""#),
+            ("C:\\tmp dir", r#"v="C:\\tmp dir""#),
+            ("line1\nline2", r#"v="line1\nline2""#),
+            ("a\tb", r#"v="a\tb""#),
+            ("a\r\n", r#"v="a\r\n""#),
+            ("bell\u{7}", r#"v="bell\u0007""#),
+            ("esc \u{1b}[31m", r#"v="esc \u001b[31m""#),
+            // 非控制字符的 Unicode 原样输出，不像 `{:?}` 那样转义
+            ("naïve\u{200b}", "v=naïve\u{200b}"),
+        ] {
+            assert_eq!(fmt_logfmt(&map(&[("v", value)])), expected, "{:?}", value);
+        }
+        for (key, expected) in [
+            ("user id", "user_id=1"),
+            ("a=b", "a_b=1"),
+            ("say\"x", "say_x=1"),
+            ("tab\there", "tab_here=1"),
+            ("", "_=1"),
+        ] {
+            assert_eq!(fmt_logfmt(&map(&[(key, "1")])), expected, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn test_fmt_bytes() {
+        assert_eq!(fmt_hex(&[]), "");
+        assert_eq!(fmt_base64(&[]), "");
+        assert_eq!(fmt_hex(&[0x00, 0x0f, 0xab, 0xff]), "000fabff");
+        // RFC 4648 测试向量
+        for (input, encoded) in [
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(fmt_base64(input.as_bytes()), encoded);
+        }
+        assert_eq!(fmt_base64(&[0xfb, 0xff]), "+/8=");
+
+        let hash = [0xa1, 0xb2, 0xc3, 0xd4, 0xe5, 0xf6];
+        assert_eq!(fmt_hex_short(&hash, 4), "a1b2…e5f6");
+        assert_eq!(fmt_hex_short(&hash, 6), "a1b2c3d4e5f6");
+        assert_eq!(fmt_hex_short(&[], 4), "");
+
+        assert_eq!(fmt_opt_hex(&Some(vec![0xde, 0xad])), "dead");
+        assert_eq!(fmt_opt_hex(&None::<Vec<u8>>), "null");
+        assert_eq!(fmt_opt_base64(&Some([0xfb, 0xff])), "+/8=");
+        assert_eq!(fmt_opt_base64(&None::<&[u8]>), "null");
+    }
+
+    #[test]
+    fn test_fmt_signed_percent() {
+        assert_eq!(fmt_percent(0.025, 2), "2.50%");
+        assert_eq!(fmt_percent(-0.012, 1), "-1.2%");
+        assert_eq!(fmt_signed_percent(0.025, 2), "+2.50%");
+        assert_eq!(fmt_signed_percent(-0.012, 2), "-1.20%");
+        assert_eq!(fmt_signed_percent(1.5, 0), "+150%");
+        // 0、-0 以及舍入后为 0 的值
+        assert_eq!(fmt_signed_percent(0.0, 2), "+0.00%");
+        assert_eq!(fmt_signed_percent(-0.0, 2), "+0.00%");
+        assert_eq!(fmt_signed_percent(-0.00001, 2), "+0.00%");
+        assert_eq!(fmt_signed_percent_with(0.0, 2, ""), "0.00%");
+        assert_eq!(fmt_signed_percent_with(-0.0001, 1, "±"), "±0.0%");
+        assert_eq!(fmt_signed_percent_with(0.5, 1, ""), "+50.0%");
+
+        assert_eq!(fmt_signed_percent(f64::NAN, 2), "NaN");
+        assert_eq!(fmt_signed_percent(f64::INFINITY, 2), "+inf%");
+        assert_eq!(fmt_signed_percent(f64::NEG_INFINITY, 2), "-inf%");
+        assert_eq!(fmt_percent(f64::NAN, 2), "NaN");
+        assert_eq!(fmt_opt_signed_percent(&Some(-0.5), 1), "-50.0%");
+        assert_eq!(fmt_opt_signed_percent(&None, 1), "null");
+    }
+
+    #[cfg(feature = "broadcast")]
+    #[tokio::test]
+    async fn test_trace_kv_with_message() {
+        skip_if_events_compiled_out!();
+        use crate::{BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::Registry::default().with(layer));
+
+        trace_kv!(info, "operation completed"; "id" => "data_id", "qty" => 3);
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 1).await;
+        assert_eq!(logs[0].message, "operation completed");
+        assert_eq!(logs[0].fields["id"], "\"data_id\"");
+        assert_eq!(logs[0].fields["qty"], "3");
+    }
+
+    #[cfg(feature = "broadcast")]
+    #[tokio::test]
+    async fn test_trace_kv_at_dynamic_level() {
+        skip_if_events_compiled_out!();
+        use crate::{BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::Registry::default().with(layer));
+
+        for level in [tracing::Level::WARN, tracing::Level::DEBUG] {
+            trace_kv_at!(level, "retrying"; "attempt" => 2);
+        }
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        let levels: Vec<&str> = logs.iter().map(|log| log.level.as_str()).collect();
+        assert_eq!(levels, ["WARN", "DEBUG"]);
+        assert_eq!(logs[1].fields["attempt"], "2");
+    }
+
+    #[cfg(feature = "broadcast")]
+    #[test]
+    fn test_render_table() {
+        use crate::tracing_utils::{display_width, render_table, render_table_styled, Column};
+        #[cfg(feature = "tz")]
+        use crate::tracing_utils::{fmt_naive_datetime_tz, render_table_in};
+        use crate::LogEntry;
+
+        let entry = |level: &str, target: &str, message: &str, symbol: Option<serde_json::Value>| {
+            LogEntry {
+                timestamp: "2024-06-01T12:00:00+00:00".into(),
+                level: level.to_string(),
+                target: target.to_string(),
+                message: message.to_string(),
+                fields: symbol
+                    .map(|s| [("symbol".to_string(), s)].into())
+                    .unwrap_or_default(),
+                ..Default::default()
+            }
+        };
+        let entries = [
+            entry("INFO", "order", "订单已成交", Some(json!("BTC"))),
+            entry("WARN", "国际化", "🚀 launch\nsecond line", Some(json!(42))),
+            entry("ERROR", "order::risk", &"x".repeat(80), None),
+        ];
+        assert_eq!(display_width("订单🚀a\u{0301}"), 7);
+
+        let columns = [Column::Level, Column::Target, Column::Field("symbol"), Column::Message];
+        // 不限宽度：CJK 按两列对齐，换行替换为空格
+        let wide = render_table(&entries[..2], &columns, 0);
+        assert_eq!(
+            wide,
+            "LEVEL  TARGET  SYMBOL  MESSAGE\n\
+             -----  ------  ------  ---------------------\n\
+             INFO   order   BTC     订单已成交\n\
+             WARN   国际化  42      🚀 launch second line\n"
+        );
+        // 超长 message 所在的列先被收窄
+        let narrow = render_table(&entries, &columns, 40);
+        assert_eq!(
+            narrow,
+            "LEVEL  TARGET       SYMBOL  MESSAGE\n\
+             -----  -----------  ------  ------------\n\
+             INFO   order        BTC     订单已成交\n\
+             WARN   国际化       42      🚀 launch s…\n\
+             ERROR  order::risk          xxxxxxxxxxx…\n"
+        );
+        assert!(narrow.lines().all(|line| display_width(line) <= 40));
+        // 截断不会切开双宽字符
+        let cjk = render_table(&entries[..1], &[Column::Message], 6);
+        assert_eq!(cjk, "MESSA…\n------\n订单…\n");
+
+        let columns = [Column::Timestamp, Column::Level];
+        let colored = render_table_styled(&entries[..1], &columns, 0, true);
+        assert_eq!(
+            colored,
+            "TIMESTAMP                  LEVEL\n\
+             -------------------------  -----\n\
+             2024-06-01T12:00:00+00:00  \x1b[32mINFO\x1b[0m\n"
+        );
+        assert_eq!(render_table(&entries, &[], 80), "");
+
+        #[cfg(feature = "tz")]
+        {
+            let tz = crate::timezone::Tz::America__New_York;
+            let zoned = render_table_in(&entries[..1], &columns, 0, false, tz);
+            assert!(zoned.ends_with("2024-06-01T08:00:00-04:00  INFO\n"), "{}", zoned);
+            let naive = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(12, 0, 0);
+            assert_eq!(fmt_naive_datetime_tz(&naive, tz), "2024-01-15 07:00:00 -05:00");
+            assert_eq!(fmt_naive_datetime_tz(&None, tz), "null");
+        }
+    }
+
+    /// `cargo test --features max_level_info static_max_level`
+    #[cfg(all(feature = "broadcast", feature = "max_level_info"))]
+    #[tokio::test]
+    async fn test_static_max_level_compiles_out() {
+        use crate::{BroadcastLogLayer, LogCache};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tracing::level_filters::{LevelFilter, STATIC_MAX_LEVEL};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        // 同时启用了更严格的 feature（如 `--all-features`）时跳过
+        if STATIC_MAX_LEVEL != LevelFilter::INFO {
+            return;
+        }
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::Registry::default().with(layer));
+        let evaluated = AtomicUsize::new(0);
+        let probe = || evaluated.fetch_add(1, Ordering::Relaxed);
+
+        tracing::debug!("debug");
+        trace_kv!(trace, "trace"; "probe" => probe());
+        trace_kv_at!(tracing::Level::DEBUG, "dynamic debug"; "probe" => probe());
+        trace_kv_at!(tracing::Level::INFO, "dynamic info"; "probe" => probe());
+        tracing::info!("info");
+
+        let logs = crate::broadcast::tests::wait_for_cache(&cache, 2).await;
+        let messages: Vec<&str> = logs.iter().map(|log| log.message.as_str()).collect();
+        assert_eq!(messages, ["dynamic info", "info"]);
+        assert_eq!(evaluated.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_fmt_duration() {
+        assert_eq!(fmt_duration(Duration::from_nanos(420)), "420ns");
+        assert_eq!(fmt_duration(Duration::from_micros(850)), "850µs");
+        assert_eq!(fmt_duration(Duration::from_micros(12_345)), "12.3ms");
+        assert_eq!(fmt_duration(Duration::from_millis(1_234)), "1.234s");
+        assert_eq!(fmt_duration(Duration::from_secs(123)), "2m03s");
+        assert_eq!(fmt_duration(Duration::from_secs(3_723)), "1h02m03s");
+        // 四舍五入后进位到更大的单位
+        assert_eq!(fmt_duration(Duration::from_micros(999_960)), "1.000s");
+        assert_eq!(fmt_duration(Duration::from_micros(59_999_600)), "1m00s");
+        assert_eq!(fmt_duration(Duration::from_micros(999_940)), "999.9ms");
+        assert_eq!(fmt_duration(Duration::from_micros(59_999_400)), "59.999s");
+        assert_eq!(fmt_duration(Duration::from_micros(119_999_600)), "2m00s");
+        assert_eq!(
+            fmt_duration(Duration::from_micros(3_599_999_600)),
+            "1h00m00s"
+        );
+        assert_eq!(fmt_duration(Duration::from_nanos(999_960)), "1.0ms");
+        assert_eq!(fmt_duration(Duration::from_nanos(1_500)), "2µs");
+    }
+
+    #[cfg(feature = "broadcast")]
+    #[tokio::test]
+    async fn test_log_scope() {
+        skip_if_events_compiled_out!();
+        use crate::broadcast::tests::wait_for_cache;
+        use crate::{attach_cache_to_span, BroadcastLogLayer, LogCache};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone()).without_persistence();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::Registry::default().with(layer));
+
+        let span = tracing::info_span!("request");
+        let span_cache = LogCache::default();
+        assert!(attach_cache_to_span(&span, span_cache.clone()));
+        let scope = span.in_scope(|| crate::log_scope!(tracing::Level::DEBUG, "place_order"));
+        tracing::info!("outside");
+        // 离开 span 后 drop，leave 仍记在 span 下
+        drop(scope);
+
+        let logs = wait_for_cache(&cache, 3).await;
+        let messages: Vec<_> = logs.iter().map(|log| log.message.as_str()).collect();
+        assert_eq!(messages, ["enter", "outside", "leave"]);
+        assert_eq!(logs[0].level, "DEBUG");
+        assert_eq!(logs[0].target, module_path!());
+        assert_eq!(logs[2].fields["scope"], "place_order");
+        assert!(logs[2].fields["elapsed"].as_str().unwrap().ends_with('s'));
+        let scoped = wait_for_cache(&span_cache, 2).await;
+        let scoped: Vec<_> = scoped.iter().map(|log| log.message.as_str()).collect();
+        assert_eq!(scoped, ["enter", "leave"]);
+    }
+}
@@ -0,0 +1,320 @@
+//! 错误后临时提高捕获级别
+//!
+//! 平时只收录不低于 `base`（如 INFO）的事件；一条 ERROR 经过后打开一个时间窗口，
+//! 窗口内不低于 `verbose`（默认 TRACE）的低级别事件也进入广播 / 缓存 / 持久化，
+//! 窗口内再次出现的 ERROR 会把窗口顺延。窗口可以是全局的，也可以只覆盖与触发的 ERROR
+//! 同一 target 前缀的事件，见 `VerbosityScope`。
+//!
+//! 这是 Layer 自己的级别门槛，代替 `with_min_level`：全局过滤指令须放行到 `verbose` 级别，
+//! 否则低级别事件根本到不了 Layer。窗口打开时在 ERROR 之后输出一条 `VERBOSE_TARGET` 标记，
+//! 结束后（在下一条事件到达时）输出另一条，带上窗口内额外收录的条数；
+//! 打开次数与额外条数另计入 `LogStats::verbose_windows` / `verbose_entries`。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+use tracing::Level;
+
+use crate::clock::{monotonic_ns, Clock};
+use crate::escalation::serialize_level;
+use crate::routing::target_has_prefix;
+use crate::tracing_utils::epoch_millis_to_rfc3339;
+use crate::{LogEntry, LogStats, LOG_ENTRY_SCHEMA};
+
+/// 窗口打开 / 关闭标记使用的 target
+pub const VERBOSE_TARGET: &str = "listen_tracing::verbose";
+
+/// 默认窗口长度
+pub const DEFAULT_VERBOSE_WINDOW: Duration = Duration::from_secs(10);
+
+/// 窗口覆盖的范围
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerbosityScope {
+    /// 所有 target
+    #[default]
+    Global,
+    /// 与触发的 ERROR 的 target 前 N 段（按 `::` 分隔）相同的 target 及其子模块，
+    /// `Prefix(1)` 即同一 crate；不同前缀的 ERROR 各自打开窗口
+    Prefix(usize),
+}
+
+impl VerbosityScope {
+    /// `target` 触发的窗口所覆盖的前缀，全局时为 None
+    fn prefix(self, target: &str) -> Option<String> {
+        match self {
+            VerbosityScope::Global => None,
+            VerbosityScope::Prefix(n) => Some(
+                target
+                    .split("::")
+                    .take(n.max(1))
+                    .collect::<Vec<_>>()
+                    .join("::"),
+            ),
+        }
+    }
+}
+
+/// 自适应捕获级别的配置，见模块文档
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AdaptiveVerbosity {
+    /// 平时的最低级别
+    #[serde(serialize_with = "serialize_level")]
+    pub base: Level,
+    /// 窗口内的最低级别
+    #[serde(serialize_with = "serialize_level")]
+    pub verbose: Level,
+    pub window: Duration,
+    pub scope: VerbosityScope,
+}
+
+impl AdaptiveVerbosity {
+    /// 平时收录不低于 `base` 的事件，ERROR 后 `DEFAULT_VERBOSE_WINDOW` 内全局收录所有级别
+    pub fn new(base: Level) -> Self {
+        Self {
+            base,
+            verbose: Level::TRACE,
+            window: DEFAULT_VERBOSE_WINDOW,
+            scope: VerbosityScope::Global,
+        }
+    }
+
+    pub fn with_verbose(mut self, level: Level) -> Self {
+        self.verbose = level;
+        self
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_scope(mut self, scope: VerbosityScope) -> Self {
+        self.scope = scope;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Window {
+    prefix: Option<String>,
+    until: Instant,
+    captured: u64,
+}
+
+impl Window {
+    fn covers(&self, target: &str) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| target_has_prefix(target, prefix))
+    }
+}
+
+/// 一条事件经过门槛后的结果
+#[derive(Debug, Default)]
+pub(crate) struct Admission {
+    pub(crate) admitted: bool,
+    /// 已结束窗口的关闭标记，应在该事件之前输出
+    pub(crate) closed: Vec<LogEntry>,
+    /// 该事件打开了新窗口时的标记，应在该事件之后输出
+    pub(crate) opened: Option<LogEntry>,
+}
+
+/// 按 `AdaptiveVerbosity` 判断事件是否收录，并维护打开中的窗口
+#[derive(Debug)]
+pub(crate) struct VerbosityGate {
+    config: AdaptiveVerbosity,
+    /// 是否有尚未关闭的窗口，没有时不取锁
+    active: AtomicBool,
+    windows: Mutex<Vec<Window>>,
+}
+
+impl VerbosityGate {
+    pub(crate) fn new(config: AdaptiveVerbosity) -> Self {
+        Self {
+            config,
+            active: AtomicBool::new(false),
+            windows: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn config(&self) -> &AdaptiveVerbosity {
+        &self.config
+    }
+
+    pub(crate) fn admit(
+        &self,
+        target: &str,
+        level: Level,
+        clock: &impl Clock,
+        stats: &LogStats,
+    ) -> Admission {
+        let mut admission = Admission::default();
+        let base = level <= self.config.base;
+        // 平时的非 ERROR 事件不碰锁
+        if !self.active.load(Ordering::Relaxed) && level != Level::ERROR {
+            admission.admitted = base;
+            return admission;
+        }
+        let now = clock.instant();
+        let mut windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+        windows.retain(|window| {
+            if window.until > now {
+                return true;
+            }
+            admission.closed.push(marker(
+                clock,
+                "verbose capture window closed",
+                window.prefix.as_deref(),
+                [("captured", json!(window.captured))],
+            ));
+            false
+        });
+        if level == Level::ERROR {
+            let prefix = self.config.scope.prefix(target);
+            let until = now + self.config.window;
+            match windows.iter_mut().find(|window| window.prefix == prefix) {
+                Some(window) => window.until = until,
+                None => {
+                    stats.record_verbose_window();
+                    admission.opened = Some(marker(
+                        clock,
+                        "verbose capture window opened",
+                        prefix.as_deref(),
+                        [
+                            ("trigger_target", json!(target)),
+                            ("window_ms", json!(self.config.window.as_millis() as u64)),
+                            ("verbose_level", json!(self.config.verbose.as_str())),
+                        ],
+                    ));
+                    windows.push(Window {
+                        prefix,
+                        until,
+                        captured: 0,
+                    });
+                }
+            }
+            admission.admitted = true;
+        } else if base {
+            admission.admitted = true;
+        } else if level <= self.config.verbose {
+            if let Some(window) = windows.iter_mut().find(|window| window.covers(target)) {
+                window.captured += 1;
+                stats.record_verbose_entry();
+                admission.admitted = true;
+            }
+        }
+        self.active.store(!windows.is_empty(), Ordering::Relaxed);
+        admission
+    }
+}
+
+fn marker<const N: usize>(
+    clock: &impl Clock,
+    message: &str,
+    prefix: Option<&str>,
+    fields: [(&str, serde_json::Value); N],
+) -> LogEntry {
+    LogEntry {
+        schema: LOG_ENTRY_SCHEMA,
+        timestamp: epoch_millis_to_rfc3339(clock.now().timestamp_millis()).into(),
+        level: "INFO".to_string(),
+        target: VERBOSE_TARGET.to_string(),
+        message: message.to_string(),
+        fields: [("scope", json!(prefix.unwrap_or("*")))]
+            .into_iter()
+            .chain(fields)
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+        mono_ns: Some(monotonic_ns()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::tests::wait_for_cache;
+    use crate::clock::MockClock;
+    use crate::{BroadcastLogLayer, LogCache};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[tokio::test]
+    async fn test_error_opens_verbose_window() {
+        skip_if_events_compiled_out!();
+        let clock = MockClock::new(chrono::Utc::now());
+        let (tx, _rx) = tokio::sync::broadcast::channel(32);
+        let cache = LogCache::default();
+        let layer = BroadcastLogLayer::new(tx, cache.clone())
+            .without_persistence()
+            .with_clock(clock.clone())
+            .with_adaptive_verbosity(
+                AdaptiveVerbosity::new(Level::INFO)
+                    .with_verbose(Level::DEBUG)
+                    .with_window(Duration::from_secs(10)),
+            );
+        let stats = layer.stats();
+        let _guard = tracing::subscriber::set_default(Registry::default().with(layer));
+
+        tracing::debug!("before");
+        tracing::info!("steady");
+        tracing::error!("failed");
+        tracing::debug!("context");
+        tracing::trace!("too low");
+        clock.advance(Duration::from_secs(6));
+        tracing::error!("failed again");
+        clock.advance(Duration::from_secs(6));
+        // 第二条 ERROR 把窗口顺延到 16 秒
+        tracing::debug!("still open");
+        clock.advance(Duration::from_secs(5));
+        tracing::debug!("after");
+
+        let logs = wait_for_cache(&cache, 7).await;
+        let messages: Vec<_> = logs.iter().map(|log| log.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "steady",
+                "failed",
+                "verbose capture window opened",
+                "context",
+                "failed again",
+                "still open",
+                "verbose capture window closed",
+            ]
+        );
+        assert_eq!(logs[2].target, VERBOSE_TARGET);
+        assert_eq!(logs[2].fields["scope"], json!("*"));
+        assert_eq!(logs[6].fields["captured"], json!(2));
+        let snapshot = stats.snapshot();
+        assert_eq!((snapshot.verbose_windows, snapshot.verbose_entries), (1, 2));
+    }
+
+    #[test]
+    fn test_prefix_scope() {
+        let clock = MockClock::new(chrono::Utc::now());
+        let stats = LogStats::default();
+        let gate = VerbosityGate::new(
+            AdaptiveVerbosity::new(Level::WARN).with_scope(VerbosityScope::Prefix(1)),
+        );
+        let admit = |target, level| gate.admit(target, level, &clock, &stats);
+
+        assert!(!admit("bot::executor", Level::DEBUG).admitted);
+        let opened = admit("bot::executor", Level::ERROR).opened.unwrap();
+        assert_eq!(opened.fields["scope"], json!("bot"));
+        assert!(admit("bot::risk", Level::DEBUG).admitted);
+        assert!(admit("bot", Level::TRACE).admitted);
+        assert!(!admit("botx", Level::DEBUG).admitted);
+        assert!(!admit("hyper::client", Level::DEBUG).admitted);
+
+        clock.advance(DEFAULT_VERBOSE_WINDOW);
+        let after = admit("bot::risk", Level::DEBUG);
+        assert!(!after.admitted);
+        assert_eq!(after.closed.len(), 1);
+        assert_eq!(after.closed[0].fields["captured"], json!(2));
+    }
+}
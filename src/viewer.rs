@@ -0,0 +1,111 @@
+//! 零依赖的浏览器日志查看页
+//!
+//! 本 crate 不依赖任何 HTTP 框架，页面以常量提供，由调用方的服务挂到 `/logs/view`。
+//! 页面假定同源下有两个端点：
+//! - `GET /logs?level=&keyword=&page_size=`：返回 `store::LogPage` 的 JSON
+//! - `GET /logs/stream`：SSE，每条消息的 data 为一条 `LogEntry` 的 JSON
+
+/// 查看页的 Content-Type
+pub const LOG_VIEWER_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+
+/// 查看页 HTML：加载最近的日志，并通过 SSE 实时追加；支持级别筛选与关键字搜索
+pub const LOG_VIEWER_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>listen-tracing logs</title>
+<style>
+  body { font: 13px/1.4 ui-monospace, Menlo, Consolas, monospace; margin: 0; background: #111; color: #ddd; }
+  header { position: sticky; top: 0; display: flex; gap: 8px; padding: 8px; background: #1b1b1b; border-bottom: 1px solid #333; }
+  input, select { font: inherit; background: #222; color: #ddd; border: 1px solid #444; padding: 2px 6px; }
+  table { border-collapse: collapse; width: 100%; }
+  td { padding: 2px 8px; border-bottom: 1px solid #222; vertical-align: top; white-space: pre-wrap; }
+  td.ts { color: #888; white-space: nowrap; }
+  td.target { color: #8ab; }
+  .ERROR { color: #f55; font-weight: bold; } .WARN { color: #fc3; } .INFO { color: #6c6; }
+  .DEBUG { color: #69f; } .TRACE { color: #777; }
+  #status { margin-left: auto; color: #888; }
+</style>
+</head>
+<body>
+<header>
+  <select id="level">
+    <option value="">all levels</option>
+    <option>ERROR</option><option>WARN</option><option>INFO</option><option>DEBUG</option><option>TRACE</option>
+  </select>
+  <input id="keyword" placeholder="keyword" size="30">
+  <span id="status">connecting…</span>
+</header>
+<table><tbody id="rows"></tbody></table>
+<script>
+const MAX_ROWS = 2000;
+const rows = document.getElementById("rows");
+const level = document.getElementById("level");
+const keyword = document.getElementById("keyword");
+const status = document.getElementById("status");
+
+function matches(entry) {
+  if (level.value && entry.level.toUpperCase() !== level.value) return false;
+  const k = keyword.value.toLowerCase();
+  return !k || entry.message.toLowerCase().includes(k) || entry.target.toLowerCase().includes(k);
+}
+
+function row(entry) {
+  const tr = document.createElement("tr");
+  for (const [cls, text] of [["ts", entry.timestamp], [entry.level.toUpperCase(), entry.level],
+                             ["target", entry.target], ["msg", entry.message]]) {
+    const td = document.createElement("td");
+    td.className = cls;
+    td.textContent = text;
+    tr.appendChild(td);
+  }
+  return tr;
+}
+
+function prepend(entry) {
+  if (!matches(entry)) return;
+  rows.insertBefore(row(entry), rows.firstChild);
+  while (rows.childElementCount > MAX_ROWS) rows.removeChild(rows.lastChild);
+}
+
+async function reload() {
+  const params = new URLSearchParams({ page_size: "500" });
+  if (level.value) params.set("level", level.value);
+  if (keyword.value) params.set("keyword", keyword.value);
+  const page = await (await fetch("/logs?" + params)).json();
+  rows.replaceChildren(...page.items.map(row));
+}
+
+let timer;
+level.addEventListener("change", reload);
+keyword.addEventListener("input", () => { clearTimeout(timer); timer = setTimeout(reload, 250); });
+
+const source = new EventSource("/logs/stream");
+source.onopen = () => { status.textContent = "live"; };
+source.onerror = () => { status.textContent = "reconnecting…"; };
+source.onmessage = (event) => prepend(JSON.parse(event.data));
+reload();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewer_page() {
+        assert!(LOG_VIEWER_CONTENT_TYPE.starts_with("text/html"));
+        assert!(LOG_VIEWER_HTML.starts_with("<!DOCTYPE html>"));
+        assert!(LOG_VIEWER_HTML.trim_end().ends_with("</html>"));
+        assert!(LOG_VIEWER_HTML.contains(r#"fetch("/logs?""#));
+        assert!(LOG_VIEWER_HTML.contains(r#"new EventSource("/logs/stream")"#));
+        // 查询参数名与 LogQuery 一致
+        let query: crate::LogQuery = serde_json::from_value(
+            serde_json::json!({"level": "WARN", "keyword": "x", "page_size": 500}),
+        )
+        .unwrap();
+        assert_eq!(query.page_size, Some(500));
+    }
+}
@@ -0,0 +1,37 @@
+//! `active_log_paths` 随每次全局安装 / 挂载更新
+//!
+//! 会安装全局 subscriber，因此单独作为一个测试二进制。
+
+#![cfg(feature = "broadcast")]
+
+use listen_tracing::{active_log_paths, setup_tracing_with_layer, BroadcastLogLayer, LogCache};
+
+#[tokio::test]
+async fn test_active_log_paths_follow_attach() {
+    assert!(active_log_paths().is_empty());
+    let dir = std::env::temp_dir();
+    let first = dir.join(format!(
+        "listen-tracing-active-a-{}.jsonl",
+        std::process::id()
+    ));
+    let second = dir.join(format!(
+        "listen-tracing-active-b-{}.jsonl",
+        std::process::id()
+    ));
+
+    let layer = |path: &std::path::Path| {
+        let (tx, _rx) = tokio::sync::broadcast::channel(16);
+        BroadcastLogLayer::new(tx, LogCache::default()).with_persist_path(path)
+    };
+    let config = setup_tracing_with_layer(layer(&first)).unwrap();
+    assert!(!config.attached);
+    assert_eq!(active_log_paths(), [first.as_path()]);
+
+    // 第二个 Layer 挂载到已安装的 subscriber 上
+    let config = setup_tracing_with_layer(layer(&second)).unwrap();
+    assert!(config.attached);
+    assert_eq!(active_log_paths(), [second.as_path()]);
+
+    let _ = std::fs::remove_file(&first);
+    let _ = std::fs::remove_file(&second);
+}
@@ -0,0 +1,102 @@
+//! 逐个 feature 组合执行 `cargo check`，确认 cfg 划分没有遗漏
+//!
+//! 每个组合都要单独编译，耗时较长，默认忽略：
+//! `cargo test --test feature_combinations -- --ignored`
+
+use std::path::Path;
+use std::process::Command;
+
+/// 单独启用的 feature（均不含默认 feature）
+const COMBINATIONS: &[&str] = &[
+    "",
+    "utils",
+    "setup",
+    "utils,setup",
+    "broadcast",
+    "cbor",
+    "gelf",
+    "msgpack",
+    "notify",
+    "postgres",
+    "redis",
+    "signal",
+    "syslog",
+    "tcp_sink",
+    "tz",
+    "utils,max_level_info",
+    "setup,release_max_level_warn",
+];
+
+/// 不应出现在依赖树中的 crate：(feature 组合, crate 名)
+const ABSENT: &[(&str, &str)] = &[
+    ("utils", "tokio"),
+    ("setup", "tokio"),
+    ("utils", "tracing-subscriber"),
+];
+
+fn cargo(args: &[&str]) -> std::process::Output {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    Command::new(env!("CARGO"))
+        .args(args)
+        .current_dir(manifest_dir)
+        // 与外层构建分开，避免争用 target 目录的锁
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(manifest_dir).join("target/feature-combinations"),
+        )
+        .output()
+        .expect("failed to run cargo")
+}
+
+#[test]
+#[ignore]
+fn test_feature_combinations_build() {
+    let failed: Vec<String> = COMBINATIONS
+        .iter()
+        .filter_map(|features| {
+            let output = cargo(&[
+                "check",
+                "--lib",
+                "--tests",
+                "--no-default-features",
+                "--features",
+                features,
+            ]);
+            (!output.status.success()).then(|| {
+                format!(
+                    "[{}]\n{}",
+                    features,
+                    String::from_utf8_lossy(&output.stderr)
+                )
+            })
+        })
+        .collect();
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}
+
+#[test]
+#[ignore]
+fn test_minimal_features_skip_runtime_deps() {
+    for (features, krate) in ABSENT {
+        let output = cargo(&[
+            "tree",
+            "--no-default-features",
+            "--features",
+            features,
+            "--edges",
+            "normal",
+            "--prefix",
+            "none",
+        ]);
+        assert!(output.status.success());
+        let tree = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            !tree
+                .lines()
+                .any(|line| line.split_whitespace().next() == Some(krate)),
+            "[{}] depends on {}",
+            features,
+            krate
+        );
+    }
+}